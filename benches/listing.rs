@@ -0,0 +1,153 @@
+use std::collections::HashMap;
+
+use criterion::{BatchSize, BenchmarkId, Criterion, criterion_group, criterion_main};
+
+use esemese_backend::models::{
+    PhotoAttributes,
+    catalog::{GridFile, ListedFile},
+    categories::CategoryResponse,
+    groups::GroupsWithThumbnailResponse,
+    pinata::{PinataFile, PinataGroup},
+};
+use esemese_backend::routes::{categories::category_query, groups::group_with_thumbnail};
+
+fn file(i: usize) -> PinataFile {
+    let mut keyvalues = HashMap::new();
+    keyvalues.insert("category".to_string(), "street".to_string());
+    keyvalues.insert("camera".to_string(), "Fujifilm X100V".to_string());
+    keyvalues.insert("lens".to_string(), "23mm f/2".to_string());
+    keyvalues.insert("iso".to_string(), "400".to_string());
+
+    PinataFile {
+        id: format!("file-{i}"),
+        name: format!("photo-{i}.jpg"),
+        cid: format!("bafybeigdyrzt5sfp7udm7hu76uh7y26nf3efuylqabf3oclgtqy55fbzd{i}"),
+        size: 4_000_000,
+        number_of_files: 1,
+        mime_type: "image/jpeg".to_string(),
+        group_id: "group-0".to_string(),
+        keyvalues: PhotoAttributes::from_keyvalues(keyvalues),
+        created_at: "2025-07-01T12:00:00Z".parse().unwrap(),
+        width: None,
+        height: None,
+        orientation: None,
+        blurhash: None,
+        captured_at: None,
+        account: None,
+    }
+}
+
+fn group(i: usize) -> PinataGroup {
+    PinataGroup {
+        id: format!("group-{i}"),
+        name: format!("Collection {i}"),
+        is_public: Some(true),
+        created_at: "2025-07-01T12:00:00Z".parse().unwrap(),
+        account: None,
+    }
+}
+
+fn metadata_filtering(c: &mut Criterion) {
+    let mut bench = c.benchmark_group("category_filter");
+
+    for count in [1, 5, 20] {
+        let categories: Vec<String> = (0..count).map(|i| format!("category-{i}")).collect();
+        bench.bench_with_input(
+            BenchmarkId::from_parameter(count),
+            &categories,
+            |b, cats| {
+                b.iter(|| {
+                    category_query(cats, &[])
+                        .url("https://api.pinata.cloud")
+                        .unwrap()
+                })
+            },
+        );
+    }
+
+    bench.finish();
+}
+
+fn response_shaping(c: &mut Criterion) {
+    c.bench_function("groups_with_thumbnails/100", |b| {
+        b.iter_batched(
+            || {
+                (0..100)
+                    .map(|i| (group(i), vec![file(i)]))
+                    .collect::<Vec<_>>()
+            },
+            |groups| {
+                let collections = groups
+                    .into_iter()
+                    .map(|(group, files)| group_with_thumbnail(group, files, 24))
+                    .collect();
+
+                serde_json::to_vec(&GroupsWithThumbnailResponse {
+                    success: true,
+                    collections,
+                    message: None,
+                })
+                .unwrap()
+            },
+            BatchSize::SmallInput,
+        )
+    });
+
+    c.bench_function("category_response/500", |b| {
+        b.iter_batched(
+            || {
+                (0..500)
+                    .map(|i| ListedFile::Detail(Box::new(file(i))))
+                    .collect()
+            },
+            |images| {
+                serde_json::to_vec(&CategoryResponse {
+                    success: true,
+                    images,
+                    preload: Vec::new(),
+                    message: None,
+                    warnings: Vec::new(),
+                })
+                .unwrap()
+            },
+            BatchSize::SmallInput,
+        )
+    });
+
+    // what `?view=grid` cuts the same listing down to
+    c.bench_function("category_response_grid/500", |b| {
+        b.iter_batched(
+            || {
+                (0..500)
+                    .map(|i| {
+                        let file = file(i);
+                        ListedFile::Grid(GridFile {
+                            thumbnail_url: Some(format!(
+                                "https://gateway.example.com/files/{}?img-width=400",
+                                file.cid
+                            )),
+                            id: file.id,
+                            width: file.width,
+                            height: file.height,
+                            blurhash: file.blurhash,
+                        })
+                    })
+                    .collect()
+            },
+            |images| {
+                serde_json::to_vec(&CategoryResponse {
+                    success: true,
+                    images,
+                    preload: Vec::new(),
+                    message: None,
+                    warnings: Vec::new(),
+                })
+                .unwrap()
+            },
+            BatchSize::SmallInput,
+        )
+    });
+}
+
+criterion_group!(benches, metadata_filtering, response_shaping);
+criterion_main!(benches);
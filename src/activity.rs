@@ -0,0 +1,117 @@
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::task::{Context, Poll};
+
+use axum::body::{Body, Bytes};
+use http_body::{Frame, SizeHint};
+use metrics::gauge;
+
+use crate::models::status::BackgroundJobs;
+
+// Work a restart would cut short. Each kind is counted while a `Tracked` for it is
+// alive, published as a gauge and reported by GET /admin/status.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Activity {
+    // until the last byte of the response is sent
+    Request,
+    // admitted to the upload queue, including a background job holding its slot,
+    // or writing a chunk of an upload session
+    Upload,
+    EventStream,
+    UploadJob,
+    Processing,
+    Reencode,
+    Notification,
+}
+
+const KINDS: usize = 7;
+
+static COUNTS: [AtomicUsize; KINDS] = [const { AtomicUsize::new(0) }; KINDS];
+
+impl Activity {
+    fn index(self) -> usize {
+        self as usize
+    }
+
+    fn publish(self, count: usize) {
+        let count = count as f64;
+        match self {
+            Self::Request => gauge!("http_requests_in_flight").set(count),
+            Self::Upload => gauge!("uploads_in_progress").set(count),
+            Self::EventStream => gauge!("event_streams_open").set(count),
+            Self::UploadJob => gauge!("background_jobs_running", "kind" => "upload").set(count),
+            Self::Processing => {
+                gauge!("background_jobs_running", "kind" => "processing").set(count)
+            }
+            Self::Reencode => gauge!("background_jobs_running", "kind" => "reencode").set(count),
+            Self::Notification => {
+                gauge!("background_jobs_running", "kind" => "notification").set(count)
+            }
+        }
+    }
+}
+
+pub fn count(activity: Activity) -> usize {
+    COUNTS[activity.index()].load(Ordering::SeqCst)
+}
+
+pub fn background_jobs() -> BackgroundJobs {
+    BackgroundJobs {
+        uploads: count(Activity::UploadJob),
+        processing: count(Activity::Processing),
+        reencode: count(Activity::Reencode),
+        notifications: count(Activity::Notification),
+    }
+}
+
+// counts its activity as in progress until dropped
+#[derive(Debug)]
+pub struct Tracked(Activity);
+
+impl Tracked {
+    pub fn start(activity: Activity) -> Self {
+        let count = COUNTS[activity.index()].fetch_add(1, Ordering::SeqCst) + 1;
+        activity.publish(count);
+        Self(activity)
+    }
+}
+
+impl Drop for Tracked {
+    fn drop(&mut self) {
+        let count = COUNTS[self.0.index()].fetch_sub(1, Ordering::SeqCst) - 1;
+        self.0.publish(count);
+    }
+}
+
+// wrap a response body so its request counts as in flight until the body is done
+pub fn tracked_body(tracked: Tracked, body: Body) -> Body {
+    Body::new(TrackedBody {
+        _tracked: tracked,
+        inner: body,
+    })
+}
+
+struct TrackedBody {
+    _tracked: Tracked,
+    inner: Body,
+}
+
+impl http_body::Body for TrackedBody {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        Pin::new(&mut self.inner).poll_frame(cx)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
@@ -0,0 +1,62 @@
+use std::collections::BTreeMap;
+use std::sync::LazyLock;
+use std::time::Duration;
+
+use tracing::warn;
+
+use crate::errors::ApiError;
+use crate::imaging;
+use crate::models::files::ImageAnalysis;
+use crate::pinata::{PinataClient, gateway};
+use crate::store::JsonStore;
+
+// originals fetched for an analysis only have to be downloaded once
+const FETCH_TTL: Duration = Duration::from_secs(300);
+
+// Exposure diagnostics by file id. Too large for keyvalues, so they're kept here;
+// anything missing is worked out from the original when first asked for.
+static ANALYSES: LazyLock<JsonStore<BTreeMap<String, ImageAnalysis>>> =
+    LazyLock::new(|| JsonStore::open("file_analysis"));
+
+pub fn get(file_id: &str) -> Option<ImageAnalysis> {
+    ANALYSES.read(|analyses| analyses.get(file_id).cloned())
+}
+
+pub fn record(file_id: &str, analysis: ImageAnalysis) -> Result<(), ApiError> {
+    ANALYSES.update(|analyses| {
+        analyses.insert(file_id.to_string(), analysis);
+    })
+}
+
+pub fn remove(file_id: &str) {
+    if let Err(e) = ANALYSES.update(|analyses| analyses.remove(file_id)) {
+        warn!("Failed to drop the analysis of {file_id}: {e}");
+    }
+}
+
+async fn analyze_bytes(file_id: &str, bytes: Vec<u8>) -> Result<ImageAnalysis, ApiError> {
+    let analysis = tokio::task::spawn_blocking(move || {
+        imaging::decode(&bytes).map(|decoded| imaging::analyze(&decoded.image))
+    })
+    .await
+    .map_err(|e| ApiError::Internal(format!("Image analysis failed: {e}")))??;
+
+    record(file_id, analysis.clone())?;
+    Ok(analysis)
+}
+
+// analyze an original that has no stored analysis yet, from the gateway
+pub async fn compute(
+    pinata: &PinataClient,
+    file_id: &str,
+    cid: &str,
+) -> Result<ImageAnalysis, ApiError> {
+    let original = gateway::fetch(pinata, cid, None, FETCH_TTL)
+        .await?
+        .ok_or_else(|| {
+            ApiError::Api("PINATA_GATEWAY must be set to analyze earlier uploads".to_string())
+        })?
+        .bytes()
+        .await?;
+    analyze_bytes(file_id, original.to_vec()).await
+}
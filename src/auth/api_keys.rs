@@ -0,0 +1,79 @@
+use chrono::Utc;
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+
+use crate::db::Db;
+use crate::errors::ApiError;
+use crate::models::api_keys::{ApiKey, KeyScope};
+
+// header third parties present their key in
+pub const API_KEY_HEADER: &str = "x-api-key";
+
+const SECRET_PREFIX: &str = "esk_";
+
+// Issued keys live in the local database, indexed by the sha256 of their secret, so
+// issuing them needs DATABASE_URL.
+
+// issue a new key, returning its record and the secret (which is only ever shown here)
+pub async fn create(
+    db: &Db,
+    name: &str,
+    scope: KeyScope,
+    rate_limit_per_minute: Option<u32>,
+    groups: Option<Vec<String>>,
+) -> Result<(ApiKey, String), ApiError> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(ApiError::Validation("Key name is required".to_string()));
+    }
+    if groups.is_some() && scope != KeyScope::Upload {
+        return Err(ApiError::Validation(
+            "Only upload keys can be limited to groups".to_string(),
+        ));
+    }
+    if groups.as_ref().is_some_and(Vec::is_empty) {
+        return Err(ApiError::Validation(
+            "groups must name at least one group, or be left out".to_string(),
+        ));
+    }
+
+    let secret = format!("{SECRET_PREFIX}{}", random_hex(24));
+    let key = ApiKey {
+        id: random_hex(8),
+        name: name.to_string(),
+        scope,
+        prefix: secret[..SECRET_PREFIX.len() + 6].to_string(),
+        rate_limit_per_minute,
+        groups,
+        created_at: Utc::now(),
+        revoked_at: None,
+    };
+
+    db.insert_api_key(&hash_secret(&secret), &key).await?;
+    Ok((key, secret))
+}
+
+// mark a key revoked; the record stays so usage history still resolves
+pub async fn revoke(db: &Db, id: &str) -> Result<Option<ApiKey>, ApiError> {
+    db.revoke_api_key(id, Utc::now()).await
+}
+
+// the active key for a presented secret
+pub async fn authenticate(db: &Db, secret: &str) -> Result<Option<ApiKey>, ApiError> {
+    let key = db.api_key_by_secret(&hash_secret(secret)).await?;
+    Ok(key.filter(|key| key.revoked_at.is_none()))
+}
+
+fn hash_secret(secret: &str) -> String {
+    to_hex(&Sha256::digest(secret.as_bytes()))
+}
+
+fn random_hex(bytes: usize) -> String {
+    let mut buf = vec![0u8; bytes];
+    rand::rng().fill_bytes(&mut buf);
+    to_hex(&buf)
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
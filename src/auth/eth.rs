@@ -0,0 +1,220 @@
+use std::sync::LazyLock;
+
+use num_bigint::BigUint;
+
+// The two pieces of Ethereum cryptography a wallet signature needs: Keccak-256 and
+// recovering the signer's key from a secp256k1 signature. Signatures are only ever
+// checked, never made, so nothing here handles a secret.
+
+const ROUND_CONSTANTS: [u64; 24] = [
+    0x0000000000000001,
+    0x0000000000008082,
+    0x800000000000808a,
+    0x8000000080008000,
+    0x000000000000808b,
+    0x0000000080000001,
+    0x8000000080008081,
+    0x8000000000008009,
+    0x000000000000008a,
+    0x0000000000000088,
+    0x0000000080008009,
+    0x000000008000000a,
+    0x000000008000808b,
+    0x800000000000008b,
+    0x8000000000008089,
+    0x8000000000008003,
+    0x8000000000008002,
+    0x8000000000000080,
+    0x000000000000800a,
+    0x800000008000000a,
+    0x8000000080008081,
+    0x8000000000008080,
+    0x0000000080000001,
+    0x8000000080008008,
+];
+const ROTATIONS: [u32; 24] = [
+    1, 3, 6, 10, 15, 21, 28, 36, 45, 55, 2, 14, 27, 41, 56, 8, 25, 43, 62, 18, 39, 61, 20, 44,
+];
+const LANES: [usize; 24] = [
+    10, 7, 11, 17, 18, 3, 5, 16, 8, 21, 24, 4, 15, 23, 19, 13, 12, 2, 20, 14, 22, 9, 6, 1,
+];
+// bytes absorbed per permutation for a 256-bit output
+const RATE: usize = 136;
+
+fn keccak_f(state: &mut [u64; 25]) {
+    for constant in ROUND_CONSTANTS {
+        let mut columns = [0u64; 5];
+        for (x, column) in columns.iter_mut().enumerate() {
+            *column = (0..5).fold(0, |acc, y| acc ^ state[x + 5 * y]);
+        }
+        for x in 0..5 {
+            let d = columns[(x + 4) % 5] ^ columns[(x + 1) % 5].rotate_left(1);
+            for y in 0..5 {
+                state[x + 5 * y] ^= d;
+            }
+        }
+
+        let mut last = state[1];
+        for (lane, rotation) in LANES.iter().zip(ROTATIONS) {
+            let next = state[*lane];
+            state[*lane] = last.rotate_left(rotation);
+            last = next;
+        }
+
+        for y in 0..5 {
+            let row: [u64; 5] = std::array::from_fn(|x| state[x + 5 * y]);
+            for x in 0..5 {
+                state[x + 5 * y] = row[x] ^ (!row[(x + 1) % 5] & row[(x + 2) % 5]);
+            }
+        }
+
+        state[0] ^= constant;
+    }
+}
+
+// Keccak-256 as Ethereum uses it, with the original padding rather than SHA-3's
+pub fn keccak256(data: &[u8]) -> [u8; 32] {
+    let mut state = [0u64; 25];
+    let mut padded = data.to_vec();
+    padded.push(0x01);
+    padded.resize(padded.len().div_ceil(RATE) * RATE, 0);
+    let last = padded.len() - 1;
+    padded[last] |= 0x80;
+
+    for block in padded.chunks(RATE) {
+        for (lane, bytes) in state.iter_mut().zip(block.chunks(8)) {
+            *lane ^= u64::from_le_bytes(bytes.try_into().unwrap());
+        }
+        keccak_f(&mut state);
+    }
+
+    let mut out = [0u8; 32];
+    for (bytes, lane) in out.chunks_mut(8).zip(state) {
+        bytes.copy_from_slice(&lane.to_le_bytes());
+    }
+    out
+}
+
+fn hex_number(hex: &str) -> BigUint {
+    BigUint::parse_bytes(hex.as_bytes(), 16).unwrap()
+}
+
+// the secp256k1 field, group order and generator
+struct Curve {
+    p: BigUint,
+    n: BigUint,
+    g: Point,
+}
+
+static CURVE: LazyLock<Curve> = LazyLock::new(|| Curve {
+    p: hex_number("fffffffffffffffffffffffffffffffffffffffffffffffffffffffefffffc2f"),
+    n: hex_number("fffffffffffffffffffffffffffffffebaaedce6af48a03bbfd25e8cd0364141"),
+    g: Some((
+        hex_number("79be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798"),
+        hex_number("483ada7726a3c4655da4fbfc0e1108a8fd17b448a68554199c47d08ffb10d4b8"),
+    )),
+});
+
+// an affine point, None being the point at infinity
+type Point = Option<(BigUint, BigUint)>;
+
+// a^-1 mod m, for prime m
+fn inverse(a: &BigUint, m: &BigUint) -> BigUint {
+    a.modpow(&(m - 2u32), m)
+}
+
+// a - b mod m, for a and b already below m
+fn sub_mod(a: &BigUint, b: &BigUint, m: &BigUint) -> BigUint {
+    (a + m - b) % m
+}
+
+fn add(a: &Point, b: &Point) -> Point {
+    let p = &CURVE.p;
+    let (Some((x1, y1)), Some((x2, y2))) = (a, b) else {
+        return a.clone().or_else(|| b.clone());
+    };
+
+    let slope = if x1 == x2 {
+        if (y1 + y2) % p == BigUint::ZERO {
+            return None;
+        }
+        // doubling: 3x^2 / 2y, since a = 0 on this curve
+        (3u32 * x1 * x1) % p * inverse(&(2u32 * y1 % p), p) % p
+    } else {
+        sub_mod(y2, y1, p) * inverse(&sub_mod(x2, x1, p), p) % p
+    };
+    let x3 = sub_mod(&(&slope * &slope % p), &((x1 + x2) % p), p);
+    let y3 = sub_mod(&(&slope * sub_mod(x1, &x3, p) % p), y1, p);
+    Some((x3, y3))
+}
+
+fn multiply(k: &BigUint, point: &Point) -> Point {
+    let mut result = None;
+    for bit in (0..k.bits()).rev() {
+        result = add(&result, &result);
+        if k.bit(bit) {
+            result = add(&result, point);
+        }
+    }
+    result
+}
+
+// The uncompressed public key (x and y, 32 bytes each) that made `signature` over
+// the 32-byte `hash`. Signatures are `r || s || v`, with v 0 or 1, or 27 or 28.
+pub fn recover_public_key(hash: &[u8; 32], signature: &[u8; 65]) -> Option<[u8; 64]> {
+    let Curve { p, n, g } = &*CURVE;
+    let r = BigUint::from_bytes_be(&signature[..32]);
+    let s = BigUint::from_bytes_be(&signature[32..64]);
+    let recovery = match signature[64] {
+        v @ (0 | 1) => v,
+        v @ (27 | 28) => v - 27,
+        _ => return None,
+    };
+    if r == BigUint::ZERO || s == BigUint::ZERO || &r >= n || &s >= n || &r >= p {
+        return None;
+    }
+
+    // the point r is the x of, picked by the recovery bit from the two with that x
+    let rhs = (r.modpow(&3u32.into(), p) + 7u32) % p;
+    let mut y = rhs.modpow(&((p + 1u32) / 4u32), p);
+    if &y * &y % p != rhs {
+        return None;
+    }
+    if y.bit(0) != (recovery == 1) {
+        y = p - y;
+    }
+    let point_r = Some((r.clone(), y));
+
+    // Q = r^-1 (sR - eG)
+    let e = BigUint::from_bytes_be(hash) % n;
+    let r_inverse = inverse(&r, n);
+    let u1 = sub_mod(&BigUint::ZERO, &(e * &r_inverse % n), n);
+    let u2 = s * &r_inverse % n;
+    let (x, y) = add(&multiply(&u1, g), &multiply(&u2, &point_r))?;
+
+    let mut key = [0u8; 64];
+    for (half, coordinate) in key.chunks_mut(32).zip([x, y]) {
+        let bytes = coordinate.to_bytes_be();
+        half[32 - bytes.len()..].copy_from_slice(&bytes);
+    }
+    Some(key)
+}
+
+// the lowercase `0x...` address of a public key
+pub fn address(public_key: &[u8; 64]) -> String {
+    let hash = keccak256(public_key);
+    let hex: String = hash[12..].iter().map(|b| format!("{b:02x}")).collect();
+    format!("0x{hex}")
+}
+
+// EIP-191 `personal_sign`: what a wallet actually signs when asked to sign `message`
+pub fn personal_message_hash(message: &str) -> [u8; 32] {
+    let prefixed = format!("\x19Ethereum Signed Message:\n{}{message}", message.len());
+    keccak256(prefixed.as_bytes())
+}
+
+// the address that signed `message` with `personal_sign`
+pub fn recover_signer(message: &str, signature: &[u8; 65]) -> Option<String> {
+    let key = recover_public_key(&personal_message_hash(message), signature)?;
+    Some(address(&key))
+}
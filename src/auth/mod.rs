@@ -0,0 +1,31 @@
+pub mod api_keys;
+pub mod eth;
+pub mod roles;
+pub mod siwe;
+pub mod usage;
+pub mod visitors;
+
+use crate::config;
+
+// compare secrets without leaking, through timing, how much of a guess was right
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+// compare a presented bearer token against ADMIN_TOKEN
+pub fn is_admin_token(token: &str) -> bool {
+    config::auth()
+        .admin_token
+        .as_deref()
+        .is_some_and(|expected| constant_time_eq(expected.as_bytes(), token.as_bytes()))
+}
+
+// the token from an `Authorization: Bearer <token>` header
+pub fn bearer_token(headers: &axum::http::HeaderMap) -> Option<&str> {
+    headers
+        .get(axum::http::header::AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+        .map(str::trim)
+}
@@ -0,0 +1,120 @@
+use std::marker::PhantomData;
+
+use axum::{
+    extract::FromRequestParts,
+    http::{Extensions, HeaderMap, request::Parts},
+    response::{IntoResponse, Response},
+};
+use serde::Serialize;
+
+use super::{bearer_token, is_admin_token, siwe};
+use crate::errors::ApiError;
+use crate::models::{ApiKey, KeyScope};
+
+// What a request's credentials let it do. Each role may do everything the ones
+// before it may.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    // reads the public catalog, as anyone may
+    Viewer,
+    // also uploads, possibly only into some groups
+    Uploader,
+    // the owner: everything, deletes and catalog management included
+    Admin,
+}
+
+impl From<KeyScope> for Role {
+    fn from(scope: KeyScope) -> Self {
+        match scope {
+            KeyScope::ReadOnly => Self::Viewer,
+            KeyScope::Upload => Self::Uploader,
+            KeyScope::Admin => Self::Admin,
+        }
+    }
+}
+
+// who a request is from, as far as its credentials tell
+#[derive(Debug, Clone)]
+pub struct Principal {
+    pub role: Role,
+    // the API key the request came with, if any
+    pub key_id: Option<String>,
+    // the groups an uploader may upload into, any when unset
+    pub groups: Option<Vec<String>>,
+}
+
+impl Principal {
+    // The admin token or an owner session make the owner, otherwise the API key
+    // `api_key_scope` attached decides. Requests without either are viewers.
+    pub fn of(headers: &HeaderMap, extensions: &Extensions) -> Self {
+        if bearer_token(headers).is_some_and(|t| is_admin_token(t) || siwe::is_owner_session(t)) {
+            return Self {
+                role: Role::Admin,
+                key_id: None,
+                groups: None,
+            };
+        }
+        match extensions.get::<ApiKey>() {
+            Some(key) => Self {
+                role: key.scope.into(),
+                key_id: Some(key.id.clone()),
+                groups: key.groups.clone().filter(|_| key.scope == KeyScope::Upload),
+            },
+            None => Self {
+                role: Role::Viewer,
+                key_id: None,
+                groups: None,
+            },
+        }
+    }
+
+    // whether this principal may upload into `group_id`, or into a new group;
+    // uploaders held to some groups can't start new ones
+    pub fn may_upload_to(&self, group_id: Option<&str>, new_group: bool) -> bool {
+        match (self.role, &self.groups) {
+            (Role::Viewer, _) => false,
+            (_, None) => true,
+            (_, Some(groups)) => {
+                !new_group && group_id.is_some_and(|id| groups.iter().any(|g| g == id))
+            }
+        }
+    }
+}
+
+// the least role a `RequireRole` asks for
+pub trait MinimumRole {
+    const ROLE: Role;
+}
+
+pub struct Uploader;
+
+impl MinimumRole for Uploader {
+    const ROLE: Role = Role::Uploader;
+}
+
+// Refuses requests whose principal is below `R`, handing the principal to the
+// handler otherwise, e.g. `RequireRole(principal, _): RequireRole<Uploader>`
+pub struct RequireRole<R: MinimumRole>(pub Principal, pub PhantomData<R>);
+
+impl<S: Send + Sync, R: MinimumRole> FromRequestParts<S> for RequireRole<R> {
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let principal = Principal::of(&parts.headers, &parts.extensions);
+        if principal.role >= R::ROLE {
+            return Ok(Self(principal, PhantomData));
+        }
+        let needed = serde_json::to_value(R::ROLE)
+            .ok()
+            .and_then(|role| role.as_str().map(str::to_string))
+            .unwrap_or_default();
+        Err(match principal.key_id {
+            Some(key_id) => {
+                ApiError::Forbidden(format!("API key {key_id} doesn't have the {needed} role"))
+            }
+            None => ApiError::Unauthorized(format!("The {needed} role is required")),
+        }
+        .into_response())
+    }
+}
@@ -0,0 +1,252 @@
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex};
+
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use chrono::{DateTime, Duration, Utc};
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+use super::{constant_time_eq, eth};
+use crate::config;
+
+// Sign-In With Ethereum (EIP-4361) for the owner: a nonce is handed out, the owner's
+// wallet signs a message naming it, and a valid signature by OWNER_ADDRESS is traded
+// for a session token (an HS256 JWT signed with SESSION_SECRET) that's accepted
+// wherever the admin token is.
+
+// how long a nonce waits to be signed
+const NONCE_TTL: Duration = Duration::minutes(10);
+// outstanding nonces kept at most, since anyone can ask for one
+const MAX_NONCES: usize = 1024;
+
+const HEADER: &str = r#"{"alg":"HS256","typ":"JWT"}"#;
+
+static NONCES: LazyLock<Mutex<HashMap<String, DateTime<Utc>>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+#[derive(Debug)]
+pub enum SignInError {
+    // the message or signature couldn't be read
+    Malformed(String),
+    // well formed, but not a sign-in by the owner that's valid now
+    Rejected(String),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Claims {
+    sub: String,
+    iat: i64,
+    exp: i64,
+}
+
+// the fields of an EIP-4361 message this backend checks
+#[derive(Debug)]
+struct Message {
+    domain: String,
+    address: String,
+    version: String,
+    nonce: String,
+    issued_at: DateTime<Utc>,
+    expiration_time: Option<DateTime<Utc>>,
+    not_before: Option<DateTime<Utc>>,
+}
+
+pub fn is_enabled() -> bool {
+    let auth = config::auth();
+    auth.owner_address.is_some() && auth.session_secret.is_some()
+}
+
+// a fresh nonce for the next sign-in message, and when it stops being accepted
+pub fn issue_nonce() -> (String, DateTime<Utc>) {
+    let mut buf = [0u8; 16];
+    rand::rng().fill_bytes(&mut buf);
+    let nonce: String = buf.iter().map(|b| format!("{b:02x}")).collect();
+
+    let now = Utc::now();
+    let expires_at = now + NONCE_TTL;
+    let mut nonces = NONCES.lock().unwrap_or_else(|e| e.into_inner());
+    nonces.retain(|_, expires| *expires > now);
+    if nonces.len() >= MAX_NONCES
+        && let Some(oldest) = nonces
+            .iter()
+            .min_by_key(|(_, expires)| **expires)
+            .map(|(nonce, _)| nonce.clone())
+    {
+        nonces.remove(&oldest);
+    }
+    nonces.insert(nonce.clone(), expires_at);
+    (nonce, expires_at)
+}
+
+fn malformed(message: &str) -> SignInError {
+    SignInError::Malformed(format!("Not a sign-in message: {message}"))
+}
+
+fn timestamp(field: &str, value: &str) -> Result<DateTime<Utc>, SignInError> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|t| t.with_timezone(&Utc))
+        .map_err(|_| malformed(&format!("{field} must be an RFC 3339 timestamp")))
+}
+
+fn parse(text: &str) -> Result<Message, SignInError> {
+    let mut lines = text.lines();
+    let domain = lines
+        .next()
+        .and_then(|line| line.strip_suffix(" wants you to sign in with your Ethereum account:"))
+        .ok_or_else(|| malformed("the first line doesn't name a domain"))?;
+    // a scheme may come before the domain
+    let domain = domain.split_once("://").map_or(domain, |(_, rest)| rest);
+    let address = lines
+        .next()
+        .filter(|line| line.starts_with("0x") && line.len() == 42)
+        .ok_or_else(|| malformed("the second line isn't an address"))?;
+
+    // the statement is free text, the fields after it are `Name: value` lines
+    let mut fields = HashMap::new();
+    for line in lines {
+        if let Some((name, value)) = line.split_once(": ") {
+            fields.entry(name).or_insert(value);
+        }
+    }
+    let field = |name: &str| {
+        fields
+            .get(name)
+            .map(|v| v.to_string())
+            .ok_or_else(|| malformed(&format!("{name} is missing")))
+    };
+
+    Ok(Message {
+        domain: domain.to_string(),
+        address: address.to_ascii_lowercase(),
+        version: field("Version")?,
+        nonce: field("Nonce")?,
+        issued_at: timestamp("Issued At", &field("Issued At")?)?,
+        expiration_time: fields
+            .get("Expiration Time")
+            .map(|v| timestamp("Expiration Time", v))
+            .transpose()?,
+        not_before: fields
+            .get("Not Before")
+            .map(|v| timestamp("Not Before", v))
+            .transpose()?,
+    })
+}
+
+fn signature_bytes(signature: &str) -> Result<[u8; 65], SignInError> {
+    let hex = signature.trim();
+    let hex = hex.strip_prefix("0x").unwrap_or(hex);
+    let invalid =
+        || SignInError::Malformed("The signature must be 65 hex encoded bytes".to_string());
+    if hex.len() != 130 {
+        return Err(invalid());
+    }
+    let mut bytes = [0u8; 65];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(hex.get(i * 2..i * 2 + 2).ok_or_else(invalid)?, 16)
+            .map_err(|_| invalid())?;
+    }
+    Ok(bytes)
+}
+
+fn mac(secret: &str, input: &str) -> Vec<u8> {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC takes keys of any length");
+    mac.update(input.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+// Check a signed sign-in message for `domain` and open a session for its signer,
+// returning the session token, the owner's address and when the token expires
+pub fn verify(
+    text: &str,
+    signature: &str,
+    domain: &str,
+) -> Result<(String, String, DateTime<Utc>), SignInError> {
+    let auth = config::auth();
+    let (Some(owner), Some(secret)) = (&auth.owner_address, &auth.session_secret) else {
+        return Err(SignInError::Rejected("Sign-in is disabled".to_string()));
+    };
+
+    let message = parse(text)?;
+    let signature = signature_bytes(signature)?;
+    let rejected = |reason: &str| Err(SignInError::Rejected(reason.to_string()));
+    if message.version != "1" {
+        return rejected("Only version 1 sign-in messages are supported");
+    }
+    if !message.domain.eq_ignore_ascii_case(domain) {
+        return Err(SignInError::Rejected(format!(
+            "The message is for {}, not {domain}",
+            message.domain
+        )));
+    }
+    if message.address != *owner {
+        return rejected("Only the owner's address can sign in");
+    }
+    let now = Utc::now();
+    if message.expiration_time.is_some_and(|t| t <= now) {
+        return rejected("The message has expired");
+    }
+    if message.not_before.is_some_and(|t| t > now) || message.issued_at > now + Duration::minutes(5)
+    {
+        return rejected("The message isn't valid yet");
+    }
+
+    if eth::recover_signer(text, &signature).as_deref() != Some(owner.as_str()) {
+        return rejected("The signature wasn't made by the owner's address");
+    }
+    // use the nonce up only once the rest holds, so a bad attempt can't burn it
+    let fresh = NONCES
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .remove(&message.nonce)
+        .is_some_and(|expires| expires > now);
+    if !fresh {
+        return rejected("The nonce is unknown, expired or already used");
+    }
+
+    let expires_at = now + Duration::seconds(auth.session_ttl_secs as i64);
+    let claims = Claims {
+        sub: owner.clone(),
+        iat: now.timestamp(),
+        exp: expires_at.timestamp(),
+    };
+    let claims = serde_json::to_string(&claims).expect("claims serialize");
+    let input = format!(
+        "{}.{}",
+        URL_SAFE_NO_PAD.encode(HEADER),
+        URL_SAFE_NO_PAD.encode(claims)
+    );
+    let token = format!("{input}.{}", URL_SAFE_NO_PAD.encode(mac(secret, &input)));
+    Ok((token, owner.clone(), expires_at))
+}
+
+// whether a bearer token is an unexpired session of the current owner
+pub fn is_owner_session(token: &str) -> bool {
+    let auth = config::auth();
+    let (Some(owner), Some(secret)) = (&auth.owner_address, &auth.session_secret) else {
+        return false;
+    };
+    let Some((input, signature)) = token.rsplit_once('.') else {
+        return false;
+    };
+    let Some((header, claims)) = input.split_once('.') else {
+        return false;
+    };
+    let Ok(signature) = URL_SAFE_NO_PAD.decode(signature) else {
+        return false;
+    };
+    if !constant_time_eq(&mac(secret, input), &signature)
+        || header != URL_SAFE_NO_PAD.encode(HEADER)
+    {
+        return false;
+    }
+
+    URL_SAFE_NO_PAD
+        .decode(claims)
+        .ok()
+        .and_then(|claims| serde_json::from_slice::<Claims>(&claims).ok())
+        .is_some_and(|claims| claims.sub == *owner && claims.exp > Utc::now().timestamp())
+}
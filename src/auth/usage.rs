@@ -0,0 +1,134 @@
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::sync::{LazyLock, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use axum::body::{Body, Bytes};
+use chrono::{DateTime, Utc};
+use http_body::{Frame, SizeHint};
+use metrics::counter;
+
+use crate::models::api_keys::KeyUsage;
+
+const RATE_WINDOW: Duration = Duration::from_secs(60);
+
+// Per-key counters since the process started. They're in memory only; the
+// Prometheus counters carry the long-term history.
+#[derive(Debug)]
+struct Usage {
+    requests: u64,
+    bytes_served: u64,
+    rate_limited: u64,
+    last_used_at: Option<DateTime<Utc>>,
+    // fixed one-minute window for the per-key rate limit
+    window_start: Instant,
+    window_requests: u32,
+}
+
+impl Default for Usage {
+    fn default() -> Self {
+        Self {
+            requests: 0,
+            bytes_served: 0,
+            rate_limited: 0,
+            last_used_at: None,
+            window_start: Instant::now(),
+            window_requests: 0,
+        }
+    }
+}
+
+static STARTED_AT: LazyLock<DateTime<Utc>> = LazyLock::new(Utc::now);
+static USAGE: LazyLock<Mutex<HashMap<String, Usage>>> = LazyLock::new(Default::default);
+
+// start the usage clock, called once when the app is built
+pub fn install() {
+    LazyLock::force(&STARTED_AT);
+}
+
+// count a request against a key, or return how long until it may retry
+pub fn admit(key_id: &str, per_minute: Option<u32>) -> Result<(), Duration> {
+    let mut usage = USAGE.lock().unwrap();
+    let entry = usage.entry(key_id.to_string()).or_default();
+
+    let now = Instant::now();
+    if now.duration_since(entry.window_start) >= RATE_WINDOW {
+        entry.window_start = now;
+        entry.window_requests = 0;
+    }
+
+    if let Some(limit) = per_minute
+        && entry.window_requests >= limit
+    {
+        entry.rate_limited += 1;
+        counter!("api_key_rate_limited_total", "key" => key_id.to_string()).increment(1);
+        return Err(RATE_WINDOW.saturating_sub(now.duration_since(entry.window_start)));
+    }
+
+    entry.window_requests += 1;
+    entry.requests += 1;
+    entry.last_used_at = Some(Utc::now());
+    counter!("api_key_requests_total", "key" => key_id.to_string()).increment(1);
+    Ok(())
+}
+
+fn record_bytes(key_id: &str, bytes: u64) {
+    if let Some(entry) = USAGE.lock().unwrap().get_mut(key_id) {
+        entry.bytes_served += bytes;
+    }
+    counter!("api_key_bytes_served_total", "key" => key_id.to_string()).increment(bytes);
+}
+
+pub fn snapshot(key_id: &str) -> KeyUsage {
+    let usage = USAGE.lock().unwrap();
+    let entry = usage.get(key_id);
+
+    KeyUsage {
+        key_id: key_id.to_string(),
+        requests: entry.map_or(0, |e| e.requests),
+        bytes_served: entry.map_or(0, |e| e.bytes_served),
+        rate_limited: entry.map_or(0, |e| e.rate_limited),
+        last_used_at: entry.and_then(|e| e.last_used_at),
+        since: *STARTED_AT,
+    }
+}
+
+// wrap a response body so the bytes actually sent are charged to the key
+pub fn metered(key_id: String, body: Body) -> Body {
+    Body::new(MeteredBody {
+        key_id,
+        inner: body,
+    })
+}
+
+struct MeteredBody {
+    key_id: String,
+    inner: Body,
+}
+
+impl http_body::Body for MeteredBody {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let polled = Pin::new(&mut self.inner).poll_frame(cx);
+        if let Poll::Ready(Some(Ok(frame))) = &polled
+            && let Some(data) = frame.data_ref()
+        {
+            record_bytes(&self.key_id, data.len() as u64);
+        }
+        polled
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
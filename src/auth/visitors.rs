@@ -0,0 +1,59 @@
+use axum::http::HeaderMap;
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use sha2::Sha256;
+
+use crate::config;
+
+// a visitor presents their token in this header, or in the cookie of the same purpose
+pub const VISITOR_HEADER: &str = "x-visitor-token";
+pub const VISITOR_COOKIE: &str = "visitor";
+
+fn signature(secret: &str, id: &str) -> Option<String> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).ok()?;
+    mac.update(id.as_bytes());
+    Some(
+        mac.finalize()
+            .into_bytes()
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect(),
+    )
+}
+
+// a fresh visitor id and the `{id}.{signature}` token that proves it
+pub fn issue() -> Option<(String, String)> {
+    let secret = config::auth().visitor_secret.as_deref()?;
+    let mut buf = [0u8; 16];
+    rand::rng().fill_bytes(&mut buf);
+    let id: String = buf.iter().map(|b| format!("{b:02x}")).collect();
+
+    let token = format!("{id}.{}", signature(secret, &id)?);
+    Some((id, token))
+}
+
+// the visitor id a token was issued for, if its signature holds
+pub fn verify(token: &str) -> Option<String> {
+    let secret = config::auth().visitor_secret.as_deref()?;
+    let (id, presented) = token.trim().split_once('.')?;
+    let expected = signature(secret, id)?;
+
+    super::constant_time_eq(expected.as_bytes(), presented.as_bytes()).then(|| id.to_string())
+}
+
+// the token from the visitor header, falling back to the cookie
+pub fn presented_token(headers: &HeaderMap) -> Option<&str> {
+    if let Some(token) = headers.get(VISITOR_HEADER).and_then(|v| v.to_str().ok()) {
+        return Some(token);
+    }
+
+    headers
+        .get_all(axum::http::header::COOKIE)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|cookies| cookies.split(';'))
+        .find_map(|cookie| {
+            let (name, value) = cookie.trim().split_once('=')?;
+            (name == VISITOR_COOKIE).then_some(value)
+        })
+}
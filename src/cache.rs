@@ -0,0 +1,178 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use axum::body::Bytes;
+use axum::http::HeaderValue;
+use chrono::{DateTime, SecondsFormat, Utc};
+use metrics::counter;
+use moka::future::Cache;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use tracing::warn;
+
+use crate::config::CacheConfig;
+
+// listings kept on disk at most; new ones past this aren't recorded
+const MAX_LAST_GOOD: usize = 512;
+
+// a cached 200 response: its body and content type
+#[derive(Clone)]
+pub struct CachedResponse {
+    pub content_type: Option<HeaderValue>,
+    pub body: Bytes,
+}
+
+// In-memory TTL cache of listing responses keyed by path and query.
+// Anything that changes the catalog clears it wholesale; entries are cheap to rebuild.
+#[derive(Clone)]
+pub struct ResponseCache {
+    entries: Option<Cache<String, CachedResponse>>,
+}
+
+impl ResponseCache {
+    pub fn new(config: &CacheConfig) -> Self {
+        let entries = (config.ttl_secs > 0).then(|| {
+            Cache::builder()
+                .max_capacity(config.max_entries)
+                .time_to_live(Duration::from_secs(config.ttl_secs))
+                .build()
+        });
+
+        Self { entries }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.entries.is_some()
+    }
+
+    // approximate, moka counts lazily
+    pub fn entry_count(&self) -> u64 {
+        self.entries.as_ref().map_or(0, Cache::entry_count)
+    }
+
+    pub async fn get(&self, key: &str) -> Option<CachedResponse> {
+        let hit = self.entries.as_ref()?.get(key).await;
+        let outcome = if hit.is_some() { "hit" } else { "miss" };
+        counter!("response_cache_total", "outcome" => outcome).increment(1);
+        hit
+    }
+
+    pub async fn insert(&self, key: String, response: CachedResponse) {
+        if let Some(entries) = &self.entries {
+            entries.insert(key, response).await;
+        }
+    }
+
+    pub fn invalidate_all(&self) {
+        if let Some(entries) = &self.entries {
+            entries.invalidate_all();
+            counter!("response_cache_invalidations_total").increment(1);
+        }
+    }
+}
+
+// The last good body of each cached listing, kept under the data dir so a Pinata
+// outage after the cache has expired still gets an answer, marked as stale
+#[derive(Clone)]
+pub struct LastGood {
+    dir: Option<PathBuf>,
+    // digest of the body on disk by file name, so unchanged bodies aren't rewritten
+    written: Arc<Mutex<HashMap<String, [u8; 32]>>>,
+}
+
+// a recorded body and when it was recorded
+pub struct Snapshot {
+    pub body: Bytes,
+    pub saved_at: DateTime<Utc>,
+}
+
+impl LastGood {
+    // None turns serving stale responses off
+    pub fn new(dir: Option<PathBuf>) -> Self {
+        // what earlier runs recorded counts towards the cap, and is rewritten once
+        let written = dir
+            .iter()
+            .filter_map(|dir| std::fs::read_dir(dir).ok())
+            .flatten()
+            .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+            .filter(|name| name.ends_with(".json"))
+            .map(|name| (name, [0; 32]))
+            .collect();
+        Self {
+            dir,
+            written: Arc::new(Mutex::new(written)),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.dir.is_some()
+    }
+
+    fn file_name(key: &str) -> String {
+        let digest = Sha256::digest(key.as_bytes());
+        let hex: String = digest[..16].iter().map(|b| format!("{b:02x}")).collect();
+        format!("{hex}.json")
+    }
+
+    // remember `body` as the last good answer for `key`
+    pub async fn record(&self, key: &str, body: &Bytes) {
+        let Some(dir) = &self.dir else {
+            return;
+        };
+        let name = Self::file_name(key);
+        let digest: [u8; 32] = Sha256::digest(body).into();
+        {
+            let mut written = self.written.lock().unwrap();
+            if written.get(&name) == Some(&digest)
+                || !written.contains_key(&name) && written.len() >= MAX_LAST_GOOD
+            {
+                return;
+            }
+            written.insert(name.clone(), digest);
+        }
+
+        let path = dir.join(&name);
+        let tmp = path.with_extension("json.tmp");
+        let result = async {
+            tokio::fs::create_dir_all(dir).await?;
+            tokio::fs::write(&tmp, body).await?;
+            tokio::fs::rename(&tmp, &path).await
+        }
+        .await;
+        if let Err(e) = result {
+            warn!("Failed to record the last good {key}: {e}");
+            self.written.lock().unwrap().remove(&name);
+        }
+    }
+
+    // the last good answer for `key`, from this or an earlier run
+    pub async fn load(&self, key: &str) -> Option<Snapshot> {
+        let path = self.dir.as_ref()?.join(Self::file_name(key));
+        let body = tokio::fs::read(&path).await.ok()?;
+        let saved_at = tokio::fs::metadata(&path).await.ok()?.modified().ok()?;
+        Some(Snapshot {
+            body: Bytes::from(body),
+            saved_at: saved_at.into(),
+        })
+    }
+}
+
+impl Snapshot {
+    // the body with `stale: true` and `saved_at` added, None when it isn't a JSON object
+    pub fn marked_stale(&self) -> Option<Bytes> {
+        let Ok(Value::Object(mut body)) = serde_json::from_slice(&self.body) else {
+            return None;
+        };
+        body.insert("stale".to_string(), Value::Bool(true));
+        body.insert(
+            "saved_at".to_string(),
+            self.saved_at
+                .to_rfc3339_opts(SecondsFormat::Secs, true)
+                .into(),
+        );
+        counter!("stale_responses_total").increment(1);
+        serde_json::to_vec(&body).ok().map(Bytes::from)
+    }
+}
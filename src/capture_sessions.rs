@@ -0,0 +1,158 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::LazyLock;
+
+use chrono::{DateTime, Utc};
+use rand::RngCore;
+use tracing::warn;
+
+use crate::errors::ApiError;
+use crate::models::PinataFile;
+use crate::models::uploads::{CaptureDefaults, CaptureSession, CaptureSummary, UploadedFileInfo};
+use crate::store::JsonStore;
+
+// Tethered capture sessions by id, each pinning its frames into one group
+static SESSIONS: LazyLock<JsonStore<BTreeMap<String, CaptureSession>>> =
+    LazyLock::new(|| JsonStore::open("capture_sessions"));
+
+fn random_id() -> String {
+    let mut buf = [0u8; 12];
+    rand::rng().fill_bytes(&mut buf);
+    buf.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+pub fn create(
+    group_id: String,
+    account: Option<String>,
+    defaults: CaptureDefaults,
+) -> Result<CaptureSession, ApiError> {
+    let now = Utc::now();
+    let session = CaptureSession {
+        id: random_id(),
+        group_id,
+        account,
+        defaults,
+        created_at: now,
+        updated_at: now,
+        frames: 0,
+        photos: 0,
+        failed: 0,
+        bytes: 0,
+        last_file_id: None,
+        file_ids: Vec::new(),
+        summary: None,
+    };
+    SESSIONS.update(|sessions| sessions.insert(session.id.clone(), session.clone()))?;
+    Ok(session)
+}
+
+pub fn get(id: &str) -> Option<CaptureSession> {
+    SESSIONS.read(|sessions| sessions.get(id).cloned())
+}
+
+// Number the next frame, so frames sent at once still get distinct default names.
+// None when there's no such session, and a conflict once it's closed.
+pub fn next_frame(id: &str) -> Result<Option<(u64, CaptureSession)>, ApiError> {
+    SESSIONS
+        .update(|sessions| {
+            let session = sessions.get_mut(id)?;
+            if session.summary.is_some() {
+                return Some(Err(ApiError::Conflict(format!(
+                    "Capture session {id} is closed"
+                ))));
+            }
+            session.frames += 1;
+            session.updated_at = Utc::now();
+            Some(Ok((session.frames, session.clone())))
+        })?
+        .transpose()
+}
+
+// Sum up a session from its group's files as they are now. `cover` picks the highest
+// rated frame, the earliest of any tie; unrated frames are never picked.
+pub fn summarize(
+    session: &CaptureSession,
+    group_files: &[PinataFile],
+    closed_at: DateTime<Utc>,
+    cover: bool,
+) -> CaptureSummary {
+    let files: HashMap<&str, &PinataFile> = group_files
+        .iter()
+        .map(|file| (file.id.as_str(), file))
+        .collect();
+    // frames deleted since they were pinned drop out
+    let frames: Vec<&PinataFile> = session
+        .file_ids
+        .iter()
+        .filter_map(|id| files.get(id.as_str()).copied())
+        .collect();
+
+    let mut cameras = BTreeMap::new();
+    let mut lenses = BTreeMap::new();
+    for file in &frames {
+        let gear = &file.keyvalues.gear;
+        let unknown = || "unknown".to_string();
+        *cameras
+            .entry(gear.camera.clone().unwrap_or_else(unknown))
+            .or_insert(0) += 1;
+        *lenses
+            .entry(gear.lens.clone().unwrap_or_else(unknown))
+            .or_insert(0) += 1;
+    }
+
+    let mut best: Option<(u8, &str)> = None;
+    for file in &frames {
+        if let Some(rating) = file.keyvalues.rating
+            && best.is_none_or(|(top, _)| rating > top)
+        {
+            best = Some((rating, file.id.as_str()));
+        }
+    }
+
+    CaptureSummary {
+        started_at: session.created_at,
+        closed_at,
+        duration_secs: (closed_at - session.created_at).num_seconds(),
+        frames: session.frames,
+        photos: session.photos,
+        failed: session.failed,
+        bytes: session.bytes,
+        cameras,
+        lenses,
+        cover_file_id: best.filter(|_| cover).map(|(_, id)| id.to_string()),
+    }
+}
+
+// Close a session with its summary. Returns the session as it was when it's already
+// closed, so closing twice keeps the first summary.
+pub fn close(id: &str, summary: CaptureSummary) -> Result<Option<CaptureSession>, ApiError> {
+    SESSIONS.update(|sessions| {
+        let session = sessions.get_mut(id)?;
+        if session.summary.is_none() {
+            session.updated_at = summary.closed_at;
+            session.summary = Some(summary);
+        }
+        Some(session.clone())
+    })
+}
+
+// count a frame's outcome against its session
+pub fn record(id: &str, size_bytes: u64, result: Result<&UploadedFileInfo, &ApiError>) {
+    let updated = SESSIONS.update(|sessions| {
+        if let Some(session) = sessions.get_mut(id) {
+            match result {
+                Ok(info) => {
+                    session.photos += 1;
+                    session.bytes += size_bytes;
+                    session.last_file_id = Some(info.id.clone());
+                    session.file_ids.push(info.id.clone());
+                }
+                Err(_) => session.failed += 1,
+            }
+            session.updated_at = Utc::now();
+        }
+    });
+    // the frame itself is pinned either way, only the tally is off
+    if let Err(e) = updated {
+        warn!("Failed to record a frame of capture session {id}: {e}");
+    }
+}
@@ -0,0 +1,155 @@
+use std::io;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use axum::{
+    body::{Body, Bytes},
+    extract::{Request, State},
+    http::{HeaderMap, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use http_body::Frame;
+use rand::{Rng, SeedableRng, rngs::StdRng};
+use tracing::warn;
+
+// Fault injection for exercising retries and failure handling against a flaky
+// upstream. Wrap the mock Pinata backend in tests, or set CHAOS_* in a debug build
+// to wrap the app itself. Each request may override the settings with headers:
+//   x-chaos-latency-ms, x-chaos-error-rate, x-chaos-drop-rate, x-chaos-status
+#[derive(Debug, Clone, Copy)]
+pub struct ChaosConfig {
+    // delay before every request is handled
+    pub latency_ms: u64,
+    // share of requests answered with `error_status` instead of reaching the handler
+    pub error_rate: f64,
+    pub error_status: StatusCode,
+    // share of requests whose connection is cut before a body is sent
+    pub drop_rate: f64,
+    // the same seed and request order always inject the same faults
+    pub seed: u64,
+}
+
+impl Default for ChaosConfig {
+    fn default() -> Self {
+        Self {
+            latency_ms: 0,
+            error_rate: 0.0,
+            error_status: StatusCode::SERVICE_UNAVAILABLE,
+            drop_rate: 0.0,
+            seed: 0,
+        }
+    }
+}
+
+fn env_parsed<T: std::str::FromStr>(name: &str) -> Option<T> {
+    let value = std::env::var(name).ok()?;
+    let parsed = value.parse().ok();
+    if parsed.is_none() {
+        warn!("Ignoring invalid {name}={value}");
+    }
+    parsed
+}
+
+fn header_parsed<T: std::str::FromStr>(headers: &HeaderMap, name: &str) -> Option<T> {
+    headers.get(name)?.to_str().ok()?.parse().ok()
+}
+
+impl ChaosConfig {
+    // None unless at least one CHAOS_* fault is set
+    pub fn from_env() -> Option<Self> {
+        let config = Self {
+            latency_ms: env_parsed("CHAOS_LATENCY_MS").unwrap_or_default(),
+            error_rate: env_parsed("CHAOS_ERROR_RATE").unwrap_or_default(),
+            error_status: env_parsed::<u16>("CHAOS_ERROR_STATUS")
+                .and_then(|code| StatusCode::from_u16(code).ok())
+                .unwrap_or(StatusCode::SERVICE_UNAVAILABLE),
+            drop_rate: env_parsed("CHAOS_DROP_RATE").unwrap_or_default(),
+            seed: env_parsed("CHAOS_SEED").unwrap_or_default(),
+        };
+        config.injects_faults().then_some(config)
+    }
+
+    fn injects_faults(&self) -> bool {
+        self.latency_ms > 0 || self.error_rate > 0.0 || self.drop_rate > 0.0
+    }
+
+    fn with_overrides(mut self, headers: &HeaderMap) -> Self {
+        if let Some(latency_ms) = header_parsed(headers, "x-chaos-latency-ms") {
+            self.latency_ms = latency_ms;
+        }
+        if let Some(error_rate) = header_parsed(headers, "x-chaos-error-rate") {
+            self.error_rate = error_rate;
+        }
+        if let Some(drop_rate) = header_parsed(headers, "x-chaos-drop-rate") {
+            self.drop_rate = drop_rate;
+        }
+        if let Some(status) = header_parsed::<u16>(headers, "x-chaos-status")
+            .and_then(|code| StatusCode::from_u16(code).ok())
+        {
+            self.error_status = status;
+        }
+        self
+    }
+}
+
+#[derive(Clone)]
+pub struct Chaos {
+    config: ChaosConfig,
+    rng: Arc<Mutex<StdRng>>,
+}
+
+impl Chaos {
+    pub fn new(config: ChaosConfig) -> Self {
+        Self {
+            config,
+            rng: Arc::new(Mutex::new(StdRng::seed_from_u64(config.seed))),
+        }
+    }
+
+    // one draw per fault per request, so the sequence only depends on request order
+    fn roll(&self, rate: f64) -> bool {
+        let mut rng = self.rng.lock().unwrap_or_else(|e| e.into_inner());
+        rng.random_bool(rate.clamp(0.0, 1.0))
+    }
+}
+
+// a body that fails on its first poll, so the server aborts the connection mid-response
+struct DroppedBody;
+
+impl http_body::Body for DroppedBody {
+    type Data = Bytes;
+    type Error = io::Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        Poll::Ready(Some(Err(io::Error::new(
+            io::ErrorKind::ConnectionReset,
+            "connection dropped by chaos layer",
+        ))))
+    }
+}
+
+pub async fn inject(State(chaos): State<Chaos>, request: Request, next: Next) -> Response {
+    let config = chaos.config.with_overrides(request.headers());
+
+    if config.latency_ms > 0 {
+        tokio::time::sleep(Duration::from_millis(config.latency_ms)).await;
+    }
+    // always roll both, so a header override on one request doesn't shift the faults of the rest
+    let dropped = chaos.roll(config.drop_rate);
+    let errored = chaos.roll(config.error_rate);
+
+    if dropped {
+        return Response::new(Body::new(DroppedBody));
+    }
+    if errored {
+        return (config.error_status, "Injected fault").into_response();
+    }
+
+    next.run(request).await
+}
@@ -0,0 +1,1669 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt::Display;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, OnceLock};
+
+use chrono::{DateTime, Utc};
+use image::RgbaImage;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use crate::errors::ApiError;
+use crate::logging::LogFormat;
+use crate::routes::ENDPOINTS;
+use crate::routes::versions::VERSIONS;
+use crate::timezone::TimeZone;
+use crate::watermark::{self, Position};
+
+// How many items listing endpoints return when the client doesn't say, and the most they may ask for
+#[derive(Debug, Clone, Copy)]
+pub struct ListingConfig {
+    pub default_limit: usize,
+    pub max_limit: usize,
+    // images of a gallery or category listed again up front for the page to preload
+    pub preload_count: usize,
+}
+
+const DEFAULT_LIMIT: usize = 100;
+const MAX_LIMIT: usize = 1000;
+
+// Per-request upload timeout and retry bounds (clients may ask for values within them),
+// and where large uploads are spooled to
+#[derive(Debug, Clone)]
+pub struct UploadConfig {
+    pub default_timeout_secs: u64,
+    pub max_timeout_secs: u64,
+    pub default_retries: u32,
+    pub max_retries: u32,
+    // files larger than this are written to a temp file instead of held in memory
+    pub spool_threshold_bytes: u64,
+    pub spool_dir: Option<PathBuf>,
+    // largest single file accepted, checked while it is still being received
+    pub max_file_bytes: u64,
+    // largest upload request, all of its files together; replaces BODY_LIMIT_BYTES for uploads
+    pub max_request_bytes: u64,
+    // received files pinned at once; streamed files go one at a time as they arrive
+    pub parallel_files: usize,
+    // lifetime of the signed preview urls returned with each upload
+    pub preview_ttl_secs: u64,
+    pub preview_thumbnail_width: u32,
+    // take GPS and camera identifying EXIF tags out of files before they're pinned,
+    // unless an upload says otherwise
+    pub strip_exif: bool,
+    // pin a manifest of each upload's files once they're pinned
+    pub manifest: bool,
+    // upload pipeline stages left out, e.g. `watermark`
+    pub disabled_stages: Vec<String>,
+}
+
+// How many uploads run at once, how many more may wait, and what to tell the rest
+#[derive(Debug, Clone, Copy)]
+pub struct UploadQueueConfig {
+    pub concurrency: usize,
+    pub depth: usize,
+    pub retry_after_secs: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScanMode {
+    Off,
+    // size and entropy heuristics only
+    Sanity,
+    // sanity checks plus a clamd INSTREAM scan
+    Clamd,
+}
+
+impl FromStr for ScanMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "" | "off" => Ok(Self::Off),
+            "sanity" => Ok(Self::Sanity),
+            "clamd" => Ok(Self::Clamd),
+            other => Err(format!(
+                "unknown scan mode '{other}', expected off, sanity or clamd"
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScanAction {
+    Reject,
+    // upload anyway, recording the finding in the file's keyvalues
+    Flag,
+}
+
+impl FromStr for ScanAction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "" | "reject" => Ok(Self::Reject),
+            "flag" => Ok(Self::Flag),
+            other => Err(format!(
+                "unknown scan action '{other}', expected reject or flag"
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ScanConfig {
+    pub mode: ScanMode,
+    pub action: ScanAction,
+    // `host:port` or a unix socket path
+    pub clamd_address: String,
+    pub max_file_bytes: u64,
+    // bits per byte below which a large file is considered suspicious padding
+    pub min_entropy: f64,
+}
+
+// What the catalog is expected to look like, checked by the consistency report
+#[derive(Debug, Clone)]
+pub struct CatalogConfig {
+    // empty means any category is accepted
+    pub known_categories: Vec<String>,
+    // credited in the JSON-LD of photos and galleries, unless a file's `creator` and
+    // `license` keyvalues say otherwise
+    pub creator: Option<String>,
+    pub creator_url: Option<String>,
+    // a license's url, e.g. `https://creativecommons.org/licenses/by/4.0/`
+    pub license: Option<String>,
+    // check the catalog against the pinned integrity manifest at startup
+    pub integrity_check: bool,
+}
+
+// Where backend-owned state (category settings and the like) is kept
+#[derive(Debug, Clone)]
+pub struct StorageConfig {
+    pub data_dir: PathBuf,
+}
+
+// Credentials for the owner-only admin endpoints and every write
+#[derive(Debug, Clone)]
+pub struct AuthConfig {
+    // admin endpoints and writes are disabled entirely when this is unset
+    pub admin_token: Option<String>,
+    // signs anonymous visitor tokens; visitor favourites are disabled when unset
+    pub visitor_secret: Option<String>,
+    // the wallet that may sign in with Ethereum, as a lowercase `0x...` address
+    pub owner_address: Option<String>,
+    // signs the session tokens a sign-in returns; sign-in is disabled without it
+    // and an owner address
+    pub session_secret: Option<String>,
+    pub session_ttl_secs: u64,
+    // the domain a sign-in message has to be for, FRONTEND_URL's host when unset;
+    // sign-in is refused without either
+    pub siwe_domain: Option<String>,
+}
+
+// Stores are opened on first use and the admin and visitor checks run outside any
+// handler, so the storage and auth settings of the config the app is built with are
+// published here for them
+static SHARED: OnceLock<(StorageConfig, AuthConfig)> = OnceLock::new();
+
+fn shared() -> &'static (StorageConfig, AuthConfig) {
+    SHARED
+        .get()
+        .expect("Config::install runs before the storage or auth settings are read")
+}
+
+pub fn storage() -> &'static StorageConfig {
+    &shared().0
+}
+
+pub fn auth() -> &'static AuthConfig {
+    &shared().1
+}
+
+// Pinata credentials and hosts
+#[derive(Clone)]
+pub struct PinataConfig {
+    pub jwt: String,
+    pub api_url: String,
+    pub uploads_url: String,
+    // dedicated gateway domain, e.g. `example.mypinata.cloud`; needed for signed urls
+    pub gateway: Option<String>,
+    // access key for the dedicated gateway, when it restricts unsigned requests
+    pub gateway_key: Option<String>,
+    // e.g. `staging`; groups this deployment creates are named `staging::<name>`
+    // and it only lists those, while a deployment without one hides all namespaced groups
+    pub group_namespace: Option<String>,
+    // what the account above goes by in `?account=`
+    pub account: String,
+    // further accounts listings also cover, on the same hosts and namespace
+    pub accounts: Vec<PinataAccountConfig>,
+}
+
+// Another Pinata account, e.g. one for client work kept apart from a personal one
+#[derive(Clone)]
+pub struct PinataAccountConfig {
+    pub name: String,
+    pub jwt: String,
+    pub gateway: Option<String>,
+    pub gateway_key: Option<String>,
+    // groups created with a name starting with one of these go into this account
+    pub group_prefixes: Vec<String>,
+}
+
+// How the server itself listens
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    pub bind_address: SocketAddr,
+    pub cors_origins: Vec<String>,
+    // largest request body accepted, except for uploads which have their own limits
+    pub body_limit_bytes: usize,
+    // take the client address from `X-Forwarded-For`, only safe behind a proxy that sets it
+    pub trust_forwarded_for: bool,
+    pub log_format: LogFormat,
+    // what's logged, as an env-filter directive, e.g. `info` or `esemese_backend=debug`
+    pub log_level: String,
+    // how long a shutdown waits for requests and upload jobs still running
+    pub shutdown_drain_secs: u64,
+    // an HTTPS listener next to the plain one, when a certificate is configured
+    pub tls: Option<TlsConfig>,
+}
+
+// certificate chain and private key, both PEM
+#[derive(Debug, Clone)]
+pub struct TlsConfig {
+    pub bind_address: SocketAddr,
+    pub cert_path: PathBuf,
+    pub key_path: PathBuf,
+}
+
+// Everything read once at startup and carried in `AppState`. Values come from
+// the environment (and `.env`), falling back to an optional TOML file named by
+// CONFIG_FILE (default `config.toml` when present), then to the defaults below.
+#[derive(Clone)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub pinata: PinataConfig,
+    pub upload: UploadConfig,
+    pub database: Option<DatabaseConfig>,
+    pub webhooks: WebhookConfig,
+    pub cache: CacheConfig,
+    pub cache_purge: Option<CachePurgeConfig>,
+    pub frontend: FrontendConfig,
+    pub shares: ShareConfig,
+    pub proxy: ProxyConfig,
+    pub rate_limit: RateLimitConfig,
+    pub processing: ProcessingConfig,
+    pub watermark: Option<WatermarkConfig>,
+    pub listing: ListingConfig,
+    pub upload_queue: UploadQueueConfig,
+    pub scan: ScanConfig,
+    pub catalog: CatalogConfig,
+    pub storage: StorageConfig,
+    pub auth: AuthConfig,
+    pub i18n: I18nConfig,
+    pub api: ApiConfig,
+    pub telemetry: TelemetryConfig,
+}
+
+// Where spans are sent over OTLP, e.g. to Jaeger; nothing is sent without an endpoint
+#[derive(Clone)]
+pub struct TelemetryConfig {
+    // the collector's traces url, e.g. `http://localhost:4318/v1/traces`
+    pub otlp_endpoint: Option<String>,
+    pub service_name: String,
+    // sent with every export, e.g. a hosted collector's api key
+    pub otlp_headers: Vec<(String, String)>,
+}
+
+// When each API version is deprecated and goes away, announced on its responses
+#[derive(Debug, Clone, Default)]
+pub struct ApiConfig {
+    pub deprecations: BTreeMap<&'static str, Deprecation>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Deprecation {
+    pub deprecated_at: DateTime<Utc>,
+    pub sunset_at: Option<DateTime<Utc>>,
+    // where to move to, e.g. the newer version's docs
+    pub successor: Option<String>,
+}
+
+// Translated error messages by lowercase language tag (`fr`, `pt-br`), each keyed
+// by the English `error` of the response it replaces the message of
+#[derive(Debug, Clone, Default)]
+pub struct I18nConfig {
+    pub messages: BTreeMap<String, BTreeMap<String, String>>,
+    // where dates without an offset of their own are read in, e.g. capture times
+    // from cameras that don't record one
+    pub timezone: TimeZone,
+}
+
+// A mark composited onto the display variant and thumbnails; originals are pinned
+// as uploaded. Either a PNG overlay or a line of text.
+#[derive(Clone)]
+pub struct WatermarkConfig {
+    pub mark: Arc<RgbaImage>,
+    // text is drawn at a pixel per font pixel and scaled up without smoothing
+    pub pixelated: bool,
+    pub position: Position,
+    // 0 to 1
+    pub opacity: f32,
+    // width of the mark as a share of the variant's width, 0 to 1
+    pub scale: f32,
+    // recorded on each original next to its variants, so a changed mark is re-applied
+    pub fingerprint: String,
+}
+
+// Derived copies the backend generates from an original
+#[derive(Debug, Clone, Copy)]
+pub struct ProcessingConfig {
+    // longest edge of the WEBP display variant
+    pub display_width: u32,
+    // longest edges of the two thumbnails pinned with each upload
+    pub thumbnail_small: u32,
+    pub thumbnail_large: u32,
+    // color space display variants are written in
+    pub color_profile: ColorTarget,
+    // blurhash detail along each axis, 1 to 9
+    pub blurhash_x: u32,
+    pub blurhash_y: u32,
+    // uploads decoded and thumbnailed at the same time, each holding a full-size image
+    pub max_concurrent: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorTarget {
+    // keep the original's pixels and embed its ICC profile in the variant
+    Preserve,
+    // convert to sRGB, what browsers assume for untagged images
+    Srgb,
+    // convert to Display P3, keeping wide-gamut colors on screens that show them
+    DisplayP3,
+}
+
+impl FromStr for ColorTarget {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "preserve" => Ok(Self::Preserve),
+            "srgb" => Ok(Self::Srgb),
+            "display-p3" | "p3" => Ok(Self::DisplayP3),
+            other => Err(format!(
+                "unknown color profile '{other}', expected srgb, display-p3 or preserve"
+            )),
+        }
+    }
+}
+
+// Per-client caps on routes that stream files through the backend; 0 turns a cap off
+#[derive(Debug, Clone, Copy)]
+pub struct ProxyConfig {
+    pub max_streams_per_ip: usize,
+    pub bytes_per_sec_per_ip: u64,
+}
+
+// Requests a minute one client may make, counted per route and per API key, or per
+// address without one; 0 turns a budget off
+#[derive(Debug, Clone)]
+pub struct RateLimitConfig {
+    // for routes without a budget of their own
+    pub per_minute: u32,
+    // by route, e.g. `/groups`; the public lists that fan out into several Pinata
+    // calls get tighter ones
+    pub routes: BTreeMap<String, u32>,
+}
+
+// How share link downloads are served when a link doesn't allow full resolution
+#[derive(Debug, Clone, Copy)]
+pub struct ShareConfig {
+    // longest edge of the resized copy
+    pub preview_width: u32,
+    // lifetime of the signed gateway url the download is fetched through
+    pub fetch_ttl_secs: u64,
+}
+
+// Frontend pages to revalidate when the catalog changes; `{id}` is replaced
+// with the group or file id
+#[derive(Debug, Clone)]
+pub struct FrontendConfig {
+    // e.g. `https://photos.example.com`, needed for links and QR codes sent to clients
+    pub public_url: Option<String>,
+    pub group_route: String,
+    pub file_route: String,
+    // listing pages that show every change, e.g. the home page
+    pub index_routes: Vec<String>,
+    // `{token}` is replaced with the share token
+    pub share_route: String,
+}
+
+impl FrontendConfig {
+    // absolute links to a group's and a file's pages
+    pub fn group_url(&self, id: &str) -> Option<String> {
+        let base = self.public_url.as_deref()?;
+        Some(format!("{base}{}", self.group_route.replace("{id}", id)))
+    }
+
+    pub fn file_url(&self, id: &str) -> Option<String> {
+        let base = self.public_url.as_deref()?;
+        Some(format!("{base}{}", self.file_route.replace("{id}", id)))
+    }
+
+    // absolute link to a share's gallery page
+    pub fn share_url(&self, token: &str) -> Option<String> {
+        let base = self.public_url.as_deref()?;
+        Some(format!(
+            "{base}{}",
+            self.share_route.replace("{token}", token)
+        ))
+    }
+}
+
+// Listing response cache; a TTL of 0 turns it off
+#[derive(Debug, Clone)]
+pub struct CacheConfig {
+    pub ttl_secs: u64,
+    pub max_entries: u64,
+    // answer with the last good listing, marked stale, when Pinata can't be reached
+    pub serve_stale: bool,
+    // room on disk for generated thumbnails, e.g. resized share downloads; 0 turns it off
+    pub thumbnail_max_bytes: u64,
+}
+
+// Where to ask the gateway, or the CDN in front of it, to drop what it cached for a
+// file once its variants are replaced. Disabled without CACHE_PURGE_URL.
+#[derive(Clone)]
+pub struct CachePurgeConfig {
+    pub url: String,
+    // bearer token for the purge endpoint; the Pinata JWT when the endpoint is Pinata's
+    pub token: Option<String>,
+}
+
+// Shared secret for signed webhook deliveries; the receiver is disabled without one
+#[derive(Clone)]
+pub struct WebhookConfig {
+    pub secret: Option<String>,
+    // where outbound events such as a closed capture session are posted, if anywhere
+    pub notify_url: Option<String>,
+    // how far a delivery's timestamp may drift from now before it counts as a replay
+    pub tolerance_secs: u64,
+}
+
+// Local catalog mirror; listings go straight to Pinata when DATABASE_URL is unset
+#[derive(Debug, Clone)]
+pub struct DatabaseConfig {
+    // e.g. `sqlite://data/catalog.db`
+    pub url: String,
+    pub sync_interval_secs: u64,
+}
+
+// hosts are overridable so tests and local dev can point at a mock backend
+const DEFAULT_API_URL: &str = "https://api.pinata.cloud";
+const DEFAULT_UPLOADS_URL: &str = "https://uploads.pinata.cloud";
+const DEFAULT_CONFIG_FILE: &str = "config.toml";
+
+// the TOML file mirrors the env vars, grouped by section
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct FileConfig {
+    server: FileServer,
+    pinata: FilePinata,
+    upload: FileUpload,
+    database: FileDatabase,
+    webhooks: FileWebhooks,
+    cache: FileCache,
+    cache_purge: FileCachePurge,
+    frontend: FileFrontend,
+    shares: FileShares,
+    proxy: FileProxy,
+    rate_limit: FileRateLimit,
+    processing: FileProcessing,
+    watermark: FileWatermark,
+    listing: FileListing,
+    upload_queue: FileUploadQueue,
+    scan: FileScan,
+    catalog: FileCatalog,
+    storage: FileStorage,
+    auth: FileAuth,
+    i18n: FileI18n,
+    api: FileApi,
+    telemetry: FileTelemetry,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct FileTelemetry {
+    // the collector's base url, `/v1/traces` is added
+    otlp_endpoint: Option<String>,
+    service_name: Option<String>,
+    otlp_headers: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct FileApi {
+    // by version, e.g. `[api.deprecations.v1]`
+    deprecations: BTreeMap<String, FileDeprecation>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct FileDeprecation {
+    deprecated_at: Option<String>,
+    sunset_at: Option<String>,
+    successor: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct FileI18n {
+    // a TOML file of `[<language>]` tables, for translations kept apart from the config
+    file: Option<PathBuf>,
+    messages: BTreeMap<String, BTreeMap<String, String>>,
+    timezone: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct FileListing {
+    default_limit: Option<usize>,
+    max_limit: Option<usize>,
+    preload_count: Option<usize>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct FileUploadQueue {
+    concurrency: Option<usize>,
+    depth: Option<usize>,
+    retry_after_secs: Option<u64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct FileScan {
+    mode: Option<String>,
+    action: Option<String>,
+    clamd_address: Option<String>,
+    max_file_bytes: Option<u64>,
+    min_entropy: Option<f64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct FileCatalog {
+    known_categories: Option<Vec<String>>,
+    creator: Option<String>,
+    creator_url: Option<String>,
+    license: Option<String>,
+    integrity_check: Option<bool>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct FileStorage {
+    data_dir: Option<PathBuf>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct FileAuth {
+    admin_token: Option<String>,
+    visitor_secret: Option<String>,
+    owner_address: Option<String>,
+    session_secret: Option<String>,
+    session_ttl_secs: Option<u64>,
+    siwe_domain: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct FileProcessing {
+    display_width: Option<u32>,
+    thumbnail_small: Option<u32>,
+    thumbnail_large: Option<u32>,
+    color_profile: Option<String>,
+    blurhash_x: Option<u32>,
+    blurhash_y: Option<u32>,
+    max_concurrent: Option<usize>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct FileWatermark {
+    image: Option<PathBuf>,
+    text: Option<String>,
+    position: Option<String>,
+    opacity: Option<f32>,
+    scale: Option<f32>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct FileProxy {
+    max_streams_per_ip: Option<usize>,
+    bytes_per_sec_per_ip: Option<u64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct FileRateLimit {
+    per_minute: Option<u32>,
+    // `[rate_limit.routes]` with `"/groups" = 30`, merged over the defaults
+    routes: BTreeMap<String, u32>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct FileShares {
+    preview_width: Option<u32>,
+    fetch_ttl_secs: Option<u64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct FileFrontend {
+    public_url: Option<String>,
+    group_route: Option<String>,
+    file_route: Option<String>,
+    index_routes: Option<Vec<String>>,
+    share_route: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct FileCache {
+    ttl_secs: Option<u64>,
+    max_entries: Option<u64>,
+    serve_stale: Option<bool>,
+    thumbnail_max_bytes: Option<u64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct FileCachePurge {
+    url: Option<String>,
+    token: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct FileWebhooks {
+    secret: Option<String>,
+    notify_url: Option<String>,
+    tolerance_secs: Option<u64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct FileDatabase {
+    url: Option<String>,
+    sync_interval_secs: Option<u64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct FileServer {
+    bind_address: Option<String>,
+    cors_origins: Option<Vec<String>>,
+    body_limit_bytes: Option<usize>,
+    trust_forwarded_for: Option<bool>,
+    // `pretty` or `json`
+    log_format: Option<String>,
+    log_level: Option<String>,
+    shutdown_drain_secs: Option<u64>,
+    tls_bind_address: Option<String>,
+    tls_cert_path: Option<PathBuf>,
+    tls_key_path: Option<PathBuf>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct FilePinata {
+    jwt: Option<String>,
+    api_url: Option<String>,
+    uploads_url: Option<String>,
+    gateway: Option<String>,
+    gateway_key: Option<String>,
+    group_namespace: Option<String>,
+    account: Option<String>,
+    accounts: BTreeMap<String, FilePinataAccount>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct FilePinataAccount {
+    jwt: Option<String>,
+    gateway: Option<String>,
+    gateway_key: Option<String>,
+    group_prefixes: Option<Vec<String>>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct FileUpload {
+    timeout_secs: Option<u64>,
+    max_timeout_secs: Option<u64>,
+    retries: Option<u32>,
+    max_retries: Option<u32>,
+    spool_threshold_bytes: Option<u64>,
+    spool_dir: Option<PathBuf>,
+    max_file_bytes: Option<u64>,
+    max_request_bytes: Option<u64>,
+    parallel_files: Option<usize>,
+    preview_ttl_secs: Option<u64>,
+    preview_thumbnail_width: Option<u32>,
+    strip_exif: Option<bool>,
+    manifest: Option<bool>,
+    disabled_stages: Option<Vec<String>>,
+}
+
+fn config_error(message: String) -> ApiError {
+    ApiError::Api(format!("Invalid configuration: {message}"))
+}
+
+// the env var if set, else the file value, else the default; a malformed env var is an error
+fn setting<T>(name: &str, file: Option<T>, default: T) -> Result<T, ApiError>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    match env::var(name) {
+        Ok(raw) => raw
+            .trim()
+            .parse()
+            .map_err(|e| config_error(format!("{name}={raw}: {e}"))),
+        Err(_) => Ok(file.unwrap_or(default)),
+    }
+}
+
+fn optional_setting(name: &str, file: Option<String>) -> Option<String> {
+    env::var(name)
+        .ok()
+        .or(file)
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+}
+
+// a base URL, checked up front and without a trailing slash
+fn base_url(name: &str, raw: String) -> Result<String, ApiError> {
+    url::Url::parse(&raw).map_err(|e| config_error(format!("{name}={raw}: {e}")))?;
+    Ok(raw.trim_end_matches('/').to_string())
+}
+
+// a URL given out as it is, checked up front
+fn absolute_url(name: &str, raw: String) -> Result<String, ApiError> {
+    url::Url::parse(&raw).map_err(|e| config_error(format!("{name}={raw}: {e}")))?;
+    Ok(raw)
+}
+
+impl Config {
+    pub fn load() -> Result<Self, ApiError> {
+        dotenv::dotenv().ok();
+        let file = read_config_file()?;
+
+        let pinata = PinataConfig::load(file.pinata)?;
+        let cache_purge = CachePurgeConfig::load(file.cache_purge, &pinata)?;
+        Ok(Self {
+            server: ServerConfig::load(file.server)?,
+            pinata,
+            upload: UploadConfig::load(file.upload)?,
+            database: DatabaseConfig::load(file.database)?,
+            webhooks: WebhookConfig {
+                secret: optional_setting("WEBHOOK_SECRET", file.webhooks.secret),
+                notify_url: optional_setting("WEBHOOK_NOTIFY_URL", file.webhooks.notify_url)
+                    .map(|url| {
+                        url::Url::parse(&url)
+                            .map_err(|e| config_error(format!("WEBHOOK_NOTIFY_URL={url}: {e}")))?;
+                        Ok::<_, ApiError>(url)
+                    })
+                    .transpose()?,
+                tolerance_secs: setting(
+                    "WEBHOOK_TOLERANCE_SECS",
+                    file.webhooks.tolerance_secs,
+                    300,
+                )?,
+            },
+            cache: CacheConfig {
+                ttl_secs: setting("CACHE_TTL_SECS", file.cache.ttl_secs, 60)?,
+                max_entries: setting("CACHE_MAX_ENTRIES", file.cache.max_entries, 1000)?,
+                serve_stale: setting("CACHE_SERVE_STALE", file.cache.serve_stale, true)?,
+                thumbnail_max_bytes: setting(
+                    "THUMBNAIL_CACHE_MAX_BYTES",
+                    file.cache.thumbnail_max_bytes,
+                    256 * 1024 * 1024,
+                )?,
+            },
+            cache_purge,
+            frontend: FrontendConfig::load(file.frontend)?,
+            shares: ShareConfig {
+                preview_width: setting("SHARE_PREVIEW_WIDTH", file.shares.preview_width, 2048)?
+                    .max(1),
+                fetch_ttl_secs: setting("SHARE_FETCH_TTL_SECS", file.shares.fetch_ttl_secs, 60)?
+                    .max(1),
+            },
+            proxy: ProxyConfig {
+                max_streams_per_ip: setting(
+                    "PROXY_MAX_STREAMS_PER_IP",
+                    file.proxy.max_streams_per_ip,
+                    4,
+                )?,
+                bytes_per_sec_per_ip: setting(
+                    "PROXY_BYTES_PER_SEC_PER_IP",
+                    file.proxy.bytes_per_sec_per_ip,
+                    4 * 1024 * 1024,
+                )?,
+            },
+            rate_limit: RateLimitConfig::load(file.rate_limit)?,
+            watermark: WatermarkConfig::load(file.watermark)?,
+            processing: ProcessingConfig {
+                display_width: setting(
+                    "PROCESSING_DISPLAY_WIDTH",
+                    file.processing.display_width,
+                    2048,
+                )?
+                .max(1),
+                thumbnail_small: setting(
+                    "PROCESSING_THUMBNAIL_SMALL",
+                    file.processing.thumbnail_small,
+                    400,
+                )?
+                .max(1),
+                thumbnail_large: setting(
+                    "PROCESSING_THUMBNAIL_LARGE",
+                    file.processing.thumbnail_large,
+                    1200,
+                )?
+                .max(1),
+                color_profile: match optional_setting(
+                    "PROCESSING_COLOR_PROFILE",
+                    file.processing.color_profile,
+                ) {
+                    Some(raw) => raw.parse().map_err(|e| {
+                        config_error(format!("PROCESSING_COLOR_PROFILE={raw}: {e}"))
+                    })?,
+                    None => ColorTarget::Srgb,
+                },
+                blurhash_x: setting("PROCESSING_BLURHASH_X", file.processing.blurhash_x, 4)?
+                    .clamp(1, 9),
+                blurhash_y: setting("PROCESSING_BLURHASH_Y", file.processing.blurhash_y, 3)?
+                    .clamp(1, 9),
+                max_concurrent: setting(
+                    "PROCESSING_MAX_CONCURRENT",
+                    file.processing.max_concurrent,
+                    2,
+                )?
+                .max(1),
+            },
+            listing: ListingConfig::load(file.listing)?,
+            upload_queue: UploadQueueConfig {
+                concurrency: setting("UPLOAD_CONCURRENCY", file.upload_queue.concurrency, 2)?
+                    .max(1),
+                depth: setting("UPLOAD_QUEUE_DEPTH", file.upload_queue.depth, 8)?,
+                retry_after_secs: setting(
+                    "UPLOAD_RETRY_AFTER_SECS",
+                    file.upload_queue.retry_after_secs,
+                    5,
+                )?
+                .max(1),
+            },
+            scan: ScanConfig::load(file.scan)?,
+            catalog: CatalogConfig {
+                known_categories: match env::var("KNOWN_CATEGORIES") {
+                    Ok(raw) => raw.split(',').map(str::to_string).collect(),
+                    Err(_) => file.catalog.known_categories.unwrap_or_default(),
+                }
+                .into_iter()
+                .map(|c| c.trim().to_lowercase())
+                .filter(|c| !c.is_empty())
+                .collect(),
+                creator: optional_setting("CATALOG_CREATOR", file.catalog.creator),
+                creator_url: optional_setting("CATALOG_CREATOR_URL", file.catalog.creator_url)
+                    .map(|url| absolute_url("CATALOG_CREATOR_URL", url))
+                    .transpose()?,
+                license: optional_setting("CATALOG_LICENSE", file.catalog.license)
+                    .map(|url| absolute_url("CATALOG_LICENSE", url))
+                    .transpose()?,
+                integrity_check: setting(
+                    "CATALOG_INTEGRITY_CHECK",
+                    file.catalog.integrity_check,
+                    true,
+                )?,
+            },
+            storage: StorageConfig {
+                data_dir: setting("DATA_DIR", file.storage.data_dir, PathBuf::from("data"))?,
+            },
+            auth: AuthConfig::load(file.auth)?,
+            i18n: I18nConfig::load(file.i18n)?,
+            api: ApiConfig::load(file.api)?,
+            telemetry: TelemetryConfig::load(file.telemetry)?,
+        })
+    }
+
+    // publish the settings read outside of handlers; the first config installed
+    // wins, so apps built side by side in one process share them
+    pub fn install(&self) {
+        let _ = SHARED.set((self.storage.clone(), self.auth.clone()));
+    }
+}
+
+impl AuthConfig {
+    fn load(file: FileAuth) -> Result<Self, ApiError> {
+        let owner_address = optional_setting("OWNER_ADDRESS", file.owner_address)
+            .map(|address| {
+                let hex = address.strip_prefix("0x").unwrap_or_default();
+                if hex.len() != 40 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+                    return Err(config_error(format!(
+                        "OWNER_ADDRESS ({address}) must be a 0x-prefixed Ethereum address"
+                    )));
+                }
+                Ok(address.to_ascii_lowercase())
+            })
+            .transpose()?;
+
+        let session_ttl_secs = setting("SESSION_TTL_SECS", file.session_ttl_secs, 86_400)?;
+        if session_ttl_secs == 0 {
+            return Err(config_error("SESSION_TTL_SECS must be above 0".to_string()));
+        }
+
+        Ok(Self {
+            admin_token: optional_setting("ADMIN_TOKEN", file.admin_token),
+            visitor_secret: optional_setting("VISITOR_SECRET", file.visitor_secret),
+            owner_address,
+            session_secret: optional_setting("SESSION_SECRET", file.session_secret),
+            session_ttl_secs,
+            siwe_domain: optional_setting("SIWE_DOMAIN", file.siwe_domain),
+        })
+    }
+}
+
+impl WatermarkConfig {
+    fn load(file: FileWatermark) -> Result<Option<Self>, ApiError> {
+        let image = optional_setting(
+            "WATERMARK_IMAGE",
+            file.image.map(|p| p.display().to_string()),
+        );
+        let text = optional_setting("WATERMARK_TEXT", file.text);
+
+        let mut fingerprint = Sha256::new();
+        let (mark, pixelated) = match (image, text) {
+            (Some(_), Some(_)) => {
+                return Err(config_error(
+                    "set either WATERMARK_IMAGE or WATERMARK_TEXT, not both".to_string(),
+                ));
+            }
+            (None, None) => return Ok(None),
+            (Some(path), None) => {
+                let raw = std::fs::read(&path)
+                    .map_err(|e| config_error(format!("WATERMARK_IMAGE={path}: {e}")))?;
+                fingerprint.update(&raw);
+                let mark = image::load_from_memory(&raw)
+                    .map_err(|e| config_error(format!("WATERMARK_IMAGE={path}: {e}")))?;
+                (mark.into_rgba8(), false)
+            }
+            (None, Some(text)) => {
+                fingerprint.update(text.as_bytes());
+                (watermark::render_text(&text), true)
+            }
+        };
+
+        let position = setting(
+            "WATERMARK_POSITION",
+            file.position,
+            "bottom-right".to_string(),
+        )?;
+        let position: Position = position
+            .parse()
+            .map_err(|e| config_error(format!("WATERMARK_POSITION: {e}")))?;
+        let opacity = setting("WATERMARK_OPACITY", file.opacity, 0.5)?;
+        if !(0.0..=1.0).contains(&opacity) {
+            return Err(config_error(format!(
+                "WATERMARK_OPACITY ({opacity}) must be between 0 and 1"
+            )));
+        }
+        let scale = setting("WATERMARK_SCALE", file.scale, 0.2)?;
+        if !(scale > 0.0 && scale <= 1.0) {
+            return Err(config_error(format!(
+                "WATERMARK_SCALE ({scale}) must be above 0 and at most 1"
+            )));
+        }
+
+        fingerprint.update(format!("{position:?}:{opacity}:{scale}").as_bytes());
+        let fingerprint = format!("{:x}", fingerprint.finalize());
+        Ok(Some(Self {
+            mark: Arc::new(mark),
+            pixelated,
+            position,
+            opacity,
+            scale,
+            fingerprint: fingerprint[..16].to_string(),
+        }))
+    }
+}
+
+impl I18nConfig {
+    fn load(file: FileI18n) -> Result<Self, ApiError> {
+        let path = optional_setting("I18N_FILE", file.file.map(|p| p.display().to_string()));
+        let mut messages: BTreeMap<String, BTreeMap<String, String>> = match path {
+            Some(path) => {
+                let raw = std::fs::read_to_string(&path)
+                    .map_err(|e| config_error(format!("I18N_FILE={path}: {e}")))?;
+                toml::from_str(&raw).map_err(|e| config_error(format!("I18N_FILE={path}: {e}")))?
+            }
+            None => BTreeMap::new(),
+        };
+        // translations in the config file itself win over the separate file
+        for (language, translations) in file.messages {
+            messages.entry(language).or_default().extend(translations);
+        }
+
+        let messages = messages
+            .into_iter()
+            .map(|(language, translations)| (language.to_lowercase(), translations))
+            .collect();
+
+        let timezone = match optional_setting("DISPLAY_TIMEZONE", file.timezone) {
+            Some(name) => TimeZone::parse(&name)
+                .map_err(|e| config_error(format!("DISPLAY_TIMEZONE={name}: {e}")))?,
+            None => TimeZone::utc(),
+        };
+        Ok(Self { messages, timezone })
+    }
+}
+
+impl TelemetryConfig {
+    // the standard OTEL_EXPORTER_OTLP_* variables: OTEL_EXPORTER_OTLP_TRACES_ENDPOINT as
+    // the whole url, else OTEL_EXPORTER_OTLP_ENDPOINT with `/v1/traces` added, and
+    // OTEL_EXPORTER_OTLP_HEADERS as `name=value` pairs
+    fn load(file: FileTelemetry) -> Result<Self, ApiError> {
+        let otlp_endpoint = match optional_setting("OTEL_EXPORTER_OTLP_TRACES_ENDPOINT", None) {
+            Some(url) => Some(base_url("OTEL_EXPORTER_OTLP_TRACES_ENDPOINT", url)?),
+            None => optional_setting("OTEL_EXPORTER_OTLP_ENDPOINT", file.otlp_endpoint)
+                .map(|url| base_url("OTEL_EXPORTER_OTLP_ENDPOINT", url))
+                .transpose()?
+                .map(|url| format!("{url}/v1/traces")),
+        };
+
+        let otlp_headers: Vec<(String, String)> = match env::var("OTEL_EXPORTER_OTLP_HEADERS") {
+            Ok(raw) => raw
+                .split(',')
+                .map(str::trim)
+                .filter(|pair| !pair.is_empty())
+                .map(|pair| {
+                    pair.split_once('=')
+                        .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+                        .ok_or_else(|| {
+                            config_error(
+                                "OTEL_EXPORTER_OTLP_HEADERS: expected name=value pairs".to_string(),
+                            )
+                        })
+                })
+                .collect::<Result<_, _>>()?,
+            Err(_) => file.otlp_headers.into_iter().collect(),
+        };
+        if let Some((name, _)) = otlp_headers.iter().find(|(name, value)| {
+            http::HeaderName::try_from(name.as_str()).is_err()
+                || http::HeaderValue::try_from(value.as_str()).is_err()
+        }) {
+            return Err(config_error(format!(
+                "OTLP header {name}: not a valid header"
+            )));
+        }
+
+        Ok(Self {
+            otlp_endpoint,
+            service_name: setting(
+                "OTEL_SERVICE_NAME",
+                file.service_name,
+                env!("CARGO_PKG_NAME").to_string(),
+            )?,
+            otlp_headers,
+        })
+    }
+}
+
+impl ApiConfig {
+    // env vars are per version, e.g. API_V1_DEPRECATED_AT and API_V1_SUNSET_AT
+    fn load(mut file: FileApi) -> Result<Self, ApiError> {
+        if let Some(version) = file
+            .deprecations
+            .keys()
+            .find(|v| !VERSIONS.contains(&v.as_str()))
+        {
+            return Err(config_error(format!(
+                "api.deprecations.{version}: no such API version"
+            )));
+        }
+
+        let mut deprecations = BTreeMap::new();
+        for &version in VERSIONS {
+            let file = file.deprecations.remove(version).unwrap_or_default();
+            let prefix = format!("API_{}", version.to_uppercase());
+            let date = |suffix: &str, file: Option<String>| {
+                let name = format!("{prefix}_{suffix}");
+                optional_setting(&name, file)
+                    .map(|raw| {
+                        DateTime::parse_from_rfc3339(&raw)
+                            .map(|date| date.with_timezone(&Utc))
+                            .map_err(|e| config_error(format!("{name}={raw}: {e}")))
+                    })
+                    .transpose()
+            };
+
+            let sunset_at = date("SUNSET_AT", file.sunset_at)?;
+            let Some(deprecated_at) = date("DEPRECATED_AT", file.deprecated_at)? else {
+                if sunset_at.is_some() {
+                    return Err(config_error(format!(
+                        "{prefix}_SUNSET_AT needs {prefix}_DEPRECATED_AT"
+                    )));
+                }
+                continue;
+            };
+            if sunset_at.is_some_and(|sunset| sunset < deprecated_at) {
+                return Err(config_error(format!(
+                    "{prefix}_SUNSET_AT is before {prefix}_DEPRECATED_AT"
+                )));
+            }
+            let successor = optional_setting(&format!("{prefix}_SUCCESSOR"), file.successor)
+                .map(|raw| base_url(&format!("{prefix}_SUCCESSOR"), raw))
+                .transpose()?;
+
+            deprecations.insert(
+                version,
+                Deprecation {
+                    deprecated_at,
+                    sunset_at,
+                    successor,
+                },
+            );
+        }
+        Ok(Self { deprecations })
+    }
+}
+
+// budgets for the public lists that fan out into several Pinata calls a request
+const LIST_BUDGETS: &[(&str, u32)] = &[
+    ("/groups", 60),
+    ("/groups-with-thumbnails", 30),
+    ("/group-images", 60),
+    ("/files-category", 60),
+    ("/favourites", 60),
+    ("/categories", 60),
+    ("/catalog/full", 10),
+];
+
+impl RateLimitConfig {
+    // RATE_LIMIT_ROUTES takes `route=budget` pairs, e.g. `/groups=30,/catalog/full=5`
+    fn load(file: FileRateLimit) -> Result<Self, ApiError> {
+        let mut routes: BTreeMap<String, u32> = LIST_BUDGETS
+            .iter()
+            .map(|(route, budget)| (route.to_string(), *budget))
+            .collect();
+        match env::var("RATE_LIMIT_ROUTES") {
+            Ok(raw) => {
+                for pair in raw.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+                    let budget = pair.split_once('=').and_then(|(route, budget)| {
+                        Some((route.trim().to_string(), budget.trim().parse().ok()?))
+                    });
+                    let Some((route, budget)) = budget else {
+                        return Err(config_error(format!(
+                            "RATE_LIMIT_ROUTES={raw}: expected route=budget pairs"
+                        )));
+                    };
+                    routes.insert(route, budget);
+                }
+            }
+            Err(_) => routes.extend(file.routes),
+        }
+
+        if let Some(route) = routes.keys().find(|route| {
+            !ENDPOINTS.iter().any(|endpoint| {
+                endpoint
+                    .split_once(' ')
+                    .is_some_and(|(_, path)| path == *route)
+            })
+        }) {
+            return Err(config_error(format!("rate_limit.routes: no route {route}")));
+        }
+
+        Ok(Self {
+            per_minute: setting("RATE_LIMIT_PER_MINUTE", file.per_minute, 300)?,
+            routes,
+        })
+    }
+
+    // the budget a matched route gets, or the default one
+    pub fn budget(&self, route: Option<&str>) -> u32 {
+        route
+            .and_then(|route| self.routes.get(route))
+            .copied()
+            .unwrap_or(self.per_minute)
+    }
+}
+
+impl ListingConfig {
+    fn load(file: FileListing) -> Result<Self, ApiError> {
+        let max_limit = setting("LISTING_MAX_LIMIT", file.max_limit, MAX_LIMIT)?;
+        let default_limit = setting("LISTING_DEFAULT_LIMIT", file.default_limit, DEFAULT_LIMIT)?;
+        if max_limit == 0 || default_limit == 0 {
+            return Err(config_error("listing limits must be positive".to_string()));
+        }
+        if default_limit > max_limit {
+            return Err(config_error(format!(
+                "LISTING_DEFAULT_LIMIT ({default_limit}) exceeds LISTING_MAX_LIMIT ({max_limit})"
+            )));
+        }
+
+        Ok(Self {
+            default_limit,
+            max_limit,
+            preload_count: setting("LISTING_PRELOAD_COUNT", file.preload_count, 6)?,
+        })
+    }
+}
+
+impl ScanConfig {
+    fn load(file: FileScan) -> Result<Self, ApiError> {
+        let mode = setting(
+            "SCAN_MODE",
+            file.mode
+                .map(|raw| {
+                    raw.parse()
+                        .map_err(|e| config_error(format!("scan mode={raw}: {e}")))
+                })
+                .transpose()?,
+            ScanMode::Off,
+        )?;
+        let action = setting(
+            "SCAN_ACTION",
+            file.action
+                .map(|raw| {
+                    raw.parse()
+                        .map_err(|e| config_error(format!("scan action={raw}: {e}")))
+                })
+                .transpose()?,
+            ScanAction::Reject,
+        )?;
+        let min_entropy = setting("SCAN_MIN_ENTROPY", file.min_entropy, 1.0)?;
+        if !(0.0..=8.0).contains(&min_entropy) {
+            return Err(config_error(format!(
+                "SCAN_MIN_ENTROPY={min_entropy}: entropy is between 0 and 8 bits per byte"
+            )));
+        }
+
+        Ok(Self {
+            mode,
+            action,
+            clamd_address: setting(
+                "CLAMD_ADDRESS",
+                file.clamd_address,
+                "127.0.0.1:3310".to_string(),
+            )?,
+            max_file_bytes: setting(
+                "SCAN_MAX_FILE_BYTES",
+                file.max_file_bytes,
+                200 * 1024 * 1024,
+            )?,
+            min_entropy,
+        })
+    }
+}
+
+impl FrontendConfig {
+    fn load(file: FileFrontend) -> Result<Self, ApiError> {
+        let group_route = setting(
+            "FRONTEND_GROUP_ROUTE",
+            file.group_route,
+            "/collections/{id}".to_string(),
+        )?;
+        let file_route = setting(
+            "FRONTEND_FILE_ROUTE",
+            file.file_route,
+            "/photos/{id}".to_string(),
+        )?;
+        let index_routes = match env::var("FRONTEND_INDEX_ROUTES") {
+            Ok(raw) => raw
+                .split(',')
+                .map(|r| r.trim().to_string())
+                .filter(|r| !r.is_empty())
+                .collect(),
+            Err(_) => file.index_routes.unwrap_or_else(|| vec!["/".to_string()]),
+        };
+
+        let share_route = setting(
+            "FRONTEND_SHARE_ROUTE",
+            file.share_route,
+            "/share/{token}".to_string(),
+        )?;
+        let public_url = optional_setting("FRONTEND_URL", file.public_url)
+            .map(|url| base_url("FRONTEND_URL", url))
+            .transpose()?;
+
+        for route in [&group_route, &file_route, &share_route]
+            .into_iter()
+            .chain(&index_routes)
+        {
+            if !route.starts_with('/') {
+                return Err(config_error(format!(
+                    "Frontend route {route} must start with /"
+                )));
+            }
+        }
+
+        Ok(Self {
+            public_url,
+            group_route,
+            file_route,
+            index_routes,
+            share_route,
+        })
+    }
+}
+
+fn read_config_file() -> Result<FileConfig, ApiError> {
+    let (path, required) = match env::var("CONFIG_FILE") {
+        Ok(path) => (PathBuf::from(path), true),
+        Err(_) => (PathBuf::from(DEFAULT_CONFIG_FILE), false),
+    };
+
+    if !required && !Path::new(&path).exists() {
+        return Ok(FileConfig::default());
+    }
+
+    let raw = std::fs::read_to_string(&path)
+        .map_err(|e| config_error(format!("can't read {}: {e}", path.display())))?;
+    toml::from_str(&raw).map_err(|e| config_error(format!("{}: {e}", path.display())))
+}
+
+impl ServerConfig {
+    fn load(file: FileServer) -> Result<Self, ApiError> {
+        let bind_address = setting(
+            "BIND_ADDRESS",
+            file.bind_address
+                .map(|raw| {
+                    raw.parse()
+                        .map_err(|e| config_error(format!("bind_address={raw}: {e}")))
+                })
+                .transpose()?,
+            SocketAddr::from(([0, 0, 0, 0], 3000)),
+        )?;
+
+        let cors_origins = match env::var("CORS_ORIGINS") {
+            Ok(raw) => raw
+                .split(',')
+                .map(|o| o.trim().to_string())
+                .filter(|o| !o.is_empty())
+                .collect(),
+            Err(_) => file
+                .cors_origins
+                .unwrap_or_else(|| vec!["http://localhost:5173".to_string()]),
+        };
+        for origin in &cors_origins {
+            url::Url::parse(origin)
+                .map_err(|e| config_error(format!("CORS origin {origin}: {e}")))?;
+        }
+
+        let body_limit_bytes =
+            setting("BODY_LIMIT_BYTES", file.body_limit_bytes, 256 * 1024 * 1024)?;
+        if body_limit_bytes == 0 {
+            return Err(config_error(
+                "BODY_LIMIT_BYTES must be positive".to_string(),
+            ));
+        }
+
+        let log_level = setting("LOG_LEVEL", file.log_level, "info".to_string())?;
+        tracing_subscriber::EnvFilter::try_new(&log_level)
+            .map_err(|e| config_error(format!("LOG_LEVEL={log_level}: {e}")))?;
+
+        Ok(Self {
+            bind_address,
+            cors_origins,
+            body_limit_bytes,
+            trust_forwarded_for: setting("TRUST_FORWARDED_FOR", file.trust_forwarded_for, false)?,
+            log_format: setting(
+                "LOG_FORMAT",
+                file.log_format
+                    .map(|raw| {
+                        raw.parse()
+                            .map_err(|e| config_error(format!("log_format={raw}: {e}")))
+                    })
+                    .transpose()?,
+                LogFormat::Pretty,
+            )?,
+            log_level,
+            shutdown_drain_secs: setting("SHUTDOWN_DRAIN_SECS", file.shutdown_drain_secs, 30)?,
+            tls: TlsConfig::load(file.tls_bind_address, file.tls_cert_path, file.tls_key_path)?,
+        })
+    }
+}
+
+impl TlsConfig {
+    fn load(
+        bind_address: Option<String>,
+        cert_path: Option<PathBuf>,
+        key_path: Option<PathBuf>,
+    ) -> Result<Option<Self>, ApiError> {
+        let cert_path =
+            optional_setting("TLS_CERT_PATH", cert_path.map(|p| p.display().to_string()));
+        let key_path = optional_setting("TLS_KEY_PATH", key_path.map(|p| p.display().to_string()));
+        let (cert_path, key_path) = match (cert_path, key_path) {
+            (Some(cert), Some(key)) => (PathBuf::from(cert), PathBuf::from(key)),
+            (None, None) => return Ok(None),
+            _ => {
+                return Err(config_error(
+                    "TLS_CERT_PATH and TLS_KEY_PATH have to be set together".to_string(),
+                ));
+            }
+        };
+
+        let bind_address = setting(
+            "TLS_BIND_ADDRESS",
+            bind_address
+                .map(|raw| {
+                    raw.parse()
+                        .map_err(|e| config_error(format!("tls_bind_address={raw}: {e}")))
+                })
+                .transpose()?,
+            SocketAddr::from(([0, 0, 0, 0], 3443)),
+        )?;
+        Ok(Some(Self {
+            bind_address,
+            cert_path,
+            key_path,
+        }))
+    }
+}
+
+impl PinataConfig {
+    fn load(file: FilePinata) -> Result<Self, ApiError> {
+        let jwt = optional_setting("PINATA_JWT", file.jwt)
+            .ok_or_else(|| config_error("PINATA_JWT is required".to_string()))?;
+
+        let api_url = base_url(
+            "PINATA_API_URL",
+            setting("PINATA_API_URL", file.api_url, DEFAULT_API_URL.to_string())?,
+        )?;
+        let uploads_url = base_url(
+            "PINATA_UPLOADS_URL",
+            setting(
+                "PINATA_UPLOADS_URL",
+                file.uploads_url,
+                DEFAULT_UPLOADS_URL.to_string(),
+            )?,
+        )?;
+        let gateway = optional_setting("PINATA_GATEWAY", file.gateway).map(gateway_domain);
+
+        let group_namespace = optional_setting("GROUP_NAMESPACE", file.group_namespace);
+        if let Some(namespace) = &group_namespace
+            && !is_slug(namespace)
+        {
+            return Err(config_error(format!(
+                "GROUP_NAMESPACE={namespace}: use lowercase letters, digits and dashes"
+            )));
+        }
+
+        let account = setting("PINATA_ACCOUNT", file.account, "default".to_string())?;
+        let accounts = PinataAccountConfig::load_all(file.accounts)?;
+        if let Some(name) = std::iter::once(&account)
+            .chain(accounts.iter().map(|a| &a.name))
+            .find(|name| !is_slug(name))
+        {
+            return Err(config_error(format!(
+                "Pinata account {name}: use lowercase letters, digits and dashes"
+            )));
+        }
+        if accounts.iter().any(|a| a.name == account) {
+            return Err(config_error(format!(
+                "PINATA_ACCOUNTS: {account} is already the name of the PINATA_JWT account"
+            )));
+        }
+
+        Ok(Self {
+            jwt,
+            api_url,
+            uploads_url,
+            gateway,
+            gateway_key: optional_setting("PINATA_GATEWAY_KEY", file.gateway_key),
+            group_namespace,
+            account,
+            accounts,
+        })
+    }
+}
+
+impl PinataAccountConfig {
+    // PINATA_ACCOUNTS names the accounts, e.g. `client,archive`, each set up with
+    // PINATA_<NAME>_JWT, _GATEWAY, _GATEWAY_KEY and _GROUP_PREFIXES (comma separated),
+    // or `[pinata.accounts.<name>]` tables in the config file
+    fn load_all(mut file: BTreeMap<String, FilePinataAccount>) -> Result<Vec<Self>, ApiError> {
+        let names: Vec<String> = match env::var("PINATA_ACCOUNTS") {
+            Ok(raw) => raw
+                .split(',')
+                .map(str::trim)
+                .filter(|name| !name.is_empty())
+                .map(str::to_string)
+                .collect(),
+            Err(_) => file.keys().cloned().collect(),
+        };
+
+        let mut accounts: Vec<Self> = Vec::with_capacity(names.len());
+        for name in names {
+            if accounts.iter().any(|a| a.name == name) {
+                return Err(config_error(format!(
+                    "PINATA_ACCOUNTS: {name} is listed twice"
+                )));
+            }
+            let file = file.remove(&name).unwrap_or_default();
+            let var = |setting: &str| {
+                format!("PINATA_{}_{setting}", name.to_uppercase().replace('-', "_"))
+            };
+            let jwt = optional_setting(&var("JWT"), file.jwt)
+                .ok_or_else(|| config_error(format!("{} is required", var("JWT"))))?;
+            let group_prefixes = match env::var(var("GROUP_PREFIXES")) {
+                Ok(raw) => raw.split(',').map(str::to_string).collect(),
+                Err(_) => file.group_prefixes.unwrap_or_default(),
+            }
+            .into_iter()
+            .map(|prefix| prefix.trim().to_string())
+            .filter(|prefix| !prefix.is_empty())
+            .collect();
+
+            accounts.push(Self {
+                jwt,
+                gateway: optional_setting(&var("GATEWAY"), file.gateway).map(gateway_domain),
+                gateway_key: optional_setting(&var("GATEWAY_KEY"), file.gateway_key),
+                group_prefixes,
+                name,
+            });
+        }
+        Ok(accounts)
+    }
+}
+
+// a gateway as its bare domain, however it was written
+fn gateway_domain(gateway: String) -> String {
+    gateway
+        .trim_start_matches("https://")
+        .trim_end_matches('/')
+        .to_string()
+}
+
+// lowercase letters, digits and dashes, as namespaces and account names are
+fn is_slug(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+}
+
+impl CachePurgeConfig {
+    fn load(file: FileCachePurge, pinata: &PinataConfig) -> Result<Option<Self>, ApiError> {
+        let Some(url) = optional_setting("CACHE_PURGE_URL", file.url) else {
+            return Ok(None);
+        };
+        let url = base_url("CACHE_PURGE_URL", url)?;
+        let token = optional_setting("CACHE_PURGE_TOKEN", file.token)
+            .or_else(|| url.starts_with(&pinata.api_url).then(|| pinata.jwt.clone()));
+        Ok(Some(Self { url, token }))
+    }
+}
+
+impl UploadConfig {
+    fn load(file: FileUpload) -> Result<Self, ApiError> {
+        let max_timeout_secs = setting("UPLOAD_MAX_TIMEOUT_SECS", file.max_timeout_secs, 300)?;
+        let default_timeout_secs = setting("UPLOAD_TIMEOUT_SECS", file.timeout_secs, 60)?;
+        let max_retries = setting("UPLOAD_MAX_RETRIES", file.max_retries, 5)?;
+        let default_retries = setting("UPLOAD_RETRIES", file.retries, 2)?;
+        let max_file_bytes = setting(
+            "UPLOAD_MAX_FILE_BYTES",
+            file.max_file_bytes,
+            512 * 1024 * 1024,
+        )?
+        .max(1);
+        let max_request_bytes = setting(
+            "UPLOAD_MAX_REQUEST_BYTES",
+            file.max_request_bytes,
+            1024 * 1024 * 1024,
+        )?;
+        if max_request_bytes < max_file_bytes {
+            return Err(config_error(format!(
+                "UPLOAD_MAX_FILE_BYTES ({max_file_bytes}) exceeds UPLOAD_MAX_REQUEST_BYTES ({max_request_bytes})"
+            )));
+        }
+
+        if max_timeout_secs == 0 || default_timeout_secs == 0 {
+            return Err(config_error("upload timeouts must be positive".to_string()));
+        }
+        if default_timeout_secs > max_timeout_secs {
+            return Err(config_error(format!(
+                "UPLOAD_TIMEOUT_SECS ({default_timeout_secs}) exceeds UPLOAD_MAX_TIMEOUT_SECS ({max_timeout_secs})"
+            )));
+        }
+        if default_retries > max_retries {
+            return Err(config_error(format!(
+                "UPLOAD_RETRIES ({default_retries}) exceeds UPLOAD_MAX_RETRIES ({max_retries})"
+            )));
+        }
+
+        Ok(Self {
+            default_timeout_secs,
+            max_timeout_secs,
+            default_retries,
+            max_retries,
+            spool_threshold_bytes: setting(
+                "UPLOAD_SPOOL_THRESHOLD_BYTES",
+                file.spool_threshold_bytes,
+                8 * 1024 * 1024,
+            )?,
+            spool_dir: optional_setting(
+                "UPLOAD_SPOOL_DIR",
+                file.spool_dir.map(|p| p.display().to_string()),
+            )
+            .map(PathBuf::from),
+            max_file_bytes,
+            max_request_bytes,
+            parallel_files: setting("UPLOAD_PARALLEL_FILES", file.parallel_files, 4)?.max(1),
+            preview_ttl_secs: setting("UPLOAD_PREVIEW_TTL_SECS", file.preview_ttl_secs, 3600)?
+                .max(1),
+            preview_thumbnail_width: setting(
+                "UPLOAD_PREVIEW_THUMBNAIL_WIDTH",
+                file.preview_thumbnail_width,
+                400,
+            )?
+            .max(1),
+            strip_exif: setting("UPLOAD_STRIP_EXIF", file.strip_exif, false)?,
+            manifest: setting("UPLOAD_MANIFEST", file.manifest, true)?,
+            disabled_stages: match env::var("UPLOAD_DISABLED_STAGES") {
+                Ok(raw) => raw.split(',').map(str::to_string).collect(),
+                Err(_) => file.disabled_stages.unwrap_or_default(),
+            }
+            .into_iter()
+            .map(|s| s.trim().to_lowercase())
+            .filter(|s| !s.is_empty())
+            .collect(),
+        })
+    }
+}
+
+impl DatabaseConfig {
+    fn load(file: FileDatabase) -> Result<Option<Self>, ApiError> {
+        let Some(url) = optional_setting("DATABASE_URL", file.url) else {
+            return Ok(None);
+        };
+        if !url.starts_with("sqlite:") {
+            return Err(config_error(format!(
+                "DATABASE_URL={url}: only sqlite: urls are supported"
+            )));
+        }
+
+        let sync_interval_secs =
+            setting("DB_SYNC_INTERVAL_SECS", file.sync_interval_secs, 300)?.max(10);
+
+        Ok(Some(Self {
+            url,
+            sync_interval_secs,
+        }))
+    }
+}
@@ -0,0 +1,136 @@
+use chrono::{DateTime, Utc};
+use sqlx::Row;
+use sqlx::sqlite::SqliteRow;
+
+use super::{Db, db_error, parse_date};
+use crate::errors::ApiError;
+use crate::models::api_keys::{ApiKey, KeyScope};
+use crate::models::dates::format_rfc3339;
+
+const COLUMNS: &str =
+    "id, name, scope, prefix, rate_limit_per_minute, groups, created_at, revoked_at";
+
+// issued keys, which unlike the catalog tables aren't a copy of anything upstream
+impl Db {
+    pub async fn insert_api_key(&self, secret_hash: &str, key: &ApiKey) -> Result<(), ApiError> {
+        let groups = key.groups.as_ref().map(serde_json::to_string).transpose()?;
+        sqlx::query(
+            "INSERT INTO api_keys (secret_hash, id, name, scope, prefix, rate_limit_per_minute, groups, created_at, revoked_at)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(secret_hash)
+        .bind(&key.id)
+        .bind(&key.name)
+        .bind(scope_name(key.scope))
+        .bind(&key.prefix)
+        .bind(key.rate_limit_per_minute)
+        .bind(groups)
+        .bind(format_rfc3339(&key.created_at))
+        .bind(key.revoked_at.as_ref().map(format_rfc3339))
+        .execute(&self.pool)
+        .await
+        .map_err(db_error)?;
+        Ok(())
+    }
+
+    // every key ever issued, newest first
+    pub async fn api_keys(&self) -> Result<Vec<ApiKey>, ApiError> {
+        let rows = sqlx::query(&format!(
+            "SELECT {COLUMNS} FROM api_keys ORDER BY created_at DESC, id"
+        ))
+        .fetch_all(&self.pool)
+        .await
+        .map_err(db_error)?;
+        rows.iter().map(key_from_row).collect()
+    }
+
+    pub async fn api_key(&self, id: &str) -> Result<Option<ApiKey>, ApiError> {
+        let row = sqlx::query(&format!("SELECT {COLUMNS} FROM api_keys WHERE id = ?"))
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(db_error)?;
+        row.as_ref().map(key_from_row).transpose()
+    }
+
+    pub async fn api_key_by_secret(&self, secret_hash: &str) -> Result<Option<ApiKey>, ApiError> {
+        let row = sqlx::query(&format!(
+            "SELECT {COLUMNS} FROM api_keys WHERE secret_hash = ?"
+        ))
+        .bind(secret_hash)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(db_error)?;
+        row.as_ref().map(key_from_row).transpose()
+    }
+
+    pub async fn set_api_key_rate_limit(
+        &self,
+        id: &str,
+        per_minute: Option<u32>,
+    ) -> Result<Option<ApiKey>, ApiError> {
+        let row = sqlx::query(&format!(
+            "UPDATE api_keys SET rate_limit_per_minute = ? WHERE id = ? RETURNING {COLUMNS}"
+        ))
+        .bind(per_minute)
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(db_error)?;
+        row.as_ref().map(key_from_row).transpose()
+    }
+
+    // Mark a key revoked, keeping the first revocation time. The update and the read
+    // of the key share a transaction, so what's returned is what was stored.
+    pub async fn revoke_api_key(
+        &self,
+        id: &str,
+        at: DateTime<Utc>,
+    ) -> Result<Option<ApiKey>, ApiError> {
+        let mut tx = self.pool.begin().await.map_err(db_error)?;
+        sqlx::query("UPDATE api_keys SET revoked_at = COALESCE(revoked_at, ?) WHERE id = ?")
+            .bind(format_rfc3339(&at))
+            .bind(id)
+            .execute(&mut *tx)
+            .await
+            .map_err(db_error)?;
+        let row = sqlx::query(&format!("SELECT {COLUMNS} FROM api_keys WHERE id = ?"))
+            .bind(id)
+            .fetch_optional(&mut *tx)
+            .await
+            .map_err(db_error)?;
+        tx.commit().await.map_err(db_error)?;
+        row.as_ref().map(key_from_row).transpose()
+    }
+}
+
+fn scope_name(scope: KeyScope) -> &'static str {
+    match scope {
+        KeyScope::ReadOnly => "read_only",
+        KeyScope::Upload => "upload",
+        KeyScope::Admin => "admin",
+    }
+}
+
+fn key_from_row(row: &SqliteRow) -> Result<ApiKey, ApiError> {
+    let scope = match row.get::<&str, _>("scope") {
+        "admin" => KeyScope::Admin,
+        "upload" => KeyScope::Upload,
+        _ => KeyScope::ReadOnly,
+    };
+    let groups: Option<String> = row.get("groups");
+
+    Ok(ApiKey {
+        id: row.get("id"),
+        name: row.get("name"),
+        scope,
+        prefix: row.get("prefix"),
+        rate_limit_per_minute: row.get("rate_limit_per_minute"),
+        groups: groups.as_deref().map(serde_json::from_str).transpose()?,
+        created_at: parse_date(row.get("created_at"))?,
+        revoked_at: row
+            .get::<Option<String>, _>("revoked_at")
+            .map(parse_date)
+            .transpose()?,
+    })
+}
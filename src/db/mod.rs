@@ -0,0 +1,573 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePool, SqlitePoolOptions, SqliteRow};
+use sqlx::{QueryBuilder, Row, Sqlite};
+use tokio::sync::Notify;
+
+use crate::errors::ApiError;
+use crate::models::catalog::{CatalogChange, ChangeKind};
+use crate::models::dates::format_rfc3339;
+use crate::models::{PhotoAttributes, PinataFile, PinataGroup};
+use crate::pinata::{FilesQuery, FilterOp, ListOptions, SortOrder};
+
+mod api_keys;
+pub mod sync;
+
+const SCHEMA: &str = r#"
+CREATE TABLE IF NOT EXISTS files (
+    id              TEXT PRIMARY KEY,
+    name            TEXT NOT NULL,
+    cid             TEXT NOT NULL,
+    size            INTEGER NOT NULL,
+    number_of_files INTEGER NOT NULL,
+    mime_type       TEXT NOT NULL,
+    group_id        TEXT NOT NULL,
+    keyvalues       TEXT NOT NULL,
+    created_at      TEXT NOT NULL,
+    synced_at       TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS files_group_created ON files (group_id, created_at);
+CREATE INDEX IF NOT EXISTS files_created ON files (created_at);
+
+CREATE TABLE IF NOT EXISTS groups (
+    id         TEXT PRIMARY KEY,
+    name       TEXT NOT NULL,
+    is_public  INTEGER,
+    created_at TEXT NOT NULL,
+    synced_at  TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS sync_state (
+    key   TEXT PRIMARY KEY,
+    value TEXT NOT NULL
+);
+
+-- the last time each file or group was added, edited or removed, filled in by
+-- the triggers below whichever path wrote the row (sync, webhook or handler)
+CREATE TABLE IF NOT EXISTS changes (
+    kind       TEXT NOT NULL,
+    id         TEXT NOT NULL,
+    deleted    INTEGER NOT NULL,
+    changed_at TEXT NOT NULL,
+    PRIMARY KEY (kind, id)
+);
+CREATE INDEX IF NOT EXISTS changes_changed_at ON changes (changed_at);
+
+-- issued API keys by the sha256 of their secret, never the secret itself; revoked
+-- keys stay so usage history still resolves
+CREATE TABLE IF NOT EXISTS api_keys (
+    secret_hash           TEXT PRIMARY KEY,
+    id                    TEXT NOT NULL UNIQUE,
+    name                  TEXT NOT NULL,
+    scope                 TEXT NOT NULL,
+    prefix                TEXT NOT NULL,
+    rate_limit_per_minute INTEGER,
+    groups                TEXT,
+    created_at            TEXT NOT NULL,
+    revoked_at            TEXT
+);
+
+CREATE TRIGGER IF NOT EXISTS files_inserted AFTER INSERT ON files BEGIN
+    INSERT INTO changes VALUES ('file', new.id, 0, strftime('%Y-%m-%dT%H:%M:%fZ', 'now'))
+        ON CONFLICT (kind, id) DO UPDATE SET deleted = 0, changed_at = excluded.changed_at;
+    INSERT INTO changes SELECT 'group', new.group_id, 0, strftime('%Y-%m-%dT%H:%M:%fZ', 'now')
+        WHERE new.group_id <> ''
+        ON CONFLICT (kind, id) DO UPDATE SET changed_at = excluded.changed_at;
+END;
+
+-- a resync rewrites every row, only real edits count
+CREATE TRIGGER IF NOT EXISTS files_updated AFTER UPDATE ON files
+WHEN old.name IS NOT new.name OR old.cid IS NOT new.cid OR old.group_id IS NOT new.group_id
+    OR old.keyvalues IS NOT new.keyvalues OR old.mime_type IS NOT new.mime_type
+BEGIN
+    INSERT INTO changes VALUES ('file', new.id, 0, strftime('%Y-%m-%dT%H:%M:%fZ', 'now'))
+        ON CONFLICT (kind, id) DO UPDATE SET deleted = 0, changed_at = excluded.changed_at;
+    INSERT INTO changes SELECT 'group', g.id, 0, strftime('%Y-%m-%dT%H:%M:%fZ', 'now')
+        FROM (SELECT old.group_id AS id UNION SELECT new.group_id) AS g
+        WHERE g.id <> ''
+        ON CONFLICT (kind, id) DO UPDATE SET changed_at = excluded.changed_at;
+END;
+
+CREATE TRIGGER IF NOT EXISTS files_deleted AFTER DELETE ON files BEGIN
+    INSERT INTO changes VALUES ('file', old.id, 1, strftime('%Y-%m-%dT%H:%M:%fZ', 'now'))
+        ON CONFLICT (kind, id) DO UPDATE SET deleted = 1, changed_at = excluded.changed_at;
+    INSERT INTO changes SELECT 'group', old.group_id, 0, strftime('%Y-%m-%dT%H:%M:%fZ', 'now')
+        WHERE old.group_id <> ''
+        ON CONFLICT (kind, id) DO UPDATE SET changed_at = excluded.changed_at;
+END;
+
+CREATE TRIGGER IF NOT EXISTS groups_inserted AFTER INSERT ON groups BEGIN
+    INSERT INTO changes VALUES ('group', new.id, 0, strftime('%Y-%m-%dT%H:%M:%fZ', 'now'))
+        ON CONFLICT (kind, id) DO UPDATE SET deleted = 0, changed_at = excluded.changed_at;
+END;
+
+CREATE TRIGGER IF NOT EXISTS groups_updated AFTER UPDATE ON groups
+WHEN old.name IS NOT new.name OR old.is_public IS NOT new.is_public
+BEGIN
+    INSERT INTO changes VALUES ('group', new.id, 0, strftime('%Y-%m-%dT%H:%M:%fZ', 'now'))
+        ON CONFLICT (kind, id) DO UPDATE SET deleted = 0, changed_at = excluded.changed_at;
+END;
+
+CREATE TRIGGER IF NOT EXISTS groups_deleted AFTER DELETE ON groups BEGIN
+    INSERT INTO changes VALUES ('group', old.id, 1, strftime('%Y-%m-%dT%H:%M:%fZ', 'now'))
+        ON CONFLICT (kind, id) DO UPDATE SET deleted = 1, changed_at = excluded.changed_at;
+END;
+"#;
+
+const LAST_SYNCED_AT: &str = "last_synced_at";
+// progress of the sync under way, kept until it finishes
+const SYNC_STARTED_AT: &str = "sync_started_at";
+const SYNC_PAGE_TOKEN: &str = "sync_page_token";
+
+// How far a catalog sync has got: when it started and the files page to fetch next,
+// None before the first page
+#[derive(Debug, Clone)]
+pub struct SyncCheckpoint {
+    pub started_at: String,
+    pub page_token: Option<String>,
+}
+
+fn db_error(e: sqlx::Error) -> ApiError {
+    ApiError::Internal(format!("Database error: {e}"))
+}
+
+// Local mirror of the Pinata catalog. Reads are served from here once a full
+// sync has completed; writes still go to Pinata and wake the sync task.
+#[derive(Clone)]
+pub struct Db {
+    pool: SqlitePool,
+    synced: Arc<AtomicBool>,
+    sync_requested: Arc<Notify>,
+}
+
+impl Db {
+    pub async fn connect(url: &str) -> Result<Self, ApiError> {
+        let options = SqliteConnectOptions::from_str(url)
+            .map_err(db_error)?
+            .create_if_missing(true);
+
+        if let Some(dir) = options.get_filename().parent()
+            && !dir.as_os_str().is_empty()
+        {
+            std::fs::create_dir_all(dir).map_err(|e| {
+                ApiError::Internal(format!("Failed to create {}: {e}", dir.display()))
+            })?;
+        }
+
+        let pool = SqlitePoolOptions::new()
+            .max_connections(4)
+            .connect_with(options)
+            .await
+            .map_err(db_error)?;
+        sqlx::raw_sql(SCHEMA)
+            .execute(&pool)
+            .await
+            .map_err(db_error)?;
+
+        let db = Self {
+            pool,
+            synced: Arc::new(AtomicBool::new(false)),
+            sync_requested: Arc::new(Notify::new()),
+        };
+
+        // a mirror from an earlier run is good enough to serve while the first sync runs
+        if db.last_synced_at().await?.is_some() {
+            db.synced.store(true, Ordering::Relaxed);
+        }
+
+        Ok(db)
+    }
+
+    pub async fn ping(&self) -> Result<(), ApiError> {
+        sqlx::query("SELECT 1")
+            .execute(&self.pool)
+            .await
+            .map_err(db_error)?;
+        Ok(())
+    }
+
+    // whether reads can be served locally
+    pub fn is_synced(&self) -> bool {
+        self.synced.load(Ordering::Relaxed)
+    }
+
+    // ask the sync task to run now rather than at its next interval, e.g. after a write
+    pub fn request_sync(&self) {
+        self.sync_requested.notify_one();
+    }
+
+    pub async fn last_synced_at(&self) -> Result<Option<DateTime<Utc>>, ApiError> {
+        let value: Option<String> =
+            sqlx::query_scalar("SELECT value FROM sync_state WHERE key = ?")
+                .bind(LAST_SYNCED_AT)
+                .fetch_optional(&self.pool)
+                .await
+                .map_err(db_error)?;
+
+        Ok(value
+            .and_then(|raw| DateTime::parse_from_rfc3339(&raw).ok())
+            .map(|date| date.with_timezone(&Utc)))
+    }
+
+    // the sync a restart interrupted, if any
+    pub async fn sync_checkpoint(&self) -> Result<Option<SyncCheckpoint>, ApiError> {
+        let rows: Vec<(String, String)> =
+            sqlx::query_as("SELECT key, value FROM sync_state WHERE key IN (?, ?)")
+                .bind(SYNC_STARTED_AT)
+                .bind(SYNC_PAGE_TOKEN)
+                .fetch_all(&self.pool)
+                .await
+                .map_err(db_error)?;
+        let mut values: HashMap<String, String> = rows.into_iter().collect();
+
+        Ok(values
+            .remove(SYNC_STARTED_AT)
+            .map(|started_at| SyncCheckpoint {
+                started_at,
+                page_token: values.remove(SYNC_PAGE_TOKEN),
+            }))
+    }
+
+    // Start a sync from the first page with the current groups. Rows are stamped with
+    // the start time, so whatever isn't seen again by the end of the walk can go.
+    pub async fn begin_sync(&self, groups: &[PinataGroup]) -> Result<SyncCheckpoint, ApiError> {
+        let started_at = format_rfc3339(&Utc::now());
+        let mut tx = self.pool.begin().await.map_err(db_error)?;
+
+        for group in groups {
+            sqlx::query(
+                "INSERT INTO groups (id, name, is_public, created_at, synced_at) VALUES (?, ?, ?, ?, ?)
+                 ON CONFLICT (id) DO UPDATE SET name = excluded.name, is_public = excluded.is_public,
+                 created_at = excluded.created_at, synced_at = excluded.synced_at",
+            )
+            .bind(&group.id)
+            .bind(&group.name)
+            .bind(group.is_public)
+            .bind(format_rfc3339(&group.created_at))
+            .bind(&started_at)
+            .execute(&mut *tx)
+            .await
+            .map_err(db_error)?;
+        }
+
+        sqlx::query("DELETE FROM sync_state WHERE key = ?")
+            .bind(SYNC_PAGE_TOKEN)
+            .execute(&mut *tx)
+            .await
+            .map_err(db_error)?;
+        set_state(&mut tx, SYNC_STARTED_AT, &started_at).await?;
+
+        tx.commit().await.map_err(db_error)?;
+        Ok(SyncCheckpoint {
+            started_at,
+            page_token: None,
+        })
+    }
+
+    // Store one page of the files walk together with the token of the next, so the
+    // page and the checkpoint are either both kept or both lost.
+    pub async fn save_sync_page(
+        &self,
+        checkpoint: &SyncCheckpoint,
+        files: &[PinataFile],
+    ) -> Result<(), ApiError> {
+        let mut tx = self.pool.begin().await.map_err(db_error)?;
+
+        for file in files {
+            upsert_file(&mut tx, file, &checkpoint.started_at).await?;
+        }
+        if let Some(token) = &checkpoint.page_token {
+            set_state(&mut tx, SYNC_PAGE_TOKEN, token).await?;
+        }
+
+        tx.commit().await.map_err(db_error)?;
+        Ok(())
+    }
+
+    // Drop what the finished walk didn't see, which is gone upstream. Rows written
+    // since it started, e.g. by a webhook, are newer than the walk and stay.
+    // Returns how many groups and files the mirror now holds.
+    pub async fn finish_sync(
+        &self,
+        checkpoint: &SyncCheckpoint,
+    ) -> Result<(usize, usize), ApiError> {
+        let mut tx = self.pool.begin().await.map_err(db_error)?;
+
+        for table in ["files", "groups"] {
+            sqlx::query(&format!("DELETE FROM {table} WHERE synced_at < ?"))
+                .bind(&checkpoint.started_at)
+                .execute(&mut *tx)
+                .await
+                .map_err(db_error)?;
+        }
+
+        sqlx::query("DELETE FROM sync_state WHERE key IN (?, ?)")
+            .bind(SYNC_STARTED_AT)
+            .bind(SYNC_PAGE_TOKEN)
+            .execute(&mut *tx)
+            .await
+            .map_err(db_error)?;
+        set_state(&mut tx, LAST_SYNCED_AT, &format_rfc3339(&Utc::now())).await?;
+
+        let groups: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM groups")
+            .fetch_one(&mut *tx)
+            .await
+            .map_err(db_error)?;
+        let files: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM files")
+            .fetch_one(&mut *tx)
+            .await
+            .map_err(db_error)?;
+
+        tx.commit().await.map_err(db_error)?;
+        self.synced.store(true, Ordering::Relaxed);
+        Ok((groups as usize, files as usize))
+    }
+
+    // apply a single upstream change without waiting for the next full sync
+    pub async fn upsert_file(&self, file: &PinataFile) -> Result<(), ApiError> {
+        let mut conn = self.pool.acquire().await.map_err(db_error)?;
+        upsert_file(&mut conn, file, &format_rfc3339(&Utc::now())).await
+    }
+
+    pub async fn remove_file(&self, id: &str) -> Result<bool, ApiError> {
+        let result = sqlx::query("DELETE FROM files WHERE id = ?")
+            .bind(id)
+            .execute(&self.pool)
+            .await
+            .map_err(db_error)?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    // the local equivalent of walking a Pinata files listing
+    pub async fn list_files(
+        &self,
+        query: &FilesQuery,
+        options: ListOptions,
+    ) -> Result<Vec<PinataFile>, ApiError> {
+        let mut sql: QueryBuilder<Sqlite> = QueryBuilder::new(
+            "SELECT id, name, cid, size, number_of_files, mime_type, group_id, keyvalues, created_at FROM files WHERE 1 = 1",
+        );
+
+        if let Some(group) = &query.group {
+            sql.push(" AND group_id = ").push_bind(group.clone());
+        }
+        if let Some(name) = &query.name {
+            sql.push(" AND name LIKE ").push_bind(format!("%{name}%"));
+        }
+        if let Some(cid) = &query.cid {
+            sql.push(" AND cid = ").push_bind(cid.clone());
+        }
+        if let Some(mime_type) = &query.mime_type {
+            sql.push(" AND mime_type = ").push_bind(mime_type.clone());
+        }
+
+        for (key, filter) in &query.keyvalues {
+            let path = format!("$.\"{}\"", key.replace('"', ""));
+            let numeric = matches!(
+                filter.op,
+                FilterOp::Gt | FilterOp::Gte | FilterOp::Lt | FilterOp::Lte
+            );
+
+            if numeric {
+                sql.push(" AND CAST(json_extract(keyvalues, ")
+                    .push_bind(path)
+                    .push(") AS REAL)");
+            } else {
+                sql.push(" AND json_extract(keyvalues, ")
+                    .push_bind(path)
+                    .push(")");
+            }
+
+            match (&filter.op, &filter.value) {
+                (FilterOp::In, Value::Array(values)) => {
+                    sql.push(" IN (SELECT value FROM json_each(")
+                        .push_bind(Value::Array(values.clone()).to_string())
+                        .push("))");
+                }
+                (op, value) => {
+                    let op = match op {
+                        FilterOp::Eq | FilterOp::In => " = ",
+                        FilterOp::Ne => " <> ",
+                        FilterOp::Like => " LIKE ",
+                        FilterOp::Gt => " > ",
+                        FilterOp::Gte => " >= ",
+                        FilterOp::Lt => " < ",
+                        FilterOp::Lte => " <= ",
+                    };
+                    sql.push(op);
+                    match value {
+                        Value::Number(n) => sql.push_bind(n.as_f64().unwrap_or_default()),
+                        Value::String(s) => sql.push_bind(s.clone()),
+                        other => sql.push_bind(other.to_string()),
+                    };
+                }
+            }
+        }
+
+        let order = match options.order.or(query.order) {
+            Some(SortOrder::Asc) => "ASC",
+            _ => "DESC",
+        };
+        sql.push(format!(" ORDER BY created_at {order}, id {order}"));
+
+        if let Some(limit) = options.limit {
+            sql.push(" LIMIT ").push_bind(limit as i64);
+        }
+
+        let rows = sql.build().fetch_all(&self.pool).await.map_err(db_error)?;
+        rows.iter().map(file_from_row).collect()
+    }
+
+    // when a file or group was last touched, deletions included
+    pub async fn last_change_at(&self) -> Result<Option<DateTime<Utc>>, ApiError> {
+        let value: Option<String> = sqlx::query_scalar("SELECT MAX(changed_at) FROM changes")
+            .fetch_one(&self.pool)
+            .await
+            .map_err(db_error)?;
+        value.map(parse_date).transpose()
+    }
+
+    // files and groups touched after `since`, oldest change first
+    pub async fn changes_since(
+        &self,
+        since: DateTime<Utc>,
+    ) -> Result<Vec<CatalogChange>, ApiError> {
+        let rows = sqlx::query(
+            "SELECT kind, id, deleted, changed_at FROM changes WHERE changed_at > ? ORDER BY changed_at, kind, id",
+        )
+        .bind(format_rfc3339(&since))
+        .fetch_all(&self.pool)
+        .await
+        .map_err(db_error)?;
+
+        rows.iter()
+            .map(|row| {
+                Ok(CatalogChange {
+                    kind: match row.get::<&str, _>("kind") {
+                        "group" => ChangeKind::Group,
+                        _ => ChangeKind::File,
+                    },
+                    id: row.get("id"),
+                    deleted: row.get("deleted"),
+                    changed_at: parse_date(row.get("changed_at"))?,
+                })
+            })
+            .collect()
+    }
+
+    // files per group id, for groups with any
+    pub async fn group_file_counts(&self) -> Result<HashMap<String, usize>, ApiError> {
+        let rows = sqlx::query("SELECT group_id, COUNT(*) AS files FROM files GROUP BY group_id")
+            .fetch_all(&self.pool)
+            .await
+            .map_err(db_error)?;
+
+        Ok(rows
+            .iter()
+            .map(|row| (row.get("group_id"), row.get::<i64, _>("files") as usize))
+            .collect())
+    }
+
+    pub async fn list_groups(&self, limit: Option<usize>) -> Result<Vec<PinataGroup>, ApiError> {
+        let rows = sqlx::query(
+            "SELECT id, name, is_public, created_at FROM groups ORDER BY created_at DESC, id LIMIT ?",
+        )
+        .bind(limit.map_or(-1, |l| l as i64))
+        .fetch_all(&self.pool)
+        .await
+        .map_err(db_error)?;
+
+        rows.iter()
+            .map(|row| {
+                Ok(PinataGroup {
+                    id: row.get("id"),
+                    name: row.get("name"),
+                    is_public: row.get("is_public"),
+                    created_at: parse_date(row.get("created_at"))?,
+                    account: None,
+                })
+            })
+            .collect()
+    }
+}
+
+async fn set_state(
+    conn: &mut sqlx::SqliteConnection,
+    key: &str,
+    value: &str,
+) -> Result<(), ApiError> {
+    sqlx::query(
+        "INSERT INTO sync_state (key, value) VALUES (?, ?)
+         ON CONFLICT (key) DO UPDATE SET value = excluded.value",
+    )
+    .bind(key)
+    .bind(value)
+    .execute(conn)
+    .await
+    .map_err(db_error)?;
+    Ok(())
+}
+
+async fn upsert_file(
+    conn: &mut sqlx::SqliteConnection,
+    file: &PinataFile,
+    synced_at: &str,
+) -> Result<(), ApiError> {
+    let keyvalues = serde_json::to_string(&file.keyvalues.to_keyvalues())?;
+    sqlx::query(
+        "INSERT INTO files (id, name, cid, size, number_of_files, mime_type, group_id, keyvalues, created_at, synced_at)
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+         ON CONFLICT (id) DO UPDATE SET name = excluded.name, cid = excluded.cid, size = excluded.size,
+         number_of_files = excluded.number_of_files, mime_type = excluded.mime_type,
+         group_id = excluded.group_id, keyvalues = excluded.keyvalues,
+         created_at = excluded.created_at, synced_at = excluded.synced_at",
+    )
+    .bind(&file.id)
+    .bind(&file.name)
+    .bind(&file.cid)
+    .bind(file.size as i64)
+    .bind(file.number_of_files as i64)
+    .bind(&file.mime_type)
+    .bind(&file.group_id)
+    .bind(keyvalues)
+    .bind(format_rfc3339(&file.created_at))
+    .bind(synced_at)
+    .execute(conn)
+    .await
+    .map_err(db_error)?;
+    Ok(())
+}
+
+fn parse_date(raw: String) -> Result<DateTime<Utc>, ApiError> {
+    DateTime::parse_from_rfc3339(&raw)
+        .map(|date| date.with_timezone(&Utc))
+        .map_err(|e| ApiError::Internal(format!("Bad timestamp '{raw}' in database: {e}")))
+}
+
+fn file_from_row(row: &SqliteRow) -> Result<PinataFile, ApiError> {
+    let keyvalues: HashMap<String, String> = serde_json::from_str(row.get("keyvalues"))?;
+
+    Ok(PinataFile {
+        id: row.get("id"),
+        name: row.get("name"),
+        cid: row.get("cid"),
+        size: row.get::<i64, _>("size") as u64,
+        number_of_files: row.get::<i64, _>("number_of_files") as u64,
+        mime_type: row.get("mime_type"),
+        group_id: row.get("group_id"),
+        keyvalues: PhotoAttributes::from_keyvalues(keyvalues),
+        created_at: parse_date(row.get("created_at"))?,
+        width: None,
+        height: None,
+        orientation: None,
+        blurhash: None,
+        captured_at: None,
+        account: None,
+    }
+    .with_keyvalue_fields())
+}
@@ -0,0 +1,98 @@
+use std::time::{Duration, Instant};
+
+use metrics::{counter, gauge, histogram};
+use tracing::{Instrument, info, info_span, warn};
+
+use super::{Db, SyncCheckpoint};
+use crate::errors::ApiError;
+use crate::pinata::files::fetch_files_page;
+use crate::pinata::{FilesQuery, ListOptions, PinataClient, groups, rate_limit};
+
+// Pull the full catalog from Pinata into the local mirror, a page at a time. Each
+// page is checkpointed, so a sync a restart cut short carries on where it stopped.
+pub async fn sync_once(db: &Db, pinata: &PinataClient) -> Result<(usize, usize), ApiError> {
+    let mut checkpoint = match db.sync_checkpoint().await? {
+        Some(checkpoint) => {
+            info!(
+                "Resuming the catalog sync started at {}",
+                checkpoint.started_at
+            );
+            counter!("db_sync_resumed_total").increment(1);
+            checkpoint
+        }
+        None => start(db, pinata).await?,
+    };
+    let mut resumed = checkpoint.page_token.is_some();
+
+    let mut query = FilesQuery::new();
+    loop {
+        query.set_page_token(checkpoint.page_token.clone());
+        let page = match fetch_files_page(pinata, &query).await {
+            Ok(page) => page,
+            // page tokens don't last forever, so one from long ago may be refused
+            Err(ApiError::Api(e)) if resumed => {
+                warn!("Restarting the catalog sync, its checkpoint was refused: {e}");
+                checkpoint = start(db, pinata).await?;
+                resumed = false;
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+        resumed = false;
+
+        let files: Vec<_> = page.files.into_iter().filter(|f| !f.is_variant()).collect();
+        let Some(token) = page.next_page_token else {
+            db.save_sync_page(&checkpoint, &files).await?;
+            break;
+        };
+        checkpoint.page_token = Some(token);
+        db.save_sync_page(&checkpoint, &files).await?;
+
+        // a full walk, paced so it doesn't starve the requests being served
+        rate_limit::throttle().await;
+    }
+
+    db.finish_sync(&checkpoint).await
+}
+
+async fn start(db: &Db, pinata: &PinataClient) -> Result<SyncCheckpoint, ApiError> {
+    let options = ListOptions {
+        throttle: true,
+        ..ListOptions::default()
+    };
+    let groups = groups::list_groups(pinata, options).await?;
+    db.begin_sync(&groups).await
+}
+
+// Keep the mirror in step with Pinata: sync on start, then every `interval`
+// or sooner when a write asks for it. Failures keep serving the last snapshot.
+pub fn spawn(db: Db, pinata: PinataClient, interval: Duration) -> tokio::task::JoinHandle<()> {
+    let span = info_span!("catalog_sync");
+    tokio::spawn(
+        async move {
+            loop {
+                let started = Instant::now();
+                match sync_once(&db, &pinata).await {
+                    Ok((groups, files)) => {
+                        info!("Catalog sync: {groups} groups, {files} files");
+                        counter!("db_sync_total", "result" => "success").increment(1);
+                        gauge!("db_last_sync_timestamp_seconds")
+                            .set(chrono::Utc::now().timestamp() as f64);
+                        gauge!("db_files").set(files as f64);
+                    }
+                    Err(e) => {
+                        warn!("Catalog sync failed: {e}");
+                        counter!("db_sync_total", "result" => "failure").increment(1);
+                    }
+                }
+                histogram!("db_sync_duration_seconds").record(started.elapsed().as_secs_f64());
+
+                tokio::select! {
+                    _ = tokio::time::sleep(interval) => {}
+                    _ = db.sync_requested.notified() => {}
+                }
+            }
+        }
+        .instrument(span),
+    )
+}
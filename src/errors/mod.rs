@@ -1,10 +1,18 @@
 use thiserror::Error;
 
-use axum::{Json, http::StatusCode, response::IntoResponse};
+use axum::{
+    http::{HeaderValue, StatusCode, header::CONTENT_TYPE},
+    response::{IntoResponse, Response},
+};
 use reqwest;
-use serde_json;
+use serde_json::{self, Value};
+use tracing::warn;
 use url;
 
+use crate::middleware::request_id;
+
+pub const PROBLEM_JSON: &str = "application/problem+json";
+
 #[derive(Debug, Error)]
 pub enum ApiError {
     #[error("Environment variable error: {0}")]
@@ -13,9 +21,48 @@ pub enum ApiError {
     #[error("HTTP request error: {0}")]
     Request(#[from] reqwest::Error),
 
+    // Pinata refused or botched a request
     #[error("API error: {0}")]
     Api(String),
 
+    // something local failed: the disk, the database or a task of our own
+    #[error("Internal error: {0}")]
+    Internal(String),
+
+    // the request itself is at fault, so retrying it unchanged won't help
+    #[error("{0}")]
+    Validation(String),
+
+    // the request is well-formed, but what it carries can't be taken as it is, e.g. a
+    // rating out of range or a chunk that doesn't fill its Content-Range
+    #[error("{0}")]
+    Unprocessable(String),
+
+    // the request clashes with the current state, e.g. a stale confirmation
+    #[error("{0}")]
+    Conflict(String),
+
+    #[error("{0}")]
+    TooLarge(String),
+
+    #[error("{0}")]
+    NotFound(String),
+
+    // Pinata answered, but with a server error or that it's overloaded
+    #[error("{0}")]
+    Unavailable(String),
+
+    // no credentials, or ones that aren't valid
+    #[error("{0}")]
+    Unauthorized(String),
+
+    // the credentials are fine, but don't allow this
+    #[error("{0}")]
+    Forbidden(String),
+
+    #[error("{0}")]
+    UnsupportedMedia(String),
+
     #[error("Url parsing error: {0}")]
     UrlParse(#[from] url::ParseError),
 
@@ -23,32 +70,169 @@ pub enum ApiError {
     Json(#[from] serde_json::Error),
 }
 
-// function to conver error into axum responses
-impl IntoResponse for ApiError {
-    fn into_response(self) -> axum::response::Response {
-        eprintln!("API Error: {self}"); // Log all errors
-        let (status, error_message) = match self {
+// What went wrong, for clients to act on without parsing messages. Codes are only
+// ever added; one that's reported stays as it is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCode {
+    ValidationFailed,
+    Unauthorized,
+    Forbidden,
+    NotFound,
+    MethodNotAllowed,
+    Conflict,
+    PayloadTooLarge,
+    UnsupportedMediaType,
+    RateLimited,
+    // Pinata couldn't be reached, or said it can't serve right now; worth retrying
+    PinataUnavailable,
+    // Pinata answered with an error of its own
+    PinataError,
+    // switched off in this deployment's configuration
+    FeatureDisabled,
+    Internal,
+}
+
+impl ErrorCode {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::ValidationFailed => "VALIDATION_FAILED",
+            Self::Unauthorized => "UNAUTHORIZED",
+            Self::Forbidden => "FORBIDDEN",
+            Self::NotFound => "NOT_FOUND",
+            Self::MethodNotAllowed => "METHOD_NOT_ALLOWED",
+            Self::Conflict => "CONFLICT",
+            Self::PayloadTooLarge => "PAYLOAD_TOO_LARGE",
+            Self::UnsupportedMediaType => "UNSUPPORTED_MEDIA_TYPE",
+            Self::RateLimited => "RATE_LIMITED",
+            Self::PinataUnavailable => "PINATA_UNAVAILABLE",
+            Self::PinataError => "PINATA_ERROR",
+            Self::FeatureDisabled => "FEATURE_DISABLED",
+            Self::Internal => "INTERNAL_ERROR",
+        }
+    }
+}
+
+impl ApiError {
+    pub fn status(&self) -> StatusCode {
+        self.kind().0
+    }
+
+    pub fn code(&self) -> ErrorCode {
+        self.kind().1
+    }
+
+    // the status, the code and the `error` label it's reported with
+    fn kind(&self) -> (StatusCode, ErrorCode, &'static str) {
+        match self {
             Self::Env(_) => (
                 StatusCode::INTERNAL_SERVER_ERROR,
+                ErrorCode::Internal,
                 "Server configuration error",
             ),
             Self::Request(_) => (
                 StatusCode::BAD_GATEWAY,
+                ErrorCode::PinataUnavailable,
                 "Error communicating with external service",
             ),
-            Self::UrlParse(_) => (StatusCode::INTERNAL_SERVER_ERROR, "URL parsing error"),
-            Self::Api(_) => (StatusCode::BAD_GATEWAY, "External API error"),
-            Self::Json(_) => (StatusCode::INTERNAL_SERVER_ERROR, "JSON parsing error"),
-        };
+            Self::UrlParse(_) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                ErrorCode::Internal,
+                "URL parsing error",
+            ),
+            Self::Api(_) => (
+                StatusCode::BAD_GATEWAY,
+                ErrorCode::PinataError,
+                "External API error",
+            ),
+            Self::Internal(_) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                ErrorCode::Internal,
+                "Internal error",
+            ),
+            Self::Unavailable(_) => (
+                StatusCode::BAD_GATEWAY,
+                ErrorCode::PinataUnavailable,
+                "External service unavailable",
+            ),
+            Self::Validation(_) => (
+                StatusCode::BAD_REQUEST,
+                ErrorCode::ValidationFailed,
+                "Invalid request",
+            ),
+            Self::Unprocessable(_) => (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                ErrorCode::ValidationFailed,
+                "Unprocessable request",
+            ),
+            Self::Conflict(_) => (StatusCode::CONFLICT, ErrorCode::Conflict, "Conflict"),
+            Self::TooLarge(_) => (
+                StatusCode::PAYLOAD_TOO_LARGE,
+                ErrorCode::PayloadTooLarge,
+                "Payload too large",
+            ),
+            Self::NotFound(_) => (StatusCode::NOT_FOUND, ErrorCode::NotFound, "Not found"),
+            Self::Unauthorized(_) => (
+                StatusCode::UNAUTHORIZED,
+                ErrorCode::Unauthorized,
+                "Unauthorized",
+            ),
+            Self::Forbidden(_) => (StatusCode::FORBIDDEN, ErrorCode::Forbidden, "Forbidden"),
+            Self::UnsupportedMedia(_) => (
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                ErrorCode::UnsupportedMediaType,
+                "Unsupported media type",
+            ),
+            Self::Json(_) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                ErrorCode::Internal,
+                "JSON parsing error",
+            ),
+        }
+    }
+}
 
-        let body = Json(serde_json::json!({
-            "success": false,
-            "error": error_message,
-            "message": self.to_string(),
-        }));
+// function to conver error into axum responses
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        warn!("API Error: {self}"); // Log all errors
+        let (status, code, error_message) = self.kind();
+        error_response(status, code, error_message, self.to_string())
+    }
+}
 
-        (status, body).into_response()
+// The RFC 7807 problem every failure is reported with, carrying the request's id so
+// a failure someone reports can be found in the logs. `success`, `error` and
+// `message` are kept for clients written against the envelope before it.
+pub fn problem_body(status: StatusCode, code: ErrorCode, error: &str, message: String) -> Value {
+    let mut body = serde_json::json!({
+        "type": format!("urn:esemese:error:{}", code.as_str()),
+        "title": error,
+        "status": status.as_u16(),
+        "detail": message,
+        "code": code.as_str(),
+        "success": false,
+        "error": error,
+        "message": message,
+    });
+    if let Some(id) = request_id::current() {
+        body["request_id"] = id.into();
     }
+    body
+}
+
+// a problem body, maybe with members of its own added, as `application/problem+json`
+pub fn problem_response(status: StatusCode, body: &Value) -> Response {
+    let content_type = HeaderValue::from_static(PROBLEM_JSON);
+    (status, [(CONTENT_TYPE, content_type)], body.to_string()).into_response()
+}
+
+pub fn error_response(
+    status: StatusCode,
+    code: ErrorCode,
+    error: &str,
+    message: String,
+) -> Response {
+    problem_response(status, &problem_body(status, code, error, message))
 }
 
 impl From<String> for ApiError {
@@ -0,0 +1,499 @@
+use std::collections::{HashMap, HashSet};
+
+use chrono::{DateTime, FixedOffset, NaiveDateTime};
+use flate2::Crc;
+
+use crate::errors::ApiError;
+use crate::timezone::{self, TimeZone};
+
+// Removing sensitive EXIF tags before a file is pinned, since pinned files are public
+// and can't be changed afterwards. Tags are taken out where they are, so the file
+// keeps its size and nothing but the removed tags and their values changes. XMP
+// repeats the same tags, GPS position included, so its packets are blanked whole.
+
+const GPS_INFO: u16 = 0x8825;
+const EXIF_IFD: u16 = 0x8769;
+
+// when the file was last written, and when the photo was taken, with the offsets
+// from UTC newer cameras record next to each
+const DATE_TIME: u16 = 0x0132;
+const DATE_TIME_ORIGINAL: u16 = 0x9003;
+const OFFSET_TIME: u16 = 0x9010;
+const OFFSET_TIME_ORIGINAL: u16 = 0x9011;
+
+// tags outside the GPS IFD that identify the camera or its owner
+const SENSITIVE: &[(u16, &str)] = &[
+    (0x927c, "MakerNote"),
+    (0xa420, "ImageUniqueID"),
+    (0xa430, "CameraOwnerName"),
+    (0xa431, "BodySerialNumber"),
+    (0xa435, "LensSerialNumber"),
+    (0xc62f, "CameraSerialNumber"),
+    (0x02bc, "XMP"),
+];
+
+const GPS_TAGS: &[&str] = &[
+    "GPSVersionID",
+    "GPSLatitudeRef",
+    "GPSLatitude",
+    "GPSLongitudeRef",
+    "GPSLongitude",
+    "GPSAltitudeRef",
+    "GPSAltitude",
+    "GPSTimeStamp",
+    "GPSSatellites",
+    "GPSStatus",
+    "GPSMeasureMode",
+    "GPSDOP",
+    "GPSSpeedRef",
+    "GPSSpeed",
+    "GPSTrackRef",
+    "GPSTrack",
+    "GPSImgDirectionRef",
+    "GPSImgDirection",
+    "GPSMapDatum",
+    "GPSDestLatitudeRef",
+    "GPSDestLatitude",
+    "GPSDestLongitudeRef",
+    "GPSDestLongitude",
+    "GPSDestBearingRef",
+    "GPSDestBearing",
+    "GPSDestDistanceRef",
+    "GPSDestDistance",
+    "GPSProcessingMethod",
+    "GPSAreaInformation",
+    "GPSDateStamp",
+    "GPSDifferential",
+    "GPSHPositioningError",
+];
+
+const EXIF_HEADER: &[u8] = b"Exif\0\0";
+
+// how JPEG APP1 segments and PNG iTXt chunks holding XMP start
+const XMP_HEADER: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+const XMP_EXTENSION_HEADER: &[u8] = b"http://ns.adobe.com/xmp/extension/\0";
+const PNG_XMP_KEYWORD: &[u8] = b"XML:com.adobe.xmp\0";
+
+// IFDs nest only a couple deep; more is a loop in a malformed file
+const MAX_DEPTH: usize = 4;
+
+fn malformed() -> ApiError {
+    ApiError::Unprocessable(
+        "The file's EXIF data is malformed, so its location and camera tags can't be removed"
+            .to_string(),
+    )
+}
+
+// Remove the GPS and camera identifying tags from an image of type `mime`, returning
+// the names of the tags removed. Refuses files whose EXIF can't be found or read,
+// rather than pin them with it.
+pub fn strip_sensitive(bytes: &mut [u8], mime: &str) -> Result<Vec<String>, ApiError> {
+    let mut removed = Vec::new();
+    match mime {
+        "image/jpeg" => strip_jpeg(bytes, &mut removed)?,
+        "image/png" => strip_png(bytes, &mut removed)?,
+        "image/webp" => strip_webp(bytes, &mut removed)?,
+        "image/tiff" => Tiff::new(bytes)?.strip(&mut removed)?,
+        "image/gif" => {}
+        _ => {
+            return Err(ApiError::UnsupportedMedia(format!(
+                "EXIF tags can't be removed from {mime} files, upload it with stripExif=false or as a JPEG"
+            )));
+        }
+    }
+
+    let mut seen = HashSet::new();
+    removed.retain(|tag| seen.insert(tag.clone()));
+    Ok(removed)
+}
+
+// When a photo was taken, from its DateTimeOriginal or, for cameras that skip it, its
+// DateTime. A time the camera recorded no offset for is read as wall clock time in
+// `zone`. None when the file has no EXIF, or no readable date in it.
+pub fn capture_time(bytes: &[u8], zone: &TimeZone) -> Option<DateTime<FixedOffset>> {
+    let mut block = exif_block(bytes)?.to_vec();
+    let (local, offset) = Tiff::new(&mut block).ok()?.capture_time().ok()??;
+    Some(match offset {
+        Some(offset) => local
+            .and_local_timezone(offset)
+            .single()
+            .unwrap_or_else(|| zone.from_local(local)),
+        None => zone.from_local(local),
+    })
+}
+
+// the TIFF structure EXIF is stored as, found by the file's signature
+fn exif_block(bytes: &[u8]) -> Option<&[u8]> {
+    if bytes.starts_with(b"II*\0") || bytes.starts_with(b"MM\0*") {
+        return Some(bytes);
+    }
+    if bytes.starts_with(&[0xff, 0xd8]) {
+        let mut at = 2;
+        while at + 4 <= bytes.len() && bytes[at] == 0xff {
+            let marker = bytes[at + 1];
+            if marker == 0xda || marker == 0xd9 {
+                break;
+            }
+            let end = at + 2 + u16::from_be_bytes([bytes[at + 2], bytes[at + 3]]) as usize;
+            let data = bytes.get(at + 4..end)?;
+            if marker == 0xe1 && data.starts_with(EXIF_HEADER) {
+                return Some(&data[EXIF_HEADER.len()..]);
+            }
+            at = end;
+        }
+    } else if bytes.starts_with(b"\x89PNG") {
+        let mut at = 8;
+        while at + 12 <= bytes.len() {
+            let len = u32::from_be_bytes(bytes[at..at + 4].try_into().unwrap()) as usize;
+            let kind = &bytes[at + 4..at + 8];
+            if kind == b"IEND" {
+                break;
+            }
+            if kind == b"eXIf" {
+                return bytes.get(at + 8..at + 8 + len);
+            }
+            at += 12 + len;
+        }
+    } else if bytes.starts_with(b"RIFF") && bytes.get(8..12) == Some(b"WEBP") {
+        let mut at = 12;
+        while at + 8 <= bytes.len() {
+            let len = u32::from_le_bytes(bytes[at + 4..at + 8].try_into().unwrap()) as usize;
+            if &bytes[at..at + 4] == b"EXIF" {
+                let data = bytes.get(at + 8..at + 8 + len)?;
+                return Some(data.strip_prefix(EXIF_HEADER).unwrap_or(data));
+            }
+            at += 8 + len + (len & 1);
+        }
+    }
+    None
+}
+
+// the EXIF block of an APP1 segment, if it is one
+fn exif_payload(data: &mut [u8]) -> Option<&mut [u8]> {
+    data.starts_with(EXIF_HEADER)
+        .then(|| &mut data[EXIF_HEADER.len()..])
+}
+
+// overwrite an XMP packet with the spaces packets are padded with
+fn blank_xmp(packet: &mut [u8], removed: &mut Vec<String>) {
+    packet.fill(b' ');
+    removed.push("XMP".to_string());
+}
+
+fn strip_jpeg(bytes: &mut [u8], removed: &mut Vec<String>) -> Result<(), ApiError> {
+    let mut at = 2;
+    while at + 4 <= bytes.len() {
+        if bytes[at] != 0xff {
+            return Err(malformed());
+        }
+        let marker = bytes[at + 1];
+        // metadata segments all come before the scan
+        if marker == 0xda || marker == 0xd9 {
+            break;
+        }
+        let len = u16::from_be_bytes([bytes[at + 2], bytes[at + 3]]) as usize;
+        let end = at + 2 + len;
+        if len < 2 || end > bytes.len() {
+            return Err(malformed());
+        }
+        if marker == 0xe1 {
+            let data = &mut bytes[at + 4..end];
+            if let Some(header) = [XMP_HEADER, XMP_EXTENSION_HEADER]
+                .into_iter()
+                .find(|header| data.starts_with(header))
+            {
+                blank_xmp(&mut data[header.len()..], removed);
+            } else if let Some(tiff) = exif_payload(data) {
+                Tiff::new(tiff)?.strip(removed)?;
+            }
+        }
+        at = end;
+    }
+    Ok(())
+}
+
+fn strip_png(bytes: &mut [u8], removed: &mut Vec<String>) -> Result<(), ApiError> {
+    let mut at = 8;
+    while at + 12 <= bytes.len() {
+        let len = u32::from_be_bytes(bytes[at..at + 4].try_into().unwrap()) as usize;
+        let end = at + 12 + len;
+        if end > bytes.len() {
+            return Err(malformed());
+        }
+        let kind: [u8; 4] = bytes[at + 4..at + 8].try_into().unwrap();
+        if &kind == b"IEND" {
+            break;
+        }
+        let data = &mut bytes[at + 8..at + 8 + len];
+        let before = removed.len();
+        if &kind == b"iTXt" && data.starts_with(PNG_XMP_KEYWORD) {
+            // kept as an uncompressed chunk with no language, so spaces are its text
+            let text = data
+                .get_mut(PNG_XMP_KEYWORD.len()..)
+                .filter(|rest| rest.len() >= 4)
+                .ok_or_else(malformed)?;
+            let (fields, packet) = text.split_at_mut(4);
+            fields.fill(0);
+            blank_xmp(packet, removed);
+        } else if &kind == b"eXIf" {
+            Tiff::new(data)?.strip(removed)?;
+        }
+        // the chunk's CRC covers its type and data
+        if removed.len() > before {
+            let mut crc = Crc::new();
+            crc.update(&bytes[at + 4..at + 8 + len]);
+            bytes[at + 8 + len..end].copy_from_slice(&crc.sum().to_be_bytes());
+        }
+        at = end;
+    }
+    Ok(())
+}
+
+fn strip_webp(bytes: &mut [u8], removed: &mut Vec<String>) -> Result<(), ApiError> {
+    let mut at = 12;
+    while at + 8 <= bytes.len() {
+        let len = u32::from_le_bytes(bytes[at + 4..at + 8].try_into().unwrap()) as usize;
+        let end = at + 8 + len;
+        if end > bytes.len() {
+            return Err(malformed());
+        }
+        if &bytes[at..at + 4] == b"EXIF" {
+            let data = &mut bytes[at + 8..end];
+            // some writers keep the JPEG style header
+            let tiff = if data.starts_with(EXIF_HEADER) {
+                &mut data[EXIF_HEADER.len()..]
+            } else {
+                data
+            };
+            Tiff::new(tiff)?.strip(removed)?;
+        } else if &bytes[at..at + 4] == b"XMP " {
+            blank_xmp(&mut bytes[at + 8..end], removed);
+        }
+        // chunks are padded to an even length
+        at = end + (len & 1);
+    }
+    Ok(())
+}
+
+// a TIFF structure, as EXIF is stored, with offsets relative to its start
+struct Tiff<'a> {
+    data: &'a mut [u8],
+    little_endian: bool,
+}
+
+impl<'a> Tiff<'a> {
+    fn new(data: &'a mut [u8]) -> Result<Self, ApiError> {
+        let little_endian = match data.get(..2) {
+            Some(b"II") => true,
+            Some(b"MM") => false,
+            _ => return Err(malformed()),
+        };
+        let tiff = Self {
+            data,
+            little_endian,
+        };
+        if tiff.u16(2)? != 42 {
+            return Err(malformed());
+        }
+        Ok(tiff)
+    }
+
+    fn u16(&self, at: usize) -> Result<u16, ApiError> {
+        let bytes: [u8; 2] = self
+            .data
+            .get(at..at + 2)
+            .ok_or_else(malformed)?
+            .try_into()
+            .unwrap();
+        Ok(if self.little_endian {
+            u16::from_le_bytes(bytes)
+        } else {
+            u16::from_be_bytes(bytes)
+        })
+    }
+
+    fn u32(&self, at: usize) -> Result<u32, ApiError> {
+        let bytes: [u8; 4] = self
+            .data
+            .get(at..at + 4)
+            .ok_or_else(malformed)?
+            .try_into()
+            .unwrap();
+        Ok(if self.little_endian {
+            u32::from_le_bytes(bytes)
+        } else {
+            u32::from_be_bytes(bytes)
+        })
+    }
+
+    // the offsets of an IFD's entries
+    fn entries(&self, ifd: usize) -> Result<Vec<usize>, ApiError> {
+        let count = self.u16(ifd)? as usize;
+        Ok((0..count).map(|index| ifd + 2 + index * 12).collect())
+    }
+
+    // an ASCII entry's text, without the trailing NULs and padding
+    fn ascii(&self, entry: usize) -> Result<Option<String>, ApiError> {
+        if self.u16(entry + 2)? != 2 {
+            return Ok(None);
+        }
+        let (start, end) = match self.value_range(entry)? {
+            Some(range) => range,
+            None => (
+                entry + 8,
+                entry + 8 + (self.u32(entry + 4)? as usize).min(4),
+            ),
+        };
+        let text = self.data.get(start..end).ok_or_else(malformed)?;
+        let text = String::from_utf8_lossy(text);
+        Ok(Some(
+            text.trim_matches(|c: char| c == '\0' || c.is_whitespace())
+                .to_string(),
+        ))
+    }
+
+    // the capture date as the camera's clock read it, and its offset if recorded
+    fn capture_time(&self) -> Result<Option<(NaiveDateTime, Option<FixedOffset>)>, ApiError> {
+        let mut tags = HashMap::new();
+        let mut exif = None;
+        for entry in self.entries(self.u32(4)? as usize)? {
+            match self.u16(entry)? {
+                DATE_TIME => {
+                    tags.insert(DATE_TIME, self.ascii(entry)?);
+                }
+                EXIF_IFD => exif = Some(self.u32(entry + 8)? as usize),
+                _ => {}
+            }
+        }
+        if let Some(exif) = exif {
+            for entry in self.entries(exif)? {
+                let tag = self.u16(entry)?;
+                if matches!(tag, DATE_TIME_ORIGINAL | OFFSET_TIME | OFFSET_TIME_ORIGINAL) {
+                    tags.insert(tag, self.ascii(entry)?);
+                }
+            }
+        }
+
+        let tag = |tag: u16| tags.get(&tag).cloned().flatten();
+        let read = |date: u16, offset: u16| {
+            let local = NaiveDateTime::parse_from_str(&tag(date)?, "%Y:%m:%d %H:%M:%S").ok()?;
+            Some((local, tag(offset).and_then(|o| timezone::parse_offset(&o))))
+        };
+        Ok(read(DATE_TIME_ORIGINAL, OFFSET_TIME_ORIGINAL).or_else(|| read(DATE_TIME, OFFSET_TIME)))
+    }
+
+    fn strip(&mut self, removed: &mut Vec<String>) -> Result<(), ApiError> {
+        let first = self.u32(4)? as usize;
+        self.strip_ifd(first, removed, 0)
+    }
+
+    // an IFD and the ones chained after it
+    fn strip_ifd(
+        &mut self,
+        mut offset: usize,
+        removed: &mut Vec<String>,
+        depth: usize,
+    ) -> Result<(), ApiError> {
+        let mut chained = 0;
+        while offset != 0 {
+            if depth > MAX_DEPTH || chained > MAX_DEPTH {
+                return Err(malformed());
+            }
+            let mut index = 0;
+            while index < self.u16(offset)? as usize {
+                let entry = offset + 2 + index * 12;
+                let tag = self.u16(entry)?;
+                if tag == GPS_INFO {
+                    let gps = self.u32(entry + 8)? as usize;
+                    self.clear_gps(gps, removed)?;
+                    self.remove_entry(offset, index)?;
+                    removed.push("GPSInfo".to_string());
+                } else if let Some((_, name)) = SENSITIVE.iter().find(|(t, _)| *t == tag) {
+                    self.remove_entry(offset, index)?;
+                    removed.push(name.to_string());
+                } else {
+                    if tag == EXIF_IFD {
+                        let exif = self.u32(entry + 8)? as usize;
+                        self.strip_ifd(exif, removed, depth + 1)?;
+                    }
+                    index += 1;
+                }
+            }
+            let count = self.u16(offset)? as usize;
+            offset = self.u32(offset + 2 + count * 12)? as usize;
+            chained += 1;
+        }
+        Ok(())
+    }
+
+    // where an entry's value is stored when it doesn't fit in the entry itself
+    fn value_range(&self, entry: usize) -> Result<Option<(usize, usize)>, ApiError> {
+        let unit = match self.u16(entry + 2)? {
+            1 | 2 | 6 | 7 => 1,
+            3 | 8 => 2,
+            4 | 9 | 11 | 13 => 4,
+            5 | 10 | 12 => 8,
+            // unknown types can't be sized, so there's nothing to clear
+            _ => return Ok(None),
+        };
+        let size = (self.u32(entry + 4)? as usize)
+            .checked_mul(unit)
+            .ok_or_else(malformed)?;
+        if size <= 4 {
+            return Ok(None);
+        }
+        let start = self.u32(entry + 8)? as usize;
+        let end = start.checked_add(size).ok_or_else(malformed)?;
+        if end > self.data.len() {
+            return Err(malformed());
+        }
+        Ok(Some((start, end)))
+    }
+
+    // zero an entry's value and take it out of its IFD, moving the later ones up
+    fn remove_entry(&mut self, ifd: usize, index: usize) -> Result<(), ApiError> {
+        let count = self.u16(ifd)? as usize;
+        let entry = ifd + 2 + index * 12;
+        // the entries are followed by the offset of the next IFD
+        let end = ifd + 2 + count * 12 + 4;
+        if end > self.data.len() {
+            return Err(malformed());
+        }
+        if let Some((start, value_end)) = self.value_range(entry)? {
+            self.data[start..value_end].fill(0);
+        }
+
+        self.data.copy_within(entry + 12..end, entry);
+        self.data[end - 12..end].fill(0);
+        let count = count as u16 - 1;
+        let count = if self.little_endian {
+            count.to_le_bytes()
+        } else {
+            count.to_be_bytes()
+        };
+        self.data[ifd..ifd + 2].copy_from_slice(&count);
+        Ok(())
+    }
+
+    // zero the GPS IFD and every value it points at
+    fn clear_gps(&mut self, gps: usize, removed: &mut Vec<String>) -> Result<(), ApiError> {
+        let count = self.u16(gps)? as usize;
+        let end = gps + 2 + count * 12 + 4;
+        if end > self.data.len() {
+            return Err(malformed());
+        }
+        for index in 0..count {
+            let entry = gps + 2 + index * 12;
+            let tag = self.u16(entry)? as usize;
+            removed.push(match GPS_TAGS.get(tag) {
+                Some(name) => name.to_string(),
+                None => format!("GPSTag{tag:#06x}"),
+            });
+            if let Some((start, value_end)) = self.value_range(entry)? {
+                self.data[start..value_end].fill(0);
+            }
+        }
+        self.data[gps..end].fill(0);
+        Ok(())
+    }
+}
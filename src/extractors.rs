@@ -0,0 +1,189 @@
+use axum::{
+    extract::{FromRequestParts, Query},
+    http::{HeaderValue, StatusCode, header::SET_COOKIE, request::Parts},
+    response::{IntoResponse, Response},
+};
+use serde::Deserialize;
+
+use crate::auth::visitors::{self, VISITOR_COOKIE, VISITOR_HEADER};
+use crate::errors::{ErrorCode, error_response};
+use crate::models::catalog::ListingView;
+use crate::state::AppState;
+
+// how long a visitor cookie lasts, a year
+const VISITOR_COOKIE_MAX_AGE_SECS: u64 = 365 * 24 * 60 * 60;
+
+#[derive(Debug, Deserialize)]
+struct LimitParams {
+    limit: Option<String>,
+}
+
+// `?limit=` for listing endpoints: the configured default when absent, capped at the configured max
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limit(pub usize);
+
+impl FromRequestParts<AppState> for Limit {
+    type Rejection = Response;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let listing = state.config.listing;
+        let Query(params) = Query::<LimitParams>::from_request_parts(parts, state)
+            .await
+            .map_err(|e| {
+                error_response(
+                    StatusCode::BAD_REQUEST,
+                    ErrorCode::ValidationFailed,
+                    "Invalid query",
+                    e.body_text(),
+                )
+            })?;
+
+        let Some(raw) = params.limit else {
+            return Ok(Limit(listing.default_limit));
+        };
+
+        match raw.trim().parse::<usize>() {
+            Ok(0) | Err(_) => Err(error_response(
+                StatusCode::BAD_REQUEST,
+                ErrorCode::ValidationFailed,
+                "Invalid limit",
+                format!(
+                    "limit must be a whole number between 1 and {}",
+                    listing.max_limit
+                ),
+            )),
+            Ok(limit) => Ok(Limit(limit.min(listing.max_limit))),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ViewParams {
+    view: Option<String>,
+}
+
+// `?view=grid|detail` for file listings, detail when absent
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct View(pub ListingView);
+
+impl<S: Send + Sync> FromRequestParts<S> for View {
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Query(params) = Query::<ViewParams>::from_request_parts(parts, state)
+            .await
+            .map_err(|e| {
+                error_response(
+                    StatusCode::BAD_REQUEST,
+                    ErrorCode::ValidationFailed,
+                    "Invalid query",
+                    e.body_text(),
+                )
+            })?;
+
+        match params.view.as_deref().map(str::trim) {
+            None | Some("") => Ok(View(ListingView::default())),
+            Some(raw) if raw.eq_ignore_ascii_case("grid") => Ok(View(ListingView::Grid)),
+            Some(raw) if raw.eq_ignore_ascii_case("detail") => Ok(View(ListingView::Detail)),
+            Some(raw) => Err(error_response(
+                StatusCode::BAD_REQUEST,
+                ErrorCode::ValidationFailed,
+                "Invalid view",
+                format!("view must be grid or detail, not '{raw}'"),
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct AccountParams {
+    account: Option<String>,
+}
+
+// The state a listing is served from: narrowed to the Pinata account `?account=`
+// names, else covering every configured account
+#[derive(Clone)]
+pub struct Scoped(pub AppState);
+
+impl FromRequestParts<AppState> for Scoped {
+    type Rejection = Response;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let Query(params) = Query::<AccountParams>::from_request_parts(parts, state)
+            .await
+            .map_err(|e| {
+                error_response(
+                    StatusCode::BAD_REQUEST,
+                    ErrorCode::ValidationFailed,
+                    "Invalid query",
+                    e.body_text(),
+                )
+            })?;
+
+        match params.account.as_deref().map(str::trim) {
+            Some(name) if !name.is_empty() => state
+                .for_account(name)
+                .map(Scoped)
+                .map_err(IntoResponse::into_response),
+            _ => Ok(Scoped(state.clone())),
+        }
+    }
+}
+
+// The anonymous visitor behind a request. A request without a valid token is
+// given a new visitor, whose token is handed back by `respond`.
+#[derive(Debug, Clone)]
+pub struct Visitor {
+    pub id: String,
+    issued: Option<String>,
+}
+
+impl Visitor {
+    pub fn respond(self, body: impl IntoResponse) -> Response {
+        let mut response = body.into_response();
+        let Some(token) = self.issued else {
+            return response;
+        };
+
+        let cookie = format!(
+            "{VISITOR_COOKIE}={token}; Path=/; Max-Age={VISITOR_COOKIE_MAX_AGE_SECS}; HttpOnly; SameSite=Lax"
+        );
+        let headers = response.headers_mut();
+        if let Ok(value) = HeaderValue::from_str(&token) {
+            headers.insert(VISITOR_HEADER, value);
+        }
+        if let Ok(value) = HeaderValue::from_str(&cookie) {
+            headers.append(SET_COOKIE, value);
+        }
+        response
+    }
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for Visitor {
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        if let Some(id) = visitors::presented_token(&parts.headers).and_then(visitors::verify) {
+            return Ok(Visitor { id, issued: None });
+        }
+
+        let (id, token) = visitors::issue().ok_or_else(|| {
+            error_response(
+                StatusCode::FORBIDDEN,
+                ErrorCode::FeatureDisabled,
+                "Visitor favourites disabled",
+                "Set VISITOR_SECRET to enable visitor favourites".to_string(),
+            )
+        })?;
+        Ok(Visitor {
+            id,
+            issued: Some(token),
+        })
+    }
+}
@@ -0,0 +1,107 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, LazyLock, RwLock};
+use std::time::{Duration, Instant};
+
+use tracing::warn;
+
+use crate::coalesce::Coalescer;
+use crate::errors::ApiError;
+use crate::pinata::{FilesQuery, ListOptions};
+use crate::state::AppState;
+
+// Photos per group for the collection cards. Counting takes a full listing without
+// the mirror, so counts are kept: recounted before use once the catalog changed,
+// since the response they go into is cached, and in the background once they're
+// older than REFRESH_AFTER.
+
+const REFRESH_AFTER: Duration = Duration::from_secs(300);
+
+type GroupCounts = Arc<HashMap<String, usize>>;
+
+struct Counted {
+    counts: GroupCounts,
+    at: Instant,
+    // the catalog generation the count started at
+    generation: u64,
+}
+
+static COUNTED: RwLock<Option<Counted>> = RwLock::new(None);
+// bumped by every catalog change, so a count that raced one is redone
+static GENERATION: AtomicU64 = AtomicU64::new(0);
+static REFRESHING: AtomicBool = AtomicBool::new(false);
+static RECOUNTS: LazyLock<Coalescer<Option<GroupCounts>>> = LazyLock::new(Coalescer::new);
+
+// the catalog changed, so the counts are recounted on next use
+pub fn invalidate() {
+    GENERATION.fetch_add(1, Ordering::Relaxed);
+}
+
+// Photos per group id; groups without any are missing. None when there are no
+// counts and making them failed.
+pub async fn counts(state: &AppState) -> Option<GroupCounts> {
+    let current = COUNTED
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .as_ref()
+        .map(|c| {
+            let current = c.generation == GENERATION.load(Ordering::Relaxed);
+            (c.counts.clone(), current, c.at.elapsed() < REFRESH_AFTER)
+        });
+
+    match current {
+        Some((counts, true, true)) => Some(counts),
+        Some((counts, true, false)) => {
+            if !REFRESHING.swap(true, Ordering::AcqRel) {
+                let state = state.clone();
+                tokio::spawn(async move {
+                    refresh(&state).await;
+                    REFRESHING.store(false, Ordering::Release);
+                });
+            }
+            Some(counts)
+        }
+        // the outdated counts beat none when recounting fails
+        outdated => RECOUNTS
+            .run("counts".to_string(), || refresh(state))
+            .await
+            .or(outdated.map(|(counts, _, _)| counts)),
+    }
+}
+
+async fn refresh(state: &AppState) -> Option<GroupCounts> {
+    let generation = GENERATION.load(Ordering::Relaxed);
+    let counts = match count(state).await {
+        Ok(counts) => Arc::new(counts),
+        Err(e) => {
+            warn!("Failed to count the photos per group: {e}");
+            return None;
+        }
+    };
+    *COUNTED.write().unwrap_or_else(|e| e.into_inner()) = Some(Counted {
+        counts: counts.clone(),
+        at: Instant::now(),
+        generation,
+    });
+    Some(counts)
+}
+
+async fn count(state: &AppState) -> Result<HashMap<String, usize>, ApiError> {
+    // counts are shared by requests for any account, so every account is counted
+    let state = state.unscoped();
+    if state.accounts.is_single()
+        && let Some(db) = state.db.as_ref().filter(|db| db.is_synced())
+    {
+        return db.group_file_counts().await;
+    }
+
+    let options = ListOptions {
+        throttle: true,
+        ..ListOptions::default()
+    };
+    let mut counts = HashMap::new();
+    for file in state.list_files(FilesQuery::new(), options).await? {
+        *counts.entry(file.group_id).or_default() += 1;
+    }
+    Ok(counts)
+}
@@ -0,0 +1,27 @@
+use std::collections::BTreeMap;
+use std::sync::LazyLock;
+
+use tracing::warn;
+
+use crate::errors::ApiError;
+use crate::store::JsonStore;
+
+// Cover file id by group id. Groups without one are shown with their first file.
+static COVERS: LazyLock<JsonStore<BTreeMap<String, String>>> =
+    LazyLock::new(|| JsonStore::open("group_covers"));
+
+pub fn get(group_id: &str) -> Option<String> {
+    COVERS.read(|covers| covers.get(group_id).cloned())
+}
+
+pub fn set(group_id: &str, file_id: &str) -> Result<(), ApiError> {
+    COVERS.update(|covers| {
+        covers.insert(group_id.to_string(), file_id.to_string());
+    })
+}
+
+pub fn remove(group_id: &str) {
+    if let Err(e) = COVERS.update(|covers| covers.remove(group_id)) {
+        warn!("Failed to drop the cover of group {group_id}: {e}");
+    }
+}
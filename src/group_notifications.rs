@@ -0,0 +1,71 @@
+use std::collections::BTreeMap;
+use std::sync::LazyLock;
+
+use tracing::warn;
+
+use crate::errors::ApiError;
+use crate::models::groups::{GroupNotifications, UpdateGroupNotifications};
+use crate::store::JsonStore;
+
+// Notification preferences by group id. Groups without any get the defaults.
+static PREFERENCES: LazyLock<JsonStore<BTreeMap<String, GroupNotifications>>> =
+    LazyLock::new(|| JsonStore::open("group_notifications"));
+
+// the events a group's preferences decide on, and what they're posted as
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GroupEvent {
+    Upload,
+    ShareAccess,
+    ShareExpiry,
+}
+
+impl GroupEvent {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Upload => "file.uploaded",
+            Self::ShareAccess => "share_link.accessed",
+            Self::ShareExpiry => "share_link.expired",
+        }
+    }
+}
+
+pub fn get(group_id: &str) -> GroupNotifications {
+    PREFERENCES
+        .read(|preferences| preferences.get(group_id).copied())
+        .unwrap_or_default()
+}
+
+pub fn update(
+    group_id: &str,
+    update: &UpdateGroupNotifications,
+) -> Result<GroupNotifications, ApiError> {
+    PREFERENCES.update(|preferences| {
+        let current = preferences.entry(group_id.to_string()).or_default();
+        if let Some(on) = update.on_upload {
+            current.on_upload = on;
+        }
+        if let Some(on) = update.on_share_access {
+            current.on_share_access = on;
+        }
+        if let Some(on) = update.on_share_expiry {
+            current.on_share_expiry = on;
+        }
+        *current
+    })
+}
+
+pub fn remove(group_id: &str) {
+    if let Err(e) = PREFERENCES.update(|preferences| preferences.remove(group_id)) {
+        warn!("Failed to drop the notification preferences of group {group_id}: {e}");
+    }
+}
+
+// whether `event` is posted for the group, or for files in none by the defaults
+pub fn wants(group_id: Option<&str>, event: GroupEvent) -> bool {
+    let preferences = group_id.map(get).unwrap_or_default();
+    match event {
+        GroupEvent::Upload => preferences.on_upload,
+        GroupEvent::ShareAccess => preferences.on_share_access,
+        GroupEvent::ShareExpiry => preferences.on_share_expiry,
+    }
+}
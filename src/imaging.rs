@@ -0,0 +1,436 @@
+use std::io::Cursor;
+
+use image::{
+    DynamicImage, ExtendedColorType, GenericImageView, ImageDecoder, ImageEncoder, ImageReader,
+    codecs::webp::WebPEncoder, imageops::FilterType, metadata::Orientation,
+};
+use moxcms::{ColorProfile, Layout, ProfileText, TransformOptions};
+use tracing::warn;
+
+use crate::config::{ColorTarget, ProcessingConfig, WatermarkConfig};
+use crate::errors::ApiError;
+use crate::models::files::{Clipping, Histograms, ImageAnalysis};
+use crate::watermark;
+
+// keyvalues recording what was derived from an original
+pub const WIDTH: &str = "width";
+pub const HEIGHT: &str = "height";
+// the original's EXIF orientation, 1 to 8; width and height are already upright
+pub const ORIENTATION: &str = "orientation";
+pub const BLURHASH: &str = "blurhash";
+pub const DISPLAY_CID: &str = "display_cid";
+pub const THUMBNAIL_SMALL_CID: &str = "thumbnail_small_cid";
+pub const THUMBNAIL_LARGE_CID: &str = "thumbnail_large_cid";
+// name of the ICC profile embedded in the original, when it has one
+pub const COLOR_PROFILE: &str = "color_profile";
+// set on a variant, pointing back at the original's file id, and naming which variant it is
+pub const VARIANT_OF: &str = "variant_of";
+pub const VARIANT: &str = "variant";
+// set on an upload's manifest, to the id of the request that uploaded the batch
+pub const UPLOAD_MANIFEST: &str = "upload_manifest";
+// set on the catalog's integrity manifest, to when it was recorded
+pub const INTEGRITY_MANIFEST: &str = "integrity_manifest";
+// hex SHA-256 of an uploaded original, so the same photo isn't pinned twice
+pub const CONTENT_SHA256: &str = "content_sha256";
+// fingerprint of the watermark composited onto the variants, when there is one
+pub const WATERMARK: &str = "watermark";
+// when the photo was taken per its EXIF, RFC 3339 in the offset it was taken at
+pub const CAPTURED_AT: &str = "captured_at";
+
+pub const DERIVED_KEYS: [&str; 7] = [
+    WIDTH,
+    HEIGHT,
+    ORIENTATION,
+    BLURHASH,
+    DISPLAY_CID,
+    THUMBNAIL_SMALL_CID,
+    THUMBNAIL_LARGE_CID,
+];
+
+// blurhash only needs a rough picture, so it's computed on a small copy
+const BLURHASH_SOURCE_WIDTH: u32 = 64;
+
+// What the backend generates from an original. Decoding and encoding are CPU
+// bound, so callers run `derive` on the blocking pool.
+#[derive(Debug)]
+pub struct Derived {
+    pub width: u32,
+    pub height: u32,
+    pub orientation: u8,
+    pub blurhash: String,
+    // WEBP, no wider or taller than `display_width`
+    pub display: Vec<u8>,
+    pub thumbnails: Thumbnails,
+    // description of the original's embedded ICC profile
+    pub color_profile: Option<String>,
+}
+
+// WEBP thumbnails, no larger than the configured small and large edges
+#[derive(Debug)]
+pub struct Thumbnails {
+    pub small: Vec<u8>,
+    pub large: Vec<u8>,
+}
+
+// an original's pixels, turned upright, and the ICC profile they're encoded in, if any
+pub struct Decoded {
+    pub image: DynamicImage,
+    pub icc: Option<Vec<u8>>,
+    // the EXIF orientation that was applied
+    pub orientation: u8,
+}
+
+// what the image header says, read without decoding any pixels
+#[derive(Debug, Clone, Copy)]
+pub struct Header {
+    // upright, i.e. after the EXIF orientation is applied
+    pub width: u32,
+    pub height: u32,
+    pub orientation: u8,
+}
+
+// enough of the start of a file to tell which kind of image it is
+pub const SNIFF_BYTES: usize = 16;
+
+// bytes expected at an offset into a file
+type Magic = (usize, &'static [u8]);
+
+// image formats by the bytes found at given offsets of a file
+const SIGNATURES: [(&[Magic], &str); 13] = [
+    (&[(0, b"\xff\xd8\xff")], "image/jpeg"),
+    (&[(0, b"\x89PNG\r\n\x1a\n")], "image/png"),
+    (&[(0, b"GIF87a")], "image/gif"),
+    (&[(0, b"GIF89a")], "image/gif"),
+    (&[(0, b"RIFF"), (8, b"WEBP")], "image/webp"),
+    // also most camera raw formats, e.g. DNG, NEF and CR2
+    (&[(0, b"II*\0")], "image/tiff"),
+    (&[(0, b"MM\0*")], "image/tiff"),
+    // ISO media files name their brand after `ftyp`
+    (&[(4, b"ftypavif")], "image/avif"),
+    (&[(4, b"ftypavis")], "image/avif"),
+    (&[(4, b"ftypheic")], "image/heic"),
+    (&[(4, b"ftypheix")], "image/heic"),
+    (&[(4, b"ftypmif1")], "image/heif"),
+    (&[(4, b"ftypmsf1")], "image/heif"),
+];
+
+// The MIME type of an image from the first SNIFF_BYTES of it, or None when it
+// isn't an image format the catalog takes.
+pub fn sniff_mime(head: &[u8]) -> Option<&'static str> {
+    SIGNATURES
+        .iter()
+        .find(|(parts, _)| {
+            parts.iter().all(|(offset, expected)| {
+                head.get(*offset..offset + expected.len()) == Some(*expected)
+            })
+        })
+        .map(|(_, mime)| *mime)
+}
+
+// the MIME type to pin a file as, refusing anything that isn't an image
+pub fn image_mime(filename: &str, head: &[u8]) -> Result<&'static str, ApiError> {
+    sniff_mime(head).ok_or_else(|| {
+        ApiError::UnsupportedMedia(format!(
+            "{filename} is not a supported image (JPEG, PNG, GIF, WebP, TIFF, AVIF or HEIC)"
+        ))
+    })
+}
+
+fn processing_error(e: impl std::fmt::Display) -> ApiError {
+    ApiError::Api(format!("Image processing failed: {e}"))
+}
+
+pub fn header(bytes: &[u8]) -> Result<Header, ApiError> {
+    let mut decoder = ImageReader::new(Cursor::new(bytes))
+        .with_guessed_format()
+        .map_err(processing_error)?
+        .into_decoder()
+        .map_err(processing_error)?;
+    let (width, height) = decoder.dimensions();
+    let orientation = decoder
+        .orientation()
+        .unwrap_or(Orientation::NoTransforms)
+        .to_exif();
+    // 5 to 8 turn the picture a quarter, swapping its sides
+    let (width, height) = if orientation >= 5 {
+        (height, width)
+    } else {
+        (width, height)
+    };
+    Ok(Header {
+        width,
+        height,
+        orientation,
+    })
+}
+
+pub fn decode(bytes: &[u8]) -> Result<Decoded, ApiError> {
+    let mut decoder = ImageReader::new(Cursor::new(bytes))
+        .with_guessed_format()
+        .map_err(processing_error)?
+        .into_decoder()
+        .map_err(processing_error)?;
+    // a profile we can't extract is treated like none, rather than failing the image
+    let icc = decoder.icc_profile().ok().flatten();
+    // likewise an unreadable orientation leaves the pixels as stored
+    let orientation = decoder.orientation().unwrap_or(Orientation::NoTransforms);
+    let mut image = DynamicImage::from_decoder(decoder).map_err(processing_error)?;
+    // Phones store pixels as the sensor saw them and leave the rotation to EXIF.
+    // Variants carry no EXIF, so the rotation is applied to the pixels instead.
+    image.apply_orientation(orientation);
+    Ok(Decoded {
+        image,
+        icc,
+        orientation: orientation.to_exif(),
+    })
+}
+
+// the human readable name a profile carries, e.g. `Adobe RGB (1998)`
+pub fn profile_name(icc: &[u8]) -> Option<String> {
+    let profile = ColorProfile::new_from_slice(icc).ok()?;
+    let name = match profile.description? {
+        ProfileText::PlainString(text) => text,
+        ProfileText::Localizable(texts) => texts.into_iter().next()?.value,
+        ProfileText::Description(text) => text.ascii_string,
+    };
+    let name = name.trim_matches(|c: char| c == '\0' || c.is_whitespace());
+    (!name.is_empty()).then(|| name.to_string())
+}
+
+fn target_profile(target: ColorTarget) -> Option<ColorProfile> {
+    match target {
+        ColorTarget::Preserve => None,
+        ColorTarget::Srgb => Some(ColorProfile::new_srgb()),
+        ColorTarget::DisplayP3 => Some(ColorProfile::new_display_p3()),
+    }
+}
+
+// Move 8-bit pixels into the target color space, returning the profile to embed
+// with them. Untagged images are taken to be sRGB already, and so are images whose
+// profile can't be read or converted from (e.g. a CMYK one), rather than failing them.
+fn convert_colors(
+    image: DynamicImage,
+    icc: Option<Vec<u8>>,
+    target: ColorTarget,
+) -> Result<(DynamicImage, Option<Vec<u8>>), ApiError> {
+    let alpha = image.color().has_alpha();
+    let image = if alpha {
+        DynamicImage::ImageRgba8(image.to_rgba8())
+    } else {
+        DynamicImage::ImageRgb8(image.to_rgb8())
+    };
+
+    let source = icc
+        .as_deref()
+        .and_then(|icc| match ColorProfile::new_from_slice(icc) {
+            Ok(profile) => Some(profile),
+            Err(e) => {
+                warn!("Ignoring unreadable ICC profile, assuming sRGB: {e}");
+                None
+            }
+        });
+
+    let Some(target_profile) = target_profile(target) else {
+        // only a profile viewers can make sense of is carried over
+        return Ok((image, icc.filter(|_| source.is_some())));
+    };
+    // sRGB needs no tag; anything else is embedded so viewers know what they're getting
+    let embed = match target {
+        ColorTarget::Srgb => None,
+        _ => Some(target_profile.encode().map_err(processing_error)?),
+    };
+
+    let layout = if alpha { Layout::Rgba } else { Layout::Rgb };
+    let options = TransformOptions::default();
+    let transform = source.and_then(|source| {
+        source
+            .create_transform_8bit(layout, &target_profile, layout, options)
+            .map_err(|e| warn!("Can't convert from the embedded ICC profile, assuming sRGB: {e}"))
+            .ok()
+    });
+    let transform = match transform {
+        Some(transform) => transform,
+        // the pixels are sRGB already
+        None if target == ColorTarget::Srgb => return Ok((image, None)),
+        None => ColorProfile::new_srgb()
+            .create_transform_8bit(layout, &target_profile, layout, options)
+            .map_err(processing_error)?,
+    };
+
+    let (width, height) = image.dimensions();
+    let source_pixels = image.as_bytes();
+    let mut converted = vec![0u8; source_pixels.len()];
+    transform
+        .transform(source_pixels, &mut converted)
+        .map_err(processing_error)?;
+
+    let converted = if alpha {
+        image::RgbaImage::from_raw(width, height, converted).map(DynamicImage::ImageRgba8)
+    } else {
+        image::RgbImage::from_raw(width, height, converted).map(DynamicImage::ImageRgb8)
+    }
+    .ok_or_else(|| processing_error("converted pixels don't fit the image"))?;
+    Ok((converted, embed))
+}
+
+pub fn blurhash(image: &DynamicImage, config: &ProcessingConfig) -> Result<String, ApiError> {
+    let small = image
+        .thumbnail(BLURHASH_SOURCE_WIDTH, BLURHASH_SOURCE_WIDTH)
+        .to_rgba8();
+    blurhash::encode(
+        config.blurhash_x,
+        config.blurhash_y,
+        small.width(),
+        small.height(),
+        small.as_raw(),
+    )
+    .map_err(processing_error)
+}
+
+// Shrink to fit `edge`, mark and encode. The image crate only writes lossless WEBP,
+// from 8-bit RGB(A).
+fn resized_webp(
+    image: &DynamicImage,
+    icc: Option<Vec<u8>>,
+    edge: u32,
+    mark: Option<&WatermarkConfig>,
+) -> Result<Vec<u8>, ApiError> {
+    let (width, height) = image.dimensions();
+    let resized = if width > edge || height > edge {
+        image.resize(edge, edge, FilterType::Lanczos3)
+    } else {
+        image.clone()
+    };
+    let resized = match mark {
+        Some(mark) => watermark::apply(resized, mark),
+        None => resized,
+    };
+
+    let (pixels, color) = if resized.color().has_alpha() {
+        (resized.to_rgba8().into_raw(), ExtendedColorType::Rgba8)
+    } else {
+        (resized.to_rgb8().into_raw(), ExtendedColorType::Rgb8)
+    };
+
+    let mut out = Vec::new();
+    let mut encoder = WebPEncoder::new_lossless(&mut out);
+    if let Some(icc) = icc {
+        encoder.set_icc_profile(icc).map_err(processing_error)?;
+    }
+    encoder
+        .write_image(&pixels, resized.width(), resized.height(), color)
+        .map_err(processing_error)?;
+    Ok(out)
+}
+
+pub fn display_variant(
+    image: &DynamicImage,
+    icc: Option<Vec<u8>>,
+    config: &ProcessingConfig,
+    mark: Option<&WatermarkConfig>,
+) -> Result<Vec<u8>, ApiError> {
+    resized_webp(image, icc, config.display_width, mark)
+}
+
+fn thumbnails_of(
+    image: &DynamicImage,
+    icc: Option<Vec<u8>>,
+    config: &ProcessingConfig,
+    mark: Option<&WatermarkConfig>,
+) -> Result<Thumbnails, ApiError> {
+    Ok(Thumbnails {
+        small: resized_webp(image, icc.clone(), config.thumbnail_small, mark)?,
+        large: resized_webp(image, icc, config.thumbnail_large, mark)?,
+    })
+}
+
+// What's generated for a fresh upload, leaving the display variant to the re-encode
+// job. Dimensions and orientation come from `header`.
+pub struct UploadDerived {
+    pub blurhash: String,
+    pub thumbnails: Thumbnails,
+}
+
+pub fn upload_derived(
+    decoded: Decoded,
+    config: &ProcessingConfig,
+    mark: Option<&WatermarkConfig>,
+) -> Result<UploadDerived, ApiError> {
+    let (image, icc) = convert_colors(decoded.image, decoded.icc, config.color_profile)?;
+    Ok(UploadDerived {
+        blurhash: blurhash(&image, config)?,
+        thumbnails: thumbnails_of(&image, icc, config, mark)?,
+    })
+}
+
+// The blurhash is taken before any watermark, which is only on the variants.
+pub fn derive(
+    bytes: &[u8],
+    config: &ProcessingConfig,
+    mark: Option<&WatermarkConfig>,
+) -> Result<Derived, ApiError> {
+    let Decoded {
+        image,
+        icc,
+        orientation,
+    } = decode(bytes)?;
+    let (width, height) = image.dimensions();
+    let color_profile = icc.as_deref().and_then(profile_name);
+
+    let (image, icc) = convert_colors(image, icc, config.color_profile)?;
+
+    Ok(Derived {
+        width,
+        height,
+        orientation,
+        blurhash: blurhash(&image, config)?,
+        thumbnails: thumbnails_of(&image, icc.clone(), config, mark)?,
+        display: display_variant(&image, icc, config, mark)?,
+        color_profile,
+    })
+}
+
+// Rec. 709 luma of an 8-bit pixel
+fn luminance(r: u8, g: u8, b: u8) -> u8 {
+    (0.2126 * r as f64 + 0.7152 * g as f64 + 0.0722 * b as f64).round() as u8
+}
+
+// histograms and clipping over every pixel, in the colors the original was stored in
+pub fn analyze(image: &DynamicImage) -> ImageAnalysis {
+    let (width, height) = image.dimensions();
+    let rgb = image.to_rgb8();
+
+    let mut histograms = Histograms {
+        luminance: vec![0; 256],
+        red: vec![0; 256],
+        green: vec![0; 256],
+        blue: vec![0; 256],
+    };
+    let mut luminance_sum = 0u64;
+    for pixel in rgb.pixels() {
+        let [r, g, b] = pixel.0;
+        let y = luminance(r, g, b);
+        histograms.luminance[y as usize] += 1;
+        histograms.red[r as usize] += 1;
+        histograms.green[g as usize] += 1;
+        histograms.blue[b as usize] += 1;
+        luminance_sum += y as u64;
+    }
+
+    let pixels = (width as u64 * height as u64).max(1) as f64;
+    let percent = |count: u32| count as f64 * 100.0 / pixels;
+    ImageAnalysis {
+        width,
+        height,
+        mean_luminance: luminance_sum as f64 / pixels,
+        clipping: Clipping {
+            shadows: percent(histograms.luminance[0]),
+            highlights: percent(histograms.luminance[255]),
+            red: percent(histograms.red[255]),
+            green: percent(histograms.green[255]),
+            blue: percent(histograms.blue[255]),
+        },
+        histograms,
+        analyzed_at: chrono::Utc::now(),
+    }
+}
@@ -0,0 +1,374 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::LazyLock;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use chrono::Utc;
+use metrics::counter;
+use sha2::{Digest, Sha256};
+use tokio::sync::Mutex;
+use tracing::{Instrument, info, info_span, warn};
+
+use crate::errors::ApiError;
+use crate::imaging::INTEGRITY_MANIFEST;
+use crate::models::maintenance::{
+    GroupDigest, IntegrityIssue, IntegrityManifest, IntegrityReport, IntegrityState,
+};
+use crate::pinata::{FilesQuery, ListOptions, PinataClient, files, groups, list_files};
+use crate::state::AppState;
+use crate::store::JsonStore;
+
+const MANIFEST_VERSION: u32 = 1;
+// changes are recorded into, or checked against, the manifest once they settle
+const RECORD_DELAY: Duration = Duration::from_secs(60);
+
+static STATE: LazyLock<JsonStore<IntegrityState>> =
+    LazyLock::new(|| JsonStore::open("integrity_manifest"));
+// one check or recording at a time, so they don't pin manifests over each other
+static RUNNING: Mutex<()> = Mutex::const_new(());
+// bumped by every change made through the backend
+static CHANGES: AtomicU64 = AtomicU64::new(0);
+// and by every change Pinata told of, made elsewhere
+static UPSTREAM_CHANGES: AtomicU64 = AtomicU64::new(0);
+
+pub fn last_report() -> Option<IntegrityReport> {
+    STATE.read(|state| state.last_report.clone())
+}
+
+fn digest(mut cids: Vec<String>) -> GroupDigest {
+    cids.sort();
+    let mut hasher = Sha256::new();
+    for cid in &cids {
+        hasher.update(cid.as_bytes());
+        hasher.update(b"\n");
+    }
+    GroupDigest {
+        files: cids.len(),
+        cid_sha256: format!("{:x}", hasher.finalize()),
+    }
+}
+
+// what Pinata holds right now across the listed accounts, for the groups of this
+// deployment's namespace
+async fn current(state: &AppState) -> Result<IntegrityManifest, ApiError> {
+    let mut cids: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    let mut ungrouped = Vec::new();
+    for pinata in state.accounts.unscoped().listed() {
+        let (groups, files) = account_cids(pinata).await?;
+        cids.extend(groups);
+        ungrouped.extend(files);
+    }
+
+    Ok(IntegrityManifest {
+        version: MANIFEST_VERSION,
+        created_at: Utc::now(),
+        groups: cids
+            .into_iter()
+            .map(|(id, cids)| (id, digest(cids)))
+            .collect(),
+        ungrouped: digest(ungrouped),
+    })
+}
+
+// the CIDs of each of an account's groups, and of its files in no group
+async fn account_cids(
+    pinata: &PinataClient,
+) -> Result<(BTreeMap<String, Vec<String>>, Vec<String>), ApiError> {
+    let namespace = pinata.group_namespace();
+    let ours: HashSet<String> = groups::list_groups(pinata, ListOptions::default())
+        .await?
+        .into_iter()
+        .filter_map(|group| groups::in_namespace(namespace, group))
+        .map(|group| group.id)
+        .collect();
+    let listed = list_files(pinata, FilesQuery::new(), ListOptions::default()).await?;
+
+    let mut cids: BTreeMap<String, Vec<String>> =
+        ours.into_iter().map(|id| (id, Vec::new())).collect();
+    let mut ungrouped = Vec::new();
+    for file in listed {
+        if file.group_id.is_empty() {
+            ungrouped.push(file.cid);
+        } else if let Some(group) = cids.get_mut(&file.group_id) {
+            group.push(file.cid);
+        }
+    }
+    Ok((cids, ungrouped))
+}
+
+fn compare_digest(
+    group_id: Option<&str>,
+    expected: &GroupDigest,
+    found: &GroupDigest,
+) -> Option<IntegrityIssue> {
+    let group_id = group_id.map(str::to_string);
+    if expected.files != found.files {
+        Some(IntegrityIssue::FileCountChanged {
+            group_id,
+            expected_files: expected.files,
+            found_files: found.files,
+        })
+    } else if expected.cid_sha256 != found.cid_sha256 {
+        Some(IntegrityIssue::ChecksumMismatch {
+            group_id,
+            files: found.files,
+        })
+    } else {
+        None
+    }
+}
+
+// how `found` differs from the manifest; groups made since aren't an issue
+pub fn compare(expected: &IntegrityManifest, found: &IntegrityManifest) -> Vec<IntegrityIssue> {
+    let mut issues = Vec::new();
+    for (group_id, digest) in &expected.groups {
+        match found.groups.get(group_id) {
+            Some(found) => issues.extend(compare_digest(Some(group_id), digest, found)),
+            None => issues.push(IntegrityIssue::GroupMissing {
+                group_id: group_id.clone(),
+                expected_files: digest.files,
+            }),
+        }
+    }
+    issues.extend(compare_digest(None, &expected.ungrouped, &found.ungrouped));
+    issues
+}
+
+// Pin `manifest` as the known-good catalog and keep it locally, unpinning the
+// one it replaces. Returns its CID.
+async fn record(state: &AppState, manifest: IntegrityManifest) -> Result<String, ApiError> {
+    let created_at = manifest.created_at;
+    let keyvalues = HashMap::from([(INTEGRITY_MANIFEST.to_string(), created_at.to_rfc3339())]);
+    let name = format!(
+        "integrity-manifest-{}.json",
+        created_at.format("%Y%m%dT%H%M%SZ")
+    );
+    let pinned = files::pin_bytes(
+        &state.pinata,
+        Duration::from_secs(state.config.upload.default_timeout_secs),
+        &name,
+        "application/json",
+        serde_json::to_vec_pretty(&manifest)?,
+        &keyvalues,
+    )
+    .await?;
+
+    let replaced = STATE.update(|stored| {
+        stored.manifest = Some(manifest);
+        stored.cid = Some(pinned.cid.clone());
+        stored.file_id.replace(pinned.id.clone())
+    })?;
+    if let Some(replaced) = replaced
+        && let Err(e) = files::delete_file(&state.pinata, &replaced).await
+    {
+        warn!("Failed to unpin the previous integrity manifest {replaced}: {e}");
+    }
+    info!(cid = %pinned.cid, "Recorded the catalog's integrity manifest");
+    Ok(pinned.cid)
+}
+
+fn recorded_report(manifest: &IntegrityManifest, cid: String, message: &str) -> IntegrityReport {
+    IntegrityReport {
+        success: true,
+        diverged: false,
+        checked_at: manifest.created_at,
+        manifest_created_at: Some(manifest.created_at),
+        manifest_cid: Some(cid),
+        groups_checked: manifest.groups.len(),
+        files_checked: manifest.groups.values().map(|g| g.files).sum::<usize>()
+            + manifest.ungrouped.files,
+        issues: Vec::new(),
+        message: Some(message.to_string()),
+    }
+}
+
+fn keep_report(report: &IntegrityReport) {
+    if let Err(e) = STATE.update(|stored| stored.last_report = Some(report.clone())) {
+        warn!("Failed to keep the integrity report: {e}");
+    }
+}
+
+// Record what Pinata holds now, no longer out of date unless more changes came in
+// meanwhile. Called with RUNNING held.
+async fn refresh(state: &AppState, message: &str) -> Result<IntegrityReport, ApiError> {
+    let change = CHANGES.load(Ordering::SeqCst);
+    let manifest = current(state).await?;
+    let cid = record(state, manifest.clone()).await?;
+    if CHANGES.load(Ordering::SeqCst) == change {
+        STATE.update(|stored| {
+            stored.pending = false;
+            stored.upstream = false;
+        })?;
+    }
+    let report = recorded_report(&manifest, cid, message);
+    keep_report(&report);
+    Ok(report)
+}
+
+// Take what Pinata holds now as the known-good catalog, e.g. once a divergence
+// was looked into.
+pub async fn accept(state: &AppState) -> Result<IntegrityReport, ApiError> {
+    let _running = RUNNING.lock().await;
+    let report = refresh(state, "Recorded the catalog as it is now").await?;
+    STATE.update(|stored| stored.diverged = false)?;
+    Ok(report)
+}
+
+// Check what Pinata holds against the manifest, alerting WEBHOOK_NOTIFY_URL when
+// they've diverged. The first check records the manifest instead, and so does one
+// after changes this server made itself, unless a change from elsewhere came in
+// meanwhile or a divergence is still open.
+pub async fn verify(state: &AppState) -> Result<IntegrityReport, ApiError> {
+    let _running = RUNNING.lock().await;
+    let (expected, pending, held) = STATE.read(|stored| {
+        let held = stored.diverged || stored.upstream;
+        (stored.manifest.clone(), stored.pending, held)
+    });
+    let Some(expected) = expected else {
+        return refresh(state, "No manifest yet, recorded the catalog as it is now").await;
+    };
+    if pending && !held {
+        return refresh(
+            state,
+            "The catalog changed through the backend, recorded it again",
+        )
+        .await;
+    }
+    check(state, expected).await
+}
+
+// compare Pinata with `expected`, which stays the manifest whatever is found
+async fn check(state: &AppState, expected: IntegrityManifest) -> Result<IntegrityReport, ApiError> {
+    let (cid, file_id) = STATE.read(|stored| (stored.cid.clone(), stored.file_id.clone()));
+    let found = current(state).await?;
+
+    let mut issues = compare(&expected, &found);
+    if let (Some(file_id), Some(cid)) = (&file_id, &cid) {
+        match files::get_file(&state.pinata, file_id).await {
+            Ok(_) => {}
+            Err(ApiError::NotFound(_)) => {
+                issues.insert(0, IntegrityIssue::ManifestMissing { cid: cid.clone() })
+            }
+            Err(e) => warn!("Failed to look up the pinned integrity manifest: {e}"),
+        }
+    }
+
+    let diverged = !issues.is_empty();
+    let message = if diverged {
+        // kept until accepted, so the manifest isn't recorded over what diverged
+        STATE.update(|stored| stored.diverged = true)?;
+        counter!("catalog_integrity_divergences_total").increment(1);
+        warn!(
+            "The catalog diverged from its integrity manifest: {} issues",
+            issues.len()
+        );
+        state.notifier.send(
+            "catalog.integrity_diverged",
+            serde_json::json!({
+                "manifest_cid": cid,
+                "manifest_created_at": expected.created_at,
+                "issues": issues,
+            }),
+        );
+        Some(format!(
+            "{} integrity issues; POST /maintenance/integrity/accept once they're looked into",
+            issues.len()
+        ))
+    } else {
+        None
+    };
+
+    let report = IntegrityReport {
+        success: true,
+        diverged,
+        checked_at: found.created_at,
+        manifest_created_at: Some(expected.created_at),
+        manifest_cid: cid,
+        groups_checked: expected.groups.len(),
+        files_checked: found.groups.values().map(|g| g.files).sum::<usize>()
+            + found.ungrouped.files,
+        issues,
+        message,
+    };
+    keep_report(&report);
+    Ok(report)
+}
+
+// check the catalog once the server is up, when CATALOG_INTEGRITY_CHECK is on
+pub fn spawn_startup_check(state: &AppState) {
+    if !state.config.catalog.integrity_check {
+        return;
+    }
+    let state = state.clone();
+    tokio::spawn(
+        async move {
+            match verify(&state).await {
+                Ok(report) if report.diverged => {}
+                Ok(report) => info!(
+                    "Catalog matches its integrity manifest: {} groups, {} files",
+                    report.groups_checked, report.files_checked
+                ),
+                Err(e) => warn!("Failed to check the catalog's integrity: {e}"),
+            }
+        }
+        .instrument(info_span!("integrity_check")),
+    );
+}
+
+// The backend changed the catalog itself: record the manifest again once the
+// changes settle, so they aren't flagged as diverging. Marked on disk too, so a
+// restart before then doesn't flag them either.
+pub fn catalog_changed(state: &AppState) {
+    if !state.config.catalog.integrity_check {
+        return;
+    }
+    if !STATE.read(|stored| stored.pending)
+        && let Err(e) = STATE.update(|stored| stored.pending = true)
+    {
+        warn!("Failed to mark the integrity manifest as out of date: {e}");
+    }
+
+    let change = CHANGES.fetch_add(1, Ordering::SeqCst) + 1;
+    let state = state.clone();
+    tokio::spawn(
+        async move {
+            tokio::time::sleep(RECORD_DELAY).await;
+            if CHANGES.load(Ordering::SeqCst) != change {
+                return;
+            }
+            if let Err(e) = verify(&state).await {
+                warn!("Failed to record the integrity manifest: {e}");
+            }
+        }
+        .instrument(info_span!("integrity_record")),
+    );
+}
+
+// Pinata told of a change made elsewhere: check it against the manifest once the
+// changes settle, never taking it in. Own changes waiting to be recorded are
+// checked along with it, as they can't be told apart any more.
+pub fn upstream_changed(state: &AppState) {
+    if !state.config.catalog.integrity_check {
+        return;
+    }
+    if !STATE.read(|stored| stored.upstream)
+        && let Err(e) = STATE.update(|stored| stored.upstream = true)
+    {
+        warn!("Failed to mark a change from elsewhere: {e}");
+    }
+
+    let change = UPSTREAM_CHANGES.fetch_add(1, Ordering::SeqCst) + 1;
+    let state = state.clone();
+    tokio::spawn(
+        async move {
+            tokio::time::sleep(RECORD_DELAY).await;
+            if UPSTREAM_CHANGES.load(Ordering::SeqCst) != change {
+                return;
+            }
+            if let Err(e) = verify(&state).await {
+                warn!("Failed to check the catalog's integrity: {e}");
+            }
+        }
+        .instrument(info_span!("integrity_check")),
+    );
+}
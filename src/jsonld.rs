@@ -0,0 +1,136 @@
+use axum::{
+    http::{HeaderValue, header::CONTENT_TYPE},
+    response::{IntoResponse, Response},
+};
+use serde::Serialize;
+
+use crate::config::Config;
+use crate::errors::ApiError;
+use crate::models::jsonld::{ImageGallery, Person, Photograph, Place, PropertyValue, SCHEMA_ORG};
+use crate::models::{PinataFile, PinataGroup};
+use crate::pinata::{PinataClient, gateway};
+
+// a file's own credit, set on upload or through its metadata, beats the catalog's
+const CREATOR: &str = "creator";
+const LICENSE: &str = "license";
+const LOCATION: &str = "location";
+
+fn creator(config: &Config, file: Option<&PinataFile>) -> Option<Person> {
+    let catalog = &config.catalog;
+    let (name, url) = match file.and_then(|f| f.keyvalues.extra.get(CREATOR)) {
+        Some(name) => (name.clone(), None),
+        None => (catalog.creator.clone()?, catalog.creator_url.clone()),
+    };
+    Some(Person {
+        kind: "Person",
+        name,
+        url,
+    })
+}
+
+fn license(config: &Config, file: Option<&PinataFile>) -> Option<String> {
+    file.and_then(|f| f.keyvalues.extra.get(LICENSE).cloned())
+        .or_else(|| config.catalog.license.clone())
+}
+
+fn exif_data(file: &PinataFile) -> Vec<PropertyValue> {
+    let attributes = &file.keyvalues;
+    [
+        ("camera", attributes.gear.camera.clone()),
+        ("lens", attributes.gear.lens.clone()),
+        ("iso", attributes.exposure.iso.clone()),
+        ("aperture", attributes.exposure.aperture.clone()),
+        ("shutterSpeed", attributes.exposure.shutter_speed.clone()),
+        ("orientation", file.orientation.map(|o| o.to_string())),
+    ]
+    .into_iter()
+    .filter_map(|(name, value)| {
+        Some(PropertyValue {
+            kind: "PropertyValue",
+            name,
+            value: value?,
+        })
+    })
+    .collect()
+}
+
+// A file as a `Photograph`; `standalone` gives it the `@context` of a document of
+// its own rather than one of a gallery's images
+pub fn photograph(
+    config: &Config,
+    pinata: &PinataClient,
+    file: &PinataFile,
+    standalone: bool,
+) -> Photograph {
+    let urls = gateway::public_urls(pinata, &file.cid, config.upload.preview_thumbnail_width);
+    let page = config.frontend.file_url(&file.id);
+    let attributes = &file.keyvalues;
+
+    Photograph {
+        context: standalone.then_some(SCHEMA_ORG),
+        kind: "Photograph",
+        id: page.clone(),
+        url: page,
+        identifier: file.id.clone(),
+        name: file.name.clone(),
+        description: attributes.description.clone(),
+        genre: attributes.category.clone(),
+        keywords: attributes.tags.clone(),
+        content_url: urls.as_ref().map(|(original, _)| original.clone()),
+        thumbnail_url: urls.map(|(_, thumbnail)| thumbnail),
+        encoding_format: file.mime_type.clone(),
+        width: file.width,
+        height: file.height,
+        date_created: file.captured_at,
+        upload_date: file.created_at,
+        content_location: attributes.extra.get(LOCATION).map(|name| Place {
+            kind: "Place",
+            name: name.clone(),
+        }),
+        creator: creator(config, Some(file)),
+        license: license(config, Some(file)),
+        exif_data: exif_data(file),
+    }
+}
+
+// A group as an `ImageGallery` of `files`. Its thumbnail is the cover when it has
+// one among them, otherwise the newest photo.
+pub fn image_gallery(
+    config: &Config,
+    pinata: &PinataClient,
+    group: &PinataGroup,
+    files: &[PinataFile],
+    cover_id: Option<&str>,
+) -> ImageGallery {
+    let image: Vec<Photograph> = files
+        .iter()
+        .map(|file| photograph(config, pinata, file, false))
+        .collect();
+    let thumbnail_url = cover_id
+        .and_then(|id| image.iter().find(|photo| photo.identifier == id))
+        .or(image.first())
+        .and_then(|photo| photo.thumbnail_url.clone());
+    let page = config.frontend.group_url(&group.id);
+
+    ImageGallery {
+        context: SCHEMA_ORG,
+        kind: "ImageGallery",
+        id: page.clone(),
+        url: page,
+        identifier: group.id.clone(),
+        name: group.name.clone(),
+        date_created: group.created_at,
+        creator: creator(config, None),
+        license: license(config, None),
+        thumbnail_url,
+        number_of_items: image.len(),
+        image,
+    }
+}
+
+// a document served as `application/ld+json`, ready to go into a page's script tag
+pub fn response<T: Serialize>(document: &T) -> Result<Response, ApiError> {
+    let body = serde_json::to_vec(document)?;
+    let content_type = HeaderValue::from_static("application/ld+json");
+    Ok(([(CONTENT_TYPE, content_type)], body).into_response())
+}
@@ -0,0 +1,144 @@
+use axum::{
+    Router,
+    extract::DefaultBodyLimit,
+    middleware::{from_fn, from_fn_with_state},
+};
+
+pub mod activity;
+pub mod analysis;
+pub mod auth;
+pub mod cache;
+pub mod capture_sessions;
+pub mod chaos;
+pub mod coalesce;
+pub mod config;
+pub mod db;
+pub mod errors;
+pub mod exif;
+pub mod extractors;
+pub mod group_counts;
+pub mod group_covers;
+pub mod group_notifications;
+pub mod imaging;
+pub mod integrity;
+pub mod jsonld;
+pub mod logging;
+pub mod metrics;
+pub mod middleware;
+pub mod models;
+pub mod notify;
+pub mod partial_groups;
+pub mod pinata;
+pub mod processing;
+pub mod purge;
+pub mod reencode;
+pub mod routes;
+pub mod scan;
+pub mod shares;
+pub mod spool;
+pub mod state;
+pub mod store;
+pub mod telemetry;
+pub mod thumbnail_store;
+pub mod timezone;
+pub mod tls;
+pub mod upload_jobs;
+pub mod upload_pipeline;
+pub mod upload_sessions;
+pub mod virtual_albums;
+pub mod visitor_favourites;
+pub mod watermark;
+pub use crate::errors::ApiError;
+use crate::metrics::metrics_router;
+use crate::middleware::{
+    activity::track_requests,
+    auth::{api_key_scope, require_admin_for_writes},
+    cache::response_cache,
+    deprecation::deprecation_headers,
+    format::negotiate_format,
+    i18n::localize_errors,
+    rate_limit::rate_limit,
+    request_id::request_id,
+    versioning::negotiate_version,
+};
+pub use crate::models::pinata::PinataFile;
+use crate::routes::{
+    admin::admin_router,
+    auth::auth_router,
+    catalog::catalog_router,
+    categories::categories_router,
+    fallback::{method_not_allowed, not_found},
+    favourites::favourites_router,
+    files::files_router,
+    groups::groups_router,
+    health::health_router,
+    maintenance::maintenance_router,
+    shares::shares_router,
+    uploads::uploads_router,
+    versions::versions_router,
+    webhooks::webhooks_router,
+};
+pub use crate::state::AppState;
+
+// build the full application router, shared by main and the integration tests
+pub fn app(state: AppState) -> Router {
+    let body_limit = state.config.server.body_limit_bytes;
+    metrics::install();
+    auth::usage::install();
+
+    let router = Router::new()
+        .merge(groups_router())
+        .merge(favourites_router())
+        .merge(categories_router())
+        .merge(catalog_router())
+        .merge(uploads_router(
+            state.upload_queue.clone(),
+            state.config.upload.max_request_bytes,
+        ))
+        .merge(files_router())
+        .merge(maintenance_router())
+        .merge(metrics_router())
+        .merge(health_router())
+        .merge(versions_router())
+        .merge(webhooks_router())
+        .merge(admin_router())
+        .merge(auth_router())
+        .merge(shares_router(state.proxy_limits.clone()))
+        .fallback(not_found)
+        .method_not_allowed_fallback(method_not_allowed);
+
+    // fault injection is for local testing only and never compiled into release builds
+    #[cfg(debug_assertions)]
+    let router = match chaos::ChaosConfig::from_env() {
+        Some(config) => {
+            tracing::warn!("Chaos layer enabled: {config:?}");
+            router.layer(from_fn_with_state(chaos::Chaos::new(config), chaos::inject))
+        }
+        None => router,
+    };
+
+    let router = router
+        .layer(from_fn_with_state(state.clone(), response_cache))
+        // errors are never cached, and are translated before any transcoding
+        .layer(from_fn_with_state(state.clone(), localize_errors))
+        // outside the cache, which only ever holds JSON
+        .layer(from_fn(negotiate_format))
+        // after the key is checked, so a key's scope stands in for the admin token
+        .layer(from_fn(require_admin_for_writes))
+        // after the key is checked, so a key's budget follows it between addresses
+        .layer(from_fn_with_state(state.rate_limits.clone(), rate_limit))
+        .layer(from_fn_with_state(state.clone(), api_key_scope))
+        // every response a deprecated version serves says so, refusals included
+        .layer(from_fn_with_state(state.clone(), deprecation_headers))
+        .layer(DefaultBodyLimit::max(body_limit))
+        // every log line a request leads to carries its id
+        .layer(from_fn(request_id))
+        // outermost, so every response is counted until it's fully sent
+        .layer(from_fn(track_requests))
+        .with_state(state);
+
+    // `/api/v1/groups` is routed to `/groups`, so this has to run before the routing
+    Router::new()
+        .fallback_service(router)
+        .layer(from_fn(negotiate_version))
+}
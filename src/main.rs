@@ -1,56 +1,167 @@
-use axum::{
-    Router,
-    extract::DefaultBodyLimit,
-    http::{
-        HeaderValue,
-        header::{AUTHORIZATION, CONTENT_TYPE},
-    },
-};
-
-use http::header; // Use http header
+use http::{HeaderName, HeaderValue, header}; // Use http header
 use tower_http::cors::{Any, CorsLayer}; // Use http Method // Use http Method
 
-pub mod errors;
-pub mod models;
-pub mod routes;
-use crate::errors::ApiError;
-use crate::models::pinata::PinataFile;
-use crate::routes::{
-    categories::categories_router, favourites::favourites_router, groups::groups_router,
-    uploads::uploads_router,
-};
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use axum::serve::ListenerExt;
+use tokio::sync::watch;
+
+use esemese_backend::activity::{self, Activity};
+use esemese_backend::auth::{api_keys::API_KEY_HEADER, visitors::VISITOR_HEADER};
+use esemese_backend::middleware::versioning::API_VERSION_HEADER;
+use esemese_backend::tls::{self, TlsListener};
+use esemese_backend::{AppState, app, config::Config, logging, telemetry};
+use tracing::{error, info, warn};
 
 #[tokio::main]
 async fn main() {
-    // initialize tracking
-    tracing_subscriber::fmt::init();
+    // fail fast on missing or malformed configuration, before anything binds
+    let config = Config::load().unwrap_or_else(|e| {
+        eprintln!("{e}");
+        std::process::exit(1);
+    });
+    logging::init(
+        config.server.log_format,
+        &config.server.log_level,
+        telemetry::otlp_layer(&config.telemetry),
+    );
+
+    let state = AppState::new(config).await.unwrap_or_else(|e| {
+        error!("{e}");
+        std::process::exit(1);
+    });
+    let server = state.config.server.clone();
+    let tls_config = server.tls.as_ref().map(|config| {
+        let loaded = tls::server_config(config).unwrap_or_else(|e| {
+            error!("{e}");
+            std::process::exit(1);
+        });
+        (config.bind_address, loaded)
+    });
+    state.spawn_background_tasks();
 
-    // .allow_origin(["http://localhost:5173".parse().unwrap(), "https://your-production-domain.com".parse().unwrap()])
+    let origins: Vec<HeaderValue> = server
+        .cors_origins
+        .iter()
+        .map(|origin| origin.parse().expect("origins are validated at load"))
+        .collect();
 
     let cors_layer = CorsLayer::new()
-        .allow_origin(["http://localhost:5173".parse().unwrap()])
         .allow_methods(Any)
-        .allow_origin("http://localhost:5173".parse::<HeaderValue>().unwrap())
+        .allow_origin(origins)
         // .allow_credentials(true)
         .allow_headers([
             header::AUTHORIZATION,
             header::CONTENT_TYPE,
             header::ACCEPT,
             header::ORIGIN,
+            header::CONTENT_RANGE,
+            HeaderName::from_static(API_KEY_HEADER),
+            HeaderName::from_static(VISITOR_HEADER),
+            HeaderName::from_static(API_VERSION_HEADER),
         ]);
 
-    let app = Router::new()
-        .merge(groups_router())
-        .merge(favourites_router())
-        .merge(categories_router())
-        .merge(uploads_router())
-        .layer(DefaultBodyLimit::disable())
-        .layer(cors_layer);
+    let partial_groups = state.partial_groups.clone();
+    let app = app(state).layer(cors_layer);
+
+    let listener = tokio::net::TcpListener::bind(server.bind_address)
+        .await
+        .unwrap();
+    info!("Listening on {}", server.bind_address);
+    let tls_listener = match tls_config {
+        Some((address, config)) => {
+            let listener = TlsListener::bind(address, config).await.unwrap();
+            info!("Listening for HTTPS on {address}");
+            Some(listener)
+        }
+        None => None,
+    };
+
+    let (stop, stopping) = watch::channel(false);
+    tokio::spawn(async move {
+        shutdown_signal().await;
+        let _ = stop.send(true);
+    });
+
+    // server axum; the peer address keys the per-client proxy caps. Once told to stop
+    // it takes no new connections and waits for the open ones
+    let serve = axum::serve(
+        listener,
+        app.clone()
+            .into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .with_graceful_shutdown(stopped(stopping.clone()));
+    let tls_stopped = stopped(stopping.clone());
+    let serve_tls = async move {
+        let Some(listener) = tls_listener else {
+            return Ok(());
+        };
+        // tapping is what gives connections off a listener other than TcpListener
+        // their peer address as ConnectInfo
+        axum::serve(
+            listener.tap_io(|_| {}),
+            app.into_make_service_with_connect_info::<SocketAddr>(),
+        )
+        .with_graceful_shutdown(tls_stopped)
+        .await
+    };
+    let drained = async {
+        let (served, served_tls) = tokio::join!(async { serve.await }, serve_tls);
+        served.unwrap();
+        served_tls.unwrap();
+        // background upload jobs outlive the requests that started them
+        while activity::count(Activity::UploadJob) > 0 {
+            tokio::time::sleep(Duration::from_millis(250)).await;
+        }
+    };
+    let drain = Duration::from_secs(server.shutdown_drain_secs);
+    let given_up = async {
+        stopped(stopping).await;
+        info!("Shutting down, waiting up to {drain:?} for uploads in flight");
+        tokio::time::sleep(drain).await;
+    };
+    tokio::select! {
+        _ = drained => info!("Shut down cleanly"),
+        _ = given_up => warn!("Uploads still running after {drain:?}, cutting them short"),
+    }
 
-    // Define Ip and Port
-    let address: &'static str = "0.0.0.0:3000";
-    let listener = tokio::net::TcpListener::bind(address).await.unwrap();
+    // groups created by uploads that didn't get to finish go again, unless something
+    // was pinned into them
+    partial_groups.clean_up().await;
+}
+
+// SIGTERM, as sent on a deploy, or Ctrl-C
+async fn shutdown_signal() {
+    let interrupt = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            error!("Failed to listen for Ctrl-C: {e}");
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        use tokio::signal::unix::{SignalKind, signal};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                terminate.recv().await;
+            }
+            Err(e) => {
+                error!("Failed to listen for SIGTERM: {e}");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = interrupt => {}
+        _ = terminate => {}
+    }
+}
 
-    // server axum
-    axum::serve(listener, app).await.unwrap();
+async fn stopped(mut stopping: watch::Receiver<bool>) {
+    let _ = stopping.wait_for(|stop| *stop).await;
 }
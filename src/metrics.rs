@@ -0,0 +1,48 @@
+use std::sync::OnceLock;
+
+use axum::{Router, routing::get};
+
+use crate::state::AppState;
+use metrics_exporter_prometheus::{Matcher, PrometheusBuilder, PrometheusHandle};
+
+static HANDLE: OnceLock<PrometheusHandle> = OnceLock::new();
+
+// seconds, tuned for uploads that range from a quick thumbnail to a large RAW on slow Wi-Fi
+const DURATION_BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0,
+];
+const SIZE_BUCKETS: &[f64] = &[
+    100_000.0,
+    500_000.0,
+    1_000_000.0,
+    5_000_000.0,
+    10_000_000.0,
+    25_000_000.0,
+    50_000_000.0,
+    100_000_000.0,
+];
+
+// install the global recorder once; later calls reuse it
+pub fn install() -> &'static PrometheusHandle {
+    HANDLE.get_or_init(|| {
+        PrometheusBuilder::new()
+            .set_buckets_for_metric(
+                Matcher::Suffix("duration_seconds".to_string()),
+                DURATION_BUCKETS,
+            )
+            .and_then(|b| {
+                b.set_buckets_for_metric(Matcher::Suffix("bytes".to_string()), SIZE_BUCKETS)
+            })
+            .expect("metric buckets are non-empty")
+            .install_recorder()
+            .expect("failed to install metrics recorder")
+    })
+}
+
+pub fn metrics_router() -> Router<AppState> {
+    Router::new().route("/metrics", get(render_metrics))
+}
+
+async fn render_metrics() -> String {
+    install().render()
+}
@@ -0,0 +1,10 @@
+use axum::{extract::Request, middleware::Next, response::Response};
+
+use crate::activity::{Activity, Tracked, tracked_body};
+
+// count every request as in flight until its response has been sent in full
+pub async fn track_requests(request: Request, next: Next) -> Response {
+    let tracked = Tracked::start(Activity::Request);
+    let (parts, body) = next.run(request).await.into_parts();
+    Response::from_parts(parts, tracked_body(tracked, body))
+}
@@ -0,0 +1,234 @@
+use axum::{
+    extract::{MatchedPath, Request, State},
+    http::{HeaderValue, Method, StatusCode, header::RETRY_AFTER},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use crate::auth::roles::{Principal, Role};
+use crate::auth::{api_keys, siwe, usage};
+use crate::config;
+use crate::errors::{ErrorCode, error_response};
+use crate::models::{ApiKey, KeyScope};
+use crate::state::AppState;
+
+// The public catalog reads an API key may be used for. Everything else, such as
+// maintenance, metrics, upload jobs and file analysis, stays out of a key's reach.
+const API_KEY_ROUTES: &[&str] = &[
+    "/groups",
+    "/groups-with-thumbnails",
+    "/favourites",
+    "/group-images",
+    "/files-category",
+    "/categories",
+    "/categories/taxonomy",
+    "/catalog/full",
+    "/catalog/changed",
+    "/groups/{id}/jsonld",
+    "/files/{id}/jsonld",
+    "/api/versions",
+    "/status",
+];
+
+// Uploads and the jobs and sessions they run as, open to upload keys on top of the
+// catalog reads
+const UPLOAD_ROUTES: &[&str] = &[
+    "/upload",
+    "/upload/jobs/{id}",
+    "/upload/jobs/{id}/events",
+    "/upload/sessions",
+    "/upload/sessions/{id}",
+    "/sessions",
+    "/sessions/{id}",
+    "/sessions/{id}/photo",
+    "/sessions/{id}/close",
+];
+
+// Reads that are the owner's alone: maintenance reports, metrics and file analysis
+const ADMIN_READ_ROUTES: &[&str] = &[
+    "/maintenance/consistency",
+    "/maintenance/duplicates",
+    "/maintenance/reencode",
+    "/maintenance/integrity",
+    "/metrics",
+    "/files/{id}/analysis",
+];
+
+// Writes anyone may make: Pinata's signed deliveries and a visitor's own favourites.
+// Every other write changes the catalog or spends the Pinata quota, so it takes the
+// admin token.
+const PUBLIC_WRITE_ROUTES: &[&str] = &[
+    "/webhooks/pinata",
+    "/me/favourites/{file_id}",
+    "/auth/nonce",
+    "/auth/verify",
+];
+
+fn principal(request: &Request) -> Principal {
+    Principal::of(request.headers(), request.extensions())
+}
+
+fn matched_route(request: &Request) -> Option<&str> {
+    request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(MatchedPath::as_str)
+}
+
+// Why a request is refused the owner's routes, if it is: only admins get through,
+// by the admin token, a session from signing in with Ethereum or an admin key
+fn admin_refusal(request: &Request) -> Option<Response> {
+    if principal(request).role == Role::Admin {
+        return None;
+    }
+    if config::auth().admin_token.is_none() && !siwe::is_enabled() {
+        return Some(error_response(
+            StatusCode::FORBIDDEN,
+            ErrorCode::FeatureDisabled,
+            "Admin disabled",
+            "Set ADMIN_TOKEN, or OWNER_ADDRESS and SESSION_SECRET, to enable admin endpoints and writes"
+                .to_string(),
+        ));
+    }
+
+    Some(error_response(
+        StatusCode::UNAUTHORIZED,
+        ErrorCode::Unauthorized,
+        "Unauthorized",
+        "A valid admin or session token is required".to_string(),
+    ))
+}
+
+// owner-only routes: `Authorization: Bearer <ADMIN_TOKEN or session token>`
+pub async fn require_admin(request: Request, next: Next) -> Response {
+    match admin_refusal(&request) {
+        Some(refusal) => refusal,
+        None => next.run(request).await,
+    }
+}
+
+// Uploads, deletes and group and category management take the admin token, and so
+// do the owner's reads; the catalog reads stay public. Checked before an upload waits
+// for a queue slot or sends its body. Uploaders may use the upload routes, reads of
+// their jobs and sessions included, which `api_key_scope` held their keys to.
+pub async fn require_admin_for_writes(request: Request, next: Next) -> Response {
+    if matches!(
+        *request.method(),
+        Method::GET | Method::HEAD | Method::OPTIONS
+    ) {
+        let route = matched_route(&request);
+        let refused = route.is_some_and(|route| {
+            ADMIN_READ_ROUTES.contains(&route)
+                || principal(&request).role < Role::Uploader && UPLOAD_ROUTES.contains(&route)
+        });
+        if refused && let Some(refusal) = admin_refusal(&request) {
+            return refusal;
+        }
+        return next.run(request).await;
+    }
+
+    // unmatched requests carry on to the 404 fallback
+    let open = matched_route(&request).is_none_or(|route| {
+        PUBLIC_WRITE_ROUTES.contains(&route)
+            || principal(&request).role >= Role::Uploader && UPLOAD_ROUTES.contains(&route)
+    });
+    if open {
+        return next.run(request).await;
+    }
+    match admin_refusal(&request) {
+        Some(refusal) => refusal,
+        None => next.run(request).await,
+    }
+}
+
+// Why a key is refused a request, if it is: read-only keys only read the public
+// catalog, upload keys may also upload, admin keys go wherever the admin token does.
+fn scope_refusal(key: &ApiKey, method: &Method, route: Option<&str>) -> Option<Response> {
+    let read = matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS);
+    // unmatched requests carry on to the 404 fallback
+    let catalog_read = route.is_none_or(|route| API_KEY_ROUTES.contains(&route));
+    let upload = route.is_some_and(|route| UPLOAD_ROUTES.contains(&route));
+    match key.scope {
+        KeyScope::Admin => None,
+        KeyScope::ReadOnly if !read => Some(error_response(
+            StatusCode::FORBIDDEN,
+            ErrorCode::Forbidden,
+            "Read-only API key",
+            format!("API key {} can only be used for read requests", key.id),
+        )),
+        KeyScope::ReadOnly if !catalog_read => Some(error_response(
+            StatusCode::FORBIDDEN,
+            ErrorCode::Forbidden,
+            "Route not available to API keys",
+            format!("API key {} can only be used for catalog reads", key.id),
+        )),
+        KeyScope::Upload if !(upload || read && catalog_read) => Some(error_response(
+            StatusCode::FORBIDDEN,
+            ErrorCode::Forbidden,
+            "Route not available to API keys",
+            format!(
+                "API key {} can only be used for catalog reads and uploads",
+                key.id
+            ),
+        )),
+        _ => None,
+    }
+}
+
+// Requests carrying an `x-api-key` must present an active key, used within its
+// scope. Each request and the bytes it's served are charged to the key, subject to
+// its per-minute limit. The key is attached to the request for downstream use, and
+// the admin checks after this one. Requests without a key are left alone, public
+// reads stay public. Without DATABASE_URL no key has been issued, so none is valid.
+pub async fn api_key_scope(
+    State(state): State<AppState>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let Some(secret) = request.headers().get(api_keys::API_KEY_HEADER) else {
+        return next.run(request).await;
+    };
+
+    let key = match (&state.db, secret.to_str()) {
+        (Some(db), Ok(secret)) => match api_keys::authenticate(db, secret).await {
+            Ok(key) => key,
+            Err(e) => return e.into_response(),
+        },
+        _ => None,
+    };
+    let Some(key) = key else {
+        return error_response(
+            StatusCode::UNAUTHORIZED,
+            ErrorCode::Unauthorized,
+            "Invalid API key",
+            "The API key is unknown or has been revoked".to_string(),
+        );
+    };
+
+    if let Some(refusal) = scope_refusal(&key, request.method(), matched_route(&request)) {
+        return refusal;
+    }
+
+    if let Err(retry_after) = usage::admit(&key.id, key.rate_limit_per_minute) {
+        let secs = retry_after.as_secs().max(1);
+        let mut response = error_response(
+            StatusCode::TOO_MANY_REQUESTS,
+            ErrorCode::RateLimited,
+            "Rate limit exceeded",
+            format!(
+                "API key {} is over its per-minute limit, retry in {secs}s",
+                key.id
+            ),
+        );
+        response
+            .headers_mut()
+            .insert(RETRY_AFTER, HeaderValue::from(secs));
+        return response;
+    }
+
+    let key_id = key.id.clone();
+    request.extensions_mut().insert(key);
+    next.run(request)
+        .await
+        .map(|body| usage::metered(key_id, body))
+}
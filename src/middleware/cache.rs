@@ -0,0 +1,130 @@
+use axum::{
+    body::{Body, HttpBody, to_bytes},
+    extract::{Request, State},
+    http::{HeaderValue, Method, StatusCode, header::CONTENT_TYPE},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use crate::cache::{CachedResponse, LastGood};
+use crate::state::AppState;
+
+// listing endpoints whose responses only change when the catalog does
+const CACHED_PATHS: &[&str] = &[
+    "/groups",
+    "/groups-with-thumbnails",
+    "/files-category",
+    "/group-images",
+    "/favourites",
+];
+
+// listings larger than this are served but not cached
+const MAX_CACHED_BODY_BYTES: usize = 16 * 1024 * 1024;
+
+// path plus query pairs in a stable order, so `?a=1&b=2` and `?b=2&a=1` share an entry
+fn cache_key(request: &Request) -> String {
+    let uri = request.uri();
+    let mut pairs: Vec<&str> = uri
+        .query()
+        .unwrap_or_default()
+        .split('&')
+        .filter(|p| !p.is_empty())
+        .collect();
+    pairs.sort_unstable();
+
+    format!("{}?{}", uri.path(), pairs.join("&"))
+}
+
+fn with_cache_status(mut response: Response, status: &'static str) -> Response {
+    response
+        .headers_mut()
+        .insert("x-cache", HeaderValue::from_static(status));
+    response
+}
+
+// whether a failure is Pinata's rather than the request's, so an older answer beats it
+pub fn is_upstream_failure(status: StatusCode) -> bool {
+    matches!(
+        status,
+        StatusCode::BAD_GATEWAY | StatusCode::SERVICE_UNAVAILABLE | StatusCode::GATEWAY_TIMEOUT
+    )
+}
+
+// the last good answer for `key` marked stale, when there is one
+async fn stale_response(last_good: &LastGood, key: &str) -> Option<Response> {
+    let body = last_good.load(key).await?.marked_stale()?;
+    let response = (
+        [(CONTENT_TYPE, HeaderValue::from_static("application/json"))],
+        body,
+    )
+        .into_response();
+    Some(with_cache_status(response, "stale"))
+}
+
+// serve repeated listing requests from `AppState::cache`, recording successful responses,
+// and fall back to the last good one when Pinata fails
+pub async fn response_cache(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if !(state.cache.is_enabled() || state.last_good.is_enabled())
+        || request.method() != Method::GET
+        || !CACHED_PATHS.contains(&request.uri().path())
+    {
+        return next.run(request).await;
+    }
+
+    let key = cache_key(&request);
+    if let Some(cached) = state.cache.get(&key).await {
+        let mut response = cached.body.into_response();
+        if let Some(content_type) = cached.content_type {
+            response.headers_mut().insert(CONTENT_TYPE, content_type);
+        }
+        return with_cache_status(response, "hit");
+    }
+
+    let response = next.run(request).await;
+    if is_upstream_failure(response.status())
+        && let Some(stale) = stale_response(&state.last_good, &key).await
+    {
+        return stale;
+    }
+    if response.status() != StatusCode::OK {
+        return response;
+    }
+
+    let (parts, body) = response.into_parts();
+    // too large to cache, or of unknown length: served as is, uncached
+    if body
+        .size_hint()
+        .upper()
+        .is_none_or(|upper| upper > MAX_CACHED_BODY_BYTES as u64)
+    {
+        return with_cache_status(Response::from_parts(parts, body), "bypass");
+    }
+    let Ok(body) = to_bytes(body, MAX_CACHED_BODY_BYTES).await else {
+        return with_cache_status(
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to buffer response",
+            )
+                .into_response(),
+            "miss",
+        );
+    };
+
+    state.last_good.record(&key, &body).await;
+    state
+        .cache
+        .insert(
+            key,
+            CachedResponse {
+                content_type: parts.headers.get(CONTENT_TYPE).cloned(),
+                body: body.clone(),
+            },
+        )
+        .await;
+
+    with_cache_status(Response::from_parts(parts, Body::from(body)), "miss")
+}
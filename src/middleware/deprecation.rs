@@ -0,0 +1,49 @@
+use axum::{
+    extract::{Request, State},
+    http::{HeaderName, HeaderValue, header::LINK},
+    middleware::Next,
+    response::Response,
+};
+use metrics::counter;
+
+use crate::routes::versions::version_of;
+use crate::state::AppState;
+
+static DEPRECATION: HeaderName = HeaderName::from_static("deprecation");
+static SUNSET: HeaderName = HeaderName::from_static("sunset");
+
+// Announce a deprecated version on every response it serves: `Deprecation` (RFC 9745)
+// with the date it was deprecated, `Sunset` (RFC 8594) with when it may stop working,
+// and a `successor-version` link when there's somewhere to move to.
+pub async fn deprecation_headers(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let version = version_of(request.uri().path());
+    let mut response = next.run(request).await;
+    let Some(deprecation) = state.config.api.deprecations.get(version) else {
+        return response;
+    };
+    // so it's visible who still calls a version before it goes
+    counter!("api_deprecated_requests_total", "version" => version).increment(1);
+
+    let headers = response.headers_mut();
+    let deprecated_at = format!("@{}", deprecation.deprecated_at.timestamp());
+    if let Ok(value) = HeaderValue::from_str(&deprecated_at) {
+        headers.insert(DEPRECATION.clone(), value);
+    }
+    if let Some(sunset) = deprecation.sunset_at {
+        let http_date = sunset.format("%a, %d %b %Y %H:%M:%S GMT").to_string();
+        if let Ok(value) = HeaderValue::from_str(&http_date) {
+            headers.insert(SUNSET.clone(), value);
+        }
+    }
+    if let Some(successor) = &deprecation.successor
+        && let Ok(value) =
+            HeaderValue::from_str(&format!("<{successor}>; rel=\"successor-version\""))
+    {
+        headers.append(LINK, value);
+    }
+    response
+}
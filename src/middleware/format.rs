@@ -0,0 +1,151 @@
+use axum::{
+    body::{Body, HttpBody, to_bytes},
+    extract::Request,
+    http::{
+        HeaderMap, HeaderValue, StatusCode,
+        header::{ACCEPT, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_TYPE, VARY},
+    },
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use metrics::counter;
+use serde_json::Value;
+use tracing::warn;
+
+// JSON bodies larger than this, or of unknown length, are sent as JSON rather than
+// buffered for transcoding
+const MAX_TRANSCODED_BODY_BYTES: usize = 64 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Json,
+    MessagePack,
+    Cbor,
+}
+
+impl Format {
+    fn from_media_type(media_type: &str) -> Option<Self> {
+        match media_type {
+            "application/json" | "application/*" | "*/*" => Some(Self::Json),
+            "application/msgpack" | "application/x-msgpack" | "application/vnd.msgpack" => {
+                Some(Self::MessagePack)
+            }
+            "application/cbor" => Some(Self::Cbor),
+            _ => None,
+        }
+    }
+
+    fn content_type(self) -> &'static str {
+        match self {
+            Self::Json => "application/json",
+            Self::MessagePack => "application/msgpack",
+            Self::Cbor => "application/cbor",
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Self::Json => "json",
+            Self::MessagePack => "msgpack",
+            Self::Cbor => "cbor",
+        }
+    }
+
+    fn encode(self, value: &Value) -> Result<Vec<u8>, String> {
+        match self {
+            Self::Json => serde_json::to_vec(value).map_err(|e| e.to_string()),
+            // named fields, so clients decode maps just like the JSON objects
+            Self::MessagePack => rmp_serde::to_vec_named(value).map_err(|e| e.to_string()),
+            Self::Cbor => {
+                let mut out = Vec::new();
+                ciborium::into_writer(value, &mut out).map_err(|e| e.to_string())?;
+                Ok(out)
+            }
+        }
+    }
+}
+
+// the supported format the client prefers most, by q-value and then by order;
+// no Accept header, or nothing we support, means JSON
+fn negotiate(headers: &HeaderMap) -> Format {
+    let mut best = (Format::Json, 0.0_f32);
+    for accept in headers.get_all(ACCEPT) {
+        let Ok(accept) = accept.to_str() else {
+            continue;
+        };
+        for entry in accept.split(',') {
+            let mut params = entry.split(';').map(str::trim);
+            let Some(format) = params
+                .next()
+                .and_then(|media_type| Format::from_media_type(&media_type.to_lowercase()))
+            else {
+                continue;
+            };
+            let quality = params
+                .find_map(|p| p.strip_prefix("q="))
+                .and_then(|q| q.parse().ok())
+                .unwrap_or(1.0);
+            if quality > best.1 {
+                best = (format, quality);
+            }
+        }
+    }
+    best.0
+}
+
+// Re-encode JSON responses as MessagePack or CBOR when the client asks for them
+// in `Accept`, for clients that would rather not parse JSON
+pub async fn negotiate_format(request: Request, next: Next) -> Response {
+    let format = negotiate(request.headers());
+    let mut response = next.run(request).await;
+    response
+        .headers_mut()
+        .append(VARY, HeaderValue::from_static("accept"));
+
+    let is_json = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("application/json"));
+    // compressed bodies can't be parsed, and are already small
+    let is_encoded = response.headers().contains_key(CONTENT_ENCODING);
+    if format == Format::Json || !is_json || is_encoded {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    // decided before reading any of it, so a large body still goes out whole
+    if body
+        .size_hint()
+        .upper()
+        .is_none_or(|upper| upper > MAX_TRANSCODED_BODY_BYTES as u64)
+    {
+        counter!("response_format_passthrough_total").increment(1);
+        return Response::from_parts(parts, body);
+    }
+    let encoded = match to_bytes(body, MAX_TRANSCODED_BODY_BYTES).await {
+        Ok(bytes) => serde_json::from_slice::<Value>(&bytes)
+            .map_err(|e| e.to_string())
+            .and_then(|value| format.encode(&value)),
+        Err(e) => Err(e.to_string()),
+    };
+    let encoded = match encoded {
+        Ok(encoded) => encoded,
+        Err(e) => {
+            warn!("Failed to encode response as {}: {e}", format.label());
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to encode response",
+            )
+                .into_response();
+        }
+    };
+
+    counter!("response_format_total", "format" => format.label()).increment(1);
+    parts.headers.remove(CONTENT_LENGTH);
+    parts.headers.insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static(format.content_type()),
+    );
+    Response::from_parts(parts, Body::from(encoded))
+}
@@ -0,0 +1,119 @@
+use axum::{
+    body::{Body, HttpBody, to_bytes},
+    extract::{Request, State},
+    http::{
+        HeaderMap, HeaderValue,
+        header::{ACCEPT_LANGUAGE, CONTENT_LANGUAGE, CONTENT_LENGTH, CONTENT_TYPE, VARY},
+    },
+    middleware::Next,
+    response::Response,
+};
+use serde_json::Value;
+
+use crate::config::I18nConfig;
+use crate::errors::PROBLEM_JSON;
+use crate::state::AppState;
+
+// error envelopes are small; anything larger isn't one and goes out untouched
+const MAX_ERROR_BODY_BYTES: u64 = 64 * 1024;
+
+// The configured language the client prefers most, by q-value and then by order. A
+// regional tag falls back to its language, `fr-CA` to `fr`; None means English.
+fn negotiate<'a>(config: &'a I18nConfig, headers: &HeaderMap) -> Option<&'a str> {
+    let mut preferences: Vec<(String, f32)> = headers
+        .get_all(ACCEPT_LANGUAGE)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|accept| accept.split(','))
+        .filter_map(|entry| {
+            let mut params = entry.split(';').map(str::trim);
+            let tag = params.next().filter(|t| !t.is_empty())?.to_lowercase();
+            let quality = params
+                .find_map(|p| p.strip_prefix("q="))
+                .and_then(|q| q.parse().ok())
+                .unwrap_or(1.0);
+            (quality > 0.0).then_some((tag, quality))
+        })
+        .collect();
+    // stable, so equal q-values keep the client's order
+    preferences.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+    for (tag, _) in &preferences {
+        let primary = tag.split('-').next().unwrap_or_default();
+        // English is what the messages are written in
+        if primary == "en" {
+            return None;
+        }
+        if let Some((language, _)) = config
+            .messages
+            .get_key_value(tag)
+            .or_else(|| config.messages.get_key_value(primary))
+        {
+            return Some(language);
+        }
+    }
+    None
+}
+
+// Replace the `message` of error responses with its translation for the client's
+// `Accept-Language`. The English message is kept as `detail`, since a translation
+// is per kind of error and can't carry the specifics.
+pub async fn localize_errors(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let config = &state.config.i18n;
+    let language = negotiate(config, request.headers()).map(str::to_string);
+    let mut response = next.run(request).await;
+    if !config.messages.is_empty() {
+        response
+            .headers_mut()
+            .append(VARY, HeaderValue::from_static("accept-language"));
+    }
+
+    let is_json = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("application/json") || v.starts_with(PROBLEM_JSON));
+    let is_error = response.status().is_client_error() || response.status().is_server_error();
+    let Some(language) = language.filter(|_| is_error && is_json) else {
+        return response;
+    };
+
+    let (mut parts, body) = response.into_parts();
+    if body
+        .size_hint()
+        .upper()
+        .is_none_or(|upper| upper > MAX_ERROR_BODY_BYTES)
+    {
+        return Response::from_parts(parts, body);
+    }
+    let Ok(bytes) = to_bytes(body, MAX_ERROR_BODY_BYTES as usize).await else {
+        // the body failed part way, so there's nothing left to send but the status
+        return Response::from_parts(parts, Body::empty());
+    };
+
+    let translations = &config.messages[&language];
+    let mut envelope: Value = match serde_json::from_slice(&bytes) {
+        Ok(envelope) => envelope,
+        Err(_) => return Response::from_parts(parts, Body::from(bytes)),
+    };
+    let translation = envelope
+        .get("error")
+        .and_then(Value::as_str)
+        .and_then(|error| translations.get(error));
+    let (Some(translation), Some(object)) = (translation.cloned(), envelope.as_object_mut()) else {
+        return Response::from_parts(parts, Body::from(bytes));
+    };
+
+    if let Some(message) = object.insert("message".to_string(), translation.into()) {
+        object.insert("detail".to_string(), message);
+    }
+    parts.headers.remove(CONTENT_LENGTH);
+    if let Ok(value) = HeaderValue::from_str(&language) {
+        parts.headers.insert(CONTENT_LANGUAGE, value);
+    }
+    Response::from_parts(parts, Body::from(envelope.to_string()))
+}
@@ -0,0 +1,11 @@
+pub mod activity;
+pub mod auth;
+pub mod cache;
+pub mod deprecation;
+pub mod format;
+pub mod i18n;
+pub mod proxy_limits;
+pub mod rate_limit;
+pub mod request_id;
+pub mod upload_queue;
+pub mod versioning;
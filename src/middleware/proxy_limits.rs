@@ -0,0 +1,231 @@
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use axum::{
+    body::Body,
+    extract::{ConnectInfo, Request, State},
+    http::{HeaderValue, StatusCode, header::RETRY_AFTER},
+    middleware::Next,
+    response::Response,
+};
+use chrono::{DateTime, Utc};
+use metrics::{counter, gauge};
+use tokio::sync::mpsc;
+use tokio_stream::{StreamExt, wrappers::ReceiverStream};
+
+use crate::config::ProxyConfig;
+use crate::errors::{ErrorCode, error_response};
+use crate::models::shares::LimitedClient;
+
+// stale clients are only pruned once this many are tracked
+const PRUNE_THRESHOLD: usize = 10_000;
+
+// the peer address, or the first `X-Forwarded-For` hop when the proxy in front is trusted
+pub fn client_ip(request: &Request, trust_forwarded_for: bool) -> Option<IpAddr> {
+    if trust_forwarded_for
+        && let Some(ip) = request
+            .headers()
+            .get("x-forwarded-for")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.split(',').next())
+            .and_then(|ip| ip.trim().parse().ok())
+    {
+        return Some(ip);
+    }
+
+    request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip())
+}
+
+#[derive(Debug)]
+struct Client {
+    streams: usize,
+    // bandwidth token bucket, in bytes, holding at most one second's worth
+    tokens: f64,
+    refilled_at: Instant,
+    // times this client hit each cap; kept here rather than as a metric label,
+    // which would grow a series per address
+    limited_streams: u64,
+    limited_bandwidth: u64,
+    last_limited_at: Option<DateTime<Utc>>,
+}
+
+// Per-IP caps for routes that stream files through the backend: how many
+// responses a client may have open at once, and how fast they're sent to it.
+#[derive(Clone)]
+pub struct ProxyLimits {
+    config: ProxyConfig,
+    trust_forwarded_for: bool,
+    clients: Arc<Mutex<HashMap<IpAddr, Client>>>,
+}
+
+impl ProxyLimits {
+    pub fn new(config: ProxyConfig, trust_forwarded_for: bool) -> Self {
+        Self {
+            config,
+            trust_forwarded_for,
+            clients: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    fn capacity(&self) -> f64 {
+        self.config.bytes_per_sec_per_ip as f64
+    }
+
+    // claim a stream slot for `ip`, or None when it already has its share open
+    fn open(&self, ip: IpAddr) -> Option<Stream> {
+        let mut clients = self.clients.lock().unwrap();
+        if clients.len() > PRUNE_THRESHOLD {
+            clients.retain(|_, client| client.streams > 0);
+        }
+
+        let client = clients.entry(ip).or_insert_with(|| Client {
+            streams: 0,
+            tokens: self.capacity(),
+            refilled_at: Instant::now(),
+            limited_streams: 0,
+            limited_bandwidth: 0,
+            last_limited_at: None,
+        });
+        if self.config.max_streams_per_ip > 0 && client.streams >= self.config.max_streams_per_ip {
+            client.limited_streams += 1;
+            client.last_limited_at = Some(Utc::now());
+            return None;
+        }
+
+        client.streams += 1;
+        gauge!("proxy_active_streams").increment(1.0);
+        Some(Stream {
+            limits: self.clone(),
+            ip,
+        })
+    }
+
+    // how long `ip` has to wait before `bytes` more may be sent
+    fn take(&self, ip: IpAddr, bytes: usize) -> Duration {
+        let rate = self.capacity();
+        if rate == 0.0 {
+            return Duration::ZERO;
+        }
+        let mut clients = self.clients.lock().unwrap();
+        let Some(client) = clients.get_mut(&ip) else {
+            return Duration::ZERO;
+        };
+
+        let now = Instant::now();
+        let elapsed = now.duration_since(client.refilled_at).as_secs_f64();
+        client.tokens = (client.tokens + elapsed * rate).min(rate) - bytes as f64;
+        client.refilled_at = now;
+
+        if client.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-client.tokens / rate)
+        }
+    }
+
+    fn throttled(&self, ip: IpAddr) {
+        if let Some(client) = self.clients.lock().unwrap().get_mut(&ip) {
+            client.limited_bandwidth += 1;
+            client.last_limited_at = Some(Utc::now());
+        }
+    }
+
+    // the `n` tracked clients limited most often, busiest first
+    pub fn most_limited(&self, n: usize) -> Vec<LimitedClient> {
+        let clients = self.clients.lock().unwrap();
+        let mut limited: Vec<LimitedClient> = clients
+            .iter()
+            .filter_map(|(ip, client)| {
+                Some(LimitedClient {
+                    ip: ip.to_string(),
+                    open_streams: client.streams,
+                    limited_streams: client.limited_streams,
+                    limited_bandwidth: client.limited_bandwidth,
+                    last_limited_at: client.last_limited_at?,
+                })
+            })
+            .collect();
+        limited.sort_by_key(|c| std::cmp::Reverse(c.limited_streams + c.limited_bandwidth));
+        limited.truncate(n);
+        limited
+    }
+}
+
+// an open response; the client's slot is released when it's dropped
+struct Stream {
+    limits: ProxyLimits,
+    ip: IpAddr,
+}
+
+impl Drop for Stream {
+    fn drop(&mut self) {
+        let mut clients = self.limits.clients.lock().unwrap();
+        if let Some(client) = clients.get_mut(&self.ip) {
+            client.streams -= 1;
+        }
+        gauge!("proxy_active_streams").decrement(1.0);
+    }
+}
+
+fn record_limited(reason: &'static str) {
+    counter!("proxy_limited_total", "reason" => reason).increment(1);
+}
+
+pub async fn proxy_limits(
+    State(limits): State<ProxyLimits>,
+    request: Request,
+    next: Next,
+) -> Response {
+    // without an address there's nothing to key the caps on
+    let Some(ip) = client_ip(&request, limits.trust_forwarded_for) else {
+        return next.run(request).await;
+    };
+
+    let Some(stream) = limits.open(ip) else {
+        record_limited("streams");
+        let mut response = error_response(
+            StatusCode::TOO_MANY_REQUESTS,
+            ErrorCode::RateLimited,
+            "Too many downloads",
+            format!(
+                "At most {} downloads may run at once from one address",
+                limits.config.max_streams_per_ip
+            ),
+        );
+        response
+            .headers_mut()
+            .insert(RETRY_AFTER, HeaderValue::from_static("1"));
+        return response;
+    };
+
+    // pace the body so one client can't take the whole gateway budget; the
+    // slot is held until the last byte is sent
+    let (parts, body) = next.run(request).await.into_parts();
+    let mut body = body.into_data_stream();
+    let (tx, rx) = mpsc::channel(1);
+    tokio::spawn(async move {
+        let mut throttled = false;
+        while let Some(chunk) = body.next().await {
+            if let Ok(bytes) = &chunk {
+                let wait = stream.limits.take(stream.ip, bytes.len());
+                if !wait.is_zero() {
+                    if !throttled {
+                        record_limited("bandwidth");
+                        stream.limits.throttled(stream.ip);
+                        throttled = true;
+                    }
+                    tokio::time::sleep(wait).await;
+                }
+            }
+            if tx.send(chunk).await.is_err() {
+                break;
+            }
+        }
+    });
+    Response::from_parts(parts, Body::from_stream(ReceiverStream::new(rx)))
+}
@@ -0,0 +1,143 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use axum::{
+    extract::{MatchedPath, Request, State},
+    http::{HeaderValue, StatusCode, header::RETRY_AFTER},
+    middleware::Next,
+    response::Response,
+};
+use metrics::counter;
+
+use crate::auth::roles::{Principal, Role};
+use crate::config::RateLimitConfig;
+use crate::errors::{ErrorCode, error_response};
+use crate::middleware::proxy_limits::client_ip;
+use crate::models::ApiKey;
+
+// idle buckets are only pruned once this many are tracked
+const PRUNE_THRESHOLD: usize = 10_000;
+
+// who a budget is counted against: a key wherever it's used from, else the address
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Caller {
+    Key(String),
+    Ip(IpAddr),
+}
+
+// a caller's bucket for a matched route, None for requests no route matched
+type BucketKey = (Caller, Option<String>);
+
+// a minute's worth of requests at most, refilled continuously
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    capacity: f64,
+    refilled_at: Instant,
+}
+
+impl Bucket {
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.duration_since(self.refilled_at).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.capacity / 60.0).min(self.capacity);
+        self.refilled_at = now;
+    }
+}
+
+// Per-route request budgets, so one scraper can't spend the Pinata rate limit the
+// public lists fan out into. The owner is never limited.
+#[derive(Clone)]
+pub struct RateLimits {
+    config: RateLimitConfig,
+    trust_forwarded_for: bool,
+    buckets: Arc<Mutex<HashMap<BucketKey, Bucket>>>,
+}
+
+impl RateLimits {
+    pub fn new(config: RateLimitConfig, trust_forwarded_for: bool) -> Self {
+        Self {
+            config,
+            trust_forwarded_for,
+            buckets: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    // take a request from the caller's budget for `route`, or return how long until
+    // it may retry
+    fn admit(&self, caller: Caller, route: Option<&str>, budget: u32) -> Result<(), Duration> {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() > PRUNE_THRESHOLD {
+            // a full bucket is the same as none
+            buckets.retain(|_, bucket| {
+                bucket.refill(now);
+                bucket.tokens < bucket.capacity
+            });
+        }
+
+        let capacity = budget as f64;
+        let bucket = buckets
+            .entry((caller, route.map(str::to_string)))
+            .or_insert_with(|| Bucket {
+                tokens: capacity,
+                capacity,
+                refilled_at: now,
+            });
+        bucket.refill(now);
+        if bucket.tokens < 1.0 {
+            return Err(Duration::from_secs_f64(
+                (1.0 - bucket.tokens) * 60.0 / capacity,
+            ));
+        }
+        bucket.tokens -= 1.0;
+        Ok(())
+    }
+}
+
+// inside `api_key_scope`, which has already checked the key and left it on the request
+pub async fn rate_limit(
+    State(limits): State<RateLimits>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string());
+    let budget = limits.config.budget(route.as_deref());
+    if budget == 0 {
+        return next.run(request).await;
+    }
+
+    let caller = if let Some(key) = request.extensions().get::<ApiKey>() {
+        Caller::Key(key.id.clone())
+    } else if let Some(ip) = client_ip(&request, limits.trust_forwarded_for) {
+        Caller::Ip(ip)
+    } else {
+        return next.run(request).await;
+    };
+    if Principal::of(request.headers(), request.extensions()).role == Role::Admin {
+        return next.run(request).await;
+    }
+
+    if let Err(retry_after) = limits.admit(caller, route.as_deref(), budget) {
+        let label = route.clone().unwrap_or_else(|| "unmatched".to_string());
+        counter!("rate_limited_total", "route" => label).increment(1);
+        let route = route.as_deref().unwrap_or("this server");
+        let secs = retry_after.as_secs_f64().ceil().max(1.0) as u64;
+        let mut response = error_response(
+            StatusCode::TOO_MANY_REQUESTS,
+            ErrorCode::RateLimited,
+            "Rate limit exceeded",
+            format!("At most {budget} requests a minute may be made to {route}, retry in {secs}s"),
+        );
+        response
+            .headers_mut()
+            .insert(RETRY_AFTER, HeaderValue::from(secs));
+        return response;
+    }
+
+    next.run(request).await
+}
@@ -0,0 +1,79 @@
+use std::time::Instant;
+
+use axum::{
+    extract::{MatchedPath, Request},
+    http::HeaderValue,
+    middleware::Next,
+    response::Response,
+};
+use rand::RngCore;
+use tracing::{Instrument, debug, field, info, info_span};
+
+use crate::logging::Redacted;
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+// longest caller-supplied id that's passed on rather than replaced
+const MAX_LEN: usize = 128;
+
+// The id a request goes by in the logs, on the request's extensions for handlers
+#[derive(Debug, Clone)]
+pub struct RequestId(pub String);
+
+tokio::task_local! {
+    // for code that answers without the request to hand, like error envelopes
+    static CURRENT: String;
+}
+
+// the id of the request being handled on this task, if any
+pub fn current() -> Option<String> {
+    CURRENT.try_with(Clone::clone).ok()
+}
+
+fn new_id() -> String {
+    let mut buf = [0u8; 16];
+    rand::rng().fill_bytes(&mut buf);
+    buf.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+// Run the request in a span carrying its id, taken from `X-Request-Id` so a proxy's
+// id carries through, or made up; the response says which it was
+pub async fn request_id(mut request: Request, next: Next) -> Response {
+    let id = request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .filter(|id| {
+            !id.is_empty() && id.len() <= MAX_LEN && id.bytes().all(|b| b.is_ascii_graphic())
+        })
+        .map(str::to_string)
+        .unwrap_or_else(new_id);
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or_else(|| request.uri().path(), MatchedPath::as_str)
+        .to_string();
+    let span = info_span!(
+        "request",
+        request_id = %id,
+        method = %request.method(),
+        route,
+        status = field::Empty,
+    );
+
+    span.in_scope(|| debug!(headers = ?Redacted(request.headers()), "Received"));
+    request.extensions_mut().insert(RequestId(id.clone()));
+    let started = Instant::now();
+    let mut response = CURRENT
+        .scope(id.clone(), next.run(request).instrument(span.clone()))
+        .await;
+
+    let status = response.status().as_u16();
+    let latency_ms = started.elapsed().as_millis() as u64;
+    span.record("status", status);
+    span.in_scope(|| info!(latency_ms, "Answered {status} in {latency_ms}ms"));
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    response
+}
@@ -0,0 +1,124 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+use axum::{
+    extract::{Request, State},
+    http::{HeaderValue, StatusCode, header::RETRY_AFTER},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use metrics::{counter, gauge, histogram};
+use tokio::sync::Semaphore;
+
+use crate::activity::{Activity, Tracked};
+use crate::config::UploadQueueConfig;
+use crate::errors::{ErrorCode, error_response};
+
+// Admits a fixed number of uploads at a time and parks up to `depth` more.
+// Parked requests haven't read their bodies yet, so waiting costs no memory
+// for the multipart payload; anything beyond that is turned away with a 429.
+// Queued background jobs keep their slot, since they hold their spooled files.
+#[derive(Clone)]
+pub struct UploadQueue {
+    config: UploadQueueConfig,
+    permits: Arc<Semaphore>,
+    // waiting plus active
+    admitted: Arc<AtomicUsize>,
+}
+
+impl UploadQueue {
+    pub fn new(config: UploadQueueConfig) -> Self {
+        Self {
+            config,
+            permits: Arc::new(Semaphore::new(config.concurrency)),
+            admitted: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    pub fn active(&self) -> usize {
+        self.config.concurrency - self.permits.available_permits()
+    }
+
+    // uploads holding a slot, running or parked
+    pub fn admitted(&self) -> usize {
+        self.admitted.load(Ordering::SeqCst)
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.config.concurrency + self.config.depth
+    }
+
+    fn publish(&self) {
+        let admitted = self.admitted();
+        let active = self.active();
+        gauge!("upload_queue_active").set(active as f64);
+        gauge!("upload_queue_waiting").set(admitted.saturating_sub(active) as f64);
+    }
+}
+
+// releases the queue slot however the request finishes
+struct Admission {
+    queue: UploadQueue,
+    _upload: Tracked,
+}
+
+impl Drop for Admission {
+    fn drop(&mut self) {
+        self.queue.admitted.fetch_sub(1, Ordering::SeqCst);
+        self.queue.publish();
+    }
+}
+
+// One of the queue's slots, held until the last clone is dropped. Admitted requests
+// find it in their extensions, so a background job can keep it until it's done.
+#[derive(Clone)]
+pub struct QueueSlot {
+    _admission: Arc<Admission>,
+}
+
+pub async fn upload_queue(
+    State(queue): State<UploadQueue>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let admitted = queue.admitted.fetch_add(1, Ordering::SeqCst);
+    if admitted >= queue.capacity() {
+        queue.admitted.fetch_sub(1, Ordering::SeqCst);
+        counter!("upload_queue_rejected_total").increment(1);
+
+        let mut response = error_response(
+            StatusCode::TOO_MANY_REQUESTS,
+            ErrorCode::RateLimited,
+            "Upload queue full",
+            format!(
+                "{} uploads are already in progress or queued, retry in {}s",
+                queue.capacity(),
+                queue.config.retry_after_secs
+            ),
+        );
+        response.headers_mut().insert(
+            RETRY_AFTER,
+            HeaderValue::from(queue.config.retry_after_secs),
+        );
+        return response;
+    }
+
+    let slot = QueueSlot {
+        _admission: Arc::new(Admission {
+            queue: queue.clone(),
+            _upload: Tracked::start(Activity::Upload),
+        }),
+    };
+    request.extensions_mut().insert(slot.clone());
+    queue.publish();
+
+    let waited = Instant::now();
+    let Ok(_permit) = queue.permits.acquire().await else {
+        return StatusCode::SERVICE_UNAVAILABLE.into_response();
+    };
+    histogram!("upload_queue_wait_duration_seconds").record(waited.elapsed().as_secs_f64());
+    queue.publish();
+
+    next.run(request).await
+}
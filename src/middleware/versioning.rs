@@ -0,0 +1,91 @@
+use axum::{
+    extract::Request,
+    http::{HeaderName, HeaderValue, StatusCode, Uri},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use crate::errors::{ErrorCode, error_response};
+use crate::routes::versions::{VERSIONS, route_prefix, version_of};
+
+// which version a client wants for the unprefixed paths, e.g. `Api-Version: v2`, and
+// which version answered
+pub const API_VERSION_HEADER: &str = "api-version";
+
+// Routes `/api/{version}/...` to where that version's handlers are mounted. The
+// unprefixed paths the old frontend calls are served as the version `Api-Version`
+// asks for, v1 without it. Runs before routing, so the routes, and everything keyed
+// on them, stay as they are.
+pub async fn negotiate_version(mut request: Request, next: Next) -> Response {
+    let path = request.uri().path();
+    let routed = match path.strip_prefix("/api/").map(split_version) {
+        Some((Some(version), rest)) => format!("{}{rest}", route_prefix(version)),
+        // e.g. `/api/versions`, which isn't any one version's
+        Some((None, _)) => return next.run(request).await,
+        None => match requested_version(&request) {
+            Ok(version) => format!("{}{path}", route_prefix(version)),
+            Err(wanted) => {
+                return error_response(
+                    StatusCode::BAD_REQUEST,
+                    ErrorCode::ValidationFailed,
+                    "Unknown API version",
+                    format!(
+                        "Api-Version {wanted} isn't served, expected one of {}",
+                        VERSIONS.join(", ")
+                    ),
+                )
+                .into_response();
+            }
+        },
+    };
+    let routed = match routed.trim_end_matches('/') {
+        "" => "/".to_string(),
+        trimmed => trimmed.to_string(),
+    };
+    let version = version_of(&routed);
+
+    let uri = match request.uri().query() {
+        Some(query) => format!("{routed}?{query}"),
+        None => routed,
+    };
+    if let Ok(uri) = uri.parse::<Uri>() {
+        *request.uri_mut() = uri;
+    }
+
+    let mut response = next.run(request).await;
+    response.headers_mut().insert(
+        HeaderName::from_static(API_VERSION_HEADER),
+        HeaderValue::from_static(version),
+    );
+    response
+}
+
+// `v1/groups` into v1 and `/groups`, if it starts with a version that's served
+fn split_version(path: &str) -> (Option<&'static str>, &str) {
+    let (first, rest) = match path.find('/') {
+        Some(at) => path.split_at(at),
+        None => (path, ""),
+    };
+    match VERSIONS.iter().find(|&&version| version == first) {
+        Some(&version) => (Some(version), rest),
+        None => (None, path),
+    }
+}
+
+// the version in `Api-Version`, with or without its `v`, defaulting to the first; the
+// version asked for when it isn't served
+fn requested_version(request: &Request) -> Result<&'static str, String> {
+    let Some(raw) = request.headers().get(API_VERSION_HEADER) else {
+        return Ok(VERSIONS[0]);
+    };
+    let raw = raw.to_str().unwrap_or_default().trim().to_ascii_lowercase();
+    let wanted = if raw.starts_with('v') {
+        raw
+    } else {
+        format!("v{raw}")
+    };
+    match VERSIONS.iter().find(|&&version| version == wanted) {
+        Some(&version) => Ok(version),
+        None => Err(wanted),
+    }
+}
@@ -0,0 +1,101 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::dates::{rfc3339, rfc3339_option};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyScope {
+    // public catalog data only, no writes
+    #[default]
+    ReadOnly,
+    // the catalog reads, plus uploads and the jobs and sessions they run as
+    Upload,
+    // everything the admin token can do
+    Admin,
+}
+
+// an issued key; the store indexes these by a hash of the secret, never the secret itself
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiKey {
+    pub id: String,
+    pub name: String,
+    pub scope: KeyScope,
+    // first characters of the secret, so the owner can tell keys apart
+    pub prefix: String,
+    // requests per minute, unlimited when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rate_limit_per_minute: Option<u32>,
+    // the groups an upload key may upload into, any when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub groups: Option<Vec<String>>,
+    #[serde(with = "rfc3339")]
+    pub created_at: DateTime<Utc>,
+    #[serde(
+        default,
+        with = "rfc3339_option",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateKeyRequest {
+    pub name: String,
+    // read_only unless asked for
+    #[serde(default)]
+    pub scope: KeyScope,
+    pub rate_limit_per_minute: Option<u32>,
+    // upload keys only
+    pub groups: Option<Vec<String>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SetRateLimitRequest {
+    // `null` removes the limit
+    pub per_minute: Option<u32>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CreateKeyResponse {
+    pub success: bool,
+    pub key: ApiKey,
+    // shown once, never stored
+    pub secret: String,
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct KeysResponse {
+    pub success: bool,
+    pub keys: Vec<ApiKey>,
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct KeyResponse {
+    pub success: bool,
+    pub key: ApiKey,
+    pub message: Option<String>,
+}
+
+// what a key has consumed since `since` (the last restart)
+#[derive(Debug, Serialize)]
+pub struct KeyUsage {
+    pub key_id: String,
+    pub requests: u64,
+    pub bytes_served: u64,
+    pub rate_limited: u64,
+    #[serde(with = "rfc3339_option")]
+    pub last_used_at: Option<DateTime<Utc>>,
+    #[serde(with = "rfc3339")]
+    pub since: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct KeyUsageResponse {
+    pub success: bool,
+    pub key: ApiKey,
+    pub usage: KeyUsage,
+    pub message: Option<String>,
+}
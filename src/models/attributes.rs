@@ -0,0 +1,223 @@
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::HashMap;
+
+use super::uploads::{PhotoMetadata, PhotoMetadataPatch};
+use crate::errors::ApiError;
+
+// Pinata keyvalues are a flat string map, these are the keys we own
+const CATEGORY: &str = "category";
+const DESCRIPTION: &str = "description";
+const TAGS: &str = "tags";
+const CAMERA: &str = "camera";
+const LENS: &str = "lens";
+const ISO: &str = "iso";
+const APERTURE: &str = "aperture";
+const SHUTTER_SPEED: &str = "shutterSpeed";
+const RATING: &str = "rating";
+const LOCATION: &str = "location";
+const CLIENT: &str = "client";
+
+pub const MAX_RATING: u8 = 5;
+pub const MAX_TAGS: usize = 32;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Gear {
+    pub camera: Option<String>,
+    pub lens: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Exposure {
+    pub iso: Option<String>,
+    pub aperture: Option<String>,
+    pub shutter_speed: Option<String>,
+}
+
+// Typed view of a file's keyvalues, the stable contract clients see
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PhotoAttributes {
+    pub category: Option<String>,
+    pub description: Option<String>,
+    pub tags: Vec<String>,
+    pub gear: Gear,
+    pub exposure: Exposure,
+    pub rating: Option<u8>,
+    // keyvalues we don't know about (or couldn't parse) are passed through untouched
+    pub extra: HashMap<String, String>,
+}
+
+fn non_empty(value: Option<String>) -> Option<String> {
+    value.filter(|v| !v.trim().is_empty())
+}
+
+impl PhotoAttributes {
+    pub fn from_keyvalues(mut keyvalues: HashMap<String, String>) -> Self {
+        let tags = keyvalues
+            .remove(TAGS)
+            .map(|raw| {
+                raw.split(',')
+                    .map(|t| t.trim().to_string())
+                    .filter(|t| !t.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        // a malformed rating stays in `extra` rather than being silently dropped
+        let rating = match keyvalues.remove(RATING) {
+            Some(raw) => match raw.trim().parse::<u8>() {
+                Ok(rating) => Some(rating),
+                Err(_) => {
+                    keyvalues.insert(RATING.to_string(), raw);
+                    None
+                }
+            },
+            None => None,
+        };
+
+        Self {
+            category: non_empty(keyvalues.remove(CATEGORY)),
+            description: non_empty(keyvalues.remove(DESCRIPTION)),
+            tags,
+            gear: Gear {
+                camera: non_empty(keyvalues.remove(CAMERA)),
+                lens: non_empty(keyvalues.remove(LENS)),
+            },
+            exposure: Exposure {
+                iso: non_empty(keyvalues.remove(ISO)),
+                aperture: non_empty(keyvalues.remove(APERTURE)),
+                shutter_speed: non_empty(keyvalues.remove(SHUTTER_SPEED)),
+            },
+            rating,
+            extra: keyvalues,
+        }
+    }
+
+    // flatten back into the Pinata keyvalues format, omitting empty fields
+    pub fn to_keyvalues(&self) -> HashMap<String, String> {
+        let mut keyvalues = self.extra.clone();
+        let mut insert = |key: &str, value: &Option<String>| {
+            if let Some(value) = value {
+                keyvalues.insert(key.to_string(), value.clone());
+            }
+        };
+
+        insert(CATEGORY, &self.category);
+        insert(DESCRIPTION, &self.description);
+        insert(CAMERA, &self.gear.camera);
+        insert(LENS, &self.gear.lens);
+        insert(ISO, &self.exposure.iso);
+        insert(APERTURE, &self.exposure.aperture);
+        insert(SHUTTER_SPEED, &self.exposure.shutter_speed);
+        insert(RATING, &self.rating.map(|r| r.to_string()));
+
+        if !self.tags.is_empty() {
+            keyvalues.insert(TAGS.to_string(), self.tags.join(","));
+        }
+
+        keyvalues
+    }
+
+    // merge a partial edit; untouched fields and unknown keyvalues are kept as they are
+    pub fn apply(&mut self, patch: &PhotoMetadataPatch) {
+        let set = |field: &mut Option<String>, value: &Option<String>| {
+            if let Some(value) = value {
+                *field = non_empty(Some(value.trim().to_string()));
+            }
+        };
+
+        set(&mut self.description, &patch.description);
+        set(&mut self.gear.camera, &patch.camera);
+        set(&mut self.gear.lens, &patch.lens);
+        set(&mut self.exposure.iso, &patch.iso);
+        set(&mut self.exposure.aperture, &patch.aperture);
+        set(&mut self.exposure.shutter_speed, &patch.shutter_speed);
+        if let Some(category) = &patch.category {
+            self.category = non_empty(Some(category.trim().to_lowercase()));
+        }
+        if let Some(tags) = &patch.tags {
+            self.tags = tags
+                .iter()
+                .map(|t| t.trim().to_string())
+                .filter(|t| !t.is_empty())
+                .collect();
+        }
+        if patch.rating.is_some() {
+            self.rating = patch.rating;
+            // a corrected rating replaces whatever unparseable value was there
+            self.extra.remove(RATING);
+        }
+    }
+
+    // checks run before anything is written to Pinata
+    pub fn validate(&self) -> Result<(), ApiError> {
+        if self.category.is_none() {
+            return Err(ApiError::Unprocessable(
+                "Photo category is required".to_string(),
+            ));
+        }
+
+        if let Some(rating) = self.rating
+            && rating > MAX_RATING
+        {
+            return Err(ApiError::Unprocessable(format!(
+                "Rating must be between 0 and {MAX_RATING}, got {rating}"
+            )));
+        }
+
+        if self.tags.len() > MAX_TAGS {
+            return Err(ApiError::Unprocessable(format!(
+                "At most {MAX_TAGS} tags are allowed, got {}",
+                self.tags.len()
+            )));
+        }
+
+        if let Some(tag) = self.tags.iter().find(|t| t.contains(',')) {
+            return Err(ApiError::Unprocessable(format!(
+                "Tags cannot contain commas: {tag}"
+            )));
+        }
+
+        Ok(())
+    }
+
+    // deserialize_with helper for Pinata responses, where keyvalues may be null
+    pub fn deserialize_keyvalues<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Self, D::Error> {
+        let keyvalues = Option::<HashMap<String, String>>::deserialize(deserializer)?;
+        Ok(Self::from_keyvalues(keyvalues.unwrap_or_default()))
+    }
+}
+
+impl From<&PhotoMetadata> for PhotoAttributes {
+    fn from(metadata: &PhotoMetadata) -> Self {
+        Self {
+            // categories are matched case-sensitively upstream, so store them normalized
+            category: non_empty(Some(metadata.category.trim().to_lowercase())),
+            description: non_empty(Some(metadata.description.clone())),
+            tags: metadata
+                .tags
+                .iter()
+                .map(|t| t.trim().to_string())
+                .filter(|t| !t.is_empty())
+                .collect(),
+            gear: Gear {
+                camera: non_empty(Some(metadata.camera.clone())),
+                lens: non_empty(Some(metadata.lens.clone())),
+            },
+            exposure: Exposure {
+                iso: non_empty(Some(metadata.iso.clone())),
+                aperture: non_empty(Some(metadata.aperture.clone())),
+                shutter_speed: non_empty(Some(metadata.shutter_speed.clone())),
+            },
+            rating: metadata.rating,
+            extra: [(LOCATION, &metadata.location), (CLIENT, &metadata.client)]
+                .into_iter()
+                .filter_map(|(key, value)| {
+                    non_empty(value.as_deref().map(|v| v.trim().to_string()))
+                        .map(|value| (key.to_string(), value))
+                })
+                .collect(),
+        }
+    }
+}
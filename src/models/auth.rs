@@ -0,0 +1,32 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::dates::rfc3339;
+
+#[derive(Debug, Serialize)]
+pub struct NonceResponse {
+    pub success: bool,
+    // goes in the `Nonce:` field of the message the wallet signs
+    pub nonce: String,
+    #[serde(with = "rfc3339")]
+    pub expires_at: DateTime<Utc>,
+    pub message: Option<String>,
+}
+
+// an EIP-4361 message as the wallet signed it, and its `personal_sign` signature
+#[derive(Debug, Deserialize)]
+pub struct VerifyRequest {
+    pub message: String,
+    pub signature: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SessionResponse {
+    pub success: bool,
+    // sent as `Authorization: Bearer <token>` wherever the admin token is accepted
+    pub token: String,
+    pub address: String,
+    #[serde(with = "rfc3339")]
+    pub expires_at: DateTime<Utc>,
+    pub message: Option<String>,
+}
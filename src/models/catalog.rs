@@ -0,0 +1,122 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::{PinataFile, categories::CategorySummary, dates::rfc3339};
+
+// gateway urls with a `{cid}` placeholder
+#[derive(Debug, Clone, Serialize)]
+pub struct UrlTemplates {
+    pub original: String,
+    pub thumbnail: String,
+}
+
+// One of the first images of a listing, in the order the page shows them, with what
+// a `<link rel=preload>` for it needs and what to draw until it's loaded
+#[derive(Debug, Serialize)]
+pub struct PreloadHint {
+    pub file_id: String,
+    // the thumbnail `url_templates` gives for it
+    pub url: String,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub blurhash: Option<String>,
+}
+
+// how much of each file a listing carries, as `?view=` asks
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ListingView {
+    // just what a tile of the grid needs, for clients watching their data
+    Grid,
+    #[default]
+    Detail,
+}
+
+// a file as the grid view lists it: what its tile is laid out and drawn with
+#[derive(Debug, Serialize)]
+pub struct GridFile {
+    pub id: String,
+    // the small thumbnail pinned for it, else the one `url_templates` gives; None
+    // without a gateway
+    pub thumbnail_url: Option<String>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub blurhash: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum ListedFile {
+    Detail(Box<PinataFile>),
+    Grid(GridFile),
+}
+
+#[derive(Debug, Serialize)]
+pub struct CatalogGroup {
+    pub id: String,
+    pub name: String,
+    #[serde(with = "rfc3339")]
+    pub created_at: DateTime<Utc>,
+    // newest first, the order galleries show them in
+    pub file_ids: Vec<String>,
+}
+
+// The whole public catalog in one document, for static-site builds.
+// Deliberately carries no generation time, so unchanged content keeps its ETag.
+#[derive(Debug, Serialize)]
+pub struct CatalogDocument {
+    pub success: bool,
+    // absent without a configured gateway
+    pub url_templates: Option<UrlTemplates>,
+    // in landing-grid order
+    pub categories: Vec<CategorySummary>,
+    pub groups: Vec<CatalogGroup>,
+    // newest first; ungrouped files appear here but in no group
+    pub files: Vec<PinataFile>,
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeKind {
+    File,
+    Group,
+}
+
+// a file or group added, edited or removed; editing a file also touches its group
+#[derive(Debug, Clone, Serialize)]
+pub struct CatalogChange {
+    pub kind: ChangeKind,
+    pub id: String,
+    pub deleted: bool,
+    #[serde(with = "rfc3339")]
+    pub changed_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ChangedParams {
+    #[serde(with = "rfc3339")]
+    pub since: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChangedEntry {
+    pub id: String,
+    pub deleted: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CatalogChangesResponse {
+    pub success: bool,
+    #[serde(with = "rfc3339")]
+    pub since: DateTime<Utc>,
+    // pass as the next `since`
+    #[serde(with = "rfc3339")]
+    pub until: DateTime<Utc>,
+    // false without the local mirror: only new files are found, not edits or deletions
+    pub complete: bool,
+    pub groups: Vec<ChangedEntry>,
+    pub files: Vec<ChangedEntry>,
+    // frontend pages to revalidate
+    pub routes: Vec<String>,
+    pub message: Option<String>,
+}
@@ -1,15 +1,146 @@
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
 
-use crate::PinataFile;
+use crate::models::catalog::{ListedFile, PreloadHint};
 
 #[derive(Debug, Deserialize)]
 pub struct CategoryParams {
     pub categories: Option<String>,
-    pub limit: Option<usize>,
+    // extra keyvalue filters, `key:op:value` separated by commas
+    pub filters: Option<String>,
+    // only files pinned through this pipeline, e.g. `cli`; short for `uploaded_via:eq:cli`
+    pub uploaded_via: Option<String>,
+    // query categories independently and return whatever succeeded
+    #[serde(default)]
+    pub fail_soft: bool,
 }
 #[derive(Serialize)]
 pub struct CategoryResponse {
     pub success: bool,
-    pub images: Vec<PinataFile>,
+    pub images: Vec<ListedFile>,
+    // as on `GroupImagesResponse`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub preload: Vec<PreloadHint>,
+    pub message: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<CategoryWarning>,
+}
+
+// a category that couldn't be fetched in fail-soft mode
+#[derive(Debug, Clone, Serialize)]
+pub struct CategoryWarning {
+    pub category: String,
+    pub message: String,
+}
+
+// landing-grid settings for a category, kept in the local store
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CategorySettings {
+    pub cover_file_id: Option<String>,
+    pub cover_cid: Option<String>,
+    pub display_order: Option<i32>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SetCoverRequest {
+    pub file_id: String,
+    pub display_order: Option<i32>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CategorySummary {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parent: Option<String>,
+    pub display_order: Option<i32>,
+    pub cover_file_id: Option<String>,
+    pub cover_cid: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CategoriesResponse {
+    pub success: bool,
+    pub categories: Vec<CategorySummary>,
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CategoryCoverResponse {
+    pub success: bool,
+    pub category: CategorySummary,
+    pub message: Option<String>,
+}
+
+// category aliases ("b&w" -> "black-and-white") and parent links (child -> parent)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CategoryTaxonomy {
+    #[serde(default)]
+    pub aliases: BTreeMap<String, String>,
+    #[serde(default)]
+    pub parents: BTreeMap<String, String>,
+}
+
+impl CategoryTaxonomy {
+    // the canonical name for a category or one of its aliases
+    pub fn resolve(&self, category: &str) -> String {
+        self.aliases
+            .get(category)
+            .cloned()
+            .unwrap_or_else(|| category.to_string())
+    }
+
+    // resolve aliases and pull in every descendant, so a parent browses its children too
+    pub fn expand(&self, categories: &[String]) -> Vec<String> {
+        let mut expanded = BTreeSet::new();
+        let mut pending: Vec<String> = categories.iter().map(|c| self.resolve(c)).collect();
+
+        while let Some(category) = pending.pop() {
+            if !expanded.insert(category.clone()) {
+                continue;
+            }
+            pending.extend(
+                self.parents
+                    .iter()
+                    .filter(|(_, parent)| **parent == category)
+                    .map(|(child, _)| child.clone()),
+            );
+        }
+
+        expanded.into_iter().collect()
+    }
+
+    // whether `ancestor` already sits somewhere above `category`
+    pub fn is_ancestor(&self, ancestor: &str, category: &str) -> bool {
+        let mut current = self.parents.get(category);
+        let mut steps = 0;
+        while let Some(parent) = current {
+            if parent == ancestor {
+                return true;
+            }
+            // stored links are cycle-free, but never loop forever on a hand-edited file
+            steps += 1;
+            if steps > self.parents.len() {
+                break;
+            }
+            current = self.parents.get(parent);
+        }
+        false
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SetAliasRequest {
+    pub target: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SetParentRequest {
+    pub parent: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TaxonomyResponse {
+    pub success: bool,
+    pub taxonomy: CategoryTaxonomy,
     pub message: Option<String>,
 }
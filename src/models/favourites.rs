@@ -1,6 +1,12 @@
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
-use super::{PinataFile, PinataGroup};
+use super::{
+    PinataFile, PinataGroup,
+    catalog::{ListedFile, PreloadHint},
+    dates::rfc3339,
+};
+use crate::pinata::SortOrder;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct PinataFilesData {
@@ -24,13 +30,31 @@ pub struct ApiResponse {
 #[derive(Debug, Deserialize)]
 pub struct GroupImagesParams {
     pub group_id: Option<String>,
-    pub limit: Option<usize>,
+    pub order: Option<SortOrder>,
 }
 
 #[derive(Serialize)]
 pub struct GroupImagesResponse {
     pub success: bool,
     pub group_id: String,
-    pub images: Vec<PinataFile>,
+    pub images: Vec<ListedFile>,
+    // left out of the grid view, whose images carry the same
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub preload: Vec<PreloadHint>,
+    pub message: Option<String>,
+}
+
+// a file an anonymous visitor picked from the public gallery
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VisitorFavourite {
+    pub file_id: String,
+    #[serde(with = "rfc3339")]
+    pub added_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct VisitorFavouritesResponse {
+    pub success: bool,
+    pub favourites: Vec<VisitorFavourite>,
     pub message: Option<String>,
 }
@@ -0,0 +1,139 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::{PinataFile, dates::rfc3339};
+
+// which files a bulk delete targets; at least a group or a category is required
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct DeleteFilter {
+    pub group_id: Option<String>,
+    pub category: Option<String>,
+    pub created_after: Option<DateTime<Utc>>,
+    pub created_before: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BulkDeleteRequest {
+    #[serde(default)]
+    pub ids: Vec<String>,
+    pub filter: Option<DeleteFilter>,
+    // nothing is removed unless this is false and `confirm` matches the dry run's token
+    #[serde(default = "default_dry_run")]
+    pub dry_run: bool,
+    pub confirm: Option<String>,
+}
+
+fn default_dry_run() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FileSummary {
+    pub id: String,
+    pub name: String,
+    pub cid: String,
+    pub group_id: String,
+    #[serde(with = "rfc3339")]
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<&PinataFile> for FileSummary {
+    fn from(file: &PinataFile) -> Self {
+        Self {
+            id: file.id.clone(),
+            name: file.name.clone(),
+            cid: file.cid.clone(),
+            group_id: file.group_id.clone(),
+            created_at: file.created_at,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct DeleteFailure {
+    pub id: String,
+    pub message: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BulkDeleteResponse {
+    pub success: bool,
+    pub dry_run: bool,
+    // files matching an id list are reported by id only
+    pub matched_ids: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub matched_files: Vec<FileSummary>,
+    pub deleted: Vec<String>,
+    pub failed: Vec<DeleteFailure>,
+    // pass back as `confirm` with `dry_run: false` to delete exactly this set
+    pub confirmation_token: String,
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DeleteResponse {
+    pub success: bool,
+    pub id: String,
+    // whether the local mirror held the file; always false without DATABASE_URL
+    pub removed_from_catalog: bool,
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FileResponse {
+    pub success: bool,
+    pub file: PinataFile,
+    pub message: Option<String>,
+}
+
+// 256 bins per channel, indexed by 8-bit value
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Histograms {
+    pub luminance: Vec<u32>,
+    pub red: Vec<u32>,
+    pub green: Vec<u32>,
+    pub blue: Vec<u32>,
+}
+
+// share of pixels, in percent, at the ends of the range
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Clipping {
+    // luminance 0
+    pub shadows: f64,
+    // luminance 255
+    pub highlights: f64,
+    // pixels with that channel at 255, e.g. a blown out sky in blue
+    pub red: f64,
+    pub green: f64,
+    pub blue: f64,
+}
+
+// exposure diagnostics for one photo, computed from its original
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImageAnalysis {
+    pub width: u32,
+    pub height: u32,
+    pub mean_luminance: f64,
+    pub clipping: Clipping,
+    pub histograms: Histograms,
+    #[serde(with = "rfc3339")]
+    pub analyzed_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FileAnalysisResponse {
+    pub success: bool,
+    pub file_id: String,
+    pub analysis: ImageAnalysis,
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PurgeCacheResponse {
+    pub success: bool,
+    pub file_id: String,
+    // what the purge endpoint was asked to drop
+    pub urls: Vec<String>,
+    pub cids: Vec<String>,
+    pub message: Option<String>,
+}
@@ -1,6 +1,7 @@
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
-use super::{PinataFile, PinataGroup};
+use super::{PinataFile, PinataGroup, dates::rfc3339, files::DeleteFailure};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct PinataGroupData {
@@ -18,9 +19,12 @@ pub struct GroupWithThumbnail {
     pub id: String,
     pub name: String,
     pub is_public: Option<bool>,
-    pub created_at: String,
+    #[serde(with = "rfc3339")]
+    pub created_at: DateTime<Utc>,
     pub thumbnail_image: Option<PinataFile>,
     pub photo_count: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub account: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -35,8 +39,156 @@ pub struct GroupCreationResponse {
     pub id: String,
     pub user_id: String,
     pub name: String,
-    #[serde(rename = "updatedAt")]
-    pub updated_at: String,
-    #[serde(rename = "createdAt")]
-    pub created_at: String,
+    #[serde(rename = "updatedAt", with = "rfc3339")]
+    pub updated_at: DateTime<Utc>,
+    #[serde(rename = "createdAt", with = "rfc3339")]
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Visibility {
+    #[default]
+    All,
+    Public,
+    Private,
+}
+
+// `/groups` filters, applied before `limit`
+#[derive(Debug, Default, Deserialize)]
+pub struct GroupListParams {
+    // leave out groups without a photo in them
+    #[serde(default)]
+    pub non_empty: bool,
+    // groups Pinata doesn't say the visibility of count as public
+    #[serde(default)]
+    pub visibility: Visibility,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DuplicateGroupParams {
+    // Also move the source group's files into the new group. A file belongs to one
+    // group at a time, so they leave the source group; nothing is copied.
+    #[serde(default)]
+    pub include_files: bool,
+}
+
+// optional overrides for the duplicated group
+#[derive(Debug, Default, Deserialize)]
+pub struct DuplicateGroupRequest {
+    pub name: Option<String>,
+    pub is_public: Option<bool>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DuplicateGroupResponse {
+    pub success: bool,
+    pub source_group_id: String,
+    pub group: PinataGroup,
+    pub files_moved: usize,
+    // files that stayed in the source group
+    pub failed: Vec<MembershipFailure>,
+    pub message: Option<String>,
+}
+
+// fields to change on a group; at least one is required
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct UpdateGroupRequest {
+    pub name: Option<String>,
+    pub is_public: Option<bool>,
+}
+
+// Which events WEBHOOK_NOTIFY_URL hears about for a group's files and share links,
+// so a client gallery can report what a personal collection doesn't
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GroupNotifications {
+    pub on_upload: bool,
+    pub on_share_access: bool,
+    pub on_share_expiry: bool,
+}
+
+impl Default for GroupNotifications {
+    // what groups nobody set preferences for get
+    fn default() -> Self {
+        Self {
+            on_upload: true,
+            on_share_access: false,
+            on_share_expiry: false,
+        }
+    }
+}
+
+// preferences to change on a group, the rest stay as they are; at least one is required
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct UpdateGroupNotifications {
+    pub on_upload: Option<bool>,
+    pub on_share_access: Option<bool>,
+    pub on_share_expiry: Option<bool>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct GroupNotificationsResponse {
+    pub success: bool,
+    pub group_id: String,
+    pub notifications: GroupNotifications,
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct GroupResponse {
+    pub success: bool,
+    pub group: PinataGroup,
+    pub message: Option<String>,
+}
+
+// what happens to a deleted group's files
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GroupFiles {
+    // keep them in Pinata, ungrouped
+    #[default]
+    Orphan,
+    Delete,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeleteGroupParams {
+    #[serde(default)]
+    pub files: GroupFiles,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DeleteGroupResponse {
+    pub success: bool,
+    pub group_id: String,
+    // false when some files could not be deleted, so the group was kept
+    pub group_deleted: bool,
+    pub files_deleted: Vec<String>,
+    pub failed: Vec<DeleteFailure>,
+    pub message: Option<String>,
+}
+
+// files to move into or out of a group
+#[derive(Debug, Deserialize)]
+pub struct GroupFilesRequest {
+    pub file_ids: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MembershipFailure {
+    pub id: String,
+    pub message: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct GroupFilesResponse {
+    pub success: bool,
+    pub group_id: String,
+    // files now in the group, or taken out of it
+    pub changed: Vec<String>,
+    pub failed: Vec<MembershipFailure>,
+    pub message: Option<String>,
 }
@@ -0,0 +1,49 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HealthStatus {
+    Ok,
+    // working, but a non-critical part is failing or saturated
+    Degraded,
+    Failed,
+    // not configured for this deployment
+    Disabled,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ComponentHealth {
+    pub name: &'static str,
+    pub status: HealthStatus,
+    // a failing critical component makes the whole service unready
+    pub critical: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latency_ms: Option<u64>,
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct HealthReport {
+    pub success: bool,
+    pub status: HealthStatus,
+    #[serde(with = "crate::models::dates::rfc3339")]
+    pub checked_at: DateTime<Utc>,
+    pub components: Vec<ComponentHealth>,
+}
+
+// What anyone may know of the service, e.g. for a status indicator in the frontend
+#[derive(Debug, Clone, Serialize)]
+pub struct PublicStatus {
+    pub success: bool,
+    // degraded while Pinata can't be listed and the last counts are shown, failed
+    // when there are none
+    pub status: HealthStatus,
+    // public groups, and the files in them or in none
+    pub groups: Option<usize>,
+    pub files: Option<usize>,
+    #[serde(with = "crate::models::dates::rfc3339_option")]
+    pub last_updated_at: Option<DateTime<Utc>>,
+    #[serde(with = "crate::models::dates::rfc3339")]
+    pub checked_at: DateTime<Utc>,
+}
@@ -0,0 +1,104 @@
+use chrono::{DateTime, FixedOffset, Utc};
+use serde::Serialize;
+
+use crate::models::dates::{local_rfc3339_option, rfc3339};
+
+pub const SCHEMA_ORG: &str = "https://schema.org";
+
+#[derive(Debug, Serialize)]
+pub struct Person {
+    #[serde(rename = "@type")]
+    pub kind: &'static str,
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Place {
+    #[serde(rename = "@type")]
+    pub kind: &'static str,
+    pub name: String,
+}
+
+// one EXIF field, e.g. `{"name": "iso", "value": "400"}`
+#[derive(Debug, Serialize)]
+pub struct PropertyValue {
+    #[serde(rename = "@type")]
+    pub kind: &'static str,
+    pub name: &'static str,
+    pub value: String,
+}
+
+// a schema.org `Photograph`, on its own or as one of a gallery's images
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Photograph {
+    // only on the document's top level
+    #[serde(rename = "@context", skip_serializing_if = "Option::is_none")]
+    pub context: Option<&'static str>,
+    #[serde(rename = "@type")]
+    pub kind: &'static str,
+    // the photo's page, when FRONTEND_URL is set
+    #[serde(rename = "@id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    pub identifier: String,
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub genre: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub keywords: Vec<String>,
+    // gateway links, when PINATA_GATEWAY is set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thumbnail_url: Option<String>,
+    pub encoding_format: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub width: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub height: Option<u32>,
+    // when it was taken, in the offset it was taken at
+    #[serde(with = "local_rfc3339_option", skip_serializing_if = "Option::is_none")]
+    pub date_created: Option<DateTime<FixedOffset>>,
+    #[serde(with = "rfc3339")]
+    pub upload_date: DateTime<Utc>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content_location: Option<Place>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub creator: Option<Person>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub license: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub exif_data: Vec<PropertyValue>,
+}
+
+// a schema.org `ImageGallery` of a group's photos, newest first
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImageGallery {
+    #[serde(rename = "@context")]
+    pub context: &'static str,
+    #[serde(rename = "@type")]
+    pub kind: &'static str,
+    #[serde(rename = "@id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    pub identifier: String,
+    pub name: String,
+    #[serde(with = "rfc3339")]
+    pub date_created: DateTime<Utc>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub creator: Option<Person>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub license: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thumbnail_url: Option<String>,
+    pub number_of_items: usize,
+    pub image: Vec<Photograph>,
+}
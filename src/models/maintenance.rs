@@ -0,0 +1,245 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+use super::dates::{rfc3339, rfc3339_option};
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ConsistencyIssue {
+    MissingTitle,
+    MissingCategory,
+    UnknownCategory { category: String },
+    CategoryNotNormalized { found: String, normalized: String },
+    MalformedRating { raw: String },
+    RatingOutOfRange { rating: u8 },
+}
+
+#[derive(Debug, Serialize)]
+pub struct FileReport {
+    pub id: String,
+    pub name: String,
+    pub group_id: String,
+    pub issues: Vec<ConsistencyIssue>,
+    // safe fixes `POST /maintenance/consistency/fix` would apply
+    pub fixes: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ConsistencyReport {
+    pub success: bool,
+    pub files_scanned: usize,
+    pub files_with_issues: usize,
+    pub fixable: usize,
+    pub files: Vec<FileReport>,
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FixFailure {
+    pub id: String,
+    pub message: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ConsistencyFixResponse {
+    pub success: bool,
+    pub fixed: Vec<String>,
+    pub failed: Vec<FixFailure>,
+    // files with issues that need a human
+    pub skipped: usize,
+    pub message: Option<String>,
+}
+
+// one pin of content that is pinned more than once
+#[derive(Debug, Serialize)]
+pub struct DuplicateCopy {
+    pub id: String,
+    pub name: String,
+    pub group_id: String,
+    #[serde(with = "rfc3339")]
+    pub created_at: DateTime<Utc>,
+}
+
+// every pin of one CID; the first copy is the canonical one a dedupe keeps
+#[derive(Debug, Serialize)]
+pub struct DuplicateSet {
+    pub cid: String,
+    pub size: u64,
+    pub copies: Vec<DuplicateCopy>,
+    // the groups the copies are in, ungrouped copies left out
+    pub group_ids: BTreeSet<String>,
+    // storage taken by all but the canonical copy
+    pub wasted_bytes: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DuplicatesReport {
+    pub success: bool,
+    pub files_scanned: usize,
+    pub wasted_bytes: u64,
+    pub duplicates: Vec<DuplicateSet>,
+    pub message: Option<String>,
+}
+
+// what a dedupe did with one CID
+#[derive(Debug, Serialize)]
+pub struct DedupedSet {
+    pub cid: String,
+    pub canonical_id: String,
+    pub removed: Vec<String>,
+    // groups that lost their copy and now reference the canonical one instead
+    pub referenced_from: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DedupeResponse {
+    pub success: bool,
+    pub deduped: Vec<DedupedSet>,
+    pub failed: Vec<FixFailure>,
+    pub reclaimed_bytes: u64,
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReencodeStatus {
+    Running,
+    Completed,
+    // the catalog couldn't be walked at all
+    Failed,
+}
+
+// The re-encode job's progress, persisted after every file so a restart picks
+// up where it stopped
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReencodeJob {
+    pub status: ReencodeStatus,
+    #[serde(with = "rfc3339")]
+    pub started_at: DateTime<Utc>,
+    #[serde(
+        default,
+        with = "rfc3339_option",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub finished_at: Option<DateTime<Utc>>,
+    pub total: usize,
+    // files given their missing variants during this run
+    pub processed: usize,
+    // files that already had everything, or aren't images
+    pub skipped: usize,
+    pub failed: Vec<FixFailure>,
+    // ids finished one way or another, skipped when the job resumes
+    #[serde(default)]
+    pub done: BTreeSet<String>,
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReencodeResponse {
+    pub success: bool,
+    pub status: ReencodeStatus,
+    #[serde(with = "rfc3339")]
+    pub started_at: DateTime<Utc>,
+    #[serde(with = "rfc3339_option", skip_serializing_if = "Option::is_none")]
+    pub finished_at: Option<DateTime<Utc>>,
+    pub total: usize,
+    pub processed: usize,
+    pub skipped: usize,
+    pub failed: Vec<FixFailure>,
+    pub message: Option<String>,
+}
+
+impl From<ReencodeJob> for ReencodeResponse {
+    fn from(job: ReencodeJob) -> Self {
+        Self {
+            success: job.status != ReencodeStatus::Failed,
+            status: job.status,
+            started_at: job.started_at,
+            finished_at: job.finished_at,
+            total: job.total,
+            processed: job.processed,
+            skipped: job.skipped,
+            failed: job.failed,
+            message: job.message,
+        }
+    }
+}
+
+// The files of each group when the catalog was last known to be whole, with a
+// checksum over their CIDs. Pinned next to the photos and kept locally, so what
+// Pinata has can be checked against it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IntegrityManifest {
+    pub version: u32,
+    #[serde(with = "rfc3339")]
+    pub created_at: DateTime<Utc>,
+    pub groups: BTreeMap<String, GroupDigest>,
+    // files in no group
+    pub ungrouped: GroupDigest,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GroupDigest {
+    pub files: usize,
+    // hex SHA-256 over the group's CIDs, sorted and one per line
+    pub cid_sha256: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum IntegrityIssue {
+    // the pinned copy of the manifest itself is gone
+    ManifestMissing {
+        cid: String,
+    },
+    GroupMissing {
+        group_id: String,
+        expected_files: usize,
+    },
+    FileCountChanged {
+        // None for the files in no group
+        group_id: Option<String>,
+        expected_files: usize,
+        found_files: usize,
+    },
+    // as many files as before, but not the same ones
+    ChecksumMismatch {
+        group_id: Option<String>,
+        files: usize,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IntegrityReport {
+    pub success: bool,
+    pub diverged: bool,
+    #[serde(with = "rfc3339")]
+    pub checked_at: DateTime<Utc>,
+    // the manifest checked against
+    #[serde(default, with = "rfc3339_option")]
+    pub manifest_created_at: Option<DateTime<Utc>>,
+    pub manifest_cid: Option<String>,
+    pub groups_checked: usize,
+    pub files_checked: usize,
+    pub issues: Vec<IntegrityIssue>,
+    pub message: Option<String>,
+}
+
+// the manifest last recorded, where its pinned copy is, and the last check against it
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct IntegrityState {
+    pub manifest: Option<IntegrityManifest>,
+    pub file_id: Option<String>,
+    pub cid: Option<String>,
+    // the backend changed the catalog since the manifest was recorded
+    #[serde(default)]
+    pub pending: bool,
+    // Pinata told of a change made elsewhere since the manifest was recorded
+    #[serde(default)]
+    pub upstream: bool,
+    // the last check found a divergence, not accepted yet
+    #[serde(default)]
+    pub diverged: bool,
+    pub last_report: Option<IntegrityReport>,
+}
@@ -1,19 +1,58 @@
+pub mod api_keys;
+pub use api_keys::{ApiKey, KeyScope, KeyUsage};
+
+pub mod attributes;
+pub mod auth;
+pub use attributes::PhotoAttributes;
+
+pub mod dates;
+
+pub mod files;
+pub use files::{
+    BulkDeleteRequest, BulkDeleteResponse, DeleteFilter, DeleteResponse, FileResponse,
+};
+
 pub mod favourites;
+pub mod jsonld;
+pub mod maintenance;
 pub use favourites::{ApiResponse, GroupImagesParams, PinataFilesResponse};
 
 pub mod pinata;
 pub use pinata::{PinataFile, PinataGroup};
 
+pub mod health;
+pub use health::{ComponentHealth, HealthReport, HealthStatus};
+
 pub mod groups;
 pub use groups::{
-    GroupCreationResponse, GroupWithThumbnail, GroupsWithThumbnailResponse, PinataGroupData,
-    PinataGroupResponse,
+    DeleteGroupParams, DeleteGroupResponse, DuplicateGroupParams, DuplicateGroupRequest,
+    DuplicateGroupResponse, GroupCreationResponse, GroupFiles, GroupFilesRequest,
+    GroupFilesResponse, GroupResponse, GroupWithThumbnail, GroupsWithThumbnailResponse,
+    MembershipFailure, PinataGroupData, PinataGroupResponse, UpdateGroupRequest,
 };
 
 pub mod uploads;
 pub use uploads::{
-    GroupInfo, PhotoMetadata, PhotoUpload, PinataUploadResponse, UploadResponse, UploadedFileInfo,
+    FileTiming, GroupInfo, PhotoMetadata, PhotoMetadataPatch, PhotoUpload, PinataUploadResponse,
+    PreviewUrls, UploadParams, UploadResponse, UploadedFileInfo,
+};
+
+pub mod catalog;
+pub use catalog::{
+    CatalogChange, CatalogChangesResponse, CatalogDocument, CatalogGroup, ChangeKind, UrlTemplates,
 };
 
 pub mod categories;
-pub use categories::{CategoryParams, CategoryResponse};
+pub use categories::{CategoryParams, CategoryResponse, CategoryWarning};
+
+pub mod webhooks;
+pub use webhooks::{WebhookEvent, WebhookResponse};
+
+pub mod shares;
+pub use shares::{ShareLink, ShareResponse, SharedGalleryResponse, SharesResponse};
+
+pub mod status;
+pub use status::{BackgroundJobs, ServiceStatus};
+
+pub mod versions;
+pub use versions::{ApiVersion, ApiVersionsResponse, VersionStatus};
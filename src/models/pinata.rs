@@ -1,7 +1,13 @@
+use super::{
+    attributes::PhotoAttributes,
+    dates::{local_rfc3339_option, rfc3339},
+};
+use crate::imaging;
+use chrono::{DateTime, FixedOffset, Utc};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(from = "PinataFileData")]
 pub struct PinataFile {
     pub id: String,
     pub name: String,
@@ -10,8 +16,96 @@ pub struct PinataFile {
     pub number_of_files: u64,
     pub mime_type: String,
     pub group_id: String,
-    pub keyvalues: HashMap<String, String>,
-    pub created_at: String,
+    #[serde(deserialize_with = "PhotoAttributes::deserialize_keyvalues")]
+    pub keyvalues: PhotoAttributes,
+    #[serde(with = "rfc3339")]
+    pub created_at: DateTime<Utc>,
+    // Read from the keyvalues the backend derived after upload. Width and height are
+    // upright, `orientation` is the EXIF value that was applied to get there.
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub orientation: Option<u8>,
+    // placeholder the gallery can draw while the image loads
+    pub blurhash: Option<String>,
+    // when the photo was taken, in the offset it was taken at
+    #[serde(default, with = "local_rfc3339_option")]
+    pub captured_at: Option<DateTime<FixedOffset>>,
+    // the Pinata account it was listed from, when that isn't the PINATA_JWT one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub account: Option<String>,
+}
+
+// a file as Pinata sends it, before the fields read from its keyvalues are filled in
+#[derive(Deserialize)]
+struct PinataFileData {
+    id: String,
+    name: String,
+    cid: String,
+    size: u64,
+    number_of_files: u64,
+    mime_type: String,
+    group_id: String,
+    #[serde(deserialize_with = "PhotoAttributes::deserialize_keyvalues")]
+    keyvalues: PhotoAttributes,
+    #[serde(with = "rfc3339")]
+    created_at: DateTime<Utc>,
+}
+
+impl From<PinataFileData> for PinataFile {
+    fn from(data: PinataFileData) -> Self {
+        Self {
+            id: data.id,
+            name: data.name,
+            cid: data.cid,
+            size: data.size,
+            number_of_files: data.number_of_files,
+            mime_type: data.mime_type,
+            group_id: data.group_id,
+            keyvalues: data.keyvalues,
+            created_at: data.created_at,
+            width: None,
+            height: None,
+            orientation: None,
+            blurhash: None,
+            captured_at: None,
+            account: None,
+        }
+        .with_keyvalue_fields()
+    }
+}
+
+impl PinataFile {
+    // fill in the typed fields that mirror keyvalues the backend derived
+    pub fn with_keyvalue_fields(mut self) -> Self {
+        let extra = &self.keyvalues.extra;
+        self.width = extra.get(imaging::WIDTH).and_then(|v| v.parse().ok());
+        self.height = extra.get(imaging::HEIGHT).and_then(|v| v.parse().ok());
+        self.orientation = extra.get(imaging::ORIENTATION).and_then(|v| v.parse().ok());
+        self.blurhash = extra.get(imaging::BLURHASH).cloned();
+        self.captured_at = extra
+            .get(imaging::CAPTURED_AT)
+            .and_then(|v| DateTime::parse_from_rfc3339(v).ok());
+        self
+    }
+
+    // a file the backend pinned itself, a copy derived from another file or a
+    // manifest, not a photo in its own right
+    pub fn is_variant(&self) -> bool {
+        let extra = &self.keyvalues.extra;
+        extra.contains_key(imaging::VARIANT_OF)
+            || extra.contains_key(imaging::UPLOAD_MANIFEST)
+            || extra.contains_key(imaging::INTEGRITY_MANIFEST)
+    }
+
+    // Point at the small thumbnail instead of the original, when one was pinned.
+    // The id and metadata stay the original's, so links to the photo still work.
+    pub fn into_small_thumbnail(mut self) -> Self {
+        if let Some(cid) = self.keyvalues.extra.get(imaging::THUMBNAIL_SMALL_CID) {
+            self.cid = cid.clone();
+            self.mime_type = "image/webp".to_string();
+        }
+        self
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -19,5 +113,9 @@ pub struct PinataGroup {
     pub id: String,
     pub name: String,
     pub is_public: Option<bool>,
-    pub created_at: String,
+    #[serde(with = "rfc3339")]
+    pub created_at: DateTime<Utc>,
+    // as on `PinataFile`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub account: Option<String>,
 }
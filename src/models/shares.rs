@@ -0,0 +1,183 @@
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    PinataGroup,
+    dates::{rfc3339, rfc3339_option},
+};
+
+// a client's access to one gallery, without an account
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShareLink {
+    pub token: String,
+    pub group_id: String,
+    // e.g. the client's name, shown in the admin listing
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    #[serde(with = "rfc3339")]
+    pub created_at: DateTime<Utc>,
+    #[serde(
+        default,
+        with = "rfc3339_option",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub expires_at: Option<DateTime<Utc>>,
+    #[serde(
+        default,
+        with = "rfc3339_option",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub revoked_at: Option<DateTime<Utc>>,
+    #[serde(flatten)]
+    pub policy: SharePolicy,
+    #[serde(default)]
+    pub usage: ShareUsage,
+    // when the group was told the link had expired
+    #[serde(
+        default,
+        with = "rfc3339_option",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub expiry_noticed_at: Option<DateTime<Utc>>,
+}
+
+impl ShareLink {
+    pub fn is_active(&self, now: DateTime<Utc>) -> bool {
+        self.revoked_at.is_none() && self.expires_at.is_none_or(|expires| expires > now)
+    }
+
+    // downloads left before the limit, None when unlimited
+    pub fn remaining_downloads(&self) -> Option<u32> {
+        self.policy
+            .max_downloads
+            .map(|max| max.saturating_sub(self.usage.downloads))
+    }
+}
+
+// what a share link lets its holder download
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SharePolicy {
+    // unlimited when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_downloads: Option<u32>,
+    // serve the originals rather than a resized copy
+    #[serde(default)]
+    pub full_resolution: bool,
+    // mark downloads as proofs
+    #[serde(default)]
+    pub watermark: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ShareUsage {
+    pub views: u32,
+    pub downloads: u32,
+    // downloads per file id
+    pub files: BTreeMap<String, u32>,
+    #[serde(
+        default,
+        with = "rfc3339_option",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub last_accessed_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateShareRequest {
+    pub group_id: String,
+    pub label: Option<String>,
+    // never expires when unset
+    pub expires_in_secs: Option<u64>,
+    #[serde(flatten)]
+    pub policy: SharePolicy,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ShareResponse {
+    pub success: bool,
+    pub share: ShareLink,
+    // the gallery page the link opens, when FRONTEND_URL is set
+    pub url: Option<String>,
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SharesResponse {
+    pub success: bool,
+    pub shares: Vec<ShareLink>,
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SharedGalleryResponse {
+    pub success: bool,
+    pub group: PinataGroup,
+    pub images: Vec<SharedImage>,
+    #[serde(with = "rfc3339_option", skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<DateTime<Utc>>,
+    // downloads left on the link, None when unlimited
+    pub remaining_downloads: Option<u32>,
+    // whether downloads are the originals or resized copies
+    pub full_resolution: bool,
+    pub message: Option<String>,
+}
+
+// A file as a share link shows it: gateway links to its thumbnail and display copies,
+// never the original unless the link serves full resolution. Downloads go through
+// `/share/{token}/files/{id}` so the link's policy applies.
+#[derive(Debug, Serialize)]
+pub struct SharedImage {
+    pub id: String,
+    pub name: String,
+    pub description: Option<String>,
+    pub mime_type: String,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub blurhash: Option<String>,
+    // None without a gateway
+    pub thumbnail_url: Option<String>,
+    // None without a gateway, or when downloads are watermarked and the unmarked
+    // copy mustn't be handed out
+    pub display_url: Option<String>,
+    // only when full resolution is allowed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cid: Option<String>,
+}
+
+// a client address the download caps turned away or slowed down
+#[derive(Debug, Serialize)]
+pub struct LimitedClient {
+    pub ip: String,
+    pub open_streams: usize,
+    pub limited_streams: u64,
+    pub limited_bandwidth: u64,
+    #[serde(with = "rfc3339")]
+    pub last_limited_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LimitedClientsResponse {
+    pub success: bool,
+    pub clients: Vec<LimitedClient>,
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ShareUsageResponse {
+    pub success: bool,
+    pub token: String,
+    pub group_id: String,
+    pub active: bool,
+    pub remaining_downloads: Option<u32>,
+    pub policy: SharePolicy,
+    pub usage: ShareUsage,
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct QrParams {
+    // edge length in pixels
+    pub size: Option<u32>,
+}
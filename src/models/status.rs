@@ -0,0 +1,23 @@
+use serde::Serialize;
+
+// background tasks running, by what they do
+#[derive(Debug, Serialize)]
+pub struct BackgroundJobs {
+    pub uploads: usize,
+    pub processing: usize,
+    pub reencode: usize,
+    pub notifications: usize,
+}
+
+// What a restart right now would interrupt. The request asking isn't counted.
+#[derive(Debug, Serialize)]
+pub struct ServiceStatus {
+    pub success: bool,
+    // nothing below is in progress
+    pub idle: bool,
+    pub requests_in_flight: usize,
+    // admitted to the upload queue, parked ones and background jobs included
+    pub uploads_in_progress: usize,
+    pub event_streams_open: usize,
+    pub background_jobs: BackgroundJobs,
+}
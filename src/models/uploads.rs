@@ -1,5 +1,10 @@
+use axum::http::StatusCode;
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+
+use super::PinataFile;
+use super::dates::{rfc3339, rfc3339_option};
 
 #[derive(Debug, Deserialize)]
 pub struct GroupInfo {
@@ -16,7 +21,7 @@ pub struct PhotoUpload {
     pub metadata: PhotoMetadata,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PhotoMetadata {
     pub title: String,
     pub description: String,
@@ -27,14 +32,68 @@ pub struct PhotoMetadata {
     pub aperture: String,
     #[serde(rename = "shutterSpeed")]
     pub shutter_speed: String, // Remeber - "shutterSpeed" in the JSON
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub rating: Option<u8>,
+    // where it was shot and who for, stored as plain keyvalues
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub location: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client: Option<String>,
+}
+
+// Fields to change on an uploaded photo; anything left out keeps its current value
+// and an empty string clears it. `title` renames the file itself.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PhotoMetadataPatch {
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub category: Option<String>,
+    pub camera: Option<String>,
+    pub lens: Option<String>,
+    pub iso: Option<String>,
+    pub aperture: Option<String>,
+    #[serde(rename = "shutterSpeed")]
+    pub shutter_speed: Option<String>,
+    pub tags: Option<Vec<String>>,
+    pub rating: Option<u8>,
 }
 
 #[derive(Debug, Serialize)]
 pub struct UploadResponse {
+    // false when any file failed; the others are still pinned
     pub success: bool,
     pub files: Vec<UploadedFileInfo>,
+    pub failed: Vec<UploadFailure>,
     pub group_id: Option<String>,
     pub message: Option<String>,
+    // the pinned manifest of the files published, when there were any
+    pub manifest_cid: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UploadFailure {
+    // the form field the file was sent in, e.g. `file_1`
+    pub field: String,
+    pub filename: String,
+    pub message: String,
+    // not sent; a batch whose files were all refused for the same reason gets its status
+    #[serde(skip)]
+    pub status: StatusCode,
+}
+
+// The whole upload went over UPLOAD_MAX_REQUEST_BYTES; nothing after `field` was
+// read. Reported as members of the problem.
+#[derive(Debug, Serialize)]
+pub struct UploadTooLarge {
+    // the file being received when the limit was reached
+    pub field: Option<String>,
+    pub filename: Option<String>,
+    pub limit_bytes: u64,
+    // streamed before the limit was reached, and so already pinned
+    pub files: Vec<UploadedFileInfo>,
 }
 
 #[derive(Debug, Serialize)]
@@ -43,6 +102,101 @@ pub struct UploadedFileInfo {
     pub name: String,
     pub cid: String,
     pub group_id: Option<String>, // Other fields returned from Pinata
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub preview: Option<PreviewUrls>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timing: Option<FileTiming>,
+    // the same content was already pinned, and this is that file rather than a new one
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub duplicate: bool,
+    // location and camera identifying EXIF tags taken out before pinning
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub stripped_exif_tags: Vec<String>,
+    // not sent; what the batch's manifest records of it
+    #[serde(skip)]
+    pub manifest: Option<ManifestFile>,
+}
+
+// A batch's receipt, pinned as JSON once its files are: each file published, the
+// checksum of its content and the metadata it was published with
+#[derive(Debug, Serialize)]
+pub struct UploadManifest {
+    pub version: u32,
+    #[serde(with = "rfc3339")]
+    pub created_at: DateTime<Utc>,
+    pub request_id: Option<String>,
+    pub account: String,
+    pub group_id: Option<String>,
+    pub files: Vec<ManifestFile>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ManifestFile {
+    pub id: String,
+    pub name: String,
+    pub cid: String,
+    pub group_id: Option<String>,
+    pub mime_type: String,
+    pub size_bytes: u64,
+    // hex SHA-256 of the content as pinned
+    pub sha256: String,
+    // already pinned before, and this is that file
+    pub duplicate: bool,
+    pub metadata: PhotoMetadata,
+}
+
+impl From<PinataFile> for UploadedFileInfo {
+    fn from(file: PinataFile) -> Self {
+        Self {
+            id: file.id,
+            name: file.name,
+            cid: file.cid,
+            group_id: Some(file.group_id).filter(|g| !g.is_empty()),
+            preview: None,
+            timing: None,
+            duplicate: false,
+            stripped_exif_tags: Vec::new(),
+            manifest: None,
+        }
+    }
+}
+
+// signed gateway links so a just-uploaded photo can be shown straight away
+#[derive(Debug, Clone, Serialize)]
+pub struct PreviewUrls {
+    pub original: String,
+    pub thumbnail: String,
+    #[serde(with = "rfc3339")]
+    pub expires_at: DateTime<Utc>,
+}
+
+// which pipeline sent an upload, recorded on every file it pins
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UploadSource {
+    // e.g. web, cli, watch-folder
+    pub via: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_version: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct UploadParams {
+    // include per-file timing in the response
+    #[serde(default)]
+    pub debug_timing: bool,
+    // receive the files, then pin them in a background job instead of holding the request open
+    #[serde(default)]
+    pub background: bool,
+}
+
+// where the time went for one uploaded file, in milliseconds
+#[derive(Debug, Clone, Serialize)]
+pub struct FileTiming {
+    pub size_bytes: u64,
+    pub validation_ms: f64,
+    pub group_resolution_ms: f64,
+    pub upstream_upload_ms: f64,
+    pub total_ms: f64,
 }
 
 #[derive(Debug, Deserialize)]
@@ -55,10 +209,314 @@ pub struct PinataUploadData {
     pub id: String,
     pub name: String,
     pub cid: String,
-    pub created_at: String,
+    #[serde(with = "rfc3339")]
+    pub created_at: DateTime<Utc>,
     pub size: u64,
     pub number_of_files: u32,
     pub mime_type: String,
     pub group_id: Option<String>,
     pub keyvalues: Option<HashMap<String, String>>,
 }
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    // every file was attempted; see each file for how it went
+    Completed,
+    // the job couldn't run at all, e.g. its group couldn't be created
+    Failed,
+    // the server restarted before the job finished
+    Interrupted,
+}
+
+impl JobStatus {
+    pub fn is_finished(self) -> bool {
+        !matches!(self, Self::Queued | Self::Running)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobFileStatus {
+    Queued,
+    Uploading,
+    Uploaded,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UploadJobFile {
+    // the form field the file was sent in
+    pub field: String,
+    pub filename: String,
+    pub size_bytes: u64,
+    pub status: JobFileStatus,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cid: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UploadJob {
+    pub id: String,
+    pub status: JobStatus,
+    pub group_id: Option<String>,
+    #[serde(with = "rfc3339")]
+    pub created_at: DateTime<Utc>,
+    #[serde(
+        default,
+        with = "rfc3339_option",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub finished_at: Option<DateTime<Utc>>,
+    pub files: Vec<UploadJobFile>,
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UploadJobResponse {
+    pub success: bool,
+    pub job: UploadJob,
+    pub message: Option<String>,
+}
+
+// pushed to `GET /upload/jobs/{id}/events` subscribers as the job runs
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum JobEvent {
+    // the job as it stood when the subscriber connected
+    Snapshot {
+        job: UploadJob,
+    },
+    Started {
+        group_id: Option<String>,
+    },
+    FileStarted {
+        index: usize,
+        field: String,
+    },
+    // bytes of the current attempt handed to Pinata; a retry starts again from 0
+    Progress {
+        index: usize,
+        bytes_sent: u64,
+        size_bytes: u64,
+    },
+    FileFinished {
+        index: usize,
+        file: UploadJobFile,
+    },
+    Finished {
+        status: JobStatus,
+        message: Option<String>,
+    },
+}
+
+impl JobEvent {
+    // the SSE event name, matching the `type` field
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Snapshot { .. } => "snapshot",
+            Self::Started { .. } => "started",
+            Self::FileStarted { .. } => "file_started",
+            Self::Progress { .. } => "progress",
+            Self::FileFinished { .. } => "file_finished",
+            Self::Finished { .. } => "finished",
+        }
+    }
+}
+
+// `POST /upload/sessions`: announce a file that will be sent in chunks
+#[derive(Debug, Deserialize)]
+pub struct CreateUploadSession {
+    pub filename: String,
+    pub size_bytes: u64,
+    pub metadata: PhotoMetadata,
+    #[serde(default)]
+    pub create_new_group: bool,
+    pub group_id: Option<String>,
+    pub group_name: Option<String>,
+    // the Pinata account to pin into, else routed by the group
+    pub account: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SessionStatus {
+    // waiting for more chunks, or for a retry once every byte is in
+    Receiving,
+    Pinning,
+    Completed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UploadSession {
+    pub id: String,
+    pub filename: String,
+    pub size_bytes: u64,
+    // the offset the next chunk has to start at
+    pub received_bytes: u64,
+    pub status: SessionStatus,
+    pub metadata: PhotoMetadata,
+    pub create_new_group: bool,
+    pub group_id: Option<String>,
+    pub group_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub account: Option<String>,
+    #[serde(with = "rfc3339")]
+    pub created_at: DateTime<Utc>,
+    #[serde(with = "rfc3339")]
+    pub updated_at: DateTime<Utc>,
+    // the pinned file, once completed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cid: Option<String>,
+    // why the last attempt to pin failed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    // taken from the request that opened the session; older sessions have none
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<UploadSource>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UploadSessionResponse {
+    pub success: bool,
+    pub session: UploadSession,
+    pub message: Option<String>,
+}
+
+// What every frame of a capture session is pinned with. Frames only send pixels,
+// so anything they should carry is set here once.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CaptureDefaults {
+    pub category: String,
+    pub description: String,
+    pub camera: String,
+    pub lens: String,
+    pub iso: String,
+    pub aperture: String,
+    #[serde(rename = "shutterSpeed")]
+    pub shutter_speed: String,
+    pub tags: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub location: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client: Option<String>,
+}
+
+impl CaptureDefaults {
+    // the metadata one frame is pinned with
+    pub fn metadata(&self, title: String, rating: Option<u8>) -> PhotoMetadata {
+        PhotoMetadata {
+            title,
+            description: self.description.clone(),
+            category: self.category.clone(),
+            camera: self.camera.clone(),
+            lens: self.lens.clone(),
+            iso: self.iso.clone(),
+            aperture: self.aperture.clone(),
+            shutter_speed: self.shutter_speed.clone(),
+            tags: self.tags.clone(),
+            rating,
+            location: self.location.clone(),
+            client: self.client.clone(),
+        }
+    }
+}
+
+// a capture session pins into an existing group, or one created for it
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CreateCaptureSession {
+    pub group_id: Option<String>,
+    pub group_name: Option<String>,
+    pub account: Option<String>,
+    #[serde(default)]
+    pub defaults: CaptureDefaults,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CaptureSession {
+    pub id: String,
+    pub group_id: String,
+    // the Pinata account the group is in, when it isn't the PINATA_JWT one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub account: Option<String>,
+    pub defaults: CaptureDefaults,
+    #[serde(with = "rfc3339")]
+    pub created_at: DateTime<Utc>,
+    #[serde(with = "rfc3339")]
+    pub updated_at: DateTime<Utc>,
+    // frames sent so far, numbering the next one
+    pub frames: u64,
+    pub photos: u64,
+    pub failed: u64,
+    pub bytes: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_file_id: Option<String>,
+    // every frame pinned, in order
+    #[serde(default)]
+    pub file_ids: Vec<String>,
+    // set once the session is closed, after which it takes no more frames
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub summary: Option<CaptureSummary>,
+}
+
+// What a closed capture session produced, worked out from its pinned frames as they
+// stand at close, so ratings and gear edited during the shoot are counted
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CaptureSummary {
+    #[serde(with = "rfc3339")]
+    pub started_at: DateTime<Utc>,
+    #[serde(with = "rfc3339")]
+    pub closed_at: DateTime<Utc>,
+    pub duration_secs: i64,
+    pub frames: u64,
+    pub photos: u64,
+    pub failed: u64,
+    pub bytes: u64,
+    // photos per camera and per lens; frames without one are counted as "unknown"
+    pub cameras: BTreeMap<String, u64>,
+    pub lenses: BTreeMap<String, u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cover_file_id: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CloseCaptureSession {
+    // make the highest rated frame the group's cover
+    pub auto_cover: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CaptureSessionResponse {
+    pub success: bool,
+    pub session: CaptureSession,
+    pub message: Option<String>,
+}
+
+// all optional, a frame is usually just its bytes
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CapturePhotoParams {
+    pub filename: Option<String>,
+    pub title: Option<String>,
+    pub rating: Option<u8>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CapturePhotoResponse {
+    pub success: bool,
+    pub session_id: String,
+    pub frame: u64,
+    pub file: UploadedFileInfo,
+    pub message: Option<String>,
+}
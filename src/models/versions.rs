@@ -0,0 +1,33 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VersionStatus {
+    Supported,
+    // still served, but with a date after which it may not be
+    Deprecated,
+    // past its sunset date
+    Sunset,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ApiVersion {
+    pub version: &'static str,
+    // what its paths start with, e.g. `/api/v1`
+    pub prefix: String,
+    pub status: VersionStatus,
+    #[serde(with = "crate::models::dates::rfc3339_option")]
+    pub deprecated_at: Option<DateTime<Utc>>,
+    #[serde(with = "crate::models::dates::rfc3339_option")]
+    pub sunset_at: Option<DateTime<Utc>>,
+    pub successor: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ApiVersionsResponse {
+    pub success: bool,
+    // the newest version, which new clients should use
+    pub current: &'static str,
+    pub versions: Vec<ApiVersion>,
+}
@@ -0,0 +1,25 @@
+use serde::{Deserialize, Serialize};
+
+// A catalog change reported by Pinata or another signed sender, e.g.
+// `{"event": "file.updated", "data": {"id": "..."}}`
+#[derive(Debug, Deserialize)]
+pub struct WebhookEvent {
+    pub event: String,
+    #[serde(default)]
+    pub data: WebhookData,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct WebhookData {
+    // file id for `file.*` events
+    pub id: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct WebhookResponse {
+    pub success: bool,
+    pub event: String,
+    // what the receiver did about it: updated, removed, resync or ignored
+    pub action: &'static str,
+    pub message: Option<String>,
+}
@@ -0,0 +1,125 @@
+use std::time::Duration;
+
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use metrics::counter;
+use reqwest::Client;
+use serde::Serialize;
+use sha2::Sha256;
+use tracing::{Instrument, info_span, warn};
+
+use crate::activity::{Activity, Tracked};
+use crate::config::WebhookConfig;
+use crate::errors::ApiError;
+use crate::group_notifications::{self, GroupEvent};
+use crate::telemetry::SendTraced;
+
+const SIGNATURE_HEADER: &str = "x-webhook-signature";
+const TIMESTAMP_HEADER: &str = "x-webhook-timestamp";
+
+// Outbound events, e.g. a shoot being closed, posted to WEBHOOK_NOTIFY_URL as
+// `{"event": "...", "data": {...}}`. Signed the way the receiver expects its own
+// deliveries when WEBHOOK_SECRET is set, so one secret covers both directions.
+#[derive(Clone)]
+pub struct Notifier {
+    http: Client,
+    url: Option<String>,
+    secret: Option<String>,
+}
+
+impl Notifier {
+    pub fn new(config: &WebhookConfig) -> Result<Self, ApiError> {
+        let http = Client::builder()
+            .connect_timeout(Duration::from_secs(10))
+            .timeout(Duration::from_secs(30))
+            .build()?;
+
+        Ok(Self {
+            http,
+            url: config.notify_url.clone(),
+            secret: config.secret.clone(),
+        })
+    }
+
+    // Deliver in the background and best effort: whatever happened is already done.
+    // Returns whether there's anywhere to deliver to.
+    pub fn send(&self, event: &'static str, data: impl Serialize) -> bool {
+        let Some(url) = self.url.clone() else {
+            return false;
+        };
+        let body = match serde_json::to_vec(&serde_json::json!({ "event": event, "data": data })) {
+            Ok(body) => body,
+            Err(e) => {
+                warn!("Failed to encode {event} notification: {e}");
+                return false;
+            }
+        };
+
+        let timestamp = Utc::now().timestamp().to_string();
+        let mut request = self
+            .http
+            .post(url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header(TIMESTAMP_HEADER, &timestamp);
+        if let Some(signature) = self
+            .secret
+            .as_deref()
+            .and_then(|s| sign(s, &timestamp, &body))
+        {
+            request = request.header(SIGNATURE_HEADER, format!("sha256={signature}"));
+        }
+
+        let tracked = Tracked::start(Activity::Notification);
+        let span = info_span!("notification", event);
+        tokio::spawn(
+            async move {
+                let _tracked = tracked;
+                let result = match request.body(body).send_traced().await {
+                    Ok(response) if response.status().is_success() => "success",
+                    Ok(response) => {
+                        warn!("{event} notification was refused: {}", response.status());
+                        "failure"
+                    }
+                    Err(e) => {
+                        warn!("Failed to deliver {event} notification: {e}");
+                        "failure"
+                    }
+                };
+                counter!("webhook_notifications_total", "event" => event, "result" => result)
+                    .increment(1);
+            }
+            .instrument(span),
+        );
+        true
+    }
+
+    // as `send`, unless the preferences of the group it's about turn it off
+    pub fn send_for_group(
+        &self,
+        group_id: Option<&str>,
+        event: GroupEvent,
+        data: impl Serialize,
+    ) -> bool {
+        if !group_notifications::wants(group_id, event) {
+            counter!("webhook_notifications_total", "event" => event.name(), "result" => "muted")
+                .increment(1);
+            return false;
+        }
+        self.send(event.name(), data)
+    }
+}
+
+// hex HMAC-SHA256 of `<timestamp>.<body>`
+fn sign(secret: &str, timestamp: &str, body: &[u8]) -> Option<String> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).ok()?;
+    mac.update(timestamp.as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    Some(
+        mac.finalize()
+            .into_bytes()
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect(),
+    )
+}
@@ -0,0 +1,120 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use tracing::{Instrument, info, info_span, warn};
+
+use crate::errors::ApiError;
+use crate::pinata::{FilesQuery, ListOptions, PinataClient, groups, list_files};
+
+// Groups created for an upload that hasn't finished yet, by id. One whose upload is
+// cut short, by the client going away or the server shutting down, is deleted again
+// if nothing was pinned into it.
+#[derive(Clone, Default)]
+pub struct PartialGroups {
+    open: Arc<Mutex<HashMap<String, PinataClient>>>,
+}
+
+// A group just created for an upload. Unless the upload gets to `finish` it, dropping
+// the last clone cleans the group up.
+pub struct PartialGroup {
+    groups: PartialGroups,
+    group_id: String,
+    finished: AtomicBool,
+}
+
+impl PartialGroups {
+    pub fn track(&self, pinata: &PinataClient, group_id: &str) -> Arc<PartialGroup> {
+        self.open
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(group_id.to_string(), pinata.clone());
+        Arc::new(PartialGroup {
+            groups: self.clone(),
+            group_id: group_id.to_string(),
+            finished: AtomicBool::new(false),
+        })
+    }
+
+    // clean up the groups of uploads still running, once the server has stopped
+    // waiting for them
+    pub async fn clean_up(&self) {
+        let open: Vec<String> = self
+            .open
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .keys()
+            .cloned()
+            .collect();
+        for group_id in open {
+            self.clean_up_group(&group_id).await;
+        }
+    }
+
+    async fn clean_up_group(&self, group_id: &str) {
+        let Some(pinata) = self
+            .open
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(group_id)
+            .cloned()
+        else {
+            return;
+        };
+        match delete_if_empty(&pinata, group_id).await {
+            Ok(true) => info!("Deleted group {group_id}, its upload was cut short"),
+            Ok(false) => {}
+            Err(e) => warn!("Failed to clean up group {group_id} of a cut short upload: {e}"),
+        }
+        self.open
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(group_id);
+    }
+}
+
+impl PartialGroup {
+    // the upload is over, and the group stays whatever went into it
+    pub fn finish(&self) {
+        self.finished.store(true, Ordering::SeqCst);
+        self.groups
+            .open
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(&self.group_id);
+    }
+}
+
+impl Drop for PartialGroup {
+    fn drop(&mut self) {
+        if self.finished.load(Ordering::SeqCst) {
+            return;
+        }
+        // stays listed until it's done, so a shutdown that stops this task still
+        // gets to the group
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+        let groups = self.groups.clone();
+        let group_id = self.group_id.clone();
+        let span = info_span!("partial_group_cleanup", group_id = %group_id);
+        runtime.spawn(async move { groups.clean_up_group(&group_id).await }.instrument(span));
+    }
+}
+
+// files already pinned into the group keep it
+async fn delete_if_empty(pinata: &PinataClient, group_id: &str) -> Result<bool, ApiError> {
+    let options = ListOptions {
+        limit: Some(1),
+        include_variants: true,
+        ..ListOptions::default()
+    };
+    if !list_files(pinata, FilesQuery::new().group(group_id), options)
+        .await?
+        .is_empty()
+    {
+        return Ok(false);
+    }
+    groups::delete_group(pinata, group_id).await?;
+    Ok(true)
+}
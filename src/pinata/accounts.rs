@@ -0,0 +1,121 @@
+use std::sync::Arc;
+
+use super::{PinataClient, groups};
+use crate::config::PinataConfig;
+use crate::errors::ApiError;
+
+// a configured account and the new groups routed to it
+struct Account {
+    client: PinataClient,
+    group_prefixes: Vec<String>,
+}
+
+// Every configured Pinata account, the PINATA_JWT one first. Listings cover all of
+// them unless narrowed to one by `only`, for requests naming an account and for
+// everything an upload does once its account is picked.
+#[derive(Clone)]
+pub struct PinataAccounts {
+    all: Arc<Vec<Account>>,
+    only: Option<usize>,
+}
+
+impl PinataAccounts {
+    pub fn new(primary: &PinataClient, config: &PinataConfig) -> Self {
+        let mut all = vec![Account {
+            client: primary.clone(),
+            group_prefixes: Vec::new(),
+        }];
+        all.extend(config.accounts.iter().map(|account| Account {
+            client: primary.for_account(account),
+            group_prefixes: account.group_prefixes.clone(),
+        }));
+
+        Self {
+            all: Arc::new(all),
+            only: None,
+        }
+    }
+
+    pub fn is_single(&self) -> bool {
+        self.all.len() == 1
+    }
+
+    // the PINATA_JWT account's name, whose files and groups aren't tagged with it
+    pub fn primary(&self) -> &str {
+        self.all[0].client.account()
+    }
+
+    // the account everything but listings goes to: the one narrowed to, else the primary
+    pub fn client(&self) -> &PinataClient {
+        &self.all[self.only.unwrap_or_default()].client
+    }
+
+    // the account listings are narrowed to, if any
+    pub fn scope(&self) -> Option<&str> {
+        self.only.map(|index| self.all[index].client.account())
+    }
+
+    // the accounts a listing covers
+    pub fn listed(&self) -> impl Iterator<Item = &PinataClient> {
+        self.all
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| self.only.is_none_or(|only| only == *index))
+            .map(|(_, account)| &account.client)
+    }
+
+    // narrowed to the account called `name`
+    pub fn only(&self, name: &str) -> Result<Self, ApiError> {
+        let Some(index) = self.all.iter().position(|a| a.client.account() == name) else {
+            let names: Vec<&str> = self.all.iter().map(|a| a.client.account()).collect();
+            return Err(ApiError::Validation(format!(
+                "There is no Pinata account '{name}', expected one of: {}",
+                names.join(", ")
+            )));
+        };
+        Ok(Self {
+            all: self.all.clone(),
+            only: Some(index),
+        })
+    }
+
+    // all of them again, for results shared between requests
+    pub fn unscoped(&self) -> Self {
+        Self {
+            all: self.all.clone(),
+            only: None,
+        }
+    }
+
+    // the account a new group called `name` is created in: the first with a prefix
+    // the name starts with, else the primary one
+    pub fn for_new_group(&self, name: &str) -> &str {
+        self.all
+            .iter()
+            .find(|account| {
+                account
+                    .group_prefixes
+                    .iter()
+                    .any(|prefix| name.starts_with(prefix.as_str()))
+            })
+            .unwrap_or(&self.all[0])
+            .client
+            .account()
+    }
+
+    // the account holding the group `group_id`, asked of each in turn when there are
+    // several; the primary one when none has it, so uploads fail there as they did
+    pub async fn for_group(&self, group_id: &str) -> Result<&str, ApiError> {
+        if !self.is_single() {
+            for account in self.all.iter() {
+                if groups::get_own_group(&account.client, group_id)
+                    .await?
+                    .is_some()
+                {
+                    return Ok(account.client.account());
+                }
+            }
+        }
+        Ok(self.primary())
+    }
+}
@@ -0,0 +1,226 @@
+use reqwest::{Method, multipart::Form, multipart::Part};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::time::Duration;
+use tracing::debug;
+
+use super::{FilesQuery, PinataClient, SortOrder, rate_limit};
+use crate::errors::ApiError;
+use crate::models::favourites::{PinataFilesData, PinataFilesResponse};
+use crate::models::pinata::PinataFile;
+use crate::models::uploads::{PinataUploadResponse, UploadedFileInfo};
+use crate::telemetry::SendTraced;
+
+#[derive(Debug, Deserialize)]
+struct FileEnvelope {
+    data: PinataFile,
+}
+
+// largest page Pinata will return for a files listing
+pub const MAX_PAGE_SIZE: usize = 1000;
+
+// How a paginated listing is walked
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ListOptions {
+    // stop once this many files have been collected
+    pub limit: Option<usize>,
+    // files per upstream page, sized from `limit` when unset
+    pub page_size: Option<usize>,
+    // sort by creation date
+    pub order: Option<SortOrder>,
+    // also return the thumbnails and display copies the backend pinned, which
+    // aren't photos of their own and are left out of every listing otherwise
+    pub include_variants: bool,
+    // wait on the rate limiter between pages, so a background walk leaves
+    // room for the requests being served
+    pub throttle: bool,
+}
+
+impl ListOptions {
+    pub fn limit(limit: Option<usize>) -> Self {
+        Self {
+            limit,
+            ..Self::default()
+        }
+    }
+}
+
+// fetch a single page of files matching `query`
+pub async fn fetch_files_page(
+    pinata: &PinataClient,
+    query: &FilesQuery,
+) -> Result<PinataFilesData, ApiError> {
+    let url = query.url(pinata.api_url())?;
+    debug!("Requesting URL: {url}");
+
+    let response = pinata.request(Method::GET, url).send_traced().await?;
+
+    let data: PinataFilesResponse =
+        super::read_json(super::ensure_success(response).await?).await?;
+    Ok(data.data)
+}
+
+// walk every page of `query`, stopping early once `options.limit` is reached
+pub async fn list_files(
+    pinata: &PinataClient,
+    mut query: FilesQuery,
+    options: ListOptions,
+) -> Result<Vec<PinataFile>, ApiError> {
+    let mut all_files = Vec::new();
+
+    if let Some(order) = options.order {
+        query = query.order(order);
+    }
+    // ask upstream for no more than we need, so small limits are a single request
+    if let Some(page_size) = options.page_size.or(options.limit) {
+        query = query.limit(page_size.clamp(1, MAX_PAGE_SIZE));
+    }
+
+    loop {
+        let data = fetch_files_page(pinata, &query).await?;
+        debug!("Found {} files", data.files.len());
+
+        // add files to our collection
+        all_files.extend(
+            data.files
+                .into_iter()
+                .filter(|file| options.include_variants || !file.is_variant()),
+        );
+
+        if let Some(limit_val) = options.limit
+            && all_files.len() >= limit_val
+        {
+            all_files.truncate(limit_val);
+            break;
+        }
+
+        // check for more pages
+        match data.next_page_token {
+            Some(token) => query.set_page_token(Some(token)),
+            None => break,
+        }
+        if options.throttle {
+            rate_limit::throttle().await;
+        }
+    }
+
+    debug!("Total files collected: {}", all_files.len());
+    Ok(all_files)
+}
+
+pub async fn fetch_images_from_group(
+    pinata: &PinataClient,
+    group_id: &str,
+    options: ListOptions,
+) -> Result<Vec<PinataFile>, ApiError> {
+    list_files(pinata, FilesQuery::new().group(group_id), options).await
+}
+
+pub async fn delete_file(pinata: &PinataClient, file_id: &str) -> Result<(), ApiError> {
+    let url = format!("{}/v3/files/public/{file_id}", pinata.api_url());
+    let response = pinata.request(Method::DELETE, url).send_traced().await?;
+
+    super::ensure_success(response).await?;
+    Ok(())
+}
+
+pub async fn get_file(pinata: &PinataClient, file_id: &str) -> Result<PinataFile, ApiError> {
+    let url = format!("{}/v3/files/public/{file_id}", pinata.api_url());
+    let response = pinata.request(Method::GET, url).send_traced().await?;
+
+    let data: FileEnvelope = super::read_json(super::ensure_success(response).await?).await?;
+    Ok(data.data)
+}
+
+// replace a file's name and/or keyvalues
+pub async fn update_file(
+    pinata: &PinataClient,
+    file_id: &str,
+    name: Option<&str>,
+    keyvalues: &HashMap<String, String>,
+) -> Result<PinataFile, ApiError> {
+    let mut body = serde_json::json!({ "keyvalues": keyvalues });
+    if let Some(name) = name {
+        body["name"] = serde_json::Value::String(name.to_string());
+    }
+
+    let url = format!("{}/v3/files/public/{file_id}", pinata.api_url());
+    let response = pinata
+        .request(Method::PUT, url)
+        .json(&body)
+        .send_traced()
+        .await?;
+
+    let data: FileEnvelope = super::read_json(super::ensure_success(response).await?).await?;
+    Ok(data.data)
+}
+
+// send an upload form to Pinata's uploads host, `retry` being how many times it
+// has been sent before
+pub async fn pin_form(
+    pinata: &PinataClient,
+    timeout: Duration,
+    form: Form,
+    retry: u32,
+) -> Result<UploadedFileInfo, ApiError> {
+    let response = pinata
+        .request(Method::POST, format!("{}/v3/files", pinata.uploads_url()))
+        .timeout(timeout)
+        .multipart(form)
+        .send_retry(retry)
+        .await
+        .map_err(ApiError::Request)?;
+    rate_limit::observe(response.headers());
+
+    // check if successful
+    let status = response.status();
+
+    if !status.is_success() {
+        let error_body = super::read_text(response).await?;
+        return Err(ApiError::Api(format!(
+            "Pinata API error ({}): {}",
+            status, error_body
+        )));
+    }
+
+    // parse the response to JSON
+    let data: PinataUploadResponse = super::read_json(response).await?;
+    debug!(file_id = %data.data.id, cid = %data.data.cid, "Pinned {}", data.data.name);
+
+    let file_info = UploadedFileInfo {
+        id: data.data.id,
+        name: data.data.name,
+        cid: data.data.cid,
+        group_id: data.data.group_id,
+        preview: None,
+        timing: None,
+        duplicate: false,
+        stripped_exif_tags: Vec::new(),
+        manifest: None,
+    };
+
+    Ok(file_info)
+}
+
+// pin bytes generated by the backend itself, e.g. a display variant, outside any group
+pub async fn pin_bytes(
+    pinata: &PinataClient,
+    timeout: Duration,
+    name: &str,
+    mime_type: &str,
+    data: Vec<u8>,
+    keyvalues: &HashMap<String, String>,
+) -> Result<UploadedFileInfo, ApiError> {
+    let part = Part::bytes(data)
+        .file_name(name.to_string())
+        .mime_str(mime_type)
+        .map_err(|e| ApiError::Api(format!("Invalid MIME type: {e}")))?;
+
+    let form = Form::new()
+        .text("network", "public")
+        .part("file", part)
+        .text("name", name.to_string())
+        .text("keyvalues", serde_json::to_string(keyvalues)?);
+
+    pin_form(pinata, timeout, form, 0).await
+}
@@ -0,0 +1,257 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use reqwest::Method;
+use serde::Deserialize;
+
+use super::PinataClient;
+use crate::errors::ApiError;
+use crate::imaging::{DISPLAY_CID, THUMBNAIL_LARGE_CID, THUMBNAIL_SMALL_CID};
+use crate::models::{
+    PinataFile,
+    catalog::{GridFile, ListedFile, ListingView, PreloadHint, UrlTemplates},
+    shares::{SharePolicy, SharedImage},
+    uploads::PreviewUrls,
+};
+use crate::telemetry::SendTraced;
+
+#[derive(Debug, Deserialize)]
+struct SignedUrlEnvelope {
+    data: String,
+}
+
+// ask Pinata to sign a gateway url so it can be fetched until `expires_at`
+pub async fn sign_url(
+    pinata: &PinataClient,
+    url: &str,
+    ttl: Duration,
+) -> Result<(String, DateTime<Utc>), ApiError> {
+    let now = Utc::now();
+    let body = serde_json::json!({
+        "url": url,
+        "expires": ttl.as_secs(),
+        "date": now.timestamp(),
+        "method": "GET",
+    });
+
+    let endpoint = format!("{}/v3/files/private/download_link", pinata.api_url());
+    let response = pinata
+        .request(Method::POST, endpoint)
+        .json(&body)
+        .send_traced()
+        .await?;
+
+    let data: SignedUrlEnvelope = super::read_json(super::ensure_success(response).await?).await?;
+    let expires_at = now + chrono::Duration::seconds(ttl.as_secs() as i64);
+    Ok((data.data, expires_at))
+}
+
+// placeholder substituted with a file's cid in url templates
+pub const CID_PLACEHOLDER: &str = "{cid}";
+
+fn original_url(gateway: &str, cid: &str) -> String {
+    format!("https://{gateway}/files/{cid}")
+}
+
+// Pinata's image optimisation resizes on the gateway, so the thumbnail is the same file
+fn thumbnail_url(gateway: &str, cid: &str, width: u32) -> String {
+    format!(
+        "{}?img-width={width}&img-fit=scale-down",
+        original_url(gateway, cid)
+    )
+}
+
+// gateway links with `{cid}` left in, for clients that build urls for many files at once
+pub fn url_templates(pinata: &PinataClient, thumbnail_width: u32) -> Option<UrlTemplates> {
+    let gateway = pinata.gateway()?;
+    Some(UrlTemplates {
+        original: original_url(gateway, CID_PLACEHOLDER),
+        thumbnail: thumbnail_url(gateway, CID_PLACEHOLDER, thumbnail_width),
+    })
+}
+
+// unsigned links to a public file and its thumbnail, or None without a gateway
+pub fn public_urls(
+    pinata: &PinataClient,
+    cid: &str,
+    thumbnail_width: u32,
+) -> Option<(String, String)> {
+    let gateway = pinata.gateway()?;
+    Some((
+        original_url(gateway, cid),
+        thumbnail_url(gateway, cid, thumbnail_width),
+    ))
+}
+
+// the first `count` of `files`, as the page should preload them; none without a
+// configured gateway
+pub fn preload_hints(
+    pinata: &PinataClient,
+    files: &[PinataFile],
+    count: usize,
+    thumbnail_width: u32,
+) -> Vec<PreloadHint> {
+    let Some(gateway) = pinata.gateway() else {
+        return Vec::new();
+    };
+    files
+        .iter()
+        .take(count)
+        .map(|file| PreloadHint {
+            file_id: file.id.clone(),
+            url: thumbnail_url(gateway, &file.cid, thumbnail_width),
+            width: file.width,
+            height: file.height,
+            blurhash: file.blurhash.clone(),
+        })
+        .collect()
+}
+
+// `files` as `view` lists them, grid tiles pointing at the small thumbnail pinned
+// for each, or the resized original when there's none
+pub fn listed_files(
+    pinata: &PinataClient,
+    files: Vec<PinataFile>,
+    view: ListingView,
+    thumbnail_width: u32,
+) -> Vec<ListedFile> {
+    match view {
+        ListingView::Detail => files
+            .into_iter()
+            .map(|file| ListedFile::Detail(Box::new(file)))
+            .collect(),
+        ListingView::Grid => files
+            .into_iter()
+            .map(|file| {
+                ListedFile::Grid(GridFile {
+                    thumbnail_url: pinata.gateway().map(|gateway| {
+                        variant_url(gateway, &file, THUMBNAIL_SMALL_CID, thumbnail_width)
+                    }),
+                    id: file.id,
+                    width: file.width,
+                    height: file.height,
+                    blurhash: file.blurhash,
+                })
+            })
+            .collect(),
+    }
+}
+
+// the variant of `file` pinned under the keyvalue `key`, or the original resized to
+// `width` by the gateway when there's none
+fn variant_url(gateway: &str, file: &PinataFile, key: &str, width: u32) -> String {
+    match file.keyvalues.extra.get(key) {
+        Some(cid) => original_url(gateway, cid),
+        None => thumbnail_url(gateway, &file.cid, width),
+    }
+}
+
+// `file` as a share link under `policy` shows it
+pub fn shared_image(
+    pinata: &PinataClient,
+    file: PinataFile,
+    policy: &SharePolicy,
+    thumbnail_width: u32,
+    display_width: u32,
+) -> SharedImage {
+    let gateway = pinata.gateway();
+    SharedImage {
+        thumbnail_url: gateway
+            .map(|gateway| variant_url(gateway, &file, THUMBNAIL_SMALL_CID, thumbnail_width)),
+        display_url: gateway
+            .filter(|_| !policy.watermark)
+            .map(|gateway| variant_url(gateway, &file, DISPLAY_CID, display_width)),
+        cid: policy.full_resolution.then_some(file.cid),
+        id: file.id,
+        name: file.name,
+        description: file.keyvalues.description,
+        mime_type: file.mime_type,
+        width: file.width,
+        height: file.height,
+        blurhash: file.blurhash,
+    }
+}
+
+// Every cid the gateway may have cached for a file: the original and the variants
+// the backend pinned from it
+pub fn file_cids(file: &PinataFile) -> Vec<String> {
+    let variants = [DISPLAY_CID, THUMBNAIL_SMALL_CID, THUMBNAIL_LARGE_CID]
+        .iter()
+        .filter_map(|key| file.keyvalues.extra.get(*key).cloned());
+    std::iter::once(file.cid.clone()).chain(variants).collect()
+}
+
+// the gateway links those cids are served under, the original resized to each of
+// `widths` included; empty without a configured gateway
+pub fn file_urls(pinata: &PinataClient, file: &PinataFile, widths: &[u32]) -> Vec<String> {
+    let Some(gateway) = pinata.gateway() else {
+        return Vec::new();
+    };
+    let mut urls: Vec<String> = file_cids(file)
+        .iter()
+        .map(|cid| original_url(gateway, cid))
+        .collect();
+    urls.extend(
+        widths
+            .iter()
+            .map(|width| thumbnail_url(gateway, &file.cid, *width)),
+    );
+    urls
+}
+
+// signed original and resized links for a cid, or None without a configured gateway
+pub async fn preview_urls(
+    pinata: &PinataClient,
+    cid: &str,
+    ttl: Duration,
+    thumbnail_width: u32,
+) -> Result<Option<PreviewUrls>, ApiError> {
+    let Some(gateway) = pinata.gateway() else {
+        return Ok(None);
+    };
+
+    let (original, expires_at) = sign_url(pinata, &original_url(gateway, cid), ttl).await?;
+    let (thumbnail, _) =
+        sign_url(pinata, &thumbnail_url(gateway, cid, thumbnail_width), ttl).await?;
+
+    Ok(Some(PreviewUrls {
+        original,
+        thumbnail,
+        expires_at,
+    }))
+}
+
+// download a file through a short-lived signed link, resized to `width` when given,
+// or None without a configured gateway
+pub async fn fetch(
+    pinata: &PinataClient,
+    cid: &str,
+    width: Option<u32>,
+    ttl: Duration,
+) -> Result<Option<reqwest::Response>, ApiError> {
+    let Some(gateway) = pinata.gateway() else {
+        return Ok(None);
+    };
+
+    let url = match width {
+        Some(width) => thumbnail_url(gateway, cid, width),
+        None => original_url(gateway, cid),
+    };
+    let (signed, _) = sign_url(pinata, &url, ttl).await?;
+    let response = pinata.http.get(signed).send_traced().await?;
+    Ok(Some(super::ensure_success(response).await?))
+}
+
+// whether the dedicated gateway answers at all; any HTTP response counts, since
+// the root path of a gateway isn't a file. `None` when no gateway is configured
+pub async fn probe(pinata: &PinataClient) -> Option<Result<(), ApiError>> {
+    let gateway = pinata.gateway()?;
+    let result = pinata
+        .http
+        .head(format!("https://{gateway}/"))
+        .send_traced()
+        .await
+        .map(|_| ())
+        .map_err(ApiError::from);
+    Some(result)
+}
@@ -0,0 +1,241 @@
+use reqwest::{Method, StatusCode};
+use serde::Deserialize;
+use tracing::debug;
+
+use super::{FilesQuery, GroupsQuery, ListOptions, PinataClient, list_files, rate_limit};
+use crate::errors::ApiError;
+use crate::logging::Redacted;
+use crate::models::{groups::PinataGroupResponse, pinata::PinataGroup};
+use crate::telemetry::SendTraced;
+
+#[derive(Debug, Deserialize)]
+struct GroupEnvelope {
+    data: PinataGroup,
+}
+
+const NAMESPACE_SEPARATOR: &str = "::";
+
+// the upstream name for a group created by this deployment
+pub fn namespaced_name(namespace: Option<&str>, name: &str) -> String {
+    match namespace {
+        Some(namespace) => format!("{namespace}{NAMESPACE_SEPARATOR}{name}"),
+        None => name.to_string(),
+    }
+}
+
+// the namespace a group name carries, and the name shown to clients
+fn split_namespace(name: &str) -> (Option<&str>, &str) {
+    match name.split_once(NAMESPACE_SEPARATOR) {
+        Some((namespace, rest))
+            if !namespace.is_empty()
+                && namespace
+                    .chars()
+                    .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-') =>
+        {
+            (Some(namespace), rest)
+        }
+        _ => (None, name),
+    }
+}
+
+// keep a group only if it belongs to `namespace`, with the prefix stripped from its name
+pub fn in_namespace(namespace: Option<&str>, mut group: PinataGroup) -> Option<PinataGroup> {
+    let (group_namespace, name) = split_namespace(&group.name);
+    if group_namespace != namespace {
+        return None;
+    }
+
+    group.name = name.to_string();
+    Some(group)
+}
+
+// strip this deployment's prefix from a single group, leaving other names untouched
+fn display_name(namespace: Option<&str>, mut group: PinataGroup) -> PinataGroup {
+    if let (Some(group_namespace), name) = split_namespace(&group.name)
+        && Some(group_namespace) == namespace
+    {
+        group.name = name.to_string();
+    }
+    group
+}
+
+pub async fn get_group(pinata: &PinataClient, group_id: &str) -> Result<PinataGroup, ApiError> {
+    let url = format!("{}/v3/groups/public/{group_id}", pinata.api_url());
+    let response = pinata.request(Method::GET, url).send_traced().await?;
+
+    let data: GroupEnvelope = super::read_json(super::ensure_success(response).await?).await?;
+    Ok(display_name(pinata.group_namespace(), data.data))
+}
+
+// A group this deployment may change: None when it doesn't exist or belongs to
+// another namespace, so callers can answer with a 404 either way
+pub async fn get_own_group(
+    pinata: &PinataClient,
+    group_id: &str,
+) -> Result<Option<PinataGroup>, ApiError> {
+    let url = format!("{}/v3/groups/public/{group_id}", pinata.api_url());
+    let response = pinata.request(Method::GET, url).send_traced().await?;
+    if response.status() == StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+
+    let data: GroupEnvelope = super::read_json(super::ensure_success(response).await?).await?;
+    Ok(in_namespace(pinata.group_namespace(), data.data))
+}
+
+// create a group, inside this deployment's namespace when it has one
+pub async fn create_group(
+    pinata: &PinataClient,
+    name: &str,
+    is_public: bool,
+) -> Result<PinataGroup, ApiError> {
+    let url = format!("{}/v3/groups/public", pinata.api_url());
+    let response = pinata
+        .request(Method::POST, url)
+        .json(&serde_json::json!({
+            "name": namespaced_name(pinata.group_namespace(), name),
+            "is_public": is_public,
+        }))
+        .send_traced()
+        .await?;
+
+    let data: GroupEnvelope = super::read_json(super::ensure_success(response).await?).await?;
+    Ok(display_name(pinata.group_namespace(), data.data))
+}
+
+// rename a group (within this deployment's namespace) and/or change its visibility
+pub async fn update_group(
+    pinata: &PinataClient,
+    group_id: &str,
+    name: Option<&str>,
+    is_public: Option<bool>,
+) -> Result<PinataGroup, ApiError> {
+    let mut body = serde_json::Map::new();
+    if let Some(name) = name {
+        body.insert(
+            "name".to_string(),
+            namespaced_name(pinata.group_namespace(), name).into(),
+        );
+    }
+    if let Some(is_public) = is_public {
+        body.insert("is_public".to_string(), is_public.into());
+    }
+
+    let url = format!("{}/v3/groups/public/{group_id}", pinata.api_url());
+    let response = pinata
+        .request(Method::PUT, url)
+        .json(&body)
+        .send_traced()
+        .await?;
+
+    let data: GroupEnvelope = super::read_json(super::ensure_success(response).await?).await?;
+    Ok(display_name(pinata.group_namespace(), data.data))
+}
+
+// deleting a group leaves its files in place, ungrouped
+pub async fn delete_group(pinata: &PinataClient, group_id: &str) -> Result<(), ApiError> {
+    let url = format!("{}/v3/groups/public/{group_id}", pinata.api_url());
+    let response = pinata.request(Method::DELETE, url).send_traced().await?;
+
+    super::ensure_success(response).await?;
+    Ok(())
+}
+
+// walk the public groups listing, stopping once `options.limit` groups are collected
+pub async fn list_groups(
+    pinata: &PinataClient,
+    options: ListOptions,
+) -> Result<Vec<PinataGroup>, ApiError> {
+    let mut all_groups = Vec::new();
+    let mut page_token: Option<String> = None;
+
+    loop {
+        // add the page_token as query param if avail
+        let mut query = GroupsQuery::new();
+        query.set_page_token(page_token.take());
+        let url = query.url(pinata.api_url())?;
+
+        // print url
+        debug!("Requesting URL: {url}");
+
+        // make request
+        let response = pinata.request(Method::GET, url).send_traced().await?;
+        debug!(
+            status = %response.status(),
+            headers = ?Redacted(response.headers()),
+            "Pinata answered"
+        );
+
+        // check if successful, then parse the response
+        let data: PinataGroupResponse =
+            super::read_json(super::ensure_success(response).await?).await?;
+        debug!(groups = data.data.groups.len(), "Fetched a page of groups");
+
+        // add groups to our collection
+        all_groups.extend(data.data.groups);
+
+        if let Some(limit_val) = options.limit
+            && all_groups.len() >= limit_val
+        {
+            all_groups.truncate(limit_val);
+            break;
+        }
+
+        // check if more to fetch
+        match data.data.next_page_token {
+            Some(token) => page_token = Some(token),
+            None => break,
+        }
+        if options.throttle {
+            rate_limit::throttle().await;
+        }
+    }
+
+    Ok(all_groups)
+}
+
+// Pinata files belong to a single group, so adding a file to a group moves it there
+pub async fn add_file_to_group(
+    pinata: &PinataClient,
+    group_id: &str,
+    file_id: &str,
+) -> Result<(), ApiError> {
+    let url = format!(
+        "{}/v3/groups/public/{group_id}/ids/{file_id}",
+        pinata.api_url()
+    );
+    let response = pinata.request(Method::PUT, url).send_traced().await?;
+
+    super::ensure_success(response).await?;
+    Ok(())
+}
+
+// the file stays in Pinata, ungrouped
+pub async fn remove_file_from_group(
+    pinata: &PinataClient,
+    group_id: &str,
+    file_id: &str,
+) -> Result<(), ApiError> {
+    let url = format!(
+        "{}/v3/groups/public/{group_id}/ids/{file_id}",
+        pinata.api_url()
+    );
+    let response = pinata.request(Method::DELETE, url).send_traced().await?;
+
+    super::ensure_success(response).await?;
+    Ok(())
+}
+
+// ids of every file in a group
+pub async fn group_file_ids(
+    pinata: &PinataClient,
+    group_id: &str,
+) -> Result<Vec<String>, ApiError> {
+    let files = list_files(
+        pinata,
+        FilesQuery::new().group(group_id),
+        ListOptions::default(),
+    )
+    .await?;
+    Ok(files.into_iter().map(|f| f.id).collect())
+}
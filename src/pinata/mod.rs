@@ -0,0 +1,179 @@
+use std::io::Read;
+use std::sync::Arc;
+use std::time::Duration;
+
+use flate2::read::{MultiGzDecoder, ZlibDecoder};
+use metrics::counter;
+use reqwest::header::{ACCEPT_ENCODING, CONTENT_ENCODING};
+use reqwest::{Client, IntoUrl, Method, RequestBuilder, StatusCode};
+use serde::de::DeserializeOwned;
+use tracing::warn;
+
+use crate::config::{PinataAccountConfig, PinataConfig};
+use crate::errors::ApiError;
+use crate::middleware::request_id::{self, REQUEST_ID_HEADER};
+use crate::telemetry::SendTraced;
+
+pub mod accounts;
+pub mod files;
+pub mod gateway;
+pub mod groups;
+pub use files::{ListOptions, fetch_images_from_group, list_files};
+
+pub mod query;
+pub mod rate_limit;
+pub use query::{FilesQuery, FilterOp, GroupsQuery, KeyvalueFilter, SortOrder};
+
+// What Pinata may compress its responses with. The HTTP client is built without
+// reqwest's decompression, so `read_body` inflates them; brotli has no decoder here.
+const ACCEPTED_ENCODINGS: &str = "gzip, deflate";
+
+// One pooled HTTP client plus the Pinata credentials, built once at startup
+// and cloned cheaply into every handler through `AppState`.
+#[derive(Clone)]
+pub struct PinataClient {
+    http: Client,
+    config: Arc<PinataConfig>,
+}
+
+impl PinataClient {
+    pub fn new(config: PinataConfig) -> Result<Self, ApiError> {
+        let http = Client::builder()
+            .pool_idle_timeout(Duration::from_secs(90))
+            .connect_timeout(Duration::from_secs(10))
+            .build()?;
+
+        Ok(Self {
+            http,
+            config: Arc::new(config),
+        })
+    }
+
+    // the same hosts and connection pool, with another account's credentials
+    pub fn for_account(&self, account: &PinataAccountConfig) -> Self {
+        let config = PinataConfig {
+            jwt: account.jwt.clone(),
+            gateway: account.gateway.clone(),
+            gateway_key: account.gateway_key.clone(),
+            account: account.name.clone(),
+            accounts: Vec::new(),
+            ..(*self.config).clone()
+        };
+        Self {
+            http: self.http.clone(),
+            config: Arc::new(config),
+        }
+    }
+
+    // what the account goes by in `?account=`
+    pub fn account(&self) -> &str {
+        &self.config.account
+    }
+
+    pub fn api_url(&self) -> &str {
+        &self.config.api_url
+    }
+
+    pub fn uploads_url(&self) -> &str {
+        &self.config.uploads_url
+    }
+
+    pub fn gateway(&self) -> Option<&str> {
+        self.config.gateway.as_deref()
+    }
+
+    pub fn group_namespace(&self) -> Option<&str> {
+        self.config.group_namespace.as_deref()
+    }
+
+    // an authenticated request against either Pinata host, passing on the id of the
+    // request it's made for
+    pub fn request(&self, method: Method, url: impl IntoUrl) -> RequestBuilder {
+        let builder = self
+            .http
+            .request(method, url)
+            .bearer_auth(&self.config.jwt)
+            .header(ACCEPT_ENCODING, ACCEPTED_ENCODINGS);
+        match request_id::current() {
+            Some(id) => builder.header(REQUEST_ID_HEADER, id),
+            None => builder,
+        }
+    }
+
+    // cheapest authenticated call, to check the API is reachable and the JWT accepted
+    pub async fn ping(&self) -> Result<(), ApiError> {
+        let url = format!("{}/v3/files/public?limit=1", self.api_url());
+        ensure_success(self.request(Method::GET, url).send_traced().await?).await?;
+        Ok(())
+    }
+}
+
+// turn a non-2xx Pinata response into an error carrying its status and body
+pub async fn ensure_success(response: reqwest::Response) -> Result<reqwest::Response, ApiError> {
+    rate_limit::observe(response.headers());
+
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+
+    let error_body = read_text(response).await?;
+    warn!("API request failed with status: {status}");
+    warn!("Response body: {error_body}");
+    let message = format!(
+        "API request failed with status: {}. Body: {}",
+        status, error_body
+    );
+    // what was asked for isn't there, rather than Pinata failing
+    Err(match status {
+        StatusCode::NOT_FOUND => ApiError::NotFound(message),
+        StatusCode::TOO_MANY_REQUESTS => ApiError::Unavailable(message),
+        status if status.is_server_error() => ApiError::Unavailable(message),
+        _ => ApiError::Api(message),
+    })
+}
+
+// The body of a Pinata response, inflated when it came compressed. What came over the
+// wire is counted against what it decoded to.
+pub async fn read_body(response: reqwest::Response) -> Result<Vec<u8>, ApiError> {
+    let encoding = response
+        .headers()
+        .get(CONTENT_ENCODING)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.trim().to_ascii_lowercase())
+        .unwrap_or_default();
+    let wire = response.bytes().await?;
+
+    let (label, body) = match encoding.as_str() {
+        "" | "identity" => ("identity", wire.to_vec()),
+        "gzip" | "x-gzip" => ("gzip", inflate(MultiGzDecoder::new(&wire[..]))?),
+        "deflate" => ("deflate", inflate(ZlibDecoder::new(&wire[..]))?),
+        other => {
+            return Err(ApiError::Api(format!(
+                "Pinata response has unsupported Content-Encoding: {other}"
+            )));
+        }
+    };
+    counter!("pinata_transfer_bytes_total", "encoding" => label).increment(wire.len() as u64);
+    counter!("pinata_response_bytes_total", "encoding" => label).increment(body.len() as u64);
+    Ok(body)
+}
+
+pub async fn read_json<T: DeserializeOwned>(response: reqwest::Response) -> Result<T, ApiError> {
+    let body = read_body(response).await?;
+    serde_json::from_slice(&body)
+        .map_err(|e| ApiError::Api(format!("Unexpected response from Pinata: {e}")))
+}
+
+pub async fn read_text(response: reqwest::Response) -> Result<String, ApiError> {
+    let body = read_body(response).await?;
+    Ok(String::from_utf8_lossy(&body).into_owned())
+}
+
+fn inflate(mut decoder: impl Read) -> Result<Vec<u8>, ApiError> {
+    let mut body = Vec::new();
+    decoder
+        .read_to_end(&mut body)
+        .map_err(|e| ApiError::Api(format!("Failed to decode Pinata response: {e}")))?;
+    Ok(body)
+}
@@ -0,0 +1,297 @@
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::errors::ApiError;
+
+// comparison applied to a keyvalue filter
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FilterOp {
+    Eq,
+    Ne,
+    In,
+    Like,
+    Gt,
+    Gte,
+    Lt,
+    Lte,
+}
+
+impl FilterOp {
+    fn is_numeric(self) -> bool {
+        matches!(self, Self::Gt | Self::Gte | Self::Lt | Self::Lte)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct KeyvalueFilter {
+    pub(crate) value: Value,
+    pub(crate) op: FilterOp,
+}
+
+impl KeyvalueFilter {
+    pub fn new(op: FilterOp, value: Value) -> Self {
+        Self { value, op }
+    }
+
+    // parse a client filter spec of the form `key:op:value`, e.g. `iso:gt:400` or `camera:prefix:Fuji`
+    pub fn parse(spec: &str) -> Result<(String, Self), ApiError> {
+        let mut parts = spec.splitn(3, ':');
+        let (Some(key), Some(op), Some(value)) = (parts.next(), parts.next(), parts.next()) else {
+            return Err(ApiError::Validation(format!(
+                "Invalid filter '{spec}', expected key:op:value"
+            )));
+        };
+
+        let key = key.trim();
+        let value = value.trim();
+        if key.is_empty() || value.is_empty() {
+            return Err(ApiError::Validation(format!(
+                "Invalid filter '{spec}', key and value are required"
+            )));
+        }
+
+        let (op, value) = match op.trim().to_ascii_lowercase().as_str() {
+            "eq" => (FilterOp::Eq, value.to_string()),
+            "ne" => (FilterOp::Ne, value.to_string()),
+            "like" => (FilterOp::Like, value.to_string()),
+            "prefix" => (FilterOp::Like, format!("{value}%")),
+            "gt" => (FilterOp::Gt, value.to_string()),
+            "gte" => (FilterOp::Gte, value.to_string()),
+            "lt" => (FilterOp::Lt, value.to_string()),
+            "lte" => (FilterOp::Lte, value.to_string()),
+            other => {
+                return Err(ApiError::Validation(format!(
+                    "Unsupported filter operator '{other}' in '{spec}'"
+                )));
+            }
+        };
+
+        // numeric comparisons are sent as JSON numbers so Pinata compares by value
+        let value = if op.is_numeric() {
+            let number = value
+                .parse::<f64>()
+                .ok()
+                .and_then(serde_json::Number::from_f64)
+                .ok_or_else(|| {
+                    ApiError::Validation(format!("Filter '{spec}' needs a numeric value"))
+                })?;
+            Value::Number(number)
+        } else {
+            Value::String(value)
+        };
+
+        Ok((key.to_string(), Self::new(op, value)))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    Asc,
+    Desc,
+}
+
+impl SortOrder {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Asc => "ASC",
+            Self::Desc => "DESC",
+        }
+    }
+}
+
+// Parameters of Pinata's `GET /v3/files/{network}` listing
+#[derive(Debug, Clone, Default)]
+// (fields are readable in-crate so the local catalog can answer the same query)
+pub struct FilesQuery {
+    pub(crate) group: Option<String>,
+    pub(crate) name: Option<String>,
+    pub(crate) cid: Option<String>,
+    pub(crate) mime_type: Option<String>,
+    pub(crate) keyvalues: Vec<(String, KeyvalueFilter)>,
+    pub(crate) order: Option<SortOrder>,
+    pub(crate) limit: Option<usize>,
+    pub(crate) page_token: Option<String>,
+}
+
+impl FilesQuery {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn group(mut self, group_id: impl Into<String>) -> Self {
+        self.group = Some(group_id.into());
+        self
+    }
+
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    pub fn cid(mut self, cid: impl Into<String>) -> Self {
+        self.cid = Some(cid.into());
+        self
+    }
+
+    pub fn mime_type(mut self, mime_type: impl Into<String>) -> Self {
+        self.mime_type = Some(mime_type.into());
+        self
+    }
+
+    // filter on a keyvalue, replacing any earlier filter on the same key
+    pub fn keyvalue(mut self, key: impl Into<String>, filter: KeyvalueFilter) -> Self {
+        let key = key.into();
+        self.keyvalues.retain(|(existing, _)| *existing != key);
+        self.keyvalues.push((key, filter));
+        self
+    }
+
+    pub fn keyvalue_eq(self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.keyvalue(
+            key,
+            KeyvalueFilter::new(FilterOp::Eq, Value::String(value.into())),
+        )
+    }
+
+    pub fn keyvalue_in<I, S>(self, key: impl Into<String>, values: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let values = values
+            .into_iter()
+            .map(|v| Value::String(v.into()))
+            .collect();
+        self.keyvalue(key, KeyvalueFilter::new(FilterOp::In, Value::Array(values)))
+    }
+
+    pub fn order(mut self, order: SortOrder) -> Self {
+        self.order = Some(order);
+        self
+    }
+
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    pub fn page_token(mut self, token: Option<String>) -> Self {
+        self.page_token = token;
+        self
+    }
+
+    pub fn set_page_token(&mut self, token: Option<String>) {
+        self.page_token = token;
+    }
+
+    // JSON form of the keyvalue filters, as Pinata expects in `metadata[keyvalues]`
+    pub fn keyvalues_json(&self) -> Option<String> {
+        if self.keyvalues.is_empty() {
+            return None;
+        }
+
+        let filters: Map<String, Value> = self
+            .keyvalues
+            .iter()
+            .map(|(key, filter)| (key.clone(), serde_json::json!(filter)))
+            .collect();
+
+        Some(Value::Object(filters).to_string())
+    }
+
+    // full request url for the public files listing under `base_url`
+    pub fn url(&self, base_url: &str) -> Result<Url, ApiError> {
+        let mut url = Url::parse(&format!("{base_url}/v3/files/public"))?;
+
+        {
+            let mut pairs = url.query_pairs_mut();
+
+            if let Some(group) = &self.group {
+                pairs.append_pair("group", group);
+            }
+            if let Some(name) = &self.name {
+                pairs.append_pair("name", name);
+            }
+            if let Some(cid) = &self.cid {
+                pairs.append_pair("cid", cid);
+            }
+            if let Some(mime_type) = &self.mime_type {
+                pairs.append_pair("mimeType", mime_type);
+            }
+            if let Some(keyvalues) = self.keyvalues_json() {
+                pairs.append_pair("metadata[keyvalues]", &keyvalues);
+            }
+            if let Some(order) = self.order {
+                pairs.append_pair("order", order.as_str());
+            }
+            if let Some(limit) = self.limit {
+                pairs.append_pair("limit", &limit.to_string());
+            }
+            if let Some(token) = &self.page_token {
+                pairs.append_pair("pageToken", token);
+            }
+        }
+
+        // drop the dangling `?` when no parameters were set
+        if url.query() == Some("") {
+            url.set_query(None);
+        }
+
+        Ok(url)
+    }
+}
+
+// Parameters of Pinata's `GET /v3/groups/{network}` listing
+#[derive(Debug, Clone, Default)]
+pub struct GroupsQuery {
+    name: Option<String>,
+    limit: Option<usize>,
+    page_token: Option<String>,
+}
+
+impl GroupsQuery {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    pub fn set_page_token(&mut self, token: Option<String>) {
+        self.page_token = token;
+    }
+
+    pub fn url(&self, base_url: &str) -> Result<Url, ApiError> {
+        let mut url = Url::parse(&format!("{base_url}/v3/groups/public"))?;
+
+        {
+            let mut pairs = url.query_pairs_mut();
+
+            if let Some(name) = &self.name {
+                pairs.append_pair("name", name);
+            }
+            if let Some(limit) = self.limit {
+                pairs.append_pair("limit", &limit.to_string());
+            }
+            if let Some(token) = &self.page_token {
+                pairs.append_pair("pageToken", token);
+            }
+        }
+
+        if url.query() == Some("") {
+            url.set_query(None);
+        }
+
+        Ok(url)
+    }
+}
@@ -0,0 +1,110 @@
+use std::sync::{LazyLock, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use http::HeaderMap;
+use metrics::gauge;
+
+// below this share of quota left, background work starts slowing down
+const THROTTLE_BELOW: f64 = 0.5;
+// the longest pause between background calls while quota is merely low
+const MAX_DELAY: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Quota {
+    pub limit: Option<u64>,
+    pub remaining: Option<u64>,
+    pub resets_at: Option<Instant>,
+}
+
+impl Quota {
+    // share of the window left, when known
+    pub fn ratio(&self) -> Option<f64> {
+        match (self.remaining, self.limit) {
+            (Some(remaining), Some(limit)) if limit > 0 => Some(remaining as f64 / limit as f64),
+            _ => None,
+        }
+    }
+}
+
+static QUOTA: LazyLock<Mutex<Quota>> = LazyLock::new(|| Mutex::new(Quota::default()));
+
+fn header_u64(headers: &HeaderMap, name: &str) -> Option<u64> {
+    headers.get(name)?.to_str().ok()?.trim().parse().ok()
+}
+
+// `x-ratelimit-reset` as seconds until reset, or an absolute unix timestamp
+fn reset_instant(headers: &HeaderMap) -> Option<Instant> {
+    let raw =
+        header_u64(headers, "x-ratelimit-reset").or_else(|| header_u64(headers, "retry-after"))?;
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).ok()?.as_secs();
+    let seconds = if raw > 1_000_000_000 {
+        raw.saturating_sub(now)
+    } else {
+        raw
+    };
+
+    Some(Instant::now() + Duration::from_secs(seconds))
+}
+
+// record the rate-limit headers of any Pinata response
+pub fn observe(headers: &HeaderMap) {
+    let limit = header_u64(headers, "x-ratelimit-limit");
+    let remaining = header_u64(headers, "x-ratelimit-remaining");
+    if limit.is_none() && remaining.is_none() {
+        return;
+    }
+
+    let mut quota = QUOTA.lock().unwrap();
+    quota.limit = limit.or(quota.limit);
+    quota.remaining = remaining;
+    quota.resets_at = reset_instant(headers);
+
+    if let Some(limit) = quota.limit {
+        gauge!("pinata_ratelimit_limit").set(limit as f64);
+    }
+    if let Some(remaining) = quota.remaining {
+        gauge!("pinata_ratelimit_remaining").set(remaining as f64);
+    }
+    if let Some(resets_at) = quota.resets_at {
+        gauge!("pinata_ratelimit_reset_seconds").set(
+            resets_at
+                .saturating_duration_since(Instant::now())
+                .as_secs_f64(),
+        );
+    }
+}
+
+pub fn current() -> Quota {
+    *QUOTA.lock().unwrap()
+}
+
+// how long a background job should pause before its next Pinata call
+pub fn backoff() -> Duration {
+    let quota = current();
+
+    // out of quota: wait for the window to reset
+    if quota.remaining == Some(0) {
+        return quota
+            .resets_at
+            .map(|at| at.saturating_duration_since(Instant::now()))
+            .unwrap_or(MAX_DELAY);
+    }
+
+    match quota.ratio() {
+        Some(ratio) if ratio < THROTTLE_BELOW => {
+            // ramps from zero at the threshold up to MAX_DELAY as the quota empties
+            let depletion = 1.0 - ratio / THROTTLE_BELOW;
+            MAX_DELAY.mul_f64(depletion * depletion)
+        }
+        _ => Duration::ZERO,
+    }
+}
+
+// called by background and batch work between Pinata calls; interactive requests don't wait
+pub async fn throttle() {
+    let delay = backoff();
+    if !delay.is_zero() {
+        gauge!("pinata_throttle_delay_seconds").set(delay.as_secs_f64());
+        tokio::time::sleep(delay).await;
+    }
+}
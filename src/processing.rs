@@ -0,0 +1,230 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use tokio::io::AsyncReadExt;
+use tracing::{Instrument, info, info_span, warn};
+
+use crate::activity::{Activity, Tracked};
+use crate::analysis;
+use crate::config::WatermarkConfig;
+use crate::errors::ApiError;
+use crate::exif;
+use crate::imaging::{
+    self, BLURHASH, CAPTURED_AT, HEIGHT, ORIENTATION, THUMBNAIL_LARGE_CID, THUMBNAIL_SMALL_CID,
+    Thumbnails, VARIANT, VARIANT_OF, WATERMARK, WIDTH,
+};
+use crate::models::{PinataFile, dates::format_local_rfc3339};
+use crate::pinata::{
+    FilesQuery, ListOptions, PinataClient, files, gateway, list_files, rate_limit,
+};
+use crate::spool::SpooledFile;
+use crate::state::AppState;
+use crate::virtual_albums;
+
+// a streamed upload is fetched back once, so the signed link only has to outlive one download
+const FETCH_TTL: Duration = Duration::from_secs(300);
+
+// Pin a file derived from an original, tagged so listings leave it out. Returns its CID.
+pub async fn pin_variant(
+    state: &AppState,
+    original_id: &str,
+    original_name: &str,
+    variant: &str,
+    data: Vec<u8>,
+) -> Result<String, ApiError> {
+    let keyvalues = HashMap::from([
+        (VARIANT_OF.to_string(), original_id.to_string()),
+        (VARIANT.to_string(), variant.to_string()),
+    ]);
+    let timeout = Duration::from_secs(state.config.upload.default_timeout_secs);
+    let name = format!("{original_name} ({variant}).webp");
+    rate_limit::throttle().await;
+    let pinned = files::pin_bytes(
+        &state.pinata,
+        timeout,
+        &name,
+        "image/webp",
+        data,
+        &keyvalues,
+    )
+    .await?;
+    Ok(pinned.cid)
+}
+
+// the pinned variants derived from an original
+pub async fn variants_of(
+    pinata: &PinataClient,
+    file_id: &str,
+) -> Result<Vec<PinataFile>, ApiError> {
+    let options = ListOptions {
+        include_variants: true,
+        ..ListOptions::default()
+    };
+    let query = FilesQuery::new().keyvalue_eq(VARIANT_OF, file_id);
+    list_files(pinata, query, options).await
+}
+
+// A deleted original takes its pinned variants, stored analysis and album references
+// with it. Best effort: the original is already gone, so failures are only logged.
+pub async fn file_deleted(pinata: &PinataClient, file_id: &str) {
+    analysis::remove(file_id);
+    virtual_albums::remove_file(file_id);
+
+    let variants = match variants_of(pinata, file_id).await {
+        Ok(variants) => variants,
+        Err(e) => {
+            warn!("Failed to look up the variants of {file_id}: {e}");
+            return;
+        }
+    };
+    for variant in variants {
+        rate_limit::throttle().await;
+        if let Err(e) = files::delete_file(pinata, &variant.id).await {
+            warn!("Failed to delete variant {} of {file_id}: {e}", variant.id);
+        }
+    }
+}
+
+// pin whichever thumbnails `existing` doesn't already point at, returning their keyvalues
+pub async fn pin_thumbnails(
+    state: &AppState,
+    original_id: &str,
+    original_name: &str,
+    thumbnails: Thumbnails,
+    existing: &HashMap<String, String>,
+) -> Result<Vec<(String, String)>, ApiError> {
+    let mut pinned = Vec::new();
+    for (key, variant, data) in [
+        (THUMBNAIL_SMALL_CID, "thumbnail_small", thumbnails.small),
+        (THUMBNAIL_LARGE_CID, "thumbnail_large", thumbnails.large),
+    ] {
+        if existing.contains_key(key) {
+            continue;
+        }
+        let cid = pin_variant(state, original_id, original_name, variant, data).await?;
+        pinned.push((key.to_string(), cid));
+    }
+    Ok(pinned)
+}
+
+// Work out a just-pinned upload's dimensions, analysis, blurhash and thumbnails in the
+// background, so the upload response doesn't wait. Streamed uploads aren't kept, so
+// they're fetched back from the gateway.
+pub fn spawn_for_upload(
+    state: AppState,
+    file_id: String,
+    cid: String,
+    data: Option<SpooledFile>,
+    mark: Option<WatermarkConfig>,
+) {
+    let tracked = Tracked::start(Activity::Processing);
+    let span = info_span!("processing", file_id = %file_id);
+    tokio::spawn(
+        async move {
+            let _tracked = tracked;
+            // waiting here holds the upload's spooled data, but not a decoded image
+            let Ok(_permit) = state.processing.clone().acquire_owned().await else {
+                return;
+            };
+            if let Err(e) = process_upload(&state, &file_id, &cid, data, mark).await {
+                // not every upload is an image the decoder understands
+                warn!("Skipped processing of {file_id}: {e}");
+            }
+        }
+        .instrument(span),
+    );
+}
+
+async fn upload_bytes(
+    state: &AppState,
+    cid: &str,
+    data: Option<SpooledFile>,
+) -> Result<Option<Vec<u8>>, ApiError> {
+    let Some(data) = data else {
+        let Some(response) = gateway::fetch(&state.pinata, cid, None, FETCH_TTL).await? else {
+            return Ok(None);
+        };
+        return Ok(Some(response.bytes().await?.to_vec()));
+    };
+
+    let mut bytes = Vec::with_capacity(data.len() as usize);
+    data.reader()?
+        .read_to_end(&mut bytes)
+        .await
+        .map_err(|e| ApiError::Internal(format!("Failed to read upload for processing: {e}")))?;
+    Ok(Some(bytes))
+}
+
+async fn process_upload(
+    state: &AppState,
+    file_id: &str,
+    cid: &str,
+    data: Option<SpooledFile>,
+    mark: Option<WatermarkConfig>,
+) -> Result<(), ApiError> {
+    let Some(bytes) = upload_bytes(state, cid, data).await? else {
+        info!("No gateway configured, {file_id} is processed by the next re-encode");
+        return Ok(());
+    };
+
+    // Each step is recorded as soon as it's done, so one failing later (a pin, an
+    // unsupported color profile) doesn't lose what came before it.
+    let header = imaging::header(&bytes)?;
+    let file = files::get_file(&state.pinata, file_id).await?;
+    let mut attributes = file.keyvalues.clone();
+    attributes.extra.extend([
+        (WIDTH.to_string(), header.width.to_string()),
+        (HEIGHT.to_string(), header.height.to_string()),
+        (ORIENTATION.to_string(), header.orientation.to_string()),
+    ]);
+    if let Some(captured_at) = exif::capture_time(&bytes, &state.config.i18n.timezone) {
+        attributes
+            .extra
+            .insert(CAPTURED_AT.to_string(), format_local_rfc3339(&captured_at));
+    }
+    files::update_file(&state.pinata, file_id, None, &attributes.to_keyvalues()).await?;
+    state.catalog_changed();
+
+    let (decoded, analysis) = tokio::task::spawn_blocking(move || {
+        let decoded = imaging::decode(&bytes)?;
+        let analysis = imaging::analyze(&decoded.image);
+        Ok::<_, ApiError>((decoded, analysis))
+    })
+    .await
+    .map_err(|e| ApiError::Internal(format!("Image processing failed: {e}")))??;
+    analysis::record(file_id, analysis)?;
+
+    let config = state.config.processing;
+    let fingerprint = mark.as_ref().map(|mark| mark.fingerprint.clone());
+    let derived = tokio::task::spawn_blocking(move || {
+        imaging::upload_derived(decoded, &config, mark.as_ref())
+    })
+    .await
+    .map_err(|e| ApiError::Internal(format!("Image processing failed: {e}")))??;
+    attributes
+        .extra
+        .insert(BLURHASH.to_string(), derived.blurhash);
+    let pinned = pin_thumbnails(
+        state,
+        file_id,
+        &file.name,
+        derived.thumbnails,
+        &attributes.extra,
+    )
+    .await;
+    // the blurhash is worth keeping even when a thumbnail didn't make it
+    let pinned_all = pinned.is_ok();
+    attributes.extra.extend(pinned.unwrap_or_default());
+    if pinned_all && let Some(fingerprint) = fingerprint {
+        attributes.extra.insert(WATERMARK.to_string(), fingerprint);
+    }
+    files::update_file(&state.pinata, file_id, None, &attributes.to_keyvalues()).await?;
+    state.catalog_changed();
+    if !pinned_all {
+        return Err(ApiError::Api(
+            "Failed to pin thumbnails, the next re-encode retries them".to_string(),
+        ));
+    }
+    info!("Recorded the dimensions, blurhash and thumbnails of {file_id}");
+    Ok(())
+}
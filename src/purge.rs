@@ -0,0 +1,83 @@
+use std::time::Duration;
+
+use metrics::counter;
+use reqwest::Client;
+use tracing::info;
+
+use crate::config::CachePurgeConfig;
+use crate::errors::ApiError;
+use crate::models::PinataFile;
+use crate::pinata::gateway;
+use crate::state::AppState;
+use crate::telemetry::SendTraced;
+
+// Asks the configured purge endpoint to drop cached copies of a file, posted as
+// `{"urls": [...], "cids": [...]}`, so a replaced variant stops being served.
+#[derive(Clone)]
+pub struct CachePurger {
+    http: Client,
+    config: Option<CachePurgeConfig>,
+}
+
+impl CachePurger {
+    pub fn new(config: Option<CachePurgeConfig>) -> Result<Self, ApiError> {
+        let http = Client::builder()
+            .connect_timeout(Duration::from_secs(10))
+            .timeout(Duration::from_secs(30))
+            .build()?;
+        Ok(Self { http, config })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.config.is_some()
+    }
+
+    // false when there's no purge endpoint to ask
+    pub async fn purge(&self, urls: &[String], cids: &[String]) -> Result<bool, ApiError> {
+        let Some(config) = &self.config else {
+            return Ok(false);
+        };
+
+        let mut request = self
+            .http
+            .post(&config.url)
+            .json(&serde_json::json!({ "urls": urls, "cids": cids }));
+        if let Some(token) = &config.token {
+            request = request.bearer_auth(token);
+        }
+
+        let result = match request.send_traced().await {
+            Ok(response) if response.status().is_success() => Ok(true),
+            Ok(response) => Err(ApiError::Api(format!(
+                "Cache purge was refused with status {}: {}",
+                response.status(),
+                response.text().await.unwrap_or_default()
+            ))),
+            Err(e) => Err(e.into()),
+        };
+        let outcome = if result.is_ok() { "success" } else { "failure" };
+        counter!("cache_purges_total", "result" => outcome).increment(1);
+        result
+    }
+}
+
+// Purge everything cached for a file, returning the urls and cids asked for, or None
+// when purging is off
+pub async fn purge_file(
+    state: &AppState,
+    file: &PinataFile,
+) -> Result<Option<(Vec<String>, Vec<String>)>, ApiError> {
+    // the widths the backend itself asks the gateway to resize to
+    let config = &state.config;
+    let widths = [
+        config.upload.preview_thumbnail_width,
+        config.shares.preview_width,
+    ];
+    let urls = gateway::file_urls(&state.pinata, file, &widths);
+    let cids = gateway::file_cids(file);
+    if !state.purger.purge(&urls, &cids).await? {
+        return Ok(None);
+    }
+    info!("Purged the gateway cache of {}", file.id);
+    Ok(Some((urls, cids)))
+}
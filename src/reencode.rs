@@ -0,0 +1,301 @@
+use std::sync::LazyLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use chrono::Utc;
+use tracing::{Instrument, info, info_span, warn};
+
+use crate::activity::{Activity, Tracked};
+use crate::errors::ApiError;
+use crate::exif;
+use crate::imaging::{
+    self, BLURHASH, CAPTURED_AT, COLOR_PROFILE, DERIVED_KEYS, DISPLAY_CID, HEIGHT, ORIENTATION,
+    THUMBNAIL_LARGE_CID, THUMBNAIL_SMALL_CID, WATERMARK, WIDTH,
+};
+use crate::models::{
+    PinataFile,
+    dates::format_local_rfc3339,
+    maintenance::{FixFailure, ReencodeJob, ReencodeStatus},
+};
+use crate::pinata::{FilesQuery, ListOptions, files, gateway, list_files, rate_limit};
+use crate::processing::{pin_thumbnails, pin_variant, variants_of};
+use crate::purge;
+use crate::state::AppState;
+use crate::store::JsonStore;
+
+// originals are fetched one at a time, so the signed link only has to outlive one download
+const FETCH_TTL: Duration = Duration::from_secs(300);
+
+// The last (or current) run of the re-encode job. Kept on disk so a run cut short
+// by a restart carries on from the files it hadn't reached yet.
+static JOB: LazyLock<JsonStore<Option<ReencodeJob>>> =
+    LazyLock::new(|| JsonStore::open("reencode_progress"));
+
+// set while a run is walking the catalog in this process
+static RUNNING: AtomicBool = AtomicBool::new(false);
+
+pub fn status() -> Option<ReencodeJob> {
+    JOB.read(|job| job.clone())
+}
+
+// progress is best effort: a failed write costs the resume point, not the files
+fn record(f: impl FnOnce(&mut ReencodeJob)) {
+    let result = JOB.update(|job| {
+        if let Some(job) = job {
+            f(job);
+        }
+    });
+    if let Err(e) = result {
+        warn!("Failed to record re-encode progress: {e}");
+    }
+}
+
+// Start a run, resuming the stored one if a restart interrupted it. None when
+// a run is already going.
+pub fn start(state: &AppState) -> Result<Option<ReencodeJob>, ApiError> {
+    if RUNNING.swap(true, Ordering::SeqCst) {
+        return Ok(None);
+    }
+
+    let started = JOB.update(|job| {
+        let resumable = job
+            .as_ref()
+            .is_some_and(|job| job.status == ReencodeStatus::Running);
+        if !resumable {
+            *job = Some(ReencodeJob {
+                status: ReencodeStatus::Running,
+                started_at: Utc::now(),
+                finished_at: None,
+                total: 0,
+                processed: 0,
+                skipped: 0,
+                failed: Vec::new(),
+                done: Default::default(),
+                message: None,
+            });
+        }
+        job.clone()
+    });
+    let job = match started {
+        Ok(job) => job,
+        Err(e) => {
+            RUNNING.store(false, Ordering::SeqCst);
+            return Err(e);
+        }
+    };
+
+    let tracked = Tracked::start(Activity::Reencode);
+    let state = state.clone();
+    tokio::spawn(
+        async move {
+            let _tracked = tracked;
+            run(state).await
+        }
+        .instrument(info_span!("reencode")),
+    );
+    Ok(job)
+}
+
+// pick up a run the last process didn't finish
+pub fn resume(state: &AppState) {
+    if status().is_some_and(|job| job.status == ReencodeStatus::Running) {
+        info!("Resuming the interrupted re-encode job");
+        if let Err(e) = start(state) {
+            warn!("Failed to resume the re-encode job: {e}");
+        }
+    }
+}
+
+async fn run(state: AppState) {
+    let result = walk(&state).await;
+
+    let mut changed = false;
+    record(|job| {
+        job.finished_at = Some(Utc::now());
+        changed = job.processed > 0;
+        match result {
+            Ok(()) => {
+                job.status = ReencodeStatus::Completed;
+                job.message = (!job.failed.is_empty())
+                    .then(|| format!("{} files could not be re-encoded", job.failed.len()));
+            }
+            Err(e) => {
+                warn!("Re-encode job failed: {e}");
+                job.status = ReencodeStatus::Failed;
+                job.message = Some(e.to_string());
+            }
+        }
+    });
+    if changed {
+        state.catalog_changed();
+    }
+    RUNNING.store(false, Ordering::SeqCst);
+}
+
+// whether the variants carry a different watermark than the one configured, or one
+// when none is
+fn watermark_changed(state: &AppState, file: &PinataFile) -> bool {
+    let current = state
+        .config
+        .watermark
+        .as_ref()
+        .map(|mark| &mark.fingerprint);
+    file.keyvalues.extra.get(WATERMARK) != current
+}
+
+// only images are processed, and only when something derived is missing or marked
+// with another watermark
+fn needs_reencode(state: &AppState, file: &PinataFile) -> bool {
+    file.mime_type.starts_with("image/")
+        && (DERIVED_KEYS
+            .iter()
+            .any(|key| !file.keyvalues.extra.contains_key(*key))
+            || watermark_changed(state, file))
+}
+
+async fn walk(state: &AppState) -> Result<(), ApiError> {
+    if state.pinata.gateway().is_none() {
+        return Err(ApiError::Api(
+            "PINATA_GATEWAY must be set to fetch originals for re-encoding".to_string(),
+        ));
+    }
+
+    // straight from Pinata, since the mirror may not have the latest uploads yet
+    let files: Vec<PinataFile> =
+        list_files(&state.pinata, FilesQuery::new(), ListOptions::default()).await?;
+
+    let total = files.len();
+    let done = JOB.read(|job| job.as_ref().map(|job| job.done.clone()).unwrap_or_default());
+    record(|job| job.total = total);
+    info!(
+        "Re-encode: {total} files in the catalog, {} already done",
+        done.len()
+    );
+
+    for file in files.into_iter().filter(|file| !done.contains(&file.id)) {
+        if !needs_reencode(state, &file) {
+            record(|job| {
+                job.skipped += 1;
+                job.done.insert(file.id.clone());
+            });
+            continue;
+        }
+
+        rate_limit::throttle().await;
+        let result = reencode_file(state, &file).await;
+        record(|job| {
+            match &result {
+                Ok(()) => job.processed += 1,
+                Err(e) => job.failed.push(FixFailure {
+                    id: file.id.clone(),
+                    message: e.to_string(),
+                }),
+            }
+            job.done.insert(file.id.clone());
+        });
+        match result {
+            Ok(()) => info!("Re-encoded {}", file.id),
+            Err(e) => warn!("Failed to re-encode {}: {e}", file.id),
+        }
+    }
+
+    Ok(())
+}
+
+// derive what's missing from the original, pin the display variant, and record
+// it all in the original's keyvalues. A changed watermark replaces every variant.
+async fn reencode_file(state: &AppState, file: &PinataFile) -> Result<(), ApiError> {
+    let original = gateway::fetch(&state.pinata, &file.cid, None, FETCH_TTL)
+        .await?
+        .ok_or_else(|| ApiError::Api("No gateway configured".to_string()))?
+        .bytes()
+        .await?;
+
+    let config = state.config.processing;
+    let mark = state.config.watermark.clone();
+    let source = original.clone();
+    let derived =
+        tokio::task::spawn_blocking(move || imaging::derive(&source, &config, mark.as_ref()))
+            .await
+            .map_err(|e| ApiError::Internal(format!("Image processing failed: {e}")))??;
+
+    let mut attributes = file.keyvalues.clone();
+    // files pinned before capture times were read; a missing one alone isn't a reason
+    // to re-encode
+    if !attributes.extra.contains_key(CAPTURED_AT)
+        && let Some(captured_at) = exif::capture_time(&original, &state.config.i18n.timezone)
+    {
+        attributes
+            .extra
+            .insert(CAPTURED_AT.to_string(), format_local_rfc3339(&captured_at));
+    }
+    let remark = watermark_changed(state, file);
+    if remark {
+        for key in [DISPLAY_CID, THUMBNAIL_SMALL_CID, THUMBNAIL_LARGE_CID] {
+            attributes.extra.remove(key);
+        }
+    }
+    let display_cid = match attributes.extra.get(DISPLAY_CID) {
+        Some(cid) => cid.clone(),
+        None => pin_variant(state, &file.id, &file.name, "display", derived.display).await?,
+    };
+    let thumbnails = pin_thumbnails(
+        state,
+        &file.id,
+        &file.name,
+        derived.thumbnails,
+        &attributes.extra,
+    )
+    .await?;
+
+    attributes.extra.extend([
+        (WIDTH.to_string(), derived.width.to_string()),
+        (HEIGHT.to_string(), derived.height.to_string()),
+        (ORIENTATION.to_string(), derived.orientation.to_string()),
+        (BLURHASH.to_string(), derived.blurhash),
+        (DISPLAY_CID.to_string(), display_cid),
+    ]);
+    attributes.extra.extend(thumbnails);
+    if let Some(profile) = derived.color_profile {
+        attributes.extra.insert(COLOR_PROFILE.to_string(), profile);
+    }
+    match &state.config.watermark {
+        Some(mark) => attributes
+            .extra
+            .insert(WATERMARK.to_string(), mark.fingerprint.clone()),
+        None => attributes.extra.remove(WATERMARK),
+    };
+    let updated =
+        files::update_file(&state.pinata, &file.id, None, &attributes.to_keyvalues()).await?;
+    if remark {
+        remove_replaced_variants(state, &updated).await;
+    }
+
+    // the gateway may still serve what it cached before the variants were replaced
+    if let Err(e) = purge::purge_file(state, &updated).await {
+        warn!("Failed to purge the gateway cache of {}: {e}", file.id);
+    }
+    Ok(())
+}
+
+// Unpin the variants a re-mark replaced. Best effort: the original already points at
+// the new ones, so a leftover only costs storage.
+async fn remove_replaced_variants(state: &AppState, file: &PinataFile) {
+    let current = gateway::file_cids(file);
+    let variants = match variants_of(&state.pinata, &file.id).await {
+        Ok(variants) => variants,
+        Err(e) => {
+            warn!(
+                "Failed to look up the replaced variants of {}: {e}",
+                file.id
+            );
+            return;
+        }
+    };
+    for variant in variants.iter().filter(|v| !current.contains(&v.cid)) {
+        rate_limit::throttle().await;
+        if let Err(e) = files::delete_file(&state.pinata, &variant.id).await {
+            warn!("Failed to delete replaced variant {}: {e}", variant.id);
+        }
+    }
+}
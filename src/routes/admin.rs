@@ -0,0 +1,264 @@
+use axum::{
+    Json, Router,
+    extract::{Path, State},
+    http::StatusCode,
+    middleware::from_fn,
+    response::{IntoResponse, Response},
+    routing::{delete, get, put},
+};
+use tracing::info;
+
+use crate::activity::{self, Activity};
+use crate::auth::{api_keys, usage};
+use crate::errors::{ApiError, ErrorCode, error_response};
+use crate::middleware::auth::require_admin;
+use crate::models::api_keys::{
+    CreateKeyRequest, CreateKeyResponse, KeyResponse, KeyUsageResponse, KeysResponse,
+    SetRateLimitRequest,
+};
+use crate::models::shares::{
+    CreateShareRequest, LimitedClientsResponse, ShareResponse, ShareUsageResponse, SharesResponse,
+};
+use crate::models::status::ServiceStatus;
+use crate::pinata::groups;
+use crate::shares;
+use crate::state::AppState;
+
+// clients listed by the proxy limits report
+const MOST_LIMITED_CLIENTS: usize = 50;
+
+pub fn admin_router() -> Router<AppState> {
+    Router::new()
+        .route("/admin/keys", get(list_keys).post(create_key))
+        .route("/admin/keys/{id}", delete(revoke_key))
+        .route("/admin/keys/{id}/usage", get(key_usage))
+        .route("/admin/keys/{id}/rate-limit", put(set_key_rate_limit))
+        .route("/admin/shares", get(list_shares).post(create_share))
+        .route("/admin/shares/{token}", delete(revoke_share))
+        .route("/admin/shares/{token}/usage", get(share_usage))
+        .route("/admin/proxy-limits", get(proxy_limited_clients))
+        .route("/admin/status", get(service_status))
+        .route_layer(from_fn(require_admin))
+}
+
+// keys are kept in the local database, so there are none to manage without one
+fn keys_disabled() -> Response {
+    error_response(
+        StatusCode::FORBIDDEN,
+        ErrorCode::FeatureDisabled,
+        "API keys disabled",
+        "Set DATABASE_URL to issue and manage API keys".to_string(),
+    )
+}
+
+fn no_key(id: &str) -> Response {
+    ApiError::NotFound(format!("No API key with id {id}")).into_response()
+}
+
+// Issue a key for a third party, a build pipeline or a second photographer, whose
+// upload key can be held to some groups. The secret is only returned here.
+async fn create_key(
+    State(state): State<AppState>,
+    Json(body): Json<CreateKeyRequest>,
+) -> Result<Json<CreateKeyResponse>, Response> {
+    let db = state.db.as_ref().ok_or_else(keys_disabled)?;
+    let groups = body.groups.map(|groups| {
+        let mut groups: Vec<String> = groups.iter().map(|g| g.trim().to_string()).collect();
+        groups.sort();
+        groups.dedup();
+        groups
+    });
+    // fails for a group that doesn't exist
+    for group_id in groups.iter().flatten() {
+        groups::get_own_group(&state.pinata, group_id)
+            .await
+            .map_err(IntoResponse::into_response)?
+            .ok_or_else(|| {
+                ApiError::NotFound(format!("Group {group_id} not found")).into_response()
+            })?;
+    }
+    let (key, secret) = api_keys::create(
+        db,
+        &body.name,
+        body.scope,
+        body.rate_limit_per_minute,
+        groups,
+    )
+    .await
+    .map_err(IntoResponse::into_response)?;
+    info!("Issued {:?} API key {} ({})", key.scope, key.id, key.name);
+
+    Ok(Json(CreateKeyResponse {
+        success: true,
+        key,
+        secret,
+        message: Some("Store the secret now, it can't be shown again".to_string()),
+    }))
+}
+
+async fn list_keys(State(state): State<AppState>) -> Result<Json<KeysResponse>, Response> {
+    let keys = state
+        .db
+        .as_ref()
+        .ok_or_else(keys_disabled)?
+        .api_keys()
+        .await
+        .map_err(IntoResponse::into_response)?;
+    Ok(Json(KeysResponse {
+        success: true,
+        keys,
+        message: None,
+    }))
+}
+
+async fn revoke_key(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<KeyResponse>, Response> {
+    let key = api_keys::revoke(state.db.as_ref().ok_or_else(keys_disabled)?, &id)
+        .await
+        .map_err(IntoResponse::into_response)?
+        .ok_or_else(|| no_key(&id))?;
+    info!("Revoked API key {}", key.id);
+
+    Ok(Json(KeyResponse {
+        success: true,
+        key,
+        message: None,
+    }))
+}
+
+// requests and bytes served for a key since the last restart
+async fn key_usage(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<KeyUsageResponse>, Response> {
+    let key = state
+        .db
+        .as_ref()
+        .ok_or_else(keys_disabled)?
+        .api_key(&id)
+        .await
+        .map_err(IntoResponse::into_response)?
+        .ok_or_else(|| no_key(&id))?;
+
+    Ok(Json(KeyUsageResponse {
+        success: true,
+        usage: usage::snapshot(&key.id),
+        key,
+        message: None,
+    }))
+}
+
+async fn set_key_rate_limit(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Json(body): Json<SetRateLimitRequest>,
+) -> Result<Json<KeyResponse>, Response> {
+    let key = state
+        .db
+        .as_ref()
+        .ok_or_else(keys_disabled)?
+        .set_api_key_rate_limit(&id, body.per_minute)
+        .await
+        .map_err(IntoResponse::into_response)?
+        .ok_or_else(|| no_key(&id))?;
+
+    Ok(Json(KeyResponse {
+        success: true,
+        key,
+        message: None,
+    }))
+}
+
+// give a client access to one gallery
+async fn create_share(
+    State(state): State<AppState>,
+    Json(body): Json<CreateShareRequest>,
+) -> Result<Json<ShareResponse>, ApiError> {
+    // fails for a group that doesn't exist
+    let group = groups::get_group(&state.pinata, body.group_id.trim()).await?;
+    let share = shares::create(&group.id, body.label, body.expires_in_secs, body.policy)?;
+    info!("Shared group {} as {}", group.id, share.token);
+
+    Ok(Json(ShareResponse {
+        success: true,
+        url: state.config.frontend.share_url(&share.token),
+        share,
+        message: None,
+    }))
+}
+
+async fn list_shares() -> Json<SharesResponse> {
+    Json(SharesResponse {
+        success: true,
+        shares: shares::list(),
+        message: None,
+    })
+}
+
+async fn revoke_share(
+    State(state): State<AppState>,
+    Path(token): Path<String>,
+) -> Result<Json<ShareResponse>, ApiError> {
+    let share = shares::revoke(&token)?
+        .ok_or_else(|| ApiError::NotFound(format!("No share link {token}")))?;
+    info!("Revoked share link {}", share.token);
+
+    Ok(Json(ShareResponse {
+        success: true,
+        url: state.config.frontend.share_url(&share.token),
+        share,
+        message: None,
+    }))
+}
+
+// views and downloads of a share link, per file
+async fn share_usage(Path(token): Path<String>) -> Result<Json<ShareUsageResponse>, ApiError> {
+    let share =
+        shares::get(&token).ok_or_else(|| ApiError::NotFound(format!("No share link {token}")))?;
+
+    Ok(Json(ShareUsageResponse {
+        success: true,
+        active: share.is_active(chrono::Utc::now()),
+        remaining_downloads: share.remaining_downloads(),
+        token: share.token,
+        group_id: share.group_id,
+        policy: share.policy,
+        usage: share.usage,
+        message: None,
+    }))
+}
+
+// the addresses hitting the download caps most since the last restart
+async fn proxy_limited_clients(State(state): State<AppState>) -> Json<LimitedClientsResponse> {
+    Json(LimitedClientsResponse {
+        success: true,
+        clients: state.proxy_limits.most_limited(MOST_LIMITED_CLIENTS),
+        message: None,
+    })
+}
+
+// whether anything is in progress, to tell when a restart won't cut work short
+async fn service_status() -> Json<ServiceStatus> {
+    let requests_in_flight = activity::count(Activity::Request).saturating_sub(1);
+    let uploads_in_progress = activity::count(Activity::Upload);
+    let event_streams_open = activity::count(Activity::EventStream);
+    let background_jobs = activity::background_jobs();
+    let jobs_running = background_jobs.uploads
+        + background_jobs.processing
+        + background_jobs.reencode
+        + background_jobs.notifications;
+
+    Json(ServiceStatus {
+        success: true,
+        idle: requests_in_flight == 0
+            && uploads_in_progress == 0
+            && event_streams_open == 0
+            && jobs_running == 0,
+        requests_in_flight,
+        uploads_in_progress,
+        event_streams_open,
+        background_jobs,
+    })
+}
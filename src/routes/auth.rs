@@ -0,0 +1,116 @@
+use axum::{
+    Json, Router,
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::post,
+};
+use tracing::{info, warn};
+
+use crate::auth::siwe::{self, SignInError};
+use crate::errors::{ApiError, ErrorCode, error_response};
+use crate::models::auth::{NonceResponse, SessionResponse, VerifyRequest};
+use crate::state::AppState;
+
+pub fn auth_router() -> Router<AppState> {
+    Router::new()
+        .route("/auth/nonce", post(create_nonce))
+        .route("/auth/verify", post(verify_sign_in))
+}
+
+fn sign_in_disabled() -> Response {
+    error_response(
+        StatusCode::FORBIDDEN,
+        ErrorCode::Forbidden,
+        "Sign-in disabled",
+        "Set OWNER_ADDRESS and SESSION_SECRET to enable signing in with Ethereum".to_string(),
+    )
+}
+
+// a message's domain is only worth checking against one the request doesn't pick
+fn no_domain() -> Response {
+    error_response(
+        StatusCode::FORBIDDEN,
+        ErrorCode::FeatureDisabled,
+        "Sign-in disabled",
+        "Set SIWE_DOMAIN or FRONTEND_URL to name the domain sign-in messages are for".to_string(),
+    )
+}
+
+async fn create_nonce(State(state): State<AppState>) -> Result<Json<NonceResponse>, Response> {
+    if !siwe::is_enabled() {
+        return Err(sign_in_disabled());
+    }
+    if expected_domain(&state).is_none() {
+        return Err(no_domain());
+    }
+    let (nonce, expires_at) = siwe::issue_nonce();
+    Ok(Json(NonceResponse {
+        success: true,
+        nonce,
+        expires_at,
+        message: None,
+    }))
+}
+
+// The domain sign-in messages have to be for: SIWE_DOMAIN, else the frontend's host.
+// Never the request's Host, which whoever sends it picks.
+fn expected_domain(state: &AppState) -> Option<String> {
+    if let Some(domain) = &state.config.auth.siwe_domain {
+        return Some(domain.clone());
+    }
+    state
+        .config
+        .frontend
+        .public_url
+        .as_deref()
+        .and_then(|url| url::Url::parse(url).ok())
+        .and_then(|url| {
+            let host = url.host_str()?.to_string();
+            Some(match url.port() {
+                Some(port) => format!("{host}:{port}"),
+                None => host,
+            })
+        })
+}
+
+// trade a message signed by the owner's wallet for a session token
+async fn verify_sign_in(
+    State(state): State<AppState>,
+    Json(request): Json<VerifyRequest>,
+) -> Result<Json<SessionResponse>, Response> {
+    if !siwe::is_enabled() {
+        return Err(sign_in_disabled());
+    }
+    let domain = expected_domain(&state).ok_or_else(no_domain)?;
+
+    // recovering the signer is a few hundred curve operations
+    let verified = tokio::task::spawn_blocking(move || {
+        siwe::verify(&request.message, &request.signature, &domain)
+    })
+    .await
+    .map_err(|e| ApiError::Internal(format!("Sign-in failed: {e}")).into_response())?;
+
+    match verified {
+        Ok((token, address, expires_at)) => {
+            info!("Owner {address} signed in");
+            Ok(Json(SessionResponse {
+                success: true,
+                token,
+                address,
+                expires_at,
+                message: None,
+            }))
+        }
+        Err(SignInError::Malformed(message)) => Err(ApiError::Validation(message).into_response()),
+        Err(SignInError::Rejected(message)) => {
+            warn!("Refused a sign-in: {message}");
+            Err(error_response(
+                StatusCode::UNAUTHORIZED,
+                ErrorCode::Unauthorized,
+                "Sign-in refused",
+                message,
+            ))
+        }
+    }
+}
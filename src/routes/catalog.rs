@@ -0,0 +1,274 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::io::Write;
+
+use axum::{
+    Json, Router,
+    body::Bytes,
+    extract::{Query, State},
+    http::{
+        HeaderMap, HeaderValue, StatusCode,
+        header::{ACCEPT_ENCODING, CONTENT_ENCODING, CONTENT_TYPE, ETAG, IF_NONE_MATCH, VARY},
+    },
+    response::{IntoResponse, Response},
+    routing::get,
+};
+use chrono::Utc;
+use flate2::{Compression, write::GzEncoder};
+use sha2::{Digest, Sha256};
+use tracing::{info, warn};
+
+use crate::cache::CachedResponse;
+use crate::errors::ApiError;
+use crate::middleware::cache::is_upstream_failure;
+use crate::models::catalog::{
+    CatalogChangesResponse, CatalogDocument, CatalogGroup, ChangeKind, ChangedEntry, ChangedParams,
+};
+use crate::pinata::{FilesQuery, ListOptions, gateway};
+use crate::routes::categories::category_summaries;
+use crate::state::AppState;
+
+// the built document is kept in the response cache, so catalog changes drop it too
+const FULL_CATALOG_KEY: &str = "catalog:full";
+
+pub fn catalog_router() -> Router<AppState> {
+    Router::new()
+        .route("/catalog/full", get(full_catalog))
+        .route("/catalog/changed", get(changed_since))
+}
+
+async fn build_catalog(state: &AppState) -> Result<CatalogDocument, ApiError> {
+    let groups: Vec<_> = state
+        .list_groups(None)
+        .await?
+        .into_iter()
+        .filter(|group| group.is_public != Some(false))
+        .collect();
+    let mut files = state
+        .list_files(FilesQuery::new(), ListOptions::default())
+        .await?;
+
+    // only files in this deployment's public groups, plus ungrouped ones
+    let mut members: HashMap<&str, Vec<String>> =
+        groups.iter().map(|g| (g.id.as_str(), Vec::new())).collect();
+    files.retain(|file| file.group_id.is_empty() || members.contains_key(file.group_id.as_str()));
+    files.sort_by(|a, b| {
+        b.created_at
+            .cmp(&a.created_at)
+            .then_with(|| a.id.cmp(&b.id))
+    });
+    for file in &files {
+        if let Some(ids) = members.get_mut(file.group_id.as_str()) {
+            ids.push(file.id.clone());
+        }
+    }
+
+    let groups = groups
+        .iter()
+        .map(|group| CatalogGroup {
+            id: group.id.clone(),
+            name: group.name.clone(),
+            created_at: group.created_at,
+            file_ids: members.remove(group.id.as_str()).unwrap_or_default(),
+        })
+        .collect();
+
+    Ok(CatalogDocument {
+        success: true,
+        url_templates: gateway::url_templates(
+            &state.pinata,
+            state.config.upload.preview_thumbnail_width,
+        ),
+        categories: category_summaries(&state.config.catalog.known_categories),
+        groups,
+        files,
+        message: None,
+    })
+}
+
+fn accepts_gzip(headers: &HeaderMap) -> bool {
+    headers
+        .get_all(ACCEPT_ENCODING)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .any(|encoding| {
+            let mut params = encoding.split(';').map(str::trim);
+            params
+                .next()
+                .is_some_and(|e| e.eq_ignore_ascii_case("gzip"))
+                && !params.any(|p| p == "q=0")
+        })
+}
+
+// the entire public catalog in one gzip-compressed, ETag-validated document
+async fn full_catalog(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let body = match state.cache.get(FULL_CATALOG_KEY).await {
+        Some(cached) => cached.body,
+        None => match build_catalog(&state).await {
+            Ok(document) => {
+                let body = Bytes::from(serde_json::to_vec(&document)?);
+                info!(
+                    "Built full catalog: {} groups, {} files",
+                    document.groups.len(),
+                    document.files.len()
+                );
+                state.last_good.record(FULL_CATALOG_KEY, &body).await;
+                state
+                    .cache
+                    .insert(
+                        FULL_CATALOG_KEY.to_string(),
+                        CachedResponse {
+                            content_type: None,
+                            body: body.clone(),
+                        },
+                    )
+                    .await;
+                body
+            }
+            // Pinata is down: the last catalog built, marked stale, rather than nothing
+            Err(e) if is_upstream_failure(e.status()) => {
+                let snapshot = state.last_good.load(FULL_CATALOG_KEY).await;
+                match snapshot.and_then(|snapshot| snapshot.marked_stale()) {
+                    Some(body) => {
+                        warn!("Serving the last good catalog: {e}");
+                        body
+                    }
+                    None => return Err(e),
+                }
+            }
+            Err(e) => return Err(e),
+        },
+    };
+
+    let digest = Sha256::digest(&body);
+    let etag = format!(
+        "\"{}\"",
+        digest[..16]
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect::<String>()
+    );
+    let etag = HeaderValue::from_str(&etag).expect("hex etag is a valid header");
+    let vary = HeaderValue::from_static("accept-encoding");
+
+    let unchanged = headers
+        .get_all(IF_NONE_MATCH)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .any(|tag| {
+            let tag = tag.trim();
+            tag == "*" || tag.trim_start_matches("W/") == etag
+        });
+    if unchanged {
+        return Ok((StatusCode::NOT_MODIFIED, [(ETAG, etag), (VARY, vary)]).into_response());
+    }
+
+    let json = HeaderValue::from_static("application/json");
+    if !accepts_gzip(&headers) {
+        return Ok(([(CONTENT_TYPE, json), (ETAG, etag), (VARY, vary)], body).into_response());
+    }
+
+    let mut encoder = GzEncoder::new(Vec::with_capacity(body.len() / 4), Compression::default());
+    let compressed = encoder
+        .write_all(&body)
+        .and_then(|()| encoder.finish())
+        .map_err(|e| ApiError::Internal(format!("Failed to compress catalog: {e}")))?;
+
+    Ok((
+        [
+            (CONTENT_TYPE, json),
+            (CONTENT_ENCODING, HeaderValue::from_static("gzip")),
+            (ETAG, etag),
+            (VARY, vary),
+        ],
+        compressed,
+    )
+        .into_response())
+}
+
+// what changed after `since` and which frontend pages show it, for targeted revalidation
+async fn changed_since(
+    State(state): State<AppState>,
+    Query(params): Query<ChangedParams>,
+) -> Result<Json<CatalogChangesResponse>, ApiError> {
+    let until = Utc::now();
+    // latest state per id, so something added and then removed is reported as removed
+    let mut groups: BTreeMap<String, bool> = BTreeMap::new();
+    let mut files: BTreeMap<String, bool> = BTreeMap::new();
+
+    let complete = match &state.db {
+        Some(db) => {
+            for change in db.changes_since(params.since).await? {
+                if change.changed_at > until {
+                    continue;
+                }
+                match change.kind {
+                    ChangeKind::Group => groups.insert(change.id, change.deleted),
+                    ChangeKind::File => files.insert(change.id, change.deleted),
+                };
+            }
+            true
+        }
+        None => {
+            // without the mirror, all Pinata can tell us is what was created since
+            for file in state
+                .list_files(FilesQuery::new(), ListOptions::default())
+                .await?
+                .into_iter()
+                .filter(|file| file.created_at > params.since)
+            {
+                if !file.group_id.is_empty() {
+                    groups.insert(file.group_id, false);
+                }
+                files.insert(file.id, false);
+            }
+            for group in state.list_groups(None).await? {
+                if group.created_at > params.since {
+                    groups.insert(group.id, false);
+                }
+            }
+            false
+        }
+    };
+
+    let frontend = &state.config.frontend;
+    let mut routes: BTreeSet<String> = BTreeSet::new();
+    if !groups.is_empty() || !files.is_empty() {
+        routes.extend(frontend.index_routes.iter().cloned());
+    }
+    routes.extend(
+        groups
+            .keys()
+            .map(|id| frontend.group_route.replace("{id}", id)),
+    );
+    routes.extend(
+        files
+            .keys()
+            .map(|id| frontend.file_route.replace("{id}", id)),
+    );
+
+    let entries = |changes: BTreeMap<String, bool>| {
+        changes
+            .into_iter()
+            .map(|(id, deleted)| ChangedEntry { id, deleted })
+            .collect()
+    };
+
+    Ok(Json(CatalogChangesResponse {
+        success: true,
+        since: params.since,
+        until,
+        complete,
+        message: (!complete).then(|| {
+            "Without DATABASE_URL only new files and groups are reported, not edits or deletions"
+                .to_string()
+        }),
+        groups: entries(groups),
+        files: entries(files),
+        routes: routes.into_iter().collect(),
+    }))
+}
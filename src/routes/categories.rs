@@ -1,141 +1,494 @@
-use axum::{Json, Router, extract::Query, routing::get};
-use dotenv::dotenv;
-use reqwest::{Client, Url};
-use std::env;
+use axum::{
+    Json, Router,
+    extract::{Path, Query, State},
+    routing::{get, put},
+};
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::{Arc, LazyLock};
+use tokio::task::JoinSet;
+use tracing::{info, warn};
 
 use crate::ApiError;
+use crate::coalesce::Coalescer;
+use crate::extractors::{Limit, Scoped, View};
 use crate::models::{
-    categories::{CategoryParams, CategoryResponse},
-    favourites::PinataFilesResponse,
+    catalog::ListingView,
+    categories::{
+        CategoriesResponse, CategoryCoverResponse, CategoryParams, CategoryResponse,
+        CategorySettings, CategorySummary, CategoryTaxonomy, CategoryWarning, SetAliasRequest,
+        SetCoverRequest, SetParentRequest, TaxonomyResponse,
+    },
     pinata::PinataFile,
 };
+use crate::pinata::{FilesQuery, FilterOp, KeyvalueFilter, ListOptions, files, gateway};
+use crate::routes::uploads::UPLOADED_VIA;
+use crate::state::AppState;
+use crate::store::JsonStore;
 
-pub fn categories_router() -> Router {
-    Router::new().route("/files-category", get(get_files_by_category))
+pub fn categories_router() -> Router<AppState> {
+    Router::new()
+        .route("/files-category", get(get_files_by_category))
+        .route("/categories", get(list_categories))
+        .route("/categories/taxonomy", get(get_taxonomy))
+        .route(
+            "/categories/aliases/{alias}",
+            put(set_category_alias).delete(remove_category_alias),
+        )
+        .route("/categories/{name}/cover", put(set_category_cover))
+        .route(
+            "/categories/{name}/parent",
+            put(set_category_parent).delete(remove_category_parent),
+        )
 }
+
+// cover photo and display order per category, keyed by lowercased name
+static CATEGORY_SETTINGS: LazyLock<JsonStore<BTreeMap<String, CategorySettings>>> =
+    LazyLock::new(|| JsonStore::open("category_settings"));
+
+// aliases and parent/child links, applied whenever categories are filtered
+static TAXONOMY: LazyLock<JsonStore<CategoryTaxonomy>> =
+    LazyLock::new(|| JsonStore::open("category_taxonomy"));
+// identical category queries in flight share one upstream fetch
+type CategoryOutcome = Result<(Vec<PinataFile>, Vec<CategoryWarning>), String>;
+static CATEGORY_REQUESTS: LazyLock<Coalescer<CategoryOutcome>> = LazyLock::new(Coalescer::new);
+
 pub async fn get_files_by_category(
+    Scoped(state): Scoped,
     Query(params): Query<CategoryParams>,
+    Limit(limit): Limit,
+    View(view): View,
 ) -> Result<Json<CategoryResponse>, ApiError> {
-    let categories = match &params.categories {
-        Some(cats) => cats
-            .split(",")
-            .map(|s| s.trim().to_string())
-            .collect::<Vec<_>>(),
-        None => Vec::new(),
-    };
+    let categories = normalize_categories(params.categories.as_deref());
+    let categories = TAXONOMY.read(|taxonomy| taxonomy.expand(&categories));
+    let mut filters = parse_filters(params.filters.as_deref())?;
+    if let Some(via) = params.uploaded_via.as_deref().map(str::trim) {
+        if filters.iter().any(|(key, _)| key == UPLOADED_VIA) {
+            return Err(ApiError::Validation(format!(
+                "Only one filter per key is supported, '{UPLOADED_VIA}' was given twice"
+            )));
+        }
+        let value = serde_json::Value::String(via.to_ascii_lowercase());
+        filters.push((
+            UPLOADED_VIA.to_string(),
+            KeyvalueFilter::new(FilterOp::Eq, value),
+        ));
+    }
+    let fail_soft = params.fail_soft && categories.len() > 1;
+
+    let mut key = category_cache_key(&categories, &filters, limit, fail_soft);
+    if let Some(account) = state.accounts.scope() {
+        key.push_str(&format!("|account={account}"));
+    }
+    // the state itself is still needed for the response
+    let fetching = state.clone();
+    let outcome = CATEGORY_REQUESTS
+        .run(key, || async move {
+            let result = if fail_soft {
+                fetch_files_fail_soft(fetching, categories, filters, limit).await
+            } else {
+                fetch_files_from_pinata(&fetching, categories, &filters, limit)
+                    .await
+                    .map(|files| (files, Vec::new()))
+            };
+            result.map_err(|e| e.to_string())
+        })
+        .await;
 
-    match fetch_files_from_pinata(categories).await {
-        Ok(mut files) => {
+    match outcome {
+        Ok((files, warnings)) => {
             // Filter for images only
             // let images: Vec<PinataFile> = files
             //     .into_iter()
             //     .filter(|file| file.mime_type.starts_with("image/"))
             //     .collect();
 
-            // // Apply limit if specified
-            if let Some(limit) = params.limit {
-                files = files.into_iter().take(limit).collect();
-            }
-
+            let thumbnail_width = state.config.upload.preview_thumbnail_width;
+            let preload = match view {
+                ListingView::Detail => gateway::preload_hints(
+                    &state.pinata,
+                    &files,
+                    state.config.listing.preload_count,
+                    thumbnail_width,
+                ),
+                ListingView::Grid => Vec::new(),
+            };
             Ok(Json(CategoryResponse {
                 success: true,
-                images: files,
-                message: None,
+                images: gateway::listed_files(&state.pinata, files, view, thumbnail_width),
+                preload,
+                message: (!warnings.is_empty())
+                    .then(|| format!("{} categories could not be loaded", warnings.len())),
+                warnings,
             }))
         }
         Err(e) => {
-            eprintln!("Error fetching files by categories: {e}");
-            Err(e)
+            warn!("Error fetching files by categories: {e}");
+            Err(ApiError::Api(e))
         }
     }
 }
 
-///////////////// get_files ///////
-async fn fetch_files_from_pinata(categories: Vec<String>) -> Result<Vec<PinataFile>, ApiError> {
-    dotenv().ok();
-    let api_key = env::var("PINATA_JWT").map_err(|e| {
-        eprintln!("Failed to get PINATA_JWT: {e}");
-        ApiError::Env(e)
-    })?;
+pub async fn list_categories(State(state): State<AppState>) -> Json<CategoriesResponse> {
+    Json(CategoriesResponse {
+        success: true,
+        categories: category_summaries(&state.config.catalog.known_categories),
+        message: None,
+    })
+}
+
+// known categories plus any with stored settings, ordered for the landing grid
+pub fn category_summaries(known: &[String]) -> Vec<CategorySummary> {
+    let settings = CATEGORY_SETTINGS.read(|settings| settings.clone());
+    let taxonomy = TAXONOMY.read(|taxonomy| taxonomy.clone());
+
+    let names: BTreeSet<String> = known
+        .iter()
+        .map(|name| taxonomy.resolve(name))
+        .chain(settings.keys().cloned())
+        .chain(
+            taxonomy
+                .parents
+                .iter()
+                .flat_map(|(c, p)| [c.clone(), p.clone()]),
+        )
+        .collect();
+
+    let mut categories: Vec<CategorySummary> = names
+        .into_iter()
+        .map(|name| {
+            let entry = settings.get(&name).cloned().unwrap_or_default();
+            let parent = taxonomy.parents.get(&name).cloned();
+            category_summary(name, parent, entry)
+        })
+        .collect();
 
-    let gatewat_key = env::var("PINATA_GATEWAY_KEY").map_err(|e| {
-        eprintln!("Failed to get the gatewar key: {e}");
-        ApiError::Env(e)
+    // explicitly ordered categories first, the rest alphabetically
+    categories.sort_by(|a, b| {
+        (a.display_order.is_none(), a.display_order, &a.name).cmp(&(
+            b.display_order.is_none(),
+            b.display_order,
+            &b.name,
+        ))
     });
 
-    let client = Client::new();
-    let mut all_files = Vec::new();
-    let mut page_token: Option<String> = None;
+    categories
+}
 
-    loop {
-        let mut url = Url::parse("https://api.pinata.cloud/v3/files/public")?;
+// designate a category's cover photo, and optionally where it sits in the grid
+pub async fn set_category_cover(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    Json(body): Json<SetCoverRequest>,
+) -> Result<Json<CategoryCoverResponse>, ApiError> {
+    let name = category_name(&name)?;
+    let name = TAXONOMY.read(|taxonomy| taxonomy.resolve(&name));
 
-        if !categories.is_empty() {
-            let metadata_json = if categories.len() == 1 {
-                format!(
-                    r#"{{"category":{{"value":"{}","op":"eq"}}}}"#,
-                    categories[0]
-                )
-            } else {
-                let categories_json = categories
-                    .iter()
-                    .map(|c| format!(r#""{}""#, c))
-                    .collect::<Vec<_>>()
-                    .join(",");
-
-                format!(
-                    r#"{{"category":{{"value":[{}],"op":"in"}}}}"#,
-                    categories_json
-                )
-            };
+    let file = files::get_file(&state.pinata, &body.file_id).await?;
 
-            // // url encode the json
-            // let encoded_metadata =
-            //
-            url.query_pairs_mut()
-                .append_pair("metadata[keyvalues]", &metadata_json);
+    // a cover from another category is allowed, but worth flagging
+    let file_category = file.keyvalues.category.as_deref().map(str::to_lowercase);
+    let message = (file_category.as_deref() != Some(name.as_str()))
+        .then(|| format!("File {} is not tagged with category '{name}'", file.id));
 
-            println!("{url}");
+    let entry = CATEGORY_SETTINGS.update(|settings| {
+        let entry = settings.entry(name.clone()).or_default();
+        entry.cover_file_id = Some(file.id.clone());
+        entry.cover_cid = Some(file.cid.clone());
+        if body.display_order.is_some() {
+            entry.display_order = body.display_order;
         }
+        entry.clone()
+    })?;
+
+    info!("Set cover for category {name} to {}", file.id);
+
+    let parent = TAXONOMY.read(|taxonomy| taxonomy.parents.get(&name).cloned());
+    Ok(Json(CategoryCoverResponse {
+        success: true,
+        category: category_summary(name, parent, entry),
+        message,
+    }))
+}
+
+pub async fn get_taxonomy() -> Json<TaxonomyResponse> {
+    Json(TaxonomyResponse {
+        success: true,
+        taxonomy: TAXONOMY.read(|taxonomy| taxonomy.clone()),
+        message: None,
+    })
+}
+
+// make `alias` filter as its target category
+pub async fn set_category_alias(
+    State(state): State<AppState>,
+    Path(alias): Path<String>,
+    Json(body): Json<SetAliasRequest>,
+) -> Result<Json<TaxonomyResponse>, ApiError> {
+    let alias = category_name(&alias)?;
+    let target = category_name(&body.target)?;
 
-        // add page token
-        if let Some(token) = &page_token {
-            url.query_pairs_mut().append_pair("pageToken", token);
+    let taxonomy = TAXONOMY.update(|taxonomy| {
+        // point at the canonical name so aliases never chain
+        let target = taxonomy.resolve(&target);
+        if target == alias {
+            return Err(ApiError::Validation(format!(
+                "Category '{alias}' cannot be an alias of itself"
+            )));
         }
+        if taxonomy.aliases.values().any(|existing| *existing == alias) {
+            return Err(ApiError::Conflict(format!(
+                "'{alias}' already has aliases pointing at it"
+            )));
+        }
+
+        taxonomy.aliases.insert(alias, target);
+        Ok(taxonomy.clone())
+    })??;
+    // aliases and parents change what /files-category matches
+    state.cache.invalidate_all();
+
+    Ok(Json(TaxonomyResponse {
+        success: true,
+        taxonomy,
+        message: None,
+    }))
+}
+
+pub async fn remove_category_alias(
+    State(state): State<AppState>,
+    Path(alias): Path<String>,
+) -> Result<Json<TaxonomyResponse>, ApiError> {
+    let alias = category_name(&alias)?;
 
-        println!("{url}");
-
-        let response = client
-            .get(url)
-            .header("Authorization", format!("Bearer {api_key}"))
-            .send()
-            .await?;
-
-        let status = response.status();
-
-        if !status.is_success() {
-            let error_body = response.text().await?;
-            println!("API request failed with status: {status}");
-            println!("Response body: {error_body}");
-            return Err(format!(
-                "API request failed with status: {}. Body: {}",
-                status, error_body
-            )
-            .into());
+    let taxonomy = TAXONOMY.update(|taxonomy| match taxonomy.aliases.remove(&alias) {
+        Some(_) => Ok(taxonomy.clone()),
+        None => Err(ApiError::NotFound(format!("No alias named '{alias}'"))),
+    })??;
+    // aliases and parents change what /files-category matches
+    state.cache.invalidate_all();
+
+    Ok(Json(TaxonomyResponse {
+        success: true,
+        taxonomy,
+        message: None,
+    }))
+}
+
+// nest `name` under a parent, so browsing the parent includes it
+pub async fn set_category_parent(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    Json(body): Json<SetParentRequest>,
+) -> Result<Json<TaxonomyResponse>, ApiError> {
+    let name = category_name(&name)?;
+    let parent = category_name(&body.parent)?;
+
+    let taxonomy = TAXONOMY.update(|taxonomy| {
+        let name = taxonomy.resolve(&name);
+        let parent = taxonomy.resolve(&parent);
+        if name == parent || taxonomy.is_ancestor(&name, &parent) {
+            return Err(ApiError::Conflict(format!(
+                "Making '{parent}' the parent of '{name}' would create a cycle"
+            )));
         }
 
-        // parse response
-        let data: PinataFilesResponse = response.json().await?;
-        println!("Found {} files", data.data.files.len());
+        taxonomy.parents.insert(name, parent);
+        Ok(taxonomy.clone())
+    })??;
+    // aliases and parents change what /files-category matches
+    state.cache.invalidate_all();
 
-        all_files.extend(data.data.files);
+    Ok(Json(TaxonomyResponse {
+        success: true,
+        taxonomy,
+        message: None,
+    }))
+}
 
-        match data.data.next_page_token {
-            Some(token) => page_token = Some(token),
-            None => break,
+pub async fn remove_category_parent(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> Result<Json<TaxonomyResponse>, ApiError> {
+    let name = category_name(&name)?;
+
+    let taxonomy = TAXONOMY.update(|taxonomy| {
+        let name = taxonomy.resolve(&name);
+        match taxonomy.parents.remove(&name) {
+            Some(_) => Ok(taxonomy.clone()),
+            None => Err(ApiError::NotFound(format!(
+                "Category '{name}' has no parent"
+            ))),
+        }
+    })??;
+    // aliases and parents change what /files-category matches
+    state.cache.invalidate_all();
+
+    Ok(Json(TaxonomyResponse {
+        success: true,
+        taxonomy,
+        message: None,
+    }))
+}
+
+fn category_name(raw: &str) -> Result<String, ApiError> {
+    let name = raw.trim().to_lowercase();
+    if name.is_empty() {
+        return Err(ApiError::Validation(
+            "Category name is required".to_string(),
+        ));
+    }
+    Ok(name)
+}
+
+fn category_summary(
+    name: String,
+    parent: Option<String>,
+    settings: CategorySettings,
+) -> CategorySummary {
+    CategorySummary {
+        name,
+        parent,
+        display_order: settings.display_order,
+        cover_file_id: settings.cover_file_id,
+        cover_cid: settings.cover_cid,
+    }
+}
+
+// trimmed, lowercased, sorted and de-duplicated, so equivalent queries look identical
+pub fn normalize_categories(raw: Option<&str>) -> Vec<String> {
+    let mut categories: Vec<String> = raw
+        .unwrap_or_default()
+        .split(',')
+        .map(|s| s.trim().to_lowercase())
+        .filter(|s| !s.is_empty())
+        .collect();
+
+    categories.sort();
+    categories.dedup();
+    categories
+}
+
+// deterministic key for a category listing, e.g. `?categories=street,travel` and `?categories=travel, street` match
+pub fn category_cache_key(
+    categories: &[String],
+    filters: &[(String, KeyvalueFilter)],
+    limit: usize,
+    fail_soft: bool,
+) -> String {
+    let mut filters: Vec<String> = filters
+        .iter()
+        .map(|(key, filter)| format!("{key}={}", serde_json::json!(filter)))
+        .collect();
+    filters.sort();
+
+    format!(
+        "files-category|categories={}|filters={}|limit={limit}|fail_soft={fail_soft}",
+        categories.join(","),
+        filters.join(";"),
+    )
+}
+
+// parse `?filters=iso:gt:400,camera:prefix:Fuji` into keyvalue filters
+pub fn parse_filters(raw: Option<&str>) -> Result<Vec<(String, KeyvalueFilter)>, ApiError> {
+    let Some(raw) = raw else {
+        return Ok(Vec::new());
+    };
+
+    let mut filters: Vec<(String, KeyvalueFilter)> = Vec::new();
+    for spec in raw.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+        let (key, filter) = KeyvalueFilter::parse(spec)?;
+        if filters.iter().any(|(existing, _)| *existing == key) {
+            return Err(ApiError::Validation(format!(
+                "Only one filter per key is supported, '{key}' was given twice"
+            )));
         }
+        filters.push((key, filter));
     }
 
-    println!("Total files collected: {}", all_files.len());
-    Ok(all_files)
+    Ok(filters)
+}
+
+// build the Pinata files query for the requested categories and extra filters
+pub fn category_query(categories: &[String], filters: &[(String, KeyvalueFilter)]) -> FilesQuery {
+    let query = match categories {
+        [] => FilesQuery::new(),
+        [category] => FilesQuery::new().keyvalue_eq("category", category),
+        _ => FilesQuery::new().keyvalue_in("category", categories),
+    };
+
+    filters
+        .iter()
+        .cloned()
+        .fold(query, |query, (key, filter)| query.keyvalue(key, filter))
+}
+
+///////////////// get_files ///////
+async fn fetch_files_from_pinata(
+    state: &AppState,
+    categories: Vec<String>,
+    filters: &[(String, KeyvalueFilter)],
+    limit: usize,
+) -> Result<Vec<PinataFile>, ApiError> {
+    // the limit is threaded into the pagination so we stop as soon as it's satisfied
+    state
+        .list_files(
+            category_query(&categories, filters),
+            ListOptions::limit(Some(limit)),
+        )
+        .await
+}
+
+// fetch each category on its own, so one failing upstream call doesn't sink the rest
+async fn fetch_files_fail_soft(
+    state: AppState,
+    categories: Vec<String>,
+    filters: Vec<(String, KeyvalueFilter)>,
+    limit: usize,
+) -> Result<(Vec<PinataFile>, Vec<CategoryWarning>), ApiError> {
+    let filters = Arc::new(filters);
+    let mut tasks = JoinSet::new();
+    for category in categories {
+        let filters = filters.clone();
+        let state = state.clone();
+        tasks.spawn(async move {
+            let result =
+                fetch_files_from_pinata(&state, vec![category.clone()], &filters, limit).await;
+            (category, result)
+        });
+    }
+
+    let mut files = Vec::new();
+    let mut warnings = Vec::new();
+    let mut first_error = None;
+
+    while let Some(joined) = tasks.join_next().await {
+        let (category, result) =
+            joined.map_err(|e| ApiError::Internal(format!("Category fetch task failed: {e}")))?;
+
+        match result {
+            Ok(found) => files.extend(found),
+            Err(e) => {
+                warn!("Error fetching category {category}: {e}");
+                warnings.push(CategoryWarning {
+                    category,
+                    message: e.to_string(),
+                });
+                first_error.get_or_insert(e);
+            }
+        }
+    }
+
+    // nothing to degrade to when every category failed
+    if files.is_empty()
+        && let Some(e) = first_error
+    {
+        return Err(e);
+    }
+
+    // merge the per-category results newest first, as a single query would return them
+    files.sort_by_key(|file| std::cmp::Reverse(file.created_at));
+    files.truncate(limit);
+    warnings.sort_by(|a, b| a.category.cmp(&b.category));
+
+    Ok((files, warnings))
 }
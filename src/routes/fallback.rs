@@ -0,0 +1,33 @@
+use axum::{
+    extract::OriginalUri,
+    http::{Method, StatusCode},
+    response::Response,
+};
+
+use super::ENDPOINTS;
+use crate::errors::{ErrorCode, problem_body, problem_response};
+
+// a problem listing the endpoints there are, for a client that got the path wrong
+fn with_endpoints(status: StatusCode, code: ErrorCode, error: &str, message: String) -> Response {
+    let mut body = problem_body(status, code, error, message);
+    body["endpoints"] = ENDPOINTS.into();
+    problem_response(status, &body)
+}
+
+pub async fn not_found(method: Method, OriginalUri(uri): OriginalUri) -> Response {
+    with_endpoints(
+        StatusCode::NOT_FOUND,
+        ErrorCode::NotFound,
+        "Not found",
+        format!("No route for {method} {}", uri.path()),
+    )
+}
+
+pub async fn method_not_allowed(method: Method, OriginalUri(uri): OriginalUri) -> Response {
+    with_endpoints(
+        StatusCode::METHOD_NOT_ALLOWED,
+        ErrorCode::MethodNotAllowed,
+        "Method not allowed",
+        format!("{method} is not supported on {}", uri.path()),
+    )
+}
@@ -1,115 +1,147 @@
-use axum::{Json, Router, extract::Query, routing::get};
-use dotenv::dotenv;
+use axum::{
+    Json, Router,
+    extract::{Path, Query, State},
+    response::Response,
+    routing::{get, post},
+};
+use tracing::warn;
 
 use crate::errors::ApiError;
-use reqwest::Client;
-use std::env; // handle env var
-
-use crate::models::favourites::{GroupImagesParams, GroupImagesResponse, PinataFilesResponse};
+use crate::extractors::{Limit, Scoped, View, Visitor};
+use crate::models::catalog::ListingView;
 use crate::models::pinata::PinataFile;
+use crate::pinata::{FilesQuery, ListOptions, SortOrder, files, gateway};
+use crate::state::AppState;
+use crate::{virtual_albums, visitor_favourites};
+
+use crate::models::favourites::{
+    GroupImagesParams, GroupImagesResponse, VisitorFavourite, VisitorFavouritesResponse,
+};
 
-pub fn favourites_router() -> Router {
+pub fn favourites_router() -> Router<AppState> {
     Router::new()
         .route("/favourites", get(get_favourites))
         .route("/group-images", get(get_group_images))
+        .route("/me/favourites", get(list_visitor_favourites))
+        .route(
+            "/me/favourites/{file_id}",
+            post(add_visitor_favourite).delete(remove_visitor_favourite),
+        )
+}
+
+fn visitor_favourites_response(visitor: Visitor, favourites: Vec<VisitorFavourite>) -> Response {
+    visitor.respond(Json(VisitorFavouritesResponse {
+        success: true,
+        favourites,
+        message: None,
+    }))
+}
+
+// the visitor's own selection; a first visit gets an empty list and a token
+async fn list_visitor_favourites(visitor: Visitor) -> Response {
+    let favourites = visitor_favourites::list(&visitor.id);
+    visitor_favourites_response(visitor, favourites)
+}
+
+async fn add_visitor_favourite(
+    State(state): State<AppState>,
+    visitor: Visitor,
+    Path(file_id): Path<String>,
+) -> Result<Response, ApiError> {
+    // only files that exist can be picked
+    let file = files::get_file(&state.pinata, file_id.trim()).await?;
+    let favourites = visitor_favourites::add(&visitor.id, &file.id)?;
+    Ok(visitor_favourites_response(visitor, favourites))
+}
+
+async fn remove_visitor_favourite(
+    visitor: Visitor,
+    Path(file_id): Path<String>,
+) -> Result<Response, ApiError> {
+    let favourites = visitor_favourites::remove(&visitor.id, file_id.trim())?;
+    Ok(visitor_favourites_response(visitor, favourites))
 }
 
 pub async fn get_favourites(
+    state: Scoped,
     query: Query<GroupImagesParams>,
+    limit: Limit,
+    view: View,
 ) -> Result<Json<GroupImagesResponse>, ApiError> {
     // Simply delegate to get_group_images
-    get_group_images(query).await
+    get_group_images(state, query, limit, view).await
 }
 
 pub async fn get_group_images(
+    Scoped(state): Scoped,
     Query(params): Query<GroupImagesParams>,
+    Limit(limit): Limit,
+    View(view): View,
 ) -> Result<Json<GroupImagesResponse>, ApiError> {
     let group_id = params
         .group_id
         .unwrap_or_else(|| "876d949f-6532-44af-924c-f164e5ac6b1b".to_string());
 
-    match fetch_images_from_group(&group_id, params.limit).await {
-        Ok(files) => Ok(Json(GroupImagesResponse {
-            success: true,
-            group_id,
-            images: files,
-            message: None,
-        })),
+    let options = ListOptions {
+        limit: Some(limit),
+        order: params.order,
+        ..ListOptions::default()
+    };
+
+    match state
+        .list_files(FilesQuery::new().group(&group_id), options)
+        .await
+    {
+        Ok(mut files) => {
+            add_references(&state, &group_id, &mut files, params.order).await;
+            files.truncate(limit);
+            let thumbnail_width = state.config.upload.preview_thumbnail_width;
+            let preload = match view {
+                ListingView::Detail => gateway::preload_hints(
+                    &state.pinata,
+                    &files,
+                    state.config.listing.preload_count,
+                    thumbnail_width,
+                ),
+                ListingView::Grid => Vec::new(),
+            };
+            Ok(Json(GroupImagesResponse {
+                success: true,
+                group_id,
+                images: gateway::listed_files(&state.pinata, files, view, thumbnail_width),
+                preload,
+                message: None,
+            }))
+        }
         Err(e) => {
-            eprintln!("Error fetching carousel images: {e}");
+            warn!("Error fetching carousel images: {e}");
             Err(e)
         }
     }
 }
 
-pub async fn fetch_images_from_group(
+// show the files the group references alongside the ones it holds
+async fn add_references(
+    state: &AppState,
     group_id: &str,
-    limit: Option<usize>,
-) -> Result<Vec<PinataFile>, ApiError> {
-    dotenv().ok();
-    let api_key = env::var("PINATA_JWT").map_err(|e| {
-        eprintln!("Failed to get PINATA_JWT: {e}");
-        ApiError::Env(e)
-    })?;
-
-    let client = Client::new();
-    let mut all_files = Vec::new();
-    let mut page_token: Option<String> = None;
-
-    loop {
-        let mut url = format!(
-            "https://api.pinata.cloud/v3/files/public?group={}",
-            group_id
-        );
-
-        // add the page_token as query param if avail
-        if let Some(token) = &page_token {
-            url = format!("{url}?pageToken={token}");
-        }
-
-        println!("Requesting URL: {}", url);
-
-        // request
-        let response = client
-            .get(&url)
-            .header("Authorization", format!("Bearer {api_key}"))
-            .send()
-            .await?;
-
-        println!("{response:?}");
-
-        let status = response.status();
-        if !status.is_success() {
-            let error_body = response.text().await?;
-            println!("API request failed with status: {status}");
-            println!("Response body: {error_body}");
-            return Err(format!(
-                "API request failed with status: {}. Body: {}",
-                status, error_body
-            )
-            .into());
-        }
-
-        let data: PinataFilesResponse = response.json().await?;
-        println!("Found {} files in group", data.data.files.len());
-
-        // add files to our collection
-        all_files.extend(data.data.files);
-
-        if let Some(limit_val) = limit {
-            if all_files.len() >= limit_val {
-                all_files.truncate(limit_val);
-                break;
-            }
-        }
-
-        // check for mmore pages
-        match data.data.next_page_token {
-            Some(token) => page_token = Some(token),
-            None => break,
+    files: &mut Vec<PinataFile>,
+    order: Option<SortOrder>,
+) {
+    let mut references = virtual_albums::references(group_id);
+    // e.g. the canonical copy has since been moved into the group
+    references.retain(|id| !files.iter().any(|f| f.id == *id));
+    if references.is_empty() {
+        return;
+    }
+    for file_id in references {
+        match files::get_file(&state.pinata, &file_id).await {
+            Ok(file) => files.push(file),
+            Err(e) => warn!("Skipping file {file_id} referenced by group {group_id}: {e}"),
         }
     }
-
-    println!("Total files collected: {}", all_files.len());
-    Ok(all_files)
+    match order {
+        Some(SortOrder::Asc) => files.sort_by_key(|f| f.created_at),
+        Some(SortOrder::Desc) => files.sort_by_key(|f| std::cmp::Reverse(f.created_at)),
+        None => {}
+    }
 }
@@ -0,0 +1,381 @@
+use std::collections::HashSet;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+use axum::{
+    Json, Router,
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::{delete, get, patch, post},
+};
+use tracing::{info, warn};
+
+use crate::analysis;
+use crate::errors::{ApiError, ErrorCode, error_response};
+use crate::jsonld;
+use crate::models::files::{
+    BulkDeleteRequest, BulkDeleteResponse, DeleteFailure, DeleteFilter, DeleteResponse,
+    FileAnalysisResponse, FileResponse, FileSummary, PurgeCacheResponse,
+};
+use crate::models::uploads::PhotoMetadataPatch;
+use crate::pinata::{
+    FilesQuery, ListOptions, PinataClient,
+    files::{delete_file, get_file, update_file},
+    groups, list_files, rate_limit,
+};
+use crate::processing;
+use crate::purge;
+use crate::state::AppState;
+
+pub fn files_router() -> Router<AppState> {
+    Router::new()
+        .route("/files/delete", post(bulk_delete))
+        .route("/files/{id}", delete(delete_single_file))
+        .route("/files/{id}/metadata", patch(update_metadata))
+        .route("/files/{id}/analysis", get(file_analysis))
+        .route("/files/{id}/jsonld", get(file_jsonld))
+        .route("/files/{id}/purge-cache", post(purge_file_cache))
+}
+
+// stable for a given set of ids, so a confirmed run deletes exactly what the dry run showed
+fn confirmation_token(ids: &[String]) -> String {
+    let mut hasher = DefaultHasher::new();
+    ids.hash(&mut hasher);
+    format!("{:016x}", hasher.finish())
+}
+
+async fn files_matching(
+    pinata: &PinataClient,
+    filter: &DeleteFilter,
+) -> Result<Vec<FileSummary>, ApiError> {
+    if filter.group_id.is_none() && filter.category.is_none() {
+        return Err(ApiError::Validation(
+            "A delete filter needs at least a group_id or a category".to_string(),
+        ));
+    }
+
+    let mut query = FilesQuery::new();
+    if let Some(group_id) = &filter.group_id {
+        query = query.group(group_id);
+    }
+    if let Some(category) = &filter.category {
+        query = query.keyvalue_eq("category", category.trim().to_lowercase());
+    }
+
+    let files = list_files(pinata, query, ListOptions::default()).await?;
+    let own = own_groups(pinata, files.iter().map(|f| f.group_id.as_str())).await?;
+    Ok(files
+        .iter()
+        .filter(|f| f.group_id.is_empty() || own.contains(&f.group_id))
+        .filter(|f| {
+            filter
+                .created_after
+                .is_none_or(|after| f.created_at >= after)
+        })
+        .filter(|f| {
+            filter
+                .created_before
+                .is_none_or(|before| f.created_at < before)
+        })
+        .map(FileSummary::from)
+        .collect())
+}
+
+// the groups among `group_ids` this deployment may change, so files in another
+// deployment's groups on the same account are left alone
+async fn own_groups<'a>(
+    pinata: &PinataClient,
+    group_ids: impl IntoIterator<Item = &'a str>,
+) -> Result<HashSet<String>, ApiError> {
+    let wanted: HashSet<&str> = group_ids.into_iter().filter(|id| !id.is_empty()).collect();
+    let mut own = HashSet::new();
+    for group_id in wanted {
+        if groups::get_own_group(pinata, group_id).await?.is_some() {
+            own.insert(group_id.to_string());
+        }
+    }
+    Ok(own)
+}
+
+// Ids named outright must all be this deployment's files: ungrouped, or in one of its
+// groups. Any that aren't are refused rather than quietly skipped.
+async fn check_own_files(pinata: &PinataClient, ids: &[String]) -> Result<(), ApiError> {
+    let mut files = Vec::new();
+    let mut outside = Vec::new();
+    for id in ids {
+        rate_limit::throttle().await;
+        match get_file(pinata, id).await {
+            Ok(file) => files.push(file),
+            Err(ApiError::NotFound(_)) => outside.push(id.as_str()),
+            Err(e) => return Err(e),
+        }
+    }
+    let own = own_groups(pinata, files.iter().map(|f| f.group_id.as_str())).await?;
+    outside.extend(
+        files
+            .iter()
+            .filter(|f| !f.group_id.is_empty() && !own.contains(&f.group_id))
+            .map(|f| f.id.as_str()),
+    );
+
+    if outside.is_empty() {
+        return Ok(());
+    }
+    outside.sort();
+    Err(ApiError::NotFound(format!(
+        "Files not found: {}",
+        outside.join(", ")
+    )))
+}
+
+pub async fn bulk_delete(
+    State(state): State<AppState>,
+    Json(request): Json<BulkDeleteRequest>,
+) -> Result<Json<BulkDeleteResponse>, ApiError> {
+    let max = state.config.listing.max_limit;
+
+    let (mut ids, matched_files) = match (&request.filter, request.ids.is_empty()) {
+        (Some(_), false) => {
+            return Err(ApiError::Validation(
+                "Pass either ids or a filter, not both".to_string(),
+            ));
+        }
+        (Some(filter), true) => {
+            let files = files_matching(&state.pinata, filter).await?;
+            (files.iter().map(|f| f.id.clone()).collect(), files)
+        }
+        (None, false) => (request.ids.clone(), Vec::new()),
+        (None, true) => {
+            return Err(ApiError::Validation(
+                "Nothing to delete, pass ids or a filter".to_string(),
+            ));
+        }
+    };
+
+    ids.sort();
+    ids.dedup();
+    if ids.len() > max {
+        return Err(ApiError::Validation(format!(
+            "{} files matched, at most {max} can be deleted per request",
+            ids.len()
+        )));
+    }
+
+    if request.filter.is_none() {
+        check_own_files(&state.pinata, &ids).await?;
+    }
+
+    let token = confirmation_token(&ids);
+
+    if request.dry_run {
+        return Ok(Json(BulkDeleteResponse {
+            success: true,
+            dry_run: true,
+            message: Some(format!(
+                "{} files would be deleted, repeat with dry_run=false and confirm={token}",
+                ids.len()
+            )),
+            matched_ids: ids,
+            matched_files,
+            deleted: Vec::new(),
+            failed: Vec::new(),
+            confirmation_token: token,
+        }));
+    }
+
+    // the target set must not have changed since the dry run
+    if request.confirm.as_deref() != Some(token.as_str()) {
+        return Err(ApiError::Conflict(
+            "Confirmation token missing or stale, run a dry run first".to_string(),
+        ));
+    }
+
+    let mut deleted = Vec::new();
+    let mut failed = Vec::new();
+
+    for id in &ids {
+        rate_limit::throttle().await;
+        match delete_file(&state.pinata, id).await {
+            Ok(()) => {
+                processing::file_deleted(&state.pinata, id).await;
+                deleted.push(id.clone());
+            }
+            Err(e) => {
+                warn!("Failed to delete file {id}: {e}");
+                failed.push(DeleteFailure {
+                    id: id.clone(),
+                    message: e.to_string(),
+                });
+            }
+        }
+    }
+
+    if !deleted.is_empty() {
+        state.catalog_changed();
+    }
+    info!(
+        "Bulk delete removed {} of {} files",
+        deleted.len(),
+        ids.len()
+    );
+
+    Ok(Json(BulkDeleteResponse {
+        success: failed.is_empty(),
+        dry_run: false,
+        message: (!failed.is_empty())
+            .then(|| format!("{} files could not be deleted", failed.len())),
+        matched_ids: ids,
+        matched_files,
+        deleted,
+        failed,
+        confirmation_token: token,
+    }))
+}
+
+// remove one miss-uploaded photo from Pinata, then from the mirror and cached listings
+pub async fn delete_single_file(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<DeleteResponse>, ApiError> {
+    let id = id.trim().to_string();
+    if id.is_empty() {
+        return Err(ApiError::Validation("File id is required".to_string()));
+    }
+
+    delete_file(&state.pinata, &id).await?;
+    processing::file_deleted(&state.pinata, &id).await;
+    info!("Deleted file {id}");
+
+    let removed_from_catalog = match &state.db {
+        Some(db) => match db.remove_file(&id).await {
+            Ok(removed) => removed,
+            Err(e) => {
+                // Pinata no longer has it, so the next sync drops it anyway
+                warn!("Failed to remove file {id} from the catalog: {e}");
+                db.request_sync();
+                false
+            }
+        },
+        None => false,
+    };
+    state.cache.invalidate_all();
+
+    Ok(Json(DeleteResponse {
+        success: true,
+        id,
+        removed_from_catalog,
+        message: None,
+    }))
+}
+
+// fix typos in a photo's metadata after upload, keeping everything not mentioned
+pub async fn update_metadata(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Json(patch): Json<PhotoMetadataPatch>,
+) -> Result<Json<FileResponse>, ApiError> {
+    let title = patch.title.as_deref().map(str::trim);
+    if title == Some("") {
+        return Err(ApiError::Validation(
+            "Photo title cannot be empty".to_string(),
+        ));
+    }
+
+    let file = get_file(&state.pinata, &id).await?;
+    let mut attributes = file.keyvalues.clone();
+    attributes.apply(&patch);
+    attributes.validate()?;
+
+    let updated = update_file(&state.pinata, &id, title, &attributes.to_keyvalues()).await?;
+    info!("Updated metadata of file {id}");
+
+    if let Some(db) = &state.db
+        && let Err(e) = db.upsert_file(&updated).await
+    {
+        warn!("Failed to update file {id} in the catalog: {e}");
+        db.request_sync();
+    }
+    state.cache.invalidate_all();
+
+    Ok(Json(FileResponse {
+        success: true,
+        file: updated,
+        message: None,
+    }))
+}
+
+// exposure diagnostics for the admin UI, worked out from the original when missing
+pub async fn file_analysis(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<FileAnalysisResponse>, ApiError> {
+    let analysis = match analysis::get(&id) {
+        Some(analysis) => analysis,
+        None => {
+            let file = get_file(&state.pinata, &id).await?;
+            analysis::compute(&state.pinata, &file.id, &file.cid).await?
+        }
+    };
+
+    Ok(Json(FileAnalysisResponse {
+        success: true,
+        file_id: id,
+        analysis,
+        message: None,
+    }))
+}
+
+// The file as a schema.org `Photograph`, for the markup of its page. Files in a
+// private group, or another deployment's, aren't described.
+pub async fn file_jsonld(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Response, ApiError> {
+    let file = get_file(&state.pinata, &id).await?;
+    if !file.group_id.is_empty() {
+        let public = groups::get_own_group(&state.pinata, &file.group_id)
+            .await?
+            .is_some_and(|group| group.is_public != Some(false));
+        if !public {
+            return Err(ApiError::NotFound(format!("File {id} not found")));
+        }
+    }
+    jsonld::response(&jsonld::photograph(
+        &state.config,
+        &state.pinata,
+        &file,
+        true,
+    ))
+}
+
+// drop whatever the gateway cached for a file and its variants
+pub async fn purge_file_cache(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<PurgeCacheResponse>, Response> {
+    if !state.purger.is_enabled() {
+        return Err(error_response(
+            StatusCode::SERVICE_UNAVAILABLE,
+            ErrorCode::FeatureDisabled,
+            "Cache purge disabled",
+            "Set CACHE_PURGE_URL to purge gateway caches".to_string(),
+        ));
+    }
+
+    let file = get_file(&state.pinata, &id)
+        .await
+        .map_err(IntoResponse::into_response)?;
+    let (urls, cids) = purge::purge_file(&state, &file)
+        .await
+        .map_err(IntoResponse::into_response)?
+        .unwrap_or_default();
+
+    Ok(Json(PurgeCacheResponse {
+        success: true,
+        file_id: file.id,
+        urls,
+        cids,
+        message: None,
+    }))
+}
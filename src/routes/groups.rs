@@ -1,26 +1,66 @@
-use axum::{Json, Router, routing::get};
-use dotenv::dotenv;
+use axum::{
+    Json, Router,
+    extract::{Path, Query, State},
+    response::Response,
+    routing::{delete, get, patch, post},
+};
+use tokio::task::JoinSet;
+use tracing::{debug, info, warn};
 
 use crate::errors::ApiError;
-use reqwest::Client;
-use std::env; // handle env var
+use crate::extractors::{Limit, Scoped};
+use crate::pinata::{FilesQuery, ListOptions, SortOrder, files, groups, rate_limit};
+use crate::state::AppState;
+use crate::{group_counts, group_covers, group_notifications, jsonld, processing, virtual_albums};
 
 use crate::models::{
-    favourites::{ApiResponse, PinataFilesResponse},
-    groups::{GroupWithThumbnail, GroupsWithThumbnailResponse, PinataGroupResponse},
+    favourites::ApiResponse,
+    files::DeleteFailure,
+    groups::{
+        DeleteGroupParams, DeleteGroupResponse, DuplicateGroupParams, DuplicateGroupRequest,
+        DuplicateGroupResponse, GroupFiles, GroupFilesRequest, GroupFilesResponse, GroupListParams,
+        GroupNotificationsResponse, GroupResponse, GroupWithThumbnail, GroupsWithThumbnailResponse,
+        MembershipFailure, UpdateGroupNotifications, UpdateGroupRequest, Visibility,
+    },
     pinata::{PinataFile, PinataGroup},
 };
 
-pub fn groups_router() -> Router {
+pub fn groups_router() -> Router<AppState> {
     Router::new()
         .route("/groups", get(get_pinata_groups))
         .route("/groups-with-thumbnails", get(get_groups_with_thumbnails))
+        .route("/groups/{id}", patch(update_group).delete(delete_group))
+        .route("/groups/{id}/duplicate", post(duplicate_group))
+        .route("/groups/{id}/jsonld", get(group_jsonld))
+        .route(
+            "/groups/{id}/notifications",
+            patch(update_group_notifications),
+        )
+        .route(
+            "/groups/{id}/files",
+            post(add_files_to_group).delete(remove_files_from_group),
+        )
+        .route(
+            "/groups/{id}/files/{file_id}",
+            delete(remove_file_from_group),
+        )
 }
 
-pub async fn get_pinata_groups() -> Result<Json<ApiResponse>, ApiError> {
-    match fetch_groups_from_pinata().await {
+pub async fn get_pinata_groups(
+    Scoped(state): Scoped,
+    Limit(limit): Limit,
+    Query(params): Query<GroupListParams>,
+) -> Result<Json<ApiResponse>, ApiError> {
+    let listed = match params.visibility {
+        Visibility::All if !params.non_empty => state.list_groups(Some(limit)).await,
+        _ => match state.list_groups(None).await {
+            Ok(groups) => filter_groups(&state, groups, &params, limit).await,
+            Err(e) => Err(e),
+        },
+    };
+    match listed {
         Ok(groups) => {
-            println!("Fetched {} groups", groups.len());
+            debug!("Fetched {} groups", groups.len());
 
             // Return successful response
             Ok(Json(ApiResponse {
@@ -31,7 +71,7 @@ pub async fn get_pinata_groups() -> Result<Json<ApiResponse>, ApiError> {
         }
         Err(e) => {
             // Log the error
-            eprintln!("Error fetching groups: {e}");
+            warn!("Error fetching groups: {e}");
 
             // Return error response
             Err(e)
@@ -39,176 +79,434 @@ pub async fn get_pinata_groups() -> Result<Json<ApiResponse>, ApiError> {
     }
 }
 
-pub async fn fetch_groups_from_pinata() -> Result<Vec<PinataGroup>, ApiError> {
-    dotenv().ok();
-    let api_key = env::var("PINATA_JWT").map_err(|e| {
-        eprintln!("Failed to get PINATA_JWT: {e}");
-        ApiError::Env(e)
-    })?;
+// The groups of the wanted visibility and, when asked, with a photo in them, up to
+// `limit`. Groups are checked for photos all at once, a single file each.
+async fn filter_groups(
+    state: &AppState,
+    groups: Vec<PinataGroup>,
+    params: &GroupListParams,
+    limit: usize,
+) -> Result<Vec<PinataGroup>, ApiError> {
+    let groups: Vec<PinataGroup> = groups
+        .into_iter()
+        .filter(|group| {
+            let public = group.is_public.unwrap_or(true);
+            match params.visibility {
+                Visibility::All => true,
+                Visibility::Public => public,
+                Visibility::Private => !public,
+            }
+        })
+        .collect();
+    if !params.non_empty {
+        return Ok(groups.into_iter().take(limit).collect());
+    }
 
-    let client = Client::new();
-    let mut all_groups = Vec::new();
-    let mut page_token: Option<String> = None;
+    let mut tasks = JoinSet::new();
+    for (index, group) in groups.iter().enumerate() {
+        let state = state.clone();
+        let group_id = group.id.clone();
+        tasks.spawn(async move {
+            let files = state
+                .list_files(
+                    FilesQuery::new().group(&group_id),
+                    ListOptions::limit(Some(1)),
+                )
+                .await;
+            (index, files.map(|files| !files.is_empty()))
+        });
+    }
+    let mut non_empty = vec![false; groups.len()];
+    while let Some(joined) = tasks.join_next().await {
+        let (index, has_files) =
+            joined.map_err(|e| ApiError::Internal(format!("Group lookup failed: {e}")))?;
+        non_empty[index] = has_files?;
+    }
 
-    loop {
-        let mut url: String = "https://api.pinata.cloud/v3/groups/public".to_owned();
+    Ok(groups
+        .into_iter()
+        .zip(non_empty)
+        .filter_map(|(group, non_empty)| non_empty.then_some(group))
+        .take(limit)
+        .collect())
+}
 
-        // add the page_token as query param if avail
-        if let Some(token) = &page_token {
-            url = format!("{url}?pageToken={token}");
-        }
+// shape a group, its cover and how many photos it has into a collection card
+pub fn group_with_thumbnail(
+    group: PinataGroup,
+    files: Vec<PinataFile>,
+    photo_count: usize,
+) -> GroupWithThumbnail {
+    let thumbnail = files
+        .into_iter()
+        .next()
+        .map(PinataFile::into_small_thumbnail);
+
+    GroupWithThumbnail {
+        id: group.id,
+        name: group.name,
+        is_public: group.is_public,
+        created_at: group.created_at,
+        thumbnail_image: thumbnail,
+        photo_count,
+        account: group.account,
+    }
+}
 
-        // print url
-        println!("Requesting URL: {url}");
-
-        // make request
-        let response = client
-            .get(&url)
-            .header("Authorization", format!("Bearer {api_key}"))
-            .send()
-            .await?;
-
-        println!("{response:?}");
-
-        // check if successful
-        let status = response.status();
-        if !status.is_success() {
-            let error_body = response.text().await?;
-            println!("API request failed with status: {status}");
-            println!("Response body: {error_body}");
-            return Err(format!(
-                "API request failed with status: {}. Body: {}",
-                status, error_body
-            )
-            .into());
+// the cover picked for a group, while it's still one of the group's files
+async fn cover(state: &AppState, group_id: &str) -> Option<PinataFile> {
+    let file_id = group_covers::get(group_id)?;
+    files::get_file(&state.pinata, &file_id)
+        .await
+        .ok()
+        .filter(|file| file.group_id == group_id)
+}
+
+#[axum::debug_handler(state = AppState)]
+async fn get_groups_with_thumbnails(
+    Scoped(state): Scoped,
+    Limit(limit): Limit,
+) -> Result<Json<GroupsWithThumbnailResponse>, ApiError> {
+    match state.list_groups(Some(limit)).await {
+        Ok(groups) => {
+            let counts = group_counts::counts(&state).await;
+            let mut collections = Vec::new();
+
+            for group in groups {
+                let files = match cover(&state, &group.id).await {
+                    Some(cover) => vec![cover],
+                    None => state
+                        .list_files(
+                            FilesQuery::new().group(&group.id),
+                            ListOptions::limit(Some(1)),
+                        )
+                        .await
+                        .unwrap_or_default(),
+                };
+
+                // without counts, at least don't call a group with a cover empty
+                let photo_count = match &counts {
+                    Some(counts) => counts.get(&group.id).copied().unwrap_or_default(),
+                    None => files.len(),
+                };
+                collections.push(group_with_thumbnail(group, files, photo_count));
+            }
+
+            Ok(Json(GroupsWithThumbnailResponse {
+                success: true,
+                collections,
+                message: None,
+            }))
         }
+        Err(e) => {
+            warn!("Error fetching groups with thumbnails: {e}");
+            Err(e)
+        }
+    }
+}
 
-        // Parse the response
-        let data: PinataGroupResponse = response.json().await?;
-        println!("Raw API response: {data:?}");
+// the group a write is for, which has to be one of this deployment's own
+async fn own_group(state: &AppState, group_id: &str) -> Result<PinataGroup, ApiError> {
+    groups::get_own_group(&state.pinata, group_id)
+        .await?
+        .ok_or_else(|| ApiError::NotFound(format!("Group {group_id} not found")))
+}
 
-        // add groups to our collection
-        all_groups.extend(data.data.groups);
+// A public group as a schema.org `ImageGallery` of its newest photos, up to the
+// listing limit, for the markup of its page
+async fn group_jsonld(
+    State(state): State<AppState>,
+    Path(group_id): Path<String>,
+) -> Result<Response, ApiError> {
+    let group = own_group(&state, &group_id).await?;
+    if group.is_public == Some(false) {
+        return Err(ApiError::NotFound(format!("Group {group_id} not found")));
+    }
+    let options = ListOptions {
+        limit: Some(state.config.listing.max_limit),
+        order: Some(SortOrder::Desc),
+        ..ListOptions::default()
+    };
+    let files = state
+        .list_files(FilesQuery::new().group(&group.id), options)
+        .await?;
+    let cover = group_covers::get(&group.id);
+    jsonld::response(&jsonld::image_gallery(
+        &state.config,
+        &state.pinata,
+        &group,
+        &files,
+        cover.as_deref(),
+    ))
+}
 
-        // check if more to fetch
-        match data.data.next_page_token {
-            Some(token) => page_token = Some(token),
-            None => break,
+// Copy a group's settings into a new group, optionally moving (not copying) its files
+// across. Once the new group exists a file that fails to move is reported, not fatal.
+async fn duplicate_group(
+    State(state): State<AppState>,
+    Path(group_id): Path<String>,
+    Query(params): Query<DuplicateGroupParams>,
+    body: Option<Json<DuplicateGroupRequest>>,
+) -> Result<Json<DuplicateGroupResponse>, ApiError> {
+    let overrides = body.map(|Json(b)| b).unwrap_or_default();
+    let pinata = &state.pinata;
+
+    let source = own_group(&state, &group_id).await?;
+    let name = overrides
+        .name
+        .unwrap_or_else(|| format!("{} (copy)", source.name));
+    let is_public = overrides.is_public.or(source.is_public).unwrap_or(true);
+
+    // listed before the new group exists, so failing here leaves nothing behind
+    let file_ids = if params.include_files {
+        groups::group_file_ids(pinata, &source.id).await?
+    } else {
+        Vec::new()
+    };
+
+    let group = groups::create_group(pinata, &name, is_public).await?;
+    info!("Duplicated group {} into {}", source.id, group.id);
+
+    let mut files_moved = 0;
+    let mut failed = Vec::new();
+    for file_id in file_ids {
+        rate_limit::throttle().await;
+        match groups::add_file_to_group(pinata, &group.id, &file_id).await {
+            Ok(()) => files_moved += 1,
+            Err(e) => {
+                warn!("Failed to move {file_id} into group {}: {e}", group.id);
+                failed.push(MembershipFailure {
+                    id: file_id,
+                    message: e.to_string(),
+                });
+            }
         }
     }
 
-    Ok(all_groups)
+    // the new group exists whatever happened to the files
+    state.catalog_changed();
+
+    Ok(Json(DuplicateGroupResponse {
+        success: failed.is_empty(),
+        source_group_id: source.id,
+        group,
+        files_moved,
+        message: (!failed.is_empty()).then(|| format!("{} files could not be moved", failed.len())),
+        failed,
+    }))
 }
 
-async fn fetch_images_from_group(
-    group_id: &str,
-    limit: Option<usize>,
-) -> Result<Vec<PinataFile>, ApiError> {
-    dotenv().ok();
-    let api_key = env::var("PINATA_JWT").map_err(|e| {
-        eprintln!("Failed to get PINATA_JWT: {e}");
-        ApiError::Env(e)
-    })?;
+// rename a group or change its visibility
+async fn update_group(
+    State(state): State<AppState>,
+    Path(group_id): Path<String>,
+    Json(request): Json<UpdateGroupRequest>,
+) -> Result<Json<GroupResponse>, ApiError> {
+    let name = request.name.as_deref().map(str::trim);
+    if name == Some("") {
+        return Err(ApiError::Validation(
+            "Group name cannot be empty".to_string(),
+        ));
+    }
+    if name.is_none() && request.is_public.is_none() {
+        return Err(ApiError::Validation(
+            "Nothing to update, pass a name or is_public".to_string(),
+        ));
+    }
 
-    let client = Client::new();
-    let mut all_files = Vec::new();
-    let mut page_token: Option<String> = None;
+    own_group(&state, &group_id).await?;
+    let group = groups::update_group(&state.pinata, &group_id, name, request.is_public).await?;
+    info!("Updated group {}", group.id);
 
-    loop {
-        let mut url = format!(
-            "https://api.pinata.cloud/v3/files/public?group={}",
-            group_id
-        );
+    state.catalog_changed();
 
-        // add the page_token as query param if avail
-        if let Some(token) = &page_token {
-            url = format!("{url}?pageToken={token}");
-        }
+    Ok(Json(GroupResponse {
+        success: true,
+        group,
+        message: None,
+    }))
+}
 
-        println!("Requesting URL: {}", url);
-
-        // request
-        let response = client
-            .get(&url)
-            .header("Authorization", format!("Bearer {api_key}"))
-            .send()
-            .await?;
-
-        println!("{response:?}");
-
-        let status = response.status();
-        if !status.is_success() {
-            let error_body = response.text().await?;
-            println!("API request failed with status: {status}");
-            println!("Response body: {error_body}");
-            return Err(format!(
-                "API request failed with status: {}. Body: {}",
-                status, error_body
-            )
-            .into());
-        }
+// which webhook events the group's files and share links send
+async fn update_group_notifications(
+    State(state): State<AppState>,
+    Path(group_id): Path<String>,
+    Json(request): Json<UpdateGroupNotifications>,
+) -> Result<Json<GroupNotificationsResponse>, ApiError> {
+    if request.on_upload.is_none()
+        && request.on_share_access.is_none()
+        && request.on_share_expiry.is_none()
+    {
+        return Err(ApiError::Validation(
+            "Nothing to update, pass on_upload, on_share_access or on_share_expiry".to_string(),
+        ));
+    }
 
-        let data: PinataFilesResponse = response.json().await?;
-        println!("Found {} files in group", data.data.files.len());
+    own_group(&state, &group_id).await?;
+    let notifications = group_notifications::update(&group_id, &request)?;
+    info!("Updated the notification preferences of group {group_id}");
 
-        // add files to our collection
-        all_files.extend(data.data.files);
+    Ok(Json(GroupNotificationsResponse {
+        success: true,
+        group_id,
+        notifications,
+        message: None,
+    }))
+}
 
-        if let Some(limit_val) = limit {
-            if all_files.len() >= limit_val {
-                all_files.truncate(limit_val);
-                break;
+// delete a group, leaving its files ungrouped or (with `?files=delete`) deleting them first
+async fn delete_group(
+    State(state): State<AppState>,
+    Path(group_id): Path<String>,
+    Query(params): Query<DeleteGroupParams>,
+) -> Result<Json<DeleteGroupResponse>, ApiError> {
+    let pinata = &state.pinata;
+    own_group(&state, &group_id).await?;
+    let mut files_deleted = Vec::new();
+    let mut failed = Vec::new();
+
+    if params.files == GroupFiles::Delete {
+        for file_id in groups::group_file_ids(pinata, &group_id).await? {
+            rate_limit::throttle().await;
+            match files::delete_file(pinata, &file_id).await {
+                Ok(()) => {
+                    processing::file_deleted(pinata, &file_id).await;
+                    files_deleted.push(file_id);
+                }
+                Err(e) => {
+                    warn!("Failed to delete file {file_id}: {e}");
+                    failed.push(DeleteFailure {
+                        id: file_id,
+                        message: e.to_string(),
+                    });
+                }
             }
         }
+    }
 
-        // check for mmore pages
-        match data.data.next_page_token {
-            Some(token) => page_token = Some(token),
-            None => break,
-        }
+    // keep the group while it still holds files that were meant to go with it
+    let group_deleted = failed.is_empty();
+    if group_deleted {
+        groups::delete_group(pinata, &group_id).await?;
+        group_covers::remove(&group_id);
+        group_notifications::remove(&group_id);
+        virtual_albums::remove_group(&group_id);
+        info!(
+            "Deleted group {group_id} and {} of its files",
+            files_deleted.len()
+        );
     }
 
-    println!("Total files collected: {}", all_files.len());
-    Ok(all_files)
+    if group_deleted || !files_deleted.is_empty() {
+        state.catalog_changed();
+    }
+
+    Ok(Json(DeleteGroupResponse {
+        success: group_deleted,
+        message: (!group_deleted).then(|| {
+            format!(
+                "{} files could not be deleted, the group was kept",
+                failed.len()
+            )
+        }),
+        group_id,
+        group_deleted,
+        files_deleted,
+        failed,
+    }))
 }
 
-#[axum::debug_handler]
-async fn get_groups_with_thumbnails() -> Result<Json<GroupsWithThumbnailResponse>, ApiError> {
-    match fetch_groups_from_pinata().await {
-        Ok(groups) => {
-            let mut collections = Vec::new();
+#[derive(Debug, Clone, Copy)]
+enum Membership {
+    Add,
+    Remove,
+}
 
-            for group in groups {
-                let result = fetch_images_from_group(&group.id, Some(1)).await;
-
-                let (thumbnail, count) = match result {
-                    Ok(files) => {
-                        let count = files.len();
-                        let thumbnail = files.into_iter().next();
-                        (thumbnail, count)
-                    }
-                    Err(_) => (None, 0),
-                };
+// apply a membership change to each file, carrying on past individual failures
+async fn change_membership(
+    state: &AppState,
+    group_id: String,
+    mut file_ids: Vec<String>,
+    change: Membership,
+) -> Result<Json<GroupFilesResponse>, ApiError> {
+    file_ids.retain(|id| !id.trim().is_empty());
+    file_ids.sort();
+    file_ids.dedup();
+    if file_ids.is_empty() {
+        return Err(ApiError::Validation("No file ids given".to_string()));
+    }
+    let max = state.config.listing.max_limit;
+    if file_ids.len() > max {
+        return Err(ApiError::Validation(format!(
+            "At most {max} files can be moved per request, got {}",
+            file_ids.len()
+        )));
+    }
 
-                collections.push(GroupWithThumbnail {
-                    id: group.id,
-                    name: group.name,
-                    is_public: group.is_public,
-                    created_at: group.created_at,
-                    thumbnail_image: thumbnail,
-                    photo_count: count,
+    own_group(state, &group_id).await?;
+
+    let mut changed = Vec::new();
+    let mut failed = Vec::new();
+    for file_id in file_ids {
+        rate_limit::throttle().await;
+        let result = match change {
+            Membership::Add => groups::add_file_to_group(&state.pinata, &group_id, &file_id).await,
+            Membership::Remove => {
+                groups::remove_file_from_group(&state.pinata, &group_id, &file_id).await
+            }
+        };
+        match result {
+            Ok(()) => changed.push(file_id),
+            Err(e) => {
+                warn!("Failed to update group {group_id} membership of {file_id}: {e}");
+                failed.push(MembershipFailure {
+                    id: file_id,
+                    message: e.to_string(),
                 });
             }
-
-            Ok(Json(GroupsWithThumbnailResponse {
-                success: true,
-                collections,
-                message: None,
-            }))
-        }
-        Err(e) => {
-            eprintln!("Error fetching groups with thumbnails: {e}");
-            Err(e)
         }
     }
+
+    if !changed.is_empty() {
+        state.catalog_changed();
+    }
+    info!(
+        "{change:?} {} files for group {group_id}, {} failed",
+        changed.len(),
+        failed.len()
+    );
+
+    Ok(Json(GroupFilesResponse {
+        success: failed.is_empty(),
+        message: (!failed.is_empty())
+            .then(|| format!("{} files could not be updated", failed.len())),
+        group_id,
+        changed,
+        failed,
+    }))
+}
+
+// move files into a group; a Pinata file has one group, so this takes it out of its old one
+async fn add_files_to_group(
+    State(state): State<AppState>,
+    Path(group_id): Path<String>,
+    Json(request): Json<GroupFilesRequest>,
+) -> Result<Json<GroupFilesResponse>, ApiError> {
+    change_membership(&state, group_id, request.file_ids, Membership::Add).await
+}
+
+// take files out of a group, leaving them ungrouped
+async fn remove_files_from_group(
+    State(state): State<AppState>,
+    Path(group_id): Path<String>,
+    Json(request): Json<GroupFilesRequest>,
+) -> Result<Json<GroupFilesResponse>, ApiError> {
+    change_membership(&state, group_id, request.file_ids, Membership::Remove).await
+}
+
+async fn remove_file_from_group(
+    State(state): State<AppState>,
+    Path((group_id, file_id)): Path<(String, String)>,
+) -> Result<Json<GroupFilesResponse>, ApiError> {
+    change_membership(&state, group_id, vec![file_id], Membership::Remove).await
 }
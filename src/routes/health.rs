@@ -0,0 +1,293 @@
+use std::collections::HashSet;
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+use axum::{
+    Json, Router,
+    extract::State,
+    http::{HeaderValue, StatusCode, header::CACHE_CONTROL},
+    response::{IntoResponse, Response},
+    routing::get,
+};
+use chrono::{DateTime, Utc};
+use tokio::sync::Mutex;
+use tracing::warn;
+
+use crate::errors::ApiError;
+use crate::models::dates::format_rfc3339;
+use crate::models::health::{ComponentHealth, HealthReport, HealthStatus, PublicStatus};
+use crate::pinata::{FilesQuery, ListOptions, gateway};
+use crate::routes::webhooks;
+use crate::state::AppState;
+
+// a dependency slower than this is reported as failed rather than holding up the probe
+const CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+// /status is for anyone's page footer, so it's worked out at most this often
+const STATUS_TTL: Duration = Duration::from_secs(300);
+// and sooner while Pinata is failing, so recovery shows
+const FAILING_STATUS_TTL: Duration = Duration::from_secs(30);
+
+// the last /status and until when it's served; held while it's worked out again, so
+// the requests arriving meanwhile wait for that rather than each listing the catalog
+static STATUS: Mutex<Option<(Instant, PublicStatus)>> = Mutex::const_new(None);
+
+pub fn health_router() -> Router<AppState> {
+    Router::new()
+        .route("/readyz", get(readiness))
+        .route("/status", get(public_status))
+}
+
+// time a check, turning errors and timeouts into a failed component
+async fn timed<F>(name: &'static str, critical: bool, check: F) -> ComponentHealth
+where
+    F: Future<Output = Result<Option<String>, ApiError>>,
+{
+    let started = Instant::now();
+    let (status, message) = match tokio::time::timeout(CHECK_TIMEOUT, check).await {
+        Ok(Ok(message)) => (HealthStatus::Ok, message),
+        Ok(Err(e)) => (HealthStatus::Failed, Some(e.to_string())),
+        Err(_) => (
+            HealthStatus::Failed,
+            Some(format!("No answer within {}s", CHECK_TIMEOUT.as_secs())),
+        ),
+    };
+
+    ComponentHealth {
+        name,
+        status,
+        critical,
+        latency_ms: Some(started.elapsed().as_millis() as u64),
+        message,
+    }
+}
+
+fn disabled(name: &'static str, message: &str) -> ComponentHealth {
+    ComponentHealth {
+        name,
+        status: HealthStatus::Disabled,
+        critical: false,
+        latency_ms: None,
+        message: Some(message.to_string()),
+    }
+}
+
+async fn check_database(state: &AppState) -> ComponentHealth {
+    let Some(db) = &state.db else {
+        return disabled("database", "DATABASE_URL is not set, listings go to Pinata");
+    };
+
+    // the mirror is critical once configured: reads are served from it after the first sync
+    timed("database", true, async {
+        db.ping().await?;
+        Ok(Some(match db.last_synced_at().await? {
+            Some(at) if db.is_synced() => format!("Last synced at {}", format_rfc3339(&at)),
+            _ => "Initial sync pending, listings go to Pinata".to_string(),
+        }))
+    })
+    .await
+}
+
+async fn check_gateway(state: &AppState) -> ComponentHealth {
+    let started = Instant::now();
+    match tokio::time::timeout(CHECK_TIMEOUT, gateway::probe(&state.pinata)).await {
+        Ok(None) => disabled("gateway", "PINATA_GATEWAY is not set, no preview urls"),
+        result => {
+            // only previews depend on the gateway
+            let (status, message) = match result {
+                Ok(Some(Ok(()))) => (HealthStatus::Ok, None),
+                Ok(Some(Err(e))) => (HealthStatus::Failed, Some(e.to_string())),
+                _ => (
+                    HealthStatus::Failed,
+                    Some(format!("No answer within {}s", CHECK_TIMEOUT.as_secs())),
+                ),
+            };
+            ComponentHealth {
+                name: "gateway",
+                status,
+                critical: false,
+                latency_ms: Some(started.elapsed().as_millis() as u64),
+                message,
+            }
+        }
+    }
+}
+
+fn check_cache(state: &AppState) -> ComponentHealth {
+    if !state.cache.is_enabled() {
+        return disabled("cache", "CACHE_TTL_SECS is 0");
+    }
+    ComponentHealth {
+        name: "cache",
+        status: HealthStatus::Ok,
+        critical: false,
+        latency_ms: None,
+        message: Some(format!("{} cached responses", state.cache.entry_count())),
+    }
+}
+
+fn check_upload_queue(state: &AppState) -> ComponentHealth {
+    let queue = &state.upload_queue;
+    let (admitted, capacity) = (queue.admitted(), queue.capacity());
+
+    // a full queue turns uploads away but everything else keeps working
+    ComponentHealth {
+        name: "upload_queue",
+        status: if admitted >= capacity {
+            HealthStatus::Degraded
+        } else {
+            HealthStatus::Ok
+        },
+        critical: false,
+        latency_ms: None,
+        message: Some(format!(
+            "{} active, {} waiting, capacity {capacity}",
+            queue.active(),
+            admitted.saturating_sub(queue.active())
+        )),
+    }
+}
+
+fn check_webhooks(state: &AppState) -> ComponentHealth {
+    if state.config.webhooks.secret.is_none() {
+        return disabled("webhooks", "WEBHOOK_SECRET is not set");
+    }
+    ComponentHealth {
+        name: "webhooks",
+        status: HealthStatus::Ok,
+        critical: false,
+        latency_ms: None,
+        message: Some(match webhooks::last_delivery_at() {
+            Some(at) => format!("Last delivery at {}", format_rfc3339(&at)),
+            None => "No deliveries since startup".to_string(),
+        }),
+    }
+}
+
+// Component-wise readiness: 503 only when a critical dependency (Pinata, or the
+// local mirror when configured) is down; anything else just degrades the report
+async fn readiness(State(state): State<AppState>) -> Response {
+    let (pinata, database, gateway) = tokio::join!(
+        timed("pinata_api", true, async {
+            state.pinata.ping().await.map(|_| None)
+        }),
+        check_database(&state),
+        check_gateway(&state),
+    );
+    let components = vec![
+        pinata,
+        database,
+        gateway,
+        check_cache(&state),
+        check_upload_queue(&state),
+        check_webhooks(&state),
+    ];
+
+    let failing = |critical: bool| {
+        components.iter().any(|c| {
+            c.critical == critical
+                && matches!(c.status, HealthStatus::Failed | HealthStatus::Degraded)
+        })
+    };
+    let (status, code) = if failing(true) {
+        (HealthStatus::Failed, StatusCode::SERVICE_UNAVAILABLE)
+    } else if failing(false) {
+        (HealthStatus::Degraded, StatusCode::OK)
+    } else {
+        (HealthStatus::Ok, StatusCode::OK)
+    };
+
+    let report = HealthReport {
+        success: code.is_success(),
+        status,
+        checked_at: Utc::now(),
+        components,
+    };
+    (code, Json(report)).into_response()
+}
+
+// how many public groups and files there are, and when the catalog last changed
+async fn catalog_summary(
+    state: &AppState,
+) -> Result<(usize, usize, Option<DateTime<Utc>>), ApiError> {
+    let groups: Vec<_> = state
+        .list_groups(None)
+        .await?
+        .into_iter()
+        .filter(|group| group.is_public != Some(false))
+        .collect();
+    let group_ids: HashSet<&str> = groups.iter().map(|group| group.id.as_str()).collect();
+    let files: Vec<_> = state
+        .list_files(FilesQuery::new(), ListOptions::default())
+        .await?
+        .into_iter()
+        .filter(|file| file.group_id.is_empty() || group_ids.contains(file.group_id.as_str()))
+        .collect();
+
+    // the mirror also knows of edits and deletions, Pinata only of what was created
+    let changed_at = match &state.db {
+        Some(db) if db.is_synced() => db.last_change_at().await?,
+        _ => None,
+    };
+    let last_updated_at = groups
+        .iter()
+        .map(|group| group.created_at)
+        .chain(files.iter().map(|file| file.created_at))
+        .chain(changed_at)
+        .max();
+    Ok((groups.len(), files.len(), last_updated_at))
+}
+
+// Coarse health and catalog size, unauthenticated; nothing here is more than a few
+// minutes old, nor costs more than a catalog listing every few minutes
+async fn public_status(State(state): State<AppState>) -> Response {
+    let mut cached = STATUS.lock().await;
+    let (until, status) = match cached.as_ref() {
+        Some((until, status)) if *until > Instant::now() => (*until, status.clone()),
+        previous => {
+            let checked_at = Utc::now();
+            let (status, ttl) = match catalog_summary(&state).await {
+                Ok((groups, files, last_updated_at)) => (
+                    PublicStatus {
+                        success: true,
+                        status: HealthStatus::Ok,
+                        groups: Some(groups),
+                        files: Some(files),
+                        last_updated_at,
+                        checked_at,
+                    },
+                    STATUS_TTL,
+                ),
+                Err(e) => {
+                    warn!("Failed to list the catalog for /status: {e}");
+                    let previous = previous.map(|(_, status)| status);
+                    (
+                        PublicStatus {
+                            success: true,
+                            status: if previous.is_some_and(|p| p.groups.is_some()) {
+                                HealthStatus::Degraded
+                            } else {
+                                HealthStatus::Failed
+                            },
+                            groups: previous.and_then(|p| p.groups),
+                            files: previous.and_then(|p| p.files),
+                            last_updated_at: previous.and_then(|p| p.last_updated_at),
+                            checked_at,
+                        },
+                        FAILING_STATUS_TTL,
+                    )
+                }
+            };
+            let until = Instant::now() + ttl;
+            *cached = Some((until, status.clone()));
+            (until, status)
+        }
+    };
+    drop(cached);
+
+    // browsers and CDNs keep it only as long as it's served from here
+    let max_age = until.saturating_duration_since(Instant::now());
+    let cache_control = HeaderValue::from_str(&format!("public, max-age={}", max_age.as_secs()))
+        .expect("max-age is a valid header");
+    ([(CACHE_CONTROL, cache_control)], Json(status)).into_response()
+}
@@ -0,0 +1,421 @@
+use std::collections::BTreeMap;
+
+use axum::{
+    Json, Router,
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::{get, post},
+};
+use tracing::{info, warn};
+
+use crate::errors::{ApiError, ErrorCode, error_response};
+use crate::models::{
+    PhotoAttributes,
+    attributes::MAX_RATING,
+    maintenance::{
+        ConsistencyFixResponse, ConsistencyIssue, ConsistencyReport, DedupeResponse, DedupedSet,
+        DuplicateCopy, DuplicateSet, DuplicatesReport, FileReport, FixFailure, IntegrityReport,
+        ReencodeResponse,
+    },
+    pinata::PinataFile,
+};
+use crate::pinata::{
+    FilesQuery, ListOptions, PinataClient,
+    files::{delete_file, update_file},
+    list_files, rate_limit,
+};
+use crate::state::AppState;
+use crate::{integrity, processing, reencode, virtual_albums};
+
+pub fn maintenance_router() -> Router<AppState> {
+    Router::new()
+        .route("/maintenance/consistency", get(get_consistency_report))
+        .route("/maintenance/consistency/fix", post(fix_consistency))
+        .route("/maintenance/duplicates", get(get_duplicates_report))
+        .route("/maintenance/duplicates/dedupe", post(dedupe))
+        .route(
+            "/maintenance/reencode",
+            get(reencode_status).post(start_reencode),
+        )
+        .route("/maintenance/integrity", get(integrity_status))
+        .route("/maintenance/integrity/verify", post(verify_integrity))
+        .route("/maintenance/integrity/accept", post(accept_integrity))
+}
+
+// compare a file's keyvalues to the metadata schema
+pub fn check_file(file: &PinataFile, known_categories: &[String]) -> Vec<ConsistencyIssue> {
+    let mut issues = Vec::new();
+    let attributes = &file.keyvalues;
+
+    if file.name.trim().is_empty() {
+        issues.push(ConsistencyIssue::MissingTitle);
+    }
+
+    match &attributes.category {
+        None => issues.push(ConsistencyIssue::MissingCategory),
+        Some(category) => {
+            let normalized = category.trim().to_lowercase();
+            if *category != normalized {
+                issues.push(ConsistencyIssue::CategoryNotNormalized {
+                    found: category.clone(),
+                    normalized: normalized.clone(),
+                });
+            }
+            if !known_categories.is_empty() && !known_categories.contains(&normalized) {
+                issues.push(ConsistencyIssue::UnknownCategory {
+                    category: normalized,
+                });
+            }
+        }
+    }
+
+    // unparseable ratings are kept aside in `extra` by PhotoAttributes
+    if let Some(raw) = attributes.extra.get("rating") {
+        issues.push(ConsistencyIssue::MalformedRating { raw: raw.clone() });
+    }
+    if let Some(rating) = attributes.rating
+        && rating > MAX_RATING
+    {
+        issues.push(ConsistencyIssue::RatingOutOfRange { rating });
+    }
+
+    issues
+}
+
+// apply the fixes that can't lose information; None when nothing is safe to change
+pub fn safe_fix(
+    attributes: &PhotoAttributes,
+    issues: &[ConsistencyIssue],
+) -> Option<(PhotoAttributes, Vec<String>)> {
+    let mut fixed = attributes.clone();
+    let mut applied = Vec::new();
+
+    for issue in issues {
+        match issue {
+            ConsistencyIssue::CategoryNotNormalized { normalized, .. } => {
+                fixed.category = Some(normalized.clone());
+                applied.push(format!("normalize category to '{normalized}'"));
+            }
+            ConsistencyIssue::MalformedRating { raw } => {
+                fixed.extra.remove("rating");
+                applied.push(format!("remove malformed rating '{raw}'"));
+            }
+            ConsistencyIssue::RatingOutOfRange { rating } => {
+                fixed.rating = Some(MAX_RATING);
+                applied.push(format!("clamp rating {rating} to {MAX_RATING}"));
+            }
+            ConsistencyIssue::MissingTitle
+            | ConsistencyIssue::MissingCategory
+            | ConsistencyIssue::UnknownCategory { .. } => {}
+        }
+    }
+
+    (!applied.is_empty()).then_some((fixed, applied))
+}
+
+async fn scan_catalog(
+    pinata: &PinataClient,
+    known: &[String],
+) -> Result<(usize, Vec<(PinataFile, Vec<ConsistencyIssue>)>), ApiError> {
+    let files: Vec<PinataFile> =
+        list_files(pinata, FilesQuery::new(), ListOptions::default()).await?;
+    let scanned = files.len();
+
+    let flagged = files
+        .into_iter()
+        .filter_map(|file| {
+            let issues = check_file(&file, known);
+            (!issues.is_empty()).then_some((file, issues))
+        })
+        .collect();
+
+    Ok((scanned, flagged))
+}
+
+pub async fn get_consistency_report(
+    State(state): State<AppState>,
+) -> Result<Json<ConsistencyReport>, ApiError> {
+    let (files_scanned, flagged) =
+        scan_catalog(&state.pinata, &state.config.catalog.known_categories).await?;
+
+    let files: Vec<FileReport> = flagged
+        .into_iter()
+        .map(|(file, issues)| {
+            let fixes = safe_fix(&file.keyvalues, &issues)
+                .map(|(_, applied)| applied)
+                .unwrap_or_default();
+            FileReport {
+                id: file.id,
+                name: file.name,
+                group_id: file.group_id,
+                issues,
+                fixes,
+            }
+        })
+        .collect();
+
+    let fixable = files.iter().filter(|f| !f.fixes.is_empty()).count();
+    info!(
+        "Consistency check: {} of {files_scanned} files have issues, {fixable} fixable",
+        files.len()
+    );
+
+    Ok(Json(ConsistencyReport {
+        success: true,
+        files_scanned,
+        files_with_issues: files.len(),
+        fixable,
+        files,
+        message: None,
+    }))
+}
+
+pub async fn fix_consistency(
+    State(state): State<AppState>,
+) -> Result<Json<ConsistencyFixResponse>, ApiError> {
+    let (_, flagged) = scan_catalog(&state.pinata, &state.config.catalog.known_categories).await?;
+
+    let mut fixed = Vec::new();
+    let mut failed = Vec::new();
+    let mut skipped = 0;
+
+    for (file, issues) in flagged {
+        let Some((attributes, applied)) = safe_fix(&file.keyvalues, &issues) else {
+            skipped += 1;
+            continue;
+        };
+
+        rate_limit::throttle().await;
+        match update_file(&state.pinata, &file.id, None, &attributes.to_keyvalues()).await {
+            Ok(_) => {
+                info!("Fixed {}: {}", file.id, applied.join(", "));
+                fixed.push(file.id);
+            }
+            Err(e) => {
+                warn!("Failed to fix {}: {e}", file.id);
+                failed.push(FixFailure {
+                    id: file.id,
+                    message: e.to_string(),
+                });
+            }
+        }
+    }
+
+    if !fixed.is_empty() {
+        state.catalog_changed();
+    }
+
+    Ok(Json(ConsistencyFixResponse {
+        success: failed.is_empty(),
+        message: (skipped > 0).then(|| format!("{skipped} files need manual fixes")),
+        fixed,
+        failed,
+        skipped,
+    }))
+}
+
+// Files pinned more than once by CID, the oldest copy first. Identical content has
+// the same CID, so each set beyond its first copy is storage spent twice.
+async fn find_duplicates(pinata: &PinataClient) -> Result<(usize, Vec<Vec<PinataFile>>), ApiError> {
+    let files = list_files(pinata, FilesQuery::new(), ListOptions::default()).await?;
+    let scanned = files.len();
+
+    let mut by_cid: BTreeMap<String, Vec<PinataFile>> = BTreeMap::new();
+    for file in files {
+        by_cid.entry(file.cid.clone()).or_default().push(file);
+    }
+
+    let sets = by_cid
+        .into_values()
+        .filter(|copies| copies.len() > 1)
+        .map(|mut copies| {
+            copies.sort_by(|a, b| a.created_at.cmp(&b.created_at).then(a.id.cmp(&b.id)));
+            copies
+        })
+        .collect();
+    Ok((scanned, sets))
+}
+
+fn wasted_bytes(copies: &[PinataFile]) -> u64 {
+    copies.iter().skip(1).map(|f| f.size).sum()
+}
+
+pub async fn get_duplicates_report(
+    State(state): State<AppState>,
+) -> Result<Json<DuplicatesReport>, ApiError> {
+    let (files_scanned, sets) = find_duplicates(&state.pinata).await?;
+
+    let duplicates: Vec<DuplicateSet> = sets
+        .into_iter()
+        .map(|copies| DuplicateSet {
+            cid: copies[0].cid.clone(),
+            size: copies[0].size,
+            wasted_bytes: wasted_bytes(&copies),
+            group_ids: copies
+                .iter()
+                .filter(|f| !f.group_id.is_empty())
+                .map(|f| f.group_id.clone())
+                .collect(),
+            copies: copies
+                .into_iter()
+                .map(|f| DuplicateCopy {
+                    id: f.id,
+                    name: f.name,
+                    group_id: f.group_id,
+                    created_at: f.created_at,
+                })
+                .collect(),
+        })
+        .collect();
+
+    let wasted_bytes = duplicates.iter().map(|d| d.wasted_bytes).sum();
+    info!(
+        "Duplicate check: {} CIDs pinned more than once in {files_scanned} files, {wasted_bytes} bytes wasted",
+        duplicates.len()
+    );
+
+    Ok(Json(DuplicatesReport {
+        success: true,
+        files_scanned,
+        wasted_bytes,
+        duplicates,
+        message: None,
+    }))
+}
+
+// Keep the oldest copy of each duplicated CID and unpin the rest. Groups that lose
+// their copy reference the canonical one, so they still show the photo.
+pub async fn dedupe(State(state): State<AppState>) -> Result<Json<DedupeResponse>, ApiError> {
+    let (_, sets) = find_duplicates(&state.pinata).await?;
+
+    let mut deduped = Vec::new();
+    let mut failed = Vec::new();
+    let mut reclaimed_bytes = 0;
+
+    for copies in sets {
+        let Some((canonical, extra)) = copies.split_first() else {
+            continue;
+        };
+        let mut set = DedupedSet {
+            cid: canonical.cid.clone(),
+            canonical_id: canonical.id.clone(),
+            removed: Vec::new(),
+            referenced_from: Vec::new(),
+        };
+
+        for copy in extra {
+            rate_limit::throttle().await;
+            if let Err(e) = delete_file(&state.pinata, &copy.id).await {
+                warn!("Failed to unpin duplicate {}: {e}", copy.id);
+                failed.push(FixFailure {
+                    id: copy.id.clone(),
+                    message: e.to_string(),
+                });
+                continue;
+            }
+            processing::file_deleted(&state.pinata, &copy.id).await;
+            reclaimed_bytes += copy.size;
+            set.removed.push(copy.id.clone());
+
+            let group_id = &copy.group_id;
+            if group_id.is_empty()
+                || *group_id == canonical.group_id
+                || set.referenced_from.contains(group_id)
+            {
+                continue;
+            }
+            match virtual_albums::add(group_id, &canonical.id) {
+                Ok(()) => set.referenced_from.push(group_id.clone()),
+                Err(e) => {
+                    warn!(
+                        "Failed to reference {} from group {group_id}: {e}",
+                        canonical.id
+                    );
+                    failed.push(FixFailure {
+                        id: copy.id.clone(),
+                        message: format!("Unpinned, but group {group_id} lost the photo: {e}"),
+                    });
+                }
+            }
+        }
+
+        if !set.removed.is_empty() {
+            deduped.push(set);
+        }
+    }
+
+    if !deduped.is_empty() {
+        state.catalog_changed();
+    }
+    info!(
+        "Deduped {} CIDs, reclaimed {reclaimed_bytes} bytes, {} failures",
+        deduped.len(),
+        failed.len()
+    );
+
+    Ok(Json(DedupeResponse {
+        success: failed.is_empty(),
+        message: (!failed.is_empty())
+            .then(|| format!("{} copies could not be deduped", failed.len())),
+        deduped,
+        failed,
+        reclaimed_bytes,
+    }))
+}
+
+// Generate display variants, blurhashes and dimensions for files pinned before the
+// backend made them. Runs in the background; poll `GET /maintenance/reencode`.
+pub async fn start_reencode(State(state): State<AppState>) -> Result<Response, Response> {
+    let job = reencode::start(&state)
+        .map_err(IntoResponse::into_response)?
+        .ok_or_else(|| {
+            error_response(
+                StatusCode::CONFLICT,
+                ErrorCode::Conflict,
+                "Re-encode already running",
+                "Wait for the current run to finish".to_string(),
+            )
+        })?;
+
+    Ok((StatusCode::ACCEPTED, Json(ReencodeResponse::from(job))).into_response())
+}
+
+pub async fn reencode_status() -> Result<Json<ReencodeResponse>, Response> {
+    let job = reencode::status().ok_or_else(|| {
+        error_response(
+            StatusCode::NOT_FOUND,
+            ErrorCode::NotFound,
+            "No re-encode job",
+            "The re-encode job hasn't been run yet".to_string(),
+        )
+    })?;
+    Ok(Json(ReencodeResponse::from(job)))
+}
+
+// Check the catalog against its integrity manifest now, rather than waiting for the
+// next restart. A divergence is reported and alerted, and kept until accepted.
+pub async fn verify_integrity(
+    State(state): State<AppState>,
+) -> Result<Json<IntegrityReport>, ApiError> {
+    Ok(Json(integrity::verify(&state).await?))
+}
+
+// take the catalog as it is now as the known-good one
+pub async fn accept_integrity(
+    State(state): State<AppState>,
+) -> Result<Json<IntegrityReport>, ApiError> {
+    Ok(Json(integrity::accept(&state).await?))
+}
+
+pub async fn integrity_status() -> Result<Json<IntegrityReport>, Response> {
+    let report = integrity::last_report().ok_or_else(|| {
+        error_response(
+            StatusCode::NOT_FOUND,
+            ErrorCode::NotFound,
+            "No integrity check",
+            "The catalog's integrity hasn't been checked yet".to_string(),
+        )
+    })?;
+    Ok(Json(report))
+}
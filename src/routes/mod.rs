@@ -1,4 +1,90 @@
+pub mod admin;
+pub mod auth;
+pub mod catalog;
 pub mod categories;
+pub mod fallback;
 pub mod favourites;
+pub mod files;
 pub mod groups;
+pub mod health;
+pub mod maintenance;
+pub mod shares;
 pub mod uploads;
+pub mod versions;
+pub mod webhooks;
+
+// advertised in 404/405 responses, keep in sync with the routers
+pub const ENDPOINTS: &[&str] = &[
+    "GET /groups",
+    "GET /groups-with-thumbnails",
+    "PATCH /groups/{id}",
+    "DELETE /groups/{id}",
+    "POST /groups/{id}/duplicate",
+    "GET /groups/{id}/jsonld",
+    "PATCH /groups/{id}/notifications",
+    "POST /groups/{id}/files",
+    "DELETE /groups/{id}/files",
+    "DELETE /groups/{id}/files/{file_id}",
+    "GET /favourites",
+    "GET /group-images",
+    "GET /me/favourites",
+    "POST /me/favourites/{file_id}",
+    "DELETE /me/favourites/{file_id}",
+    "GET /files-category",
+    "GET /categories",
+    "GET /catalog/full",
+    "GET /catalog/changed",
+    "GET /categories/taxonomy",
+    "PUT /categories/aliases/{alias}",
+    "DELETE /categories/aliases/{alias}",
+    "PUT /categories/{name}/cover",
+    "PUT /categories/{name}/parent",
+    "DELETE /categories/{name}/parent",
+    "POST /upload",
+    "GET /upload/jobs/{id}",
+    "GET /upload/jobs/{id}/events",
+    "POST /upload/sessions",
+    "GET /upload/sessions/{id}",
+    "PATCH /upload/sessions/{id}",
+    "DELETE /upload/sessions/{id}",
+    "POST /sessions",
+    "GET /sessions/{id}",
+    "POST /sessions/{id}/photo",
+    "POST /sessions/{id}/close",
+    "POST /files/delete",
+    "DELETE /files/{id}",
+    "PATCH /files/{id}/metadata",
+    "GET /files/{id}/analysis",
+    "GET /files/{id}/jsonld",
+    "POST /files/{id}/purge-cache",
+    "GET /maintenance/consistency",
+    "POST /maintenance/consistency/fix",
+    "GET /maintenance/duplicates",
+    "POST /maintenance/duplicates/dedupe",
+    "GET /maintenance/reencode",
+    "POST /maintenance/reencode",
+    "GET /maintenance/integrity",
+    "POST /maintenance/integrity/verify",
+    "POST /maintenance/integrity/accept",
+    "GET /metrics",
+    "GET /readyz",
+    "GET /status",
+    "GET /api/versions",
+    "POST /webhooks/pinata",
+    "POST /auth/nonce",
+    "POST /auth/verify",
+    "GET /admin/keys",
+    "POST /admin/keys",
+    "DELETE /admin/keys/{id}",
+    "GET /admin/keys/{id}/usage",
+    "PUT /admin/keys/{id}/rate-limit",
+    "GET /admin/shares",
+    "POST /admin/shares",
+    "DELETE /admin/shares/{token}",
+    "GET /admin/shares/{token}/usage",
+    "GET /admin/proxy-limits",
+    "GET /admin/status",
+    "GET /share/{token}",
+    "GET /share/{token}/qr.png",
+    "GET /share/{token}/files/{file_id}",
+];
@@ -0,0 +1,310 @@
+use std::io::Cursor;
+use std::time::Duration;
+
+use axum::{
+    Json, Router,
+    body::Body,
+    extract::{Path, Query, State},
+    http::{
+        HeaderValue, StatusCode,
+        header::{CONTENT_DISPOSITION, CONTENT_TYPE},
+    },
+    middleware,
+    response::{IntoResponse, Response},
+    routing::get,
+};
+use image::{ImageFormat, Luma};
+use qrcode::QrCode;
+use tracing::info;
+
+use crate::errors::{ApiError, ErrorCode, error_response};
+use crate::group_notifications::GroupEvent;
+use crate::middleware::proxy_limits::{ProxyLimits, proxy_limits};
+use crate::models::shares::{QrParams, SharedGalleryResponse};
+use crate::pinata::{FilesQuery, ListOptions, files, gateway, groups};
+use crate::shares::{self, DownloadGrant};
+use crate::state::AppState;
+use crate::thumbnail_store::{Thumbnail, ThumbnailKey};
+
+// how stored copies of shared files are told apart: as the gateway resized them, or
+// watermarked after that
+const RESIZED: &str = "gateway";
+const WATERMARKED: &str = "watermarked.jpeg";
+
+const DEFAULT_QR_SIZE: u32 = 512;
+const MAX_QR_SIZE: u32 = 2048;
+
+// public, token-gated routes; links are managed under /admin/shares. Downloads
+// stream through the backend, so they're capped per client address
+pub fn shares_router(limits: ProxyLimits) -> Router<AppState> {
+    Router::new()
+        .route("/share/{token}/files/{file_id}", get(download_shared_file))
+        .route_layer(middleware::from_fn_with_state(limits, proxy_limits))
+        .route("/share/{token}", get(shared_gallery))
+        .route("/share/{token}/qr.png", get(share_qr_code))
+}
+
+// expired, revoked and unknown tokens all look the same to the client
+fn share_not_found() -> Response {
+    error_response(
+        StatusCode::NOT_FOUND,
+        ErrorCode::NotFound,
+        "Share link not found",
+        "This link doesn't exist or has expired".to_string(),
+    )
+}
+
+async fn shared_gallery(
+    State(state): State<AppState>,
+    Path(token): Path<String>,
+) -> Result<Json<SharedGalleryResponse>, Response> {
+    let share = shares::record_view(&token)
+        .map_err(IntoResponse::into_response)?
+        .ok_or_else(share_not_found)?;
+    state.notifier.send_for_group(
+        Some(&share.group_id),
+        GroupEvent::ShareAccess,
+        serde_json::json!({
+            "token": share.token,
+            "group_id": share.group_id,
+            "label": share.label,
+            "views": share.usage.views,
+        }),
+    );
+
+    let gallery = async {
+        let group = groups::get_group(&state.pinata, &share.group_id).await?;
+        let images = state
+            .list_files(
+                FilesQuery::new().group(&share.group_id),
+                ListOptions::default(),
+            )
+            .await?;
+        Ok::<_, ApiError>((group, images))
+    };
+    let (group, files) = gallery.await.map_err(IntoResponse::into_response)?;
+    let images = files
+        .into_iter()
+        .map(|file| {
+            gateway::shared_image(
+                &state.pinata,
+                file,
+                &share.policy,
+                state.config.upload.preview_thumbnail_width,
+                state.config.shares.preview_width,
+            )
+        })
+        .collect();
+
+    Ok(Json(SharedGalleryResponse {
+        success: true,
+        group,
+        images,
+        expires_at: share.expires_at,
+        remaining_downloads: share.remaining_downloads(),
+        full_resolution: share.policy.full_resolution,
+        message: None,
+    }))
+}
+
+fn download_limit_reached() -> Response {
+    error_response(
+        StatusCode::FORBIDDEN,
+        ErrorCode::Forbidden,
+        "Download limit reached",
+        "This link has no downloads left".to_string(),
+    )
+}
+
+fn file_not_shared() -> Response {
+    error_response(
+        StatusCode::NOT_FOUND,
+        ErrorCode::NotFound,
+        "File not found",
+        "This file isn't part of the shared gallery".to_string(),
+    )
+}
+
+// where a share download comes from: a copy made for an earlier one, or the gateway
+enum Source {
+    Stored(Thumbnail),
+    Gateway(reqwest::Response),
+}
+
+// Proxy one file of the shared gallery, applying the link's policy: resized unless
+// full resolution is allowed, watermarked when asked, and counted against its limit.
+async fn download_shared_file(
+    State(state): State<AppState>,
+    Path((token, file_id)): Path<(String, String)>,
+) -> Result<Response, Response> {
+    let share = shares::resolve(&token).ok_or_else(share_not_found)?;
+    if share.remaining_downloads() == Some(0) {
+        return Err(download_limit_reached());
+    }
+
+    let file = files::get_file(&state.pinata, &file_id)
+        .await
+        .map_err(|_| file_not_shared())?;
+    if file.group_id != share.group_id {
+        return Err(file_not_shared());
+    }
+
+    let config = state.config.shares;
+    let width = (!share.policy.full_resolution).then_some(config.preview_width);
+    // resized and watermarked copies are kept, originals stream straight through
+    let key = (width.is_some() || share.policy.watermark).then(|| {
+        let format = if share.policy.watermark {
+            WATERMARKED
+        } else {
+            RESIZED
+        };
+        ThumbnailKey::new(&file.cid, width, format)
+    });
+    let stored = match &key {
+        Some(key) => state.thumbnails.get(key).await,
+        None => None,
+    };
+    let source = match stored {
+        Some(stored) => Source::Stored(stored),
+        None => Source::Gateway(
+            gateway::fetch(
+                &state.pinata,
+                &file.cid,
+                width,
+                Duration::from_secs(config.fetch_ttl_secs),
+            )
+            .await
+            .map_err(IntoResponse::into_response)?
+            .ok_or_else(|| {
+                ApiError::Api("Set PINATA_GATEWAY to serve share downloads".to_string())
+                    .into_response()
+            })?,
+        ),
+    };
+
+    // only counted once the file is at hand
+    let share =
+        match shares::claim_download(&token, &file.id).map_err(IntoResponse::into_response)? {
+            DownloadGrant::Granted(share) => share,
+            DownloadGrant::LimitReached => return Err(download_limit_reached()),
+            DownloadGrant::NotFound => return Err(share_not_found()),
+        };
+    info!(
+        "Share {} downloaded {} ({} remaining)",
+        share.token,
+        file.id,
+        share
+            .remaining_downloads()
+            .map_or("unlimited".to_string(), |n| n.to_string())
+    );
+
+    let disposition = HeaderValue::from_str(&format!(
+        "attachment; filename=\"{}\"",
+        file.name.replace(['"', '\\'], "")
+    ))
+    .unwrap_or(HeaderValue::from_static("attachment"));
+
+    let (content_type, body) = match (source, key) {
+        (Source::Stored(stored), _) => (
+            HeaderValue::from_str(&stored.content_type)
+                .unwrap_or(HeaderValue::from_static("application/octet-stream")),
+            Body::from(stored.body),
+        ),
+        (Source::Gateway(upstream), None) => (
+            upstream_type(&upstream),
+            Body::from_stream(upstream.bytes_stream()),
+        ),
+        (Source::Gateway(upstream), Some(key)) => {
+            let mut content_type = upstream_type(&upstream);
+            let mut body = upstream
+                .bytes()
+                .await
+                .map_err(|e| ApiError::from(e).into_response())?;
+            if share.policy.watermark {
+                body = tokio::task::spawn_blocking(move || watermark(&body))
+                    .await
+                    .map_err(|e| {
+                        ApiError::Internal(format!("Watermarking failed: {e}")).into_response()
+                    })?
+                    .map_err(IntoResponse::into_response)?
+                    .into();
+                content_type = HeaderValue::from_static("image/jpeg");
+            }
+            state
+                .thumbnails
+                .put(key, content_type.to_str().unwrap_or_default(), &body)
+                .await;
+            (content_type, Body::from(body))
+        }
+    };
+
+    let headers = [
+        (CONTENT_TYPE, content_type),
+        (CONTENT_DISPOSITION, disposition),
+    ];
+    Ok((headers, body).into_response())
+}
+
+fn upstream_type(upstream: &reqwest::Response) -> HeaderValue {
+    upstream
+        .headers()
+        .get(CONTENT_TYPE)
+        .cloned()
+        .unwrap_or(HeaderValue::from_static("application/octet-stream"))
+}
+
+// lighten alternating diagonal bands, so a proof can't pass for the delivered photo
+fn watermark(image: &[u8]) -> Result<Vec<u8>, ApiError> {
+    let mut image = image::load_from_memory(image)
+        .map_err(|e| ApiError::Internal(format!("Failed to decode image for watermarking: {e}")))?
+        .to_rgb8();
+
+    let band = (image.width().max(image.height()) / 16).max(1);
+    for (x, y, pixel) in image.enumerate_pixels_mut() {
+        if ((x + y) / band) % 2 == 0 {
+            for channel in pixel.0.iter_mut() {
+                *channel = (u16::from(*channel) * 7 / 10 + 76) as u8;
+            }
+        }
+    }
+
+    let mut jpeg = Vec::new();
+    image
+        .write_to(&mut Cursor::new(&mut jpeg), ImageFormat::Jpeg)
+        .map_err(|e| ApiError::Internal(format!("Failed to encode watermarked image: {e}")))?;
+    Ok(jpeg)
+}
+
+// a printable QR code pointing at the share's gallery page
+async fn share_qr_code(
+    State(state): State<AppState>,
+    Path(token): Path<String>,
+    Query(params): Query<QrParams>,
+) -> Result<Response, Response> {
+    let share = shares::resolve(&token).ok_or_else(share_not_found)?;
+    let url = state
+        .config
+        .frontend
+        .share_url(&share.token)
+        .ok_or_else(|| {
+            ApiError::Api("Set FRONTEND_URL to generate share QR codes".to_string()).into_response()
+        })?;
+
+    let size = params
+        .size
+        .unwrap_or(DEFAULT_QR_SIZE)
+        .clamp(64, MAX_QR_SIZE);
+    let code = QrCode::new(url.as_bytes()).map_err(|e| {
+        ApiError::Internal(format!("Failed to encode QR code: {e}")).into_response()
+    })?;
+    let image = code.render::<Luma<u8>>().min_dimensions(size, size).build();
+
+    let mut png = Vec::new();
+    image
+        .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+        .map_err(|e| {
+            ApiError::Internal(format!("Failed to render QR code: {e}")).into_response()
+        })?;
+
+    Ok(([(CONTENT_TYPE, "image/png")], png).into_response())
+}
@@ -1,341 +1,1810 @@
-use axum::{Json, Router, extract::multipart::Multipart, routing::post};
-use reqwest::Client;
+use axum::{
+    Extension, Json, Router,
+    body::Bytes,
+    extract::{
+        DefaultBodyLimit, Path, Query, State,
+        multipart::{Field, Multipart, MultipartError},
+    },
+    http::{HeaderMap, StatusCode, header::CONTENT_RANGE},
+    middleware,
+    response::{
+        IntoResponse, Response,
+        sse::{Event, KeepAlive, Sse},
+    },
+    routing::{get, post},
+};
+use chrono::Utc;
+use metrics::{counter, histogram};
+use reqwest::{Body, Method, multipart::Part};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use tokio::sync::{Semaphore, broadcast, mpsc};
+use tokio::task::JoinSet;
+use tokio_stream::{
+    Stream, StreamExt,
+    wrappers::{BroadcastStream, ReceiverStream},
+};
+use tracing::{Instrument, debug, info, info_span, warn};
 
-use dotenv::dotenv;
 use std::collections::HashMap;
-use std::env;
-use std::time::Duration;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use crate::errors::ApiError;
+use crate::activity::{Activity, Tracked};
+use crate::auth::roles::{Principal, RequireRole, Uploader};
+use crate::config::{ScanMode, UploadConfig};
+use crate::errors::{ApiError, ErrorCode, error_response, problem_body, problem_response};
+use crate::imaging::{CONTENT_SHA256, SNIFF_BYTES, UPLOAD_MANIFEST, image_mime};
+use crate::middleware::request_id;
+use crate::middleware::upload_queue::{QueueSlot, UploadQueue, upload_queue};
 use crate::models::{
+    PhotoAttributes,
     groups::GroupCreationResponse,
-    uploads::{PhotoMetadata, PinataUploadResponse, UploadResponse, UploadedFileInfo},
+    uploads::{
+        CapturePhotoParams, CapturePhotoResponse, CaptureSession, CaptureSessionResponse,
+        CloseCaptureSession, CreateCaptureSession, CreateUploadSession, FileTiming, JobEvent,
+        JobFileStatus, JobStatus, ManifestFile, PhotoMetadata, SessionStatus, UploadFailure,
+        UploadJob, UploadJobFile, UploadJobResponse, UploadManifest, UploadParams, UploadResponse,
+        UploadSession, UploadSessionResponse, UploadSource, UploadTooLarge, UploadedFileInfo,
+    },
+};
+use crate::partial_groups::PartialGroup;
+use crate::pinata::{
+    FilesQuery, ListOptions, PinataClient, files, gateway, groups, rate_limit, read_json, read_text,
 };
+use crate::scan::scan_upload;
+use crate::spool::{
+    Progress, SpooledFile, Spooler, over_request_limit, read_error, spool_body, spool_field,
+    too_large,
+};
+use crate::state::AppState;
+use crate::telemetry::SendTraced;
+use crate::upload_pipeline::{PinnedFile, UploadFile, UploadSettings};
+use crate::{capture_sessions, group_covers, upload_jobs, upload_sessions};
+
+// chunks buffered between the incoming field and the outbound request
+const STREAM_BUFFER_CHUNKS: usize = 4;
+
+// bumped whenever the shape of the pinned upload manifest changes
+const MANIFEST_VERSION: u32 = 1;
+
+// Clients name themselves in these headers, e.g. `X-Upload-Source: watch-folder` and
+// `X-Client-Version: 1.4.2`, and every file they pin is tagged with the keyvalues
+// below, so `/files-category?uploaded_via=...` can find what one pipeline produced.
+const SOURCE_HEADER: &str = "x-upload-source";
+const CLIENT_VERSION_HEADER: &str = "x-client-version";
+pub const UPLOADED_VIA: &str = "uploaded_via";
+pub const CLIENT_VERSION: &str = "client_version";
+const MAX_SOURCE_LEN: usize = 64;
+
+// what a request says sent it, or `default` when it doesn't say
+fn upload_source(headers: &HeaderMap, default: &str) -> Result<UploadSource, ApiError> {
+    let header = |name: &str| -> Result<Option<String>, ApiError> {
+        let Some(value) = headers.get(name) else {
+            return Ok(None);
+        };
+        let value = value
+            .to_str()
+            .map(str::trim)
+            .ok()
+            .filter(|v| {
+                !v.is_empty()
+                    && v.len() <= MAX_SOURCE_LEN
+                    && v.chars()
+                        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_' | '+'))
+            })
+            .ok_or_else(|| {
+                ApiError::Validation(format!(
+                    "The {name} header must be up to {MAX_SOURCE_LEN} letters, digits, '.', '-', '_' or '+'"
+                ))
+            })?;
+        Ok(Some(value.to_string()))
+    };
+
+    Ok(UploadSource {
+        via: header(SOURCE_HEADER)?
+            .map(|via| via.to_ascii_lowercase())
+            .unwrap_or_else(|| default.to_string()),
+        client_version: header(CLIENT_VERSION_HEADER)?,
+    })
+}
+
+// the queue lives in AppState so /readyz can report on it
+pub fn uploads_router(queue: UploadQueue, max_request_bytes: u64) -> Router<AppState> {
+    let body_limit = usize::try_from(max_request_bytes).unwrap_or(usize::MAX);
+    Router::new()
+        .route("/upload", post(upload_photo))
+        .route("/sessions/{id}/photo", post(upload_capture_photo))
+        .route_layer(middleware::from_fn_with_state(queue, upload_queue))
+        // instead of BODY_LIMIT_BYTES, enforced as the form is streamed in
+        .route_layer(DefaultBodyLimit::max(body_limit))
+        // polling doesn't wait in the upload queue
+        .route("/upload/jobs/{id}", get(upload_job_status))
+        .route("/upload/jobs/{id}/events", get(upload_job_events))
+        // chunks are small requests of their own, so sessions don't hold a queue slot either
+        .route("/upload/sessions", post(create_upload_session))
+        .route(
+            "/upload/sessions/{id}",
+            get(upload_session_status)
+                .patch(append_upload_chunk)
+                .delete(cancel_upload_session),
+        )
+        .route("/sessions", post(create_capture_session))
+        .route("/sessions/{id}", get(capture_session_status))
+        .route("/sessions/{id}/close", post(close_capture_session))
+}
+
+// time spent in the stages of a single file's upload
+#[derive(Debug, Default)]
+struct StageTimings {
+    group_resolution: Duration,
+    upstream_upload: Duration,
+}
+
+// how long to wait on Pinata and how often to retry, bounded by the upload config
+#[derive(Debug, Clone, Copy)]
+struct RetryPolicy {
+    timeout: Duration,
+    max_retries: u32,
+}
+
+impl RetryPolicy {
+    fn new(config: &UploadConfig, timeout_secs: Option<u64>, max_retries: Option<u32>) -> Self {
+        Self {
+            timeout: Duration::from_secs(
+                timeout_secs
+                    .unwrap_or(config.default_timeout_secs)
+                    .clamp(1, config.max_timeout_secs),
+            ),
+            max_retries: max_retries
+                .unwrap_or(config.default_retries)
+                .min(config.max_retries),
+        }
+    }
+}
+
+// form fields that apply to every file, so they have to come before the first one
+#[derive(Debug, Default)]
+struct UploadOptions {
+    create_new_group: bool,
+    group_id: Option<String>,
+    group_name: Option<String>,
+    // the Pinata account named, rather than the one the group routes to
+    account: Option<String>,
+    timeout_secs: Option<u64>,
+    max_retries: Option<u32>,
+    strip_exif: Option<bool>,
+    source: Option<UploadSource>,
+}
+
+impl UploadOptions {
+    fn policy(&self, config: &UploadConfig) -> RetryPolicy {
+        RetryPolicy::new(config, self.timeout_secs, self.max_retries)
+    }
+
+    fn settings(&self, config: &UploadConfig) -> UploadSettings {
+        UploadSettings {
+            strip_exif: self.strip_exif.unwrap_or(config.strip_exif),
+        }
+    }
+
+    // uploaders held to some groups may only upload into those
+    fn check_allowed(&self, principal: &Principal) -> Result<(), ApiError> {
+        if principal.may_upload_to(self.group_id.as_deref(), self.create_new_group) {
+            return Ok(());
+        }
+        let groups = principal.groups.as_deref().unwrap_or_default().join(", ");
+        Err(ApiError::Forbidden(format!(
+            "This key can only upload into an existing group of: {groups}"
+        )))
+    }
+}
+
+// the group files are pinned into, resolved once before the first file is sent
+#[derive(Clone)]
+struct UploadTarget {
+    policy: RetryPolicy,
+    group_id: Option<String>,
+    // the group when this upload created it, cleaned up if the upload doesn't finish
+    created: Option<Arc<PartialGroup>>,
+    // the Pinata account the files go to
+    account: String,
+    // reported against the first file only
+    group_resolution: Duration,
+    settings: UploadSettings,
+    source: Option<UploadSource>,
+}
+
+// where a file's bytes come from: straight off the request, or already received,
+// optionally reporting how much of it has been sent on
+enum FileSource<'a> {
+    Stream(Box<Field<'a>>),
+    Spooled(SpooledFile, Option<Progress>),
+}
+
+// a file whose metadata has been read and checked
+struct ReceivedFile {
+    filename: String,
+    metadata: PhotoMetadata,
+    validation: Duration,
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
 
-pub fn uploads_router() -> Router {
-    Router::new().route("/upload", post(upload_photo))
+fn record_stage(stage: &'static str, duration: Duration) {
+    histogram!("upload_stage_duration_seconds", "stage" => stage).record(duration.as_secs_f64());
 }
 
-pub async fn upload_photo(mut multipart: Multipart) -> Result<Json<UploadResponse>, ApiError> {
-    println!("Processing upload request");
+// Files whose metadata arrives before them are streamed to Pinata while they're still
+// being received; the rest are spooled until their metadata turns up.
+pub async fn upload_photo(
+    State(state): State<AppState>,
+    RequireRole(principal, _): RequireRole<Uploader>,
+    Query(params): Query<UploadParams>,
+    Extension(slot): Extension<QueueSlot>,
+    headers: HeaderMap,
+    mut multipart: Multipart,
+) -> Result<Response, ApiError> {
+    debug!("Processing upload request");
 
-    let mut create_new_group = false;
-    let mut group_id: Option<String> = None;
-    let mut group_name: Option<String> = None;
+    let upload_config = &state.config.upload;
+    // scanning needs the whole file before it's pinned, and a background job
+    // needs it before the request returns
+    let streaming = !params.background && state.config.scan.mode == ScanMode::Off;
 
-    let mut files: HashMap<String, Vec<u8>> = HashMap::new();
-    let mut file_names: HashMap<String, String> = HashMap::new();
+    let mut options = UploadOptions {
+        source: Some(upload_source(&headers, "api")?),
+        ..UploadOptions::default()
+    };
+    let mut target: Option<UploadTarget> = None;
+
+    let mut pending: Vec<(String, String, SpooledFile)> = Vec::new();
     let mut metadata_map: HashMap<String, PhotoMetadata> = HashMap::new();
+    let mut validation_times: HashMap<String, Duration> = HashMap::new();
+    let mut uploaded_files = Vec::new();
+    let mut failed = Vec::new();
 
     while let Some(field) = match multipart.next_field().await {
         Ok(Some(f)) => Some(f),
         Ok(None) => None,
+        Err(e) if e.status() == StatusCode::PAYLOAD_TOO_LARGE => {
+            return Ok(request_too_large(&state, None, uploaded_files));
+        }
         Err(e) => {
-            println!("Error reading next field: {e}",);
-            return Err(ApiError::Api(format!(
+            info!("Error reading next field: {e}",);
+            return Err(ApiError::Validation(format!(
                 "Failed to process multipart form: {e}",
             )));
         }
     } {
         let name = field.name().unwrap_or("").to_string();
 
+        let is_option = matches!(
+            name.as_str(),
+            "createNewGroup"
+                | "groupId"
+                | "groupName"
+                | "account"
+                | "timeout_secs"
+                | "max_retries"
+                | "stripExif"
+        );
+        if is_option && target.is_some() {
+            return Err(ApiError::Validation(format!(
+                "The {name} field must come before the files in the form"
+            )));
+        }
+
         if name == "createNewGroup" {
-            let value = field.text().await.map_err(|err| {
-                ApiError::Api(format!("Failed to read createNewGroup field: {err}"))
-            })?;
-            create_new_group = value.parse::<bool>().unwrap_or(false);
+            let value = field
+                .text()
+                .await
+                .map_err(|e| form_error(e, "createNewGroup field"))?;
+            options.create_new_group = value.parse::<bool>().unwrap_or(false);
         } else if name == "groupId" {
-            group_id =
-                Some(field.text().await.map_err(|err| {
-                    ApiError::Api(format!("Failed to read groupId field: {err}"))
-                })?);
+            options.group_id = Some(
+                field
+                    .text()
+                    .await
+                    .map_err(|e| form_error(e, "groupId field"))?,
+            );
         } else if name == "groupName" {
-            group_name = Some(field.text().await.map_err(|err| {
-                ApiError::Api(format!("Failed to read groupName field: {}", err))
+            options.group_name = Some(
+                field
+                    .text()
+                    .await
+                    .map_err(|e| form_error(e, "groupName field"))?,
+            );
+        } else if name == "account" {
+            let value = field
+                .text()
+                .await
+                .map_err(|e| form_error(e, "account field"))?;
+            options.account = Some(value.trim().to_string()).filter(|v| !v.is_empty());
+        } else if name == "stripExif" {
+            let value = field
+                .text()
+                .await
+                .map_err(|e| form_error(e, "stripExif field"))?;
+            options.strip_exif = Some(value.trim().parse::<bool>().map_err(|_| {
+                ApiError::Validation(format!("stripExif must be true or false, got '{value}'"))
             })?);
+        } else if name == "timeout_secs" || name == "max_retries" {
+            let value = field
+                .text()
+                .await
+                .map_err(|e| form_error(e, &format!("{name} field")))?;
+            let number = value.trim().parse::<u64>().map_err(|_| {
+                ApiError::Validation(format!("{name} must be a whole number, got '{value}'"))
+            })?;
+
+            if name == "timeout_secs" {
+                options.timeout_secs = Some(number);
+            } else {
+                options.max_retries = Some(number.min(u32::MAX as u64) as u32);
+            }
         } else if name.starts_with("file_") {
+            // the options all came before the first file
+            options.check_allowed(&principal)?;
             // This is the field for the file
             let file_id = name.clone();
             let file_name = field.file_name().unwrap_or("unnamed_file").to_string();
 
-            match field.bytes().await {
+            // e.g. tags are stripped from the whole file before it's sent
+            let streaming = streaming
+                && !state
+                    .upload_pipeline
+                    .processes_content(&options.settings(upload_config));
+            if streaming && let Some(metadata) = metadata_map.remove(&file_id) {
+                let target = match &mut target {
+                    Some(target) => target,
+                    None => target.insert(resolve_target(&state, &options, upload_config).await?),
+                };
+                let group_resolution = std::mem::take(&mut target.group_resolution);
+                let file = ReceivedFile {
+                    filename: file_name.clone(),
+                    metadata,
+                    validation: validation_times.remove(&file_id).unwrap_or_default(),
+                };
+                let result = upload_file(
+                    &state,
+                    params.debug_timing,
+                    target,
+                    group_resolution,
+                    FileSource::Stream(Box::new(field)),
+                    file,
+                )
+                .await;
+                if result.as_ref().is_err_and(over_request_limit) {
+                    let cut_off = Some((file_id, file_name));
+                    return Ok(request_too_large(&state, cut_off, uploaded_files));
+                }
+                record_result(&mut uploaded_files, &mut failed, file_id, file_name, result);
+                continue;
+            }
+
+            match spool_field(
+                field,
+                upload_config.spool_threshold_bytes,
+                upload_config.spool_dir.as_deref(),
+                upload_config.max_file_bytes,
+            )
+            .await
+            {
                 Ok(data) => {
-                    println!("File data size: {} bytes", data.len());
-                    files.insert(file_id.clone(), data.to_vec());
-                    file_names.insert(file_id, file_name);
+                    info!(
+                        "File data size: {} bytes{}",
+                        data.len(),
+                        if data.is_on_disk() {
+                            " (spooled to disk)"
+                        } else {
+                            ""
+                        }
+                    );
+                    pending.push((file_id, file_name, data));
+                }
+                Err(e) if over_request_limit(&e) => {
+                    let cut_off = Some((file_id, file_name));
+                    return Ok(request_too_large(&state, cut_off, uploaded_files));
                 }
+                // e.g. over the size limit; a broken form fails at the next field instead
                 Err(e) => {
-                    println!("Failed to read file data: {}", e);
-                    return Err(ApiError::Api(format!("Failed to read file data: {}", e)));
+                    record_result(&mut uploaded_files, &mut failed, file_id, file_name, Err(e))
                 }
             }
         } else if name.starts_with("metadata_") {
             // extract the file's unique id from metadata_{file_id}
             let fie_id = name.strip_prefix("metadata_").unwrap_or("").to_string();
-            let metadata_str = field
-                .text()
-                .await
-                .map_err(|e| ApiError::Api(format!("Failed to read metadata: {}", e)))?;
+            let metadata_str = field.text().await.map_err(|e| form_error(e, "metadata"))?;
+            let validation_started = Instant::now();
 
             let metadata: PhotoMetadata = match serde_json::from_str(&metadata_str) {
                 Ok(m) => m,
                 Err(err) => {
-                    println!("Failed to parse metadata JSON: {err}",);
-                    return Err(ApiError::Api(format!(
+                    info!("Failed to parse metadata JSON: {err}",);
+                    return Err(ApiError::Validation(format!(
                         "Failed to parse metadata JSON: {err}",
                     )));
                 }
             };
 
+            // reject bad metadata before anything is pinned
+            PhotoAttributes::from(&metadata).validate()?;
+
+            validation_times.insert(fie_id.clone(), validation_started.elapsed());
             metadata_map.insert(fie_id, metadata);
         }
     }
 
-    // upload each file to pinata
-    let mut uploaded_files = Vec::new();
-    let mut created_group_id: Option<String> = None;
-
-    for (file_id, file_data) in files {
-        let metadata = metadata_map
-            .get(&file_id)
-            .ok_or_else(|| ApiError::Api(format!("Missing metadata for file: {}", file_id)))?;
-
-        let filename = file_names.get(&file_id).unwrap_or(&file_id).clone();
-
-        // upload functionality eg
-        let pinata_result = upload_to_pinata(
-            &file_data,
-            &filename,
+    // every spooled file needs its metadata before the first of them is pinned
+    let mut ready = Vec::with_capacity(pending.len());
+    for (file_id, filename, data) in pending {
+        let metadata = metadata_map.remove(&file_id).ok_or_else(|| {
+            ApiError::Validation(format!("Missing metadata for file: {}", file_id))
+        })?;
+        let validation = validation_times.remove(&file_id).unwrap_or_default();
+        let file = ReceivedFile {
+            filename,
             metadata,
-            create_new_group,
-            &group_id,
-            &group_name,
-        )
-        .await?;
+            validation,
+        };
+        ready.push((file_id, data, file));
+    }
 
-        // if this is the first file and we created group, store the group ID
-        if create_new_group && created_group_id.is_none() {
-            created_group_id = pinata_result.group_id.clone();
+    if params.background {
+        let job = start_job(state.clone(), slot, options, ready, failed)?;
+        info!(
+            "Queued upload job {} with {} files",
+            job.id,
+            job.files.len()
+        );
+        let message = format!("Poll /upload/jobs/{} for progress", job.id);
+        let body = Json(UploadJobResponse {
+            success: true,
+            job,
+            message: Some(message),
+        });
+        return Ok((StatusCode::ACCEPTED, body).into_response());
+    }
+
+    if !ready.is_empty() {
+        let target = match &mut target {
+            Some(target) => target,
+            None => target.insert(resolve_target(&state, &options, upload_config).await?),
+        };
+        let results = pin_received(&state, params.debug_timing, target, ready, None).await?;
+        for (file_id, filename, result) in results {
+            record_result(&mut uploaded_files, &mut failed, file_id, filename, result);
         }
+    }
 
-        uploaded_files.push(pinata_result);
+    if !uploaded_files.is_empty() {
+        state.catalog_changed();
     }
 
-    //
-    let response_group_id = if create_new_group {
-        created_group_id
-    } else {
-        group_id
+    let manifest_cid = match &target {
+        Some(target) if upload_config.manifest && !uploaded_files.is_empty() => {
+            pin_manifest(&state, target, &uploaded_files).await
+        }
+        _ => None,
     };
 
-    Ok(Json(UploadResponse {
-        success: true,
+    let response_group_id = match target {
+        Some(target) => {
+            target.finish();
+            target.group_id
+        }
+        None => options.group_id,
+    };
+
+    info!(
+        "Uploaded {} files, {} failed",
+        uploaded_files.len(),
+        failed.len()
+    );
+
+    // e.g. a single file that's too large or not an image answers with a 413 or 415
+    let status = match failed.first() {
+        Some(first)
+            if uploaded_files.is_empty()
+                && first.status.is_client_error()
+                && failed.iter().all(|f| f.status == first.status) =>
+        {
+            first.status
+        }
+        _ => StatusCode::OK,
+    };
+    let body = Json(UploadResponse {
+        success: failed.is_empty(),
+        message: (!failed.is_empty()).then(|| {
+            format!(
+                "{} of {} files failed to upload",
+                failed.len(),
+                failed.len() + uploaded_files.len()
+            )
+        }),
         files: uploaded_files,
+        failed,
         group_id: response_group_id,
+        manifest_cid,
+    });
+    Ok((status, body).into_response())
+}
+
+// Nothing more of the form can be read once the request is over its limit, so the
+// upload stops at the file it was cut off in, if any. Files streamed before it stay pinned.
+fn request_too_large(
+    state: &AppState,
+    cut_off: Option<(String, String)>,
+    files: Vec<UploadedFileInfo>,
+) -> Response {
+    if !files.is_empty() {
+        state.catalog_changed();
+    }
+    let limit_bytes = state.config.upload.max_request_bytes;
+    let (field, filename) = cut_off.unzip();
+    let message = match &filename {
+        Some(filename) => {
+            format!("The upload went over its {limit_bytes} byte limit in {filename}")
+        }
+        None => format!("The upload is over its {limit_bytes} byte limit"),
+    };
+    warn!("Refused upload: {message}");
+    let status = StatusCode::PAYLOAD_TOO_LARGE;
+    let mut body = problem_body(
+        status,
+        ErrorCode::PayloadTooLarge,
+        "Payload too large",
+        message,
+    );
+    let details = serde_json::to_value(UploadTooLarge {
+        field,
+        filename,
+        limit_bytes,
+        files,
+    });
+    if let (Some(body), Ok(Value::Object(details))) = (body.as_object_mut(), details) {
+        body.extend(details);
+    }
+    problem_response(status, &body)
+}
+
+// a form field that couldn't be read; hitting the request size limit isn't a broken form
+fn form_error(e: MultipartError, what: &str) -> ApiError {
+    if e.status() == StatusCode::PAYLOAD_TOO_LARGE {
+        ApiError::TooLarge(format!(
+            "The upload went over its size limit reading {what}"
+        ))
+    } else {
+        ApiError::Validation(format!("Failed to read {what}: {e}"))
+    }
+}
+
+// a received file, keyed by its form field
+type ReadyFile = (String, SpooledFile, ReceivedFile);
+
+// pin received files a few at a time, returning each outcome in form order;
+// `job` gets progress as each file starts, sends and finishes
+async fn pin_received(
+    state: &AppState,
+    debug_timing: bool,
+    target: &mut UploadTarget,
+    ready: Vec<ReadyFile>,
+    job: Option<&str>,
+) -> Result<Vec<(String, String, Result<UploadedFileInfo, ApiError>)>, ApiError> {
+    let mut group_resolution = std::mem::take(&mut target.group_resolution);
+    let permits = Arc::new(Semaphore::new(state.config.upload.parallel_files));
+    let mut tasks = JoinSet::new();
+
+    for (index, (file_id, data, file)) in ready.into_iter().enumerate() {
+        let state = state.clone();
+        let target = target.clone();
+        let permits = permits.clone();
+        let group_resolution = std::mem::take(&mut group_resolution);
+        let job = job.map(str::to_string);
+
+        tasks.spawn(async move {
+            // held for the whole upload, bounding how many run at once
+            let _permit = permits.acquire_owned().await;
+            if let Some(job) = &job {
+                upload_jobs::file_started(job, index);
+            }
+
+            let filename = file.filename.clone();
+            let progress = job
+                .as_ref()
+                .map(|job| upload_jobs::progress(job, index, data.len()));
+            let result = upload_file(
+                &state,
+                debug_timing,
+                &target,
+                group_resolution,
+                FileSource::Spooled(data, progress),
+                file,
+            )
+            .await;
+
+            if let Some(job) = &job {
+                upload_jobs::file_finished(job, index, &result);
+            }
+            (index, file_id, filename, result)
+        });
+    }
+
+    let mut results = Vec::new();
+    while let Some(joined) = tasks.join_next().await {
+        results.push(joined.map_err(|e| ApiError::Internal(format!("Upload task failed: {e}")))?);
+    }
+    results.sort_by_key(|(index, ..)| *index);
+    Ok(results
+        .into_iter()
+        .map(|(_, file_id, filename, result)| (file_id, filename, result))
+        .collect())
+}
+
+// Record the received files as a job and pin them once earlier jobs are done. The job
+// keeps its request's upload queue slot until then, so queued jobs and the files they
+// hold are bounded by the queue.
+fn start_job(
+    state: AppState,
+    slot: QueueSlot,
+    options: UploadOptions,
+    ready: Vec<ReadyFile>,
+    failed: Vec<UploadFailure>,
+) -> Result<UploadJob, ApiError> {
+    let mut files: Vec<UploadJobFile> = ready
+        .iter()
+        .map(|(field, data, file)| UploadJobFile {
+            field: field.clone(),
+            filename: file.filename.clone(),
+            size_bytes: data.len(),
+            status: JobFileStatus::Queued,
+            id: None,
+            cid: None,
+            message: None,
+        })
+        .collect();
+    // files that were already turned away while the form was read
+    files.extend(failed.into_iter().map(|failure| UploadJobFile {
+        field: failure.field,
+        filename: failure.filename,
+        size_bytes: 0,
+        status: JobFileStatus::Failed,
+        id: None,
+        cid: None,
+        message: Some(failure.message),
+    }));
+
+    let job = upload_jobs::create(files)?;
+    let id = job.id.clone();
+
+    let tracked = Tracked::start(Activity::UploadJob);
+    let span = info_span!("upload_job", job_id = %id);
+    tokio::spawn(
+        async move {
+            let _tracked = tracked;
+            let _slot = slot;
+            let _turn = upload_jobs::TURNS.acquire().await;
+            if ready.is_empty() {
+                upload_jobs::finish(&id, None);
+                return;
+            }
+
+            let mut target = match resolve_target(&state, &options, &state.config.upload).await {
+                Ok(target) => target,
+                Err(e) => {
+                    warn!("Upload job {id} failed: {e}");
+                    upload_jobs::finish(&id, Some(e.to_string()));
+                    return;
+                }
+            };
+            upload_jobs::start(&id, target.group_id.clone());
+
+            match pin_received(&state, false, &mut target, ready, Some(&id)).await {
+                Ok(results) => {
+                    target.finish();
+                    if results.iter().any(|(_, _, result)| result.is_ok()) {
+                        state.catalog_changed();
+                    }
+                    info!("Upload job {id} finished");
+                    upload_jobs::finish(&id, None);
+                }
+                Err(e) => {
+                    warn!("Upload job {id} failed: {e}");
+                    upload_jobs::finish(&id, Some(e.to_string()));
+                }
+            }
+        }
+        .instrument(span),
+    );
+
+    Ok(job)
+}
+
+fn job_not_found(id: &str) -> Response {
+    error_response(
+        StatusCode::NOT_FOUND,
+        ErrorCode::NotFound,
+        "Upload job not found",
+        format!("No upload job {id}, finished jobs are kept for a while only"),
+    )
+}
+
+async fn upload_job_status(Path(id): Path<String>) -> Result<Json<UploadJobResponse>, Response> {
+    let job = upload_jobs::get(&id).ok_or_else(|| job_not_found(&id))?;
+
+    Ok(Json(UploadJobResponse {
+        success: job.status != JobStatus::Failed,
+        job,
         message: None,
     }))
 }
 
-async fn send_pinata_request(
-    client: &Client,
-    api_key: &str,
-    form: reqwest::multipart::Form,
-) -> Result<UploadedFileInfo, ApiError> {
-    let response = client
-        .post("https://uploads.pinata.cloud/v3/files")
-        .header("Authorization", format!("Bearer {}", api_key))
-        .multipart(form)
-        .send()
-        .await
-        .map_err(|e| ApiError::Request(e))?;
+// Live progress as Server-Sent Events: a snapshot of the job, then each change
+// until it finishes. Subscribing to a finished job gets the snapshot and the result.
+async fn upload_job_events(
+    Path(id): Path<String>,
+) -> Result<Sse<impl Stream<Item = Result<Event, axum::Error>>>, Response> {
+    let (job, events) = upload_jobs::subscribe(&id).ok_or_else(|| job_not_found(&id))?;
 
-    // check if successful
-    let status = response.status();
+    let finished = job.status.is_finished().then(|| JobEvent::Finished {
+        status: job.status,
+        message: job.message.clone(),
+    });
+    let initial = std::iter::once(JobEvent::Snapshot { job }).chain(finished);
+    // a finished job has no channel; one whose sender is already gone ends straight away
+    let events = events.unwrap_or_else(|| broadcast::channel(1).1);
+    // a subscriber that falls behind skips what it missed, the next event carries on
+    let live = BroadcastStream::new(events).filter_map(Result::ok);
 
-    if !status.is_success() {
-        let error_body = response.text().await?;
-        return Err(ApiError::Api(format!(
-            "Pinata API error ({}): {}",
-            status, error_body
+    // the stream holds this, so it counts as open until the client goes
+    let open = Tracked::start(Activity::EventStream);
+    let stream = tokio_stream::iter(initial).chain(live).map(move |event| {
+        let _open = &open;
+        Event::default().event(event.name()).json_data(&event)
+    });
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+fn session_not_found(id: &str) -> Response {
+    error_response(
+        StatusCode::NOT_FOUND,
+        ErrorCode::NotFound,
+        "Upload session not found",
+        format!("No upload session {id}, unfinished sessions expire after a day"),
+    )
+}
+
+fn session_conflict(message: String) -> Response {
+    error_response(
+        StatusCode::CONFLICT,
+        ErrorCode::Conflict,
+        "Upload session conflict",
+        message,
+    )
+}
+
+fn invalid_range(message: String) -> Response {
+    error_response(
+        StatusCode::BAD_REQUEST,
+        ErrorCode::ValidationFailed,
+        "Invalid Content-Range",
+        message,
+    )
+}
+
+fn session_response(
+    session: UploadSession,
+    message: Option<String>,
+) -> Json<UploadSessionResponse> {
+    Json(UploadSessionResponse {
+        success: true,
+        session,
+        message,
+    })
+}
+
+// Start a chunked upload: the file's size and metadata up front, then its bytes in
+// as many `PATCH /upload/sessions/{id}` requests as it takes
+async fn create_upload_session(
+    State(state): State<AppState>,
+    RequireRole(principal, _): RequireRole<Uploader>,
+    headers: HeaderMap,
+    Json(request): Json<CreateUploadSession>,
+) -> Result<Response, ApiError> {
+    PhotoAttributes::from(&request.metadata).validate()?;
+    let options = UploadOptions {
+        create_new_group: request.create_new_group,
+        group_id: request.group_id.clone(),
+        ..UploadOptions::default()
+    };
+    options.check_allowed(&principal)?;
+    if let Some(account) = &request.account {
+        state.accounts.only(account)?;
+    }
+    let source = upload_source(&headers, "api")?;
+    let max_bytes = state.config.upload.max_file_bytes;
+    if request.size_bytes > max_bytes {
+        return Err(too_large(max_bytes));
+    }
+    if request.size_bytes == 0 {
+        return Err(ApiError::Validation(
+            "size_bytes must be more than 0".to_string(),
+        ));
+    }
+
+    let session = upload_sessions::create(request, source)?;
+    info!(
+        "Opened upload session {} for {} ({} bytes)",
+        session.id, session.filename, session.size_bytes
+    );
+    Ok((StatusCode::CREATED, session_response(session, None)).into_response())
+}
+
+async fn upload_session_status(
+    Path(id): Path<String>,
+) -> Result<Json<UploadSessionResponse>, Response> {
+    let session = upload_sessions::get(&id).ok_or_else(|| session_not_found(&id))?;
+    Ok(session_response(session, None))
+}
+
+async fn cancel_upload_session(
+    Path(id): Path<String>,
+) -> Result<Json<UploadSessionResponse>, Response> {
+    let _claim = upload_sessions::claim(&id)
+        .ok_or_else(|| session_conflict("A chunk is still being written".to_string()))?;
+    let session = upload_sessions::remove(&id)
+        .map_err(IntoResponse::into_response)?
+        .ok_or_else(|| session_not_found(&id))?;
+    Ok(session_response(
+        session,
+        Some("Upload session cancelled".to_string()),
+    ))
+}
+
+// what a chunk's Content-Range asks for
+enum ChunkRange {
+    // `bytes <start>-<end>/<total>`, inclusive like HTTP ranges
+    Bytes { start: u64, end: u64, total: u64 },
+    // `bytes */<total>` with no body: pin a session whose bytes are all in
+    Finish { total: u64 },
+}
+
+fn parse_content_range(raw: &str) -> Option<ChunkRange> {
+    let (range, total) = raw.trim().strip_prefix("bytes ")?.split_once('/')?;
+    let total = total.trim().parse().ok()?;
+    if range.trim() == "*" {
+        return Some(ChunkRange::Finish { total });
+    }
+    let (start, end) = range.split_once('-')?;
+    let (start, end) = (start.trim().parse().ok()?, end.trim().parse().ok()?);
+    (start <= end).then_some(ChunkRange::Bytes { start, end, total })
+}
+
+// Append the next chunk of a session. Chunks have to arrive in order; after a
+// dropped connection, `GET` the session and carry on from `received_bytes`. The
+// file is pinned as soon as its last byte is in.
+async fn append_upload_chunk(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+    body: axum::body::Body,
+) -> Result<Json<UploadSessionResponse>, Response> {
+    let _tracked = Tracked::start(Activity::Upload);
+    let _claim = upload_sessions::claim(&id).ok_or_else(|| {
+        session_conflict("Another chunk for this session is still being written".to_string())
+    })?;
+    let session = upload_sessions::get(&id).ok_or_else(|| session_not_found(&id))?;
+    if session.status != SessionStatus::Receiving {
+        return Err(session_conflict(format!(
+            "This session is already {}",
+            if session.status == SessionStatus::Completed {
+                "completed"
+            } else {
+                "pinning"
+            }
         )));
     }
 
-    // parse the response to JSON
-    let data: PinataUploadResponse = response.json().await?;
-    println!("Raw API response: {data:?}");
+    let raw = headers
+        .get(CONTENT_RANGE)
+        .and_then(|v| v.to_str().ok())
+        .ok_or_else(|| invalid_range("A Content-Range header is required".to_string()))?;
+    let range = parse_content_range(raw).ok_or_else(|| {
+        invalid_range(format!(
+            "Expected `bytes <start>-<end>/<total>`, got '{raw}'"
+        ))
+    })?;
 
-    let file_info = UploadedFileInfo {
-        id: data.data.id,
-        name: data.data.name,
-        cid: data.data.cid,
-        group_id: data.data.group_id,
+    let total = match range {
+        ChunkRange::Bytes { total, .. } | ChunkRange::Finish { total } => total,
     };
+    if total != session.size_bytes {
+        return Err(invalid_range(format!(
+            "This session is for {} bytes, not {total}",
+            session.size_bytes
+        )));
+    }
+
+    if let ChunkRange::Bytes { start, end, .. } = range {
+        if start != session.received_bytes {
+            return Err(session_conflict(format!(
+                "The next chunk has to start at byte {}",
+                session.received_bytes
+            )));
+        }
+        if end >= session.size_bytes {
+            return Err(invalid_range(format!(
+                "The last byte of this file is {}",
+                session.size_bytes - 1
+            )));
+        }
+
+        let (written, result) =
+            upload_sessions::write_chunk(&id, start, end - start + 1, body).await;
+        upload_sessions::update(&id, |session| session.received_bytes = start + written)
+            .map_err(IntoResponse::into_response)?;
+        result.map_err(IntoResponse::into_response)?;
+    }
+
+    let session = upload_sessions::get(&id).ok_or_else(|| session_not_found(&id))?;
+    if session.received_bytes < session.size_bytes {
+        return Ok(session_response(session, None));
+    }
 
-    Ok(file_info)
+    let session = pin_session(&state, session)
+        .await
+        .map_err(IntoResponse::into_response)?;
+    Ok(session_response(
+        session,
+        Some("Upload complete".to_string()),
+    ))
 }
 
-async fn upload_to_pinata(
-    file_data: &[u8],
-    filename: &String,
-    metadata: &PhotoMetadata,
-    create_new_group: bool,
-    group_id: &Option<String>,
-    group_name: &Option<String>,
-) -> Result<UploadedFileInfo, ApiError> {
-    dotenv().ok();
-    let api_key = env::var("PINATA_JWT").map_err(|e| {
-        eprintln!("Failed to get PINATA_JWT: {e}");
-        ApiError::Env(e)
-    })?;
+// pin a session's assembled file; on failure it waits for another `bytes */<total>`
+async fn pin_session(state: &AppState, session: UploadSession) -> Result<UploadSession, ApiError> {
+    let id = session.id.clone();
+    upload_sessions::update(&id, |session| session.status = SessionStatus::Pinning)?;
 
-    // creat client, with retry abilities
-    let client = Client::builder()
-        .timeout(Duration::from_secs(60))
-        .build()
-        .map_err(|e| ApiError::Request(e))?;
-
-    let mut retries = 0;
-    let max_retries = 3;
-    let mut last_error = None;
-
-    // group creation
-    let created_group_id = if create_new_group {
-        if let Some(name) = group_name {
-            // create the group and get_id
-            match create_pinata_group(&client, &api_key, name).await {
-                Ok(id) => {
-                    println!("Created new group with ID: {}", id);
-                    Some(id)
-                }
-                Err(e) => {
-                    println!("Failed to create group: {:?}", e);
-                    return Err(e);
-                }
+    let pinned = async {
+        let options = UploadOptions {
+            create_new_group: session.create_new_group,
+            group_id: session.group_id.clone(),
+            group_name: session.group_name.clone(),
+            account: session.account.clone(),
+            source: session.source.clone(),
+            ..UploadOptions::default()
+        };
+        let target = resolve_target(state, &options, &state.config.upload).await?;
+        let data = upload_sessions::pinning_copy(&id, session.size_bytes)?;
+        let file = ReceivedFile {
+            filename: session.filename.clone(),
+            metadata: session.metadata.clone(),
+            validation: Duration::ZERO,
+        };
+        let info = upload_file(
+            state,
+            false,
+            &target,
+            target.group_resolution,
+            FileSource::Spooled(data, None),
+            file,
+        )
+        .await?;
+        target.finish();
+        Ok::<_, ApiError>(info)
+    };
+
+    match pinned.await {
+        Ok(info) => {
+            info!("Upload session {id} pinned as {}", info.id);
+            upload_sessions::complete(&id, info.id, info.cid)?;
+            state.catalog_changed();
+        }
+        Err(e) => {
+            warn!("Failed to pin upload session {id}: {e}");
+            upload_sessions::update(&id, |session| {
+                session.status = SessionStatus::Receiving;
+                session.message = Some(e.to_string());
+            })?;
+            return Err(e);
+        }
+    }
+
+    upload_sessions::get(&id)
+        .ok_or_else(|| ApiError::NotFound(format!("Upload session {id} vanished")))
+}
+
+fn capture_not_found(id: &str) -> Response {
+    error_response(
+        StatusCode::NOT_FOUND,
+        ErrorCode::NotFound,
+        "Capture session not found",
+        format!("No capture session {id}"),
+    )
+}
+
+fn capture_response(
+    session: CaptureSession,
+    message: Option<String>,
+) -> Json<CaptureSessionResponse> {
+    Json(CaptureSessionResponse {
+        success: true,
+        session,
+        message,
+    })
+}
+
+// bind a capture session to its group up front, so each frame is a single request
+async fn create_capture_session(
+    State(state): State<AppState>,
+    RequireRole(principal, _): RequireRole<Uploader>,
+    Json(request): Json<CreateCaptureSession>,
+) -> Result<Response, ApiError> {
+    PhotoAttributes::from(&request.defaults.metadata(String::new(), None)).validate()?;
+    let create_new_group = request.group_id.is_none();
+    if create_new_group && request.group_name.is_none() {
+        return Err(ApiError::Validation(
+            "A capture session needs a group_id, or a group_name to create one".to_string(),
+        ));
+    }
+
+    let options = UploadOptions {
+        create_new_group,
+        group_id: request.group_id,
+        group_name: request.group_name,
+        account: request.account,
+        ..UploadOptions::default()
+    };
+    options.check_allowed(&principal)?;
+    let target = resolve_target(&state, &options, &state.config.upload).await?;
+    // a capture session's group starts out empty
+    target.finish();
+    let group_id = target
+        .group_id
+        .ok_or_else(|| ApiError::NotFound("No group to capture into".to_string()))?;
+    if create_new_group {
+        state.catalog_changed();
+    }
+
+    let account = (target.account != state.accounts.primary()).then_some(target.account);
+    let session = capture_sessions::create(group_id, account, request.defaults)?;
+    info!(
+        "Opened capture session {} into group {}",
+        session.id, session.group_id
+    );
+    Ok((StatusCode::CREATED, capture_response(session, None)).into_response())
+}
+
+async fn capture_session_status(
+    Path(id): Path<String>,
+) -> Result<Json<CaptureSessionResponse>, Response> {
+    let session = capture_sessions::get(&id).ok_or_else(|| capture_not_found(&id))?;
+    Ok(capture_response(session, None))
+}
+
+// End a shoot in one call: sum up its frames, optionally make the best rated one the
+// group's cover, and announce it on WEBHOOK_NOTIFY_URL. Closing again changes nothing.
+async fn close_capture_session(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    body: Option<Json<CloseCaptureSession>>,
+) -> Result<Json<CaptureSessionResponse>, Response> {
+    let request = body.map(|Json(b)| b).unwrap_or_default();
+    let session = capture_sessions::get(&id).ok_or_else(|| capture_not_found(&id))?;
+    if session.summary.is_some() {
+        let message = "The session was already closed".to_string();
+        return Ok(capture_response(session, Some(message)));
+    }
+
+    let state = match &session.account {
+        Some(account) => state
+            .for_account(account)
+            .map_err(IntoResponse::into_response)?,
+        None => state,
+    };
+    let files = state
+        .list_files(
+            FilesQuery::new().group(&session.group_id),
+            ListOptions::default(),
+        )
+        .await
+        .map_err(IntoResponse::into_response)?;
+    let summary = capture_sessions::summarize(&session, &files, Utc::now(), request.auto_cover);
+    if let Some(cover) = &summary.cover_file_id {
+        group_covers::set(&session.group_id, cover).map_err(IntoResponse::into_response)?;
+        state.catalog_changed();
+    }
+
+    let session = capture_sessions::close(&id, summary)
+        .map_err(IntoResponse::into_response)?
+        .ok_or_else(|| capture_not_found(&id))?;
+    info!(
+        "Closed capture session {id} with {} photos in group {}",
+        session.photos, session.group_id
+    );
+    state.notifier.send(
+        "capture_session.closed",
+        serde_json::json!({
+            "session_id": session.id,
+            "group_id": session.group_id,
+            "summary": session.summary,
+        }),
+    );
+    Ok(capture_response(session, None))
+}
+
+// One frame as the raw request body, pinned with the session's defaults. Only the
+// filename, title and rating can be given per frame.
+async fn upload_capture_photo(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(params): Query<CapturePhotoParams>,
+    headers: HeaderMap,
+    body: axum::body::Body,
+) -> Result<Json<CapturePhotoResponse>, Response> {
+    let source = upload_source(&headers, "capture").map_err(IntoResponse::into_response)?;
+    let (frame, session) = capture_sessions::next_frame(&id)
+        .map_err(IntoResponse::into_response)?
+        .ok_or_else(|| capture_not_found(&id))?;
+
+    let config = &state.config.upload;
+    let data = spool_body(
+        body,
+        config.spool_threshold_bytes,
+        config.spool_dir.as_deref(),
+        config.max_file_bytes,
+    )
+    .await
+    .map_err(IntoResponse::into_response)?;
+    if data.is_empty() {
+        return Err(ApiError::Validation("The frame has no data".to_string()).into_response());
+    }
+
+    let non_empty = |value: Option<String>| {
+        value
+            .map(|v| v.trim().to_string())
+            .filter(|v| !v.is_empty())
+    };
+    let filename = non_empty(params.filename).unwrap_or_else(|| format!("frame-{frame:05}"));
+    let title = non_empty(params.title).unwrap_or_else(|| filename.clone());
+
+    let validation_started = Instant::now();
+    let metadata = session.defaults.metadata(title, params.rating);
+    PhotoAttributes::from(&metadata)
+        .validate()
+        .map_err(IntoResponse::into_response)?;
+    let file = ReceivedFile {
+        filename,
+        metadata,
+        validation: validation_started.elapsed(),
+    };
+
+    let target = UploadTarget {
+        policy: RetryPolicy::new(config, None, None),
+        group_id: Some(session.group_id.clone()),
+        created: None,
+        account: session
+            .account
+            .clone()
+            .unwrap_or_else(|| state.accounts.primary().to_string()),
+        group_resolution: Duration::ZERO,
+        settings: UploadSettings {
+            strip_exif: config.strip_exif,
+        },
+        source: Some(source),
+    };
+    let size_bytes = data.len();
+    let result = upload_file(
+        &state,
+        false,
+        &target,
+        Duration::ZERO,
+        FileSource::Spooled(data, None),
+        file,
+    )
+    .await;
+    capture_sessions::record(&id, size_bytes, result.as_ref());
+    let info = result.map_err(IntoResponse::into_response)?;
+    info!("Capture session {id} pinned frame {frame} as {}", info.id);
+    state.catalog_changed();
+
+    Ok(Json(CapturePhotoResponse {
+        success: true,
+        session_id: id,
+        frame,
+        file: info,
+        message: None,
+    }))
+}
+
+// one file's outcome; a failure doesn't stop the rest of the batch
+fn record_result(
+    uploaded: &mut Vec<UploadedFileInfo>,
+    failed: &mut Vec<UploadFailure>,
+    field: String,
+    filename: String,
+    result: Result<UploadedFileInfo, ApiError>,
+) {
+    match result {
+        Ok(info) => uploaded.push(info),
+        Err(e) => {
+            warn!("Failed to upload {filename} ({field}): {e}");
+            failed.push(UploadFailure {
+                field,
+                filename,
+                message: e.to_string(),
+                status: e.status(),
+            });
+        }
+    }
+}
+
+impl UploadTarget {
+    // the upload is over, and a group it created stays
+    fn finish(&self) {
+        if let Some(created) = &self.created {
+            created.finish();
+        }
+    }
+}
+
+// Create the requested group, or use the given one, for all files of this upload, in
+// the account named or else the one the group routes to
+async fn resolve_target(
+    state: &AppState,
+    options: &UploadOptions,
+    config: &UploadConfig,
+) -> Result<UploadTarget, ApiError> {
+    let accounts = &state.accounts;
+    let policy = options.policy(config);
+    info!(
+        "Upload policy: timeout {:?}, up to {} retries",
+        policy.timeout, policy.max_retries
+    );
+
+    let started = Instant::now();
+    let new_group = options
+        .group_name
+        .as_deref()
+        .filter(|_| options.create_new_group);
+    if options.create_new_group && new_group.is_none() {
+        return Err(ApiError::Validation(
+            "Group name is needed for new group creations".to_string(),
+        ));
+    }
+    let account = match (&options.account, new_group, &options.group_id) {
+        (Some(account), _, _) => account.clone(),
+        (None, Some(name), _) => accounts.for_new_group(name).to_string(),
+        (None, None, Some(group_id)) => accounts.for_group(group_id).await?.to_string(),
+        (None, None, None) => accounts.primary().to_string(),
+    };
+    let pinata = accounts.only(&account)?.client().clone();
+    if !accounts.is_single() {
+        info!("Uploading into Pinata account {account}");
+    }
+
+    let (group_id, created) = if let Some(name) = new_group {
+        // create the group and get_id
+        match create_pinata_group(&pinata, policy.timeout, name).await {
+            Ok(id) => {
+                info!("Created new group with ID: {}", id);
+                let created = state.partial_groups.track(&pinata, &id);
+                (Some(id), Some(created))
+            }
+            Err(e) => {
+                info!("Failed to create group: {:?}", e);
+                return Err(e);
             }
-        } else {
-            return Err(ApiError::Api(
-                "Group name is needed for new group creations".to_string(),
-            ));
         }
     } else {
-        group_id.clone()
+        (options.group_id.clone(), None)
     };
 
-    // On each retry, recreate multipart form for Pinata inside a closure
-    let create_form = || -> Result<reqwest::multipart::Form, ApiError> {
-        let mut form = reqwest::multipart::Form::new()
-            .text("network", "public")
-            .part(
-                "file",
-                reqwest::multipart::Part::bytes(file_data.to_vec())
-                    .file_name(filename.to_string())
-                    .mime_str("multipart/form-data")
-                    .map_err(|e| ApiError::Api(format!("Invalid MIME type: {}", e)))?,
-            )
-            .text("name", metadata.title.clone());
+    Ok(UploadTarget {
+        policy,
+        group_id,
+        created,
+        account,
+        group_resolution: started.elapsed(),
+        settings: options.settings(config),
+        source: options.source.clone(),
+    })
+}
 
-        if let Some(gid) = &created_group_id {
-            form = form.text("group_id", gid.clone());
+// Pin the receipt of what a batch published, in the account its files went to. They
+// stay pinned whatever happens to it, so a failure only costs the manifest.
+async fn pin_manifest(
+    state: &AppState,
+    target: &UploadTarget,
+    uploaded: &[UploadedFileInfo],
+) -> Option<String> {
+    let request_id = request_id::current();
+    let created_at = Utc::now();
+    let manifest = UploadManifest {
+        version: MANIFEST_VERSION,
+        created_at,
+        request_id: request_id.clone(),
+        account: target.account.clone(),
+        group_id: target.group_id.clone(),
+        files: uploaded.iter().filter_map(|f| f.manifest.clone()).collect(),
+    };
+
+    let pinned = async {
+        let state = state.for_account(&target.account)?;
+        let keyvalues = HashMap::from([(
+            UPLOAD_MANIFEST.to_string(),
+            request_id.unwrap_or_else(|| created_at.to_rfc3339()),
+        )]);
+        let name = format!(
+            "upload-manifest-{}.json",
+            created_at.format("%Y%m%dT%H%M%SZ")
+        );
+        files::pin_bytes(
+            &state.pinata,
+            target.policy.timeout,
+            &name,
+            "application/json",
+            serde_json::to_vec_pretty(&manifest)?,
+            &keyvalues,
+        )
+        .await
+    }
+    .await;
+
+    match pinned {
+        Ok(pinned) => {
+            info!(cid = %pinned.cid, "Pinned the manifest of {} files", manifest.files.len());
+            Some(pinned.cid)
+        }
+        Err(e) => {
+            warn!("Failed to pin the upload manifest: {e}");
+            None
         }
+    }
+}
 
-        // convert metadata into Pinata flat format
-        let mut keyvalues = HashMap::new();
-        keyvalues.insert("category".to_string(), metadata.category.clone());
+// pin one file and record how it went
+async fn upload_file(
+    state: &AppState,
+    debug_timing: bool,
+    target: &UploadTarget,
+    group_resolution: Duration,
+    source: FileSource<'_>,
+    file: ReceivedFile,
+) -> Result<UploadedFileInfo, ApiError> {
+    // everything from here, processing included, goes to the upload's account
+    let state = &state.for_account(&target.account)?;
+    let mut validation = file.validation;
+    let mut source = source;
+    // only images are pinned, and as the type their content says they are
+    let sniff_started = Instant::now();
+    let (mime, head) = match &mut source {
+        FileSource::Stream(field) => {
+            let head = read_head(field).await?;
+            (image_mime(&file.filename, &head.concat())?, head)
+        }
+        FileSource::Spooled(data, _) => {
+            let head = data.head(SNIFF_BYTES).await?;
+            (image_mime(&file.filename, &head)?, Vec::new())
+        }
+    };
+    validation += sniff_started.elapsed();
 
-        if !metadata.description.is_empty() {
-            keyvalues.insert("description".to_string(), metadata.description.clone());
+    let mut upload = UploadFile {
+        title: file.metadata.title.clone(),
+        attributes: PhotoAttributes::from(&file.metadata),
+        filename: file.filename,
+        mime,
+        metadata: file.metadata,
+        settings: target.settings,
+        stripped_exif_tags: Vec::new(),
+    };
+    if let Some(source) = &target.source {
+        let extra = &mut upload.attributes.extra;
+        extra.insert(UPLOADED_VIA.to_string(), source.via.clone());
+        if let Some(version) = &source.client_version {
+            extra.insert(CLIENT_VERSION.to_string(), version.clone());
         }
+    }
+    let mut stages = StageTimings {
+        group_resolution,
+        ..StageTimings::default()
+    };
+    let group_id = target.group_id.as_deref();
+    let pipeline = &state.upload_pipeline;
+    let hooks_started = Instant::now();
+    pipeline.pre_validate(state, &mut upload).await?;
+    validation += hooks_started.elapsed();
 
-        if !metadata.camera.is_empty() {
-            keyvalues.insert("camera".to_string(), metadata.camera.clone());
+    let started = Instant::now();
+    let (result, size_bytes, spooled, content_hash) = match source {
+        FileSource::Stream(field) => {
+            pipeline.pre_pin(state, &mut upload).await?;
+            let (result, size_bytes, content_hash) =
+                stream_to_pinata(state, *field, head, &upload, group_id, target.policy).await?;
+            // the content is only known once it's been sent, so a duplicate is unpinned again
+            let result = match result {
+                Ok(info) => keep_or_replace(state, &upload, info, &content_hash).await,
+                Err(e) => Err(e),
+            };
+            (result, size_bytes, None, content_hash)
         }
+        FileSource::Spooled(mut data, progress) => {
+            // suspicious files either fail here or are pinned with the finding recorded
+            let scan_started = Instant::now();
+            if let Some(finding) = scan_upload(&state.config.scan, &upload.filename, &data).await? {
+                upload
+                    .attributes
+                    .extra
+                    .insert("scan_flag".to_string(), finding);
+            }
+            validation += scan_started.elapsed();
+            pipeline.post_process(state, &mut upload, &mut data).await?;
 
-        if !metadata.lens.is_empty() {
-            keyvalues.insert("lens".to_string(), metadata.lens.clone());
+            let content_hash = data.sha256().await?;
+            let size_bytes = data.len();
+            if let Some(existing) = find_duplicate(state, &content_hash, None).await? {
+                (Ok(existing), size_bytes, None, content_hash)
+            } else {
+                upload
+                    .attributes
+                    .extra
+                    .insert(CONTENT_SHA256.to_string(), content_hash.clone());
+                pipeline.pre_pin(state, &mut upload).await?;
+                let result = upload_to_pinata(
+                    &state.pinata,
+                    &upload,
+                    &data,
+                    progress.as_ref(),
+                    group_id,
+                    target.policy,
+                    0,
+                )
+                .await;
+                (result, size_bytes, Some(data), content_hash)
+            }
         }
+    };
+    stages.upstream_upload = started.elapsed();
+    let total = validation + stages.group_resolution + stages.upstream_upload;
+
+    let outcome = match &result {
+        Ok(info) if info.duplicate => "duplicate",
+        Ok(_) => "success",
+        Err(_) => "failure",
+    };
+    counter!("upload_files_total", "result" => outcome).increment(1);
+    counter!("upload_bytes_total").increment(size_bytes);
+    histogram!("upload_file_size_bytes").record(size_bytes as f64);
+    record_stage("validation", validation);
+    record_stage("group_resolution", stages.group_resolution);
+    record_stage("upstream_upload", stages.upstream_upload);
+    record_stage("total", total);
+
+    let mut pinned = PinnedFile {
+        info: result?,
+        data: spooled,
+        watermark: None,
+    };
+    pinned.info.stripped_exif_tags = upload.stripped_exif_tags.clone();
+    pipeline.post_pin(state, &upload, &mut pinned).await;
+
+    let mut pinata_result = pinned.info;
+    pinata_result.manifest = Some(ManifestFile {
+        id: pinata_result.id.clone(),
+        name: pinata_result.name.clone(),
+        cid: pinata_result.cid.clone(),
+        group_id: pinata_result.group_id.clone(),
+        mime_type: upload.mime.to_string(),
+        size_bytes,
+        sha256: content_hash,
+        duplicate: pinata_result.duplicate,
+        metadata: upload.metadata,
+    });
+    if debug_timing {
+        pinata_result.timing = Some(FileTiming {
+            size_bytes,
+            validation_ms: millis(validation),
+            group_resolution_ms: millis(stages.group_resolution),
+            upstream_upload_ms: millis(stages.upstream_upload),
+            total_ms: millis(total),
+        });
+    }
+
+    // the file is already pinned, so a signing failure only costs the preview
+    let upload_config = &state.config.upload;
+    match gateway::preview_urls(
+        &state.pinata,
+        &pinata_result.cid,
+        Duration::from_secs(upload_config.preview_ttl_secs),
+        upload_config.preview_thumbnail_width,
+    )
+    .await
+    {
+        Ok(preview) => pinata_result.preview = preview,
+        Err(e) => warn!("Failed to sign preview urls for {}: {e}", pinata_result.id),
+    }
+
+    Ok(pinata_result)
+}
+
+// an already pinned original with the same content, other than `except`
+async fn find_duplicate(
+    state: &AppState,
+    content_hash: &str,
+    except: Option<&str>,
+) -> Result<Option<UploadedFileInfo>, ApiError> {
+    let files = state
+        .list_files(
+            FilesQuery::new().keyvalue_eq(CONTENT_SHA256, content_hash),
+            ListOptions {
+                limit: Some(2),
+                ..ListOptions::default()
+            },
+        )
+        .await?;
+    let Some(existing) = files.into_iter().find(|f| Some(f.id.as_str()) != except) else {
+        return Ok(None);
+    };
+
+    info!("Upload is a duplicate of {}", existing.id);
+    Ok(Some(UploadedFileInfo {
+        duplicate: true,
+        ..existing.into()
+    }))
+}
 
-        if !metadata.iso.is_empty() {
-            keyvalues.insert("iso".to_string(), metadata.iso.clone());
+// After a file was streamed: record its content hash, or when the same content was
+// already pinned, unpin the new copy and answer with the existing file instead.
+async fn keep_or_replace(
+    state: &AppState,
+    upload: &UploadFile,
+    info: UploadedFileInfo,
+    content_hash: &str,
+) -> Result<UploadedFileInfo, ApiError> {
+    if let Some(existing) = find_duplicate(state, content_hash, Some(&info.id)).await? {
+        if let Err(e) = files::delete_file(&state.pinata, &info.id).await {
+            warn!(
+                "Failed to unpin {}, a duplicate of {}: {e}",
+                info.id, existing.id
+            );
         }
+        return Ok(existing);
+    }
 
-        if !metadata.aperture.is_empty() {
-            keyvalues.insert("aperture".to_string(), metadata.aperture.clone());
+    let mut attributes = upload.attributes.clone();
+    attributes
+        .extra
+        .insert(CONTENT_SHA256.to_string(), content_hash.to_string());
+    // without it the next copy isn't recognised, but this one is pinned all the same
+    if let Err(e) =
+        files::update_file(&state.pinata, &info.id, None, &attributes.to_keyvalues()).await
+    {
+        warn!("Failed to record the content hash of {}: {e}", info.id);
+    }
+    Ok(info)
+}
+
+fn upload_form(
+    upload: &UploadFile,
+    file: Part,
+    group_id: Option<&str>,
+) -> Result<reqwest::multipart::Form, ApiError> {
+    let mut form = reqwest::multipart::Form::new()
+        .text("network", "public")
+        .part(
+            "file",
+            file.file_name(upload.filename.clone())
+                .mime_str(upload.mime)
+                .map_err(|e| ApiError::Api(format!("Invalid MIME type: {}", e)))?,
+        )
+        .text("name", upload.title.clone());
+
+    if let Some(gid) = group_id {
+        form = form.text("group_id", gid.to_string());
+    }
+
+    // convert metadata into Pinata flat format
+    let keyvalues = upload.attributes.to_keyvalues();
+
+    // add keyvalues to JSON
+    let keyvalues_json = serde_json::to_string(&keyvalues).map_err(ApiError::Json)?;
+    Ok(form.text("keyvalues", keyvalues_json))
+}
+
+// Forward a field to Pinata chunk by chunk. When retries are allowed the chunks are
+// also spooled, so a failed attempt can be re-sent without the client sending it again.
+async fn stream_to_pinata(
+    state: &AppState,
+    field: Field<'_>,
+    head: Vec<Bytes>,
+    upload: &UploadFile,
+    group_id: Option<&str>,
+    policy: RetryPolicy,
+) -> Result<(Result<UploadedFileInfo, ApiError>, u64, String), ApiError> {
+    let config = &state.config.upload;
+    let spooler = (policy.max_retries > 0)
+        .then(|| Spooler::new(config.spool_threshold_bytes, config.spool_dir.as_deref()));
+
+    let (tx, rx) = mpsc::channel(STREAM_BUFFER_CHUNKS);
+    let body = Body::wrap_stream(ReceiverStream::new(rx));
+    let form = upload_form(upload, Part::stream(body), group_id)?;
+
+    let pump = async move {
+        let result = pump_field(field, head, spooler, &tx, config.max_file_bytes).await;
+        // fail the outbound body too, so Pinata never pins a partial file
+        if let Err(e) = &result {
+            let _ = tx.send(Err(std::io::Error::other(e.to_string()))).await;
         }
+        result
+    };
+
+    let (sent, received) = tokio::join!(
+        files::pin_form(&state.pinata, policy.timeout, form, 0),
+        pump
+    );
+    let (size_bytes, spooled, content_hash) = received?;
+    info!("Streamed {size_bytes} bytes of {}", upload.filename);
 
-        if !metadata.shutter_speed.is_empty() {
-            keyvalues.insert("shutterSpeed".to_string(), metadata.shutter_speed.clone());
+    let result = match (sent, spooled) {
+        (Err(e), Some(data)) => {
+            let mut retries = 0;
+            if backoff(&e, &mut retries, policy).await {
+                upload_to_pinata(
+                    &state.pinata,
+                    upload,
+                    &data,
+                    None,
+                    group_id,
+                    policy,
+                    retries,
+                )
+                .await
+            } else {
+                Err(e)
+            }
         }
+        (sent, _) => sent,
+    };
+    Ok((result, size_bytes, content_hash))
+}
 
-        // add keyvalues to JSON
-        let keyvalues_json = serde_json::to_string(&keyvalues).map_err(|e| ApiError::Json(e))?;
-        let form = form.text("keyvalues", keyvalues_json);
+// read at least SNIFF_BYTES of a field, unless it's shorter
+async fn read_head(field: &mut Field<'_>) -> Result<Vec<Bytes>, ApiError> {
+    let mut head = Vec::new();
+    let mut len = 0;
+    while len < SNIFF_BYTES
+        && let Some(chunk) = field.chunk().await.map_err(read_error)?
+    {
+        len += chunk.len();
+        head.push(chunk);
+    }
+    Ok(head)
+}
+
+// copy a field into the outbound body after the `head` already read from it,
+// returning its size, the spooled copy if any and the hex SHA-256 of it
+async fn pump_field(
+    mut field: Field<'_>,
+    head: Vec<Bytes>,
+    mut spooler: Option<Spooler<'_>>,
+    tx: &mpsc::Sender<Result<Bytes, std::io::Error>>,
+    max_bytes: u64,
+) -> Result<(u64, Option<SpooledFile>, String), ApiError> {
+    let mut len = 0u64;
+    let mut hasher = Sha256::new();
+    let mut head = head.into_iter();
+
+    loop {
+        let chunk = match head.next() {
+            Some(chunk) => chunk,
+            None => match field.chunk().await.map_err(read_error)? {
+                Some(chunk) => chunk,
+                None => break,
+            },
+        };
+        len += chunk.len() as u64;
+        if len > max_bytes {
+            return Err(too_large(max_bytes));
+        }
+        hasher.update(&chunk);
+        if let Some(spooler) = &mut spooler {
+            spooler.push(&chunk).await?;
+        }
+        // the request may already have failed; keep reading so the retry has the whole file
+        let _ = tx.send(Ok(chunk)).await;
+    }
 
-        Ok(form)
+    let spooled = match spooler {
+        Some(spooler) => Some(spooler.finish().await?),
+        None => None,
     };
+    Ok((len, spooled, format!("{:x}", hasher.finalize())))
+}
+
+// wait out the backoff before the next attempt, or return false when `e` isn't worth retrying
+async fn backoff(e: &ApiError, retries: &mut u32, policy: RetryPolicy) -> bool {
+    // Only retry on certain error types
+    match e {
+        ApiError::Request(req_err)
+            if (req_err.is_timeout() || req_err.is_connect()) && *retries < policy.max_retries =>
+        {
+            // Network error, retry
+            *retries += 1;
+            let delay = 2u64.pow(*retries) * 1000; // Exponential backoff
+            warn!(
+                "Retrying Pinata upload after {}ms (retry {}/{}): {e}",
+                delay, retries, policy.max_retries
+            );
+            tokio::time::sleep(Duration::from_millis(delay)).await;
+            true
+        }
+        _ => false, // Non-retryable error, or out of retries
+    }
+}
 
-    while retries < max_retries {
+// send a received file, re-sending it from the start on each retry
+async fn upload_to_pinata(
+    pinata: &PinataClient,
+    upload: &UploadFile,
+    data: &SpooledFile,
+    progress: Option<&Progress>,
+    group_id: Option<&str>,
+    policy: RetryPolicy,
+    mut retries: u32,
+) -> Result<UploadedFileInfo, ApiError> {
+    loop {
         // Create a new form for each attempt
-        let form = create_form()?;
+        let form = upload_form(upload, data.to_part(progress)?, group_id)?;
 
-        match send_pinata_request(&client, &api_key, form).await {
+        match files::pin_form(pinata, policy.timeout, form, retries).await {
             Ok(result) => return Ok(result),
             Err(e) => {
-                // Only retry on certain error types
-                match &e {
-                    ApiError::Request(req_err) if req_err.is_timeout() || req_err.is_connect() => {
-                        // Network error, retry
-                        retries += 1;
-                        let delay = 2u64.pow(retries as u32) * 1000; // Exponential backoff
-                        eprintln!(
-                            "Retrying Pinata upload after {}ms (attempt {}/{})",
-                            delay, retries, max_retries
-                        );
-                        tokio::time::sleep(Duration::from_millis(delay)).await;
-                        last_error = Some(e);
-                    }
-                    _ => return Err(e), // Non-retryable error
+                if !backoff(&e, &mut retries, policy).await {
+                    return Err(e);
                 }
             }
         }
     }
-
-    // If we got here, all retries failed
-    Err(last_error.unwrap_or_else(|| ApiError::Api("Maximum retries exceeded".to_string())))
 }
 
 async fn create_pinata_group(
-    client: &Client,
-    api_key: &str,
+    pinata: &PinataClient,
+    timeout: Duration,
     group_name: &str,
 ) -> Result<String, ApiError> {
-    println!("Creating new Pinata group: {}", group_name);
+    debug!("Creating new Pinata group: {}", group_name);
 
     // group creation payload
     let group_payload = serde_json::json!({
-        "name": group_name,
+        "name": groups::namespaced_name(pinata.group_namespace(), group_name),
         "is_public": true
     });
 
-    let response = client
-        .post("https://api.pinata.cloud/groups")
-        .header("Authorization", format!("Bearer {}", api_key))
+    let response = pinata
+        .request(Method::POST, format!("{}/groups", pinata.api_url()))
+        .timeout(timeout)
         .json(&group_payload)
-        .send()
+        .send_traced()
         .await
-        .map_err(|e| ApiError::Request(e))?;
+        .map_err(ApiError::Request)?;
+    rate_limit::observe(response.headers());
 
     let status = response.status();
 
     if !status.is_success() {
-        let error_body = response.text().await?;
+        let error_body = read_text(response).await?;
         return Err(ApiError::Api(format!(
             "Pinata API error ({}): {}",
             status, error_body
         )));
     }
 
-    let data: GroupCreationResponse = response.json().await.map_err(|e| ApiError::Request(e))?;
-    println!("Group creation response: {:?}", data);
+    let data: GroupCreationResponse = read_json(response).await?;
+    debug!(group_id = %data.id, "Pinata created group {}", data.name);
 
     Ok(data.id)
 }
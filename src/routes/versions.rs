@@ -0,0 +1,65 @@
+use axum::{Json, Router, extract::State, routing::get};
+use chrono::Utc;
+
+use crate::models::versions::{ApiVersion, ApiVersionsResponse, VersionStatus};
+use crate::state::AppState;
+
+// Versions of the API, oldest first, each served under `/api/{version}`. v1's routes
+// are the unprefixed ones; a new version's are nested under its own route prefix,
+// e.g. `/v2`, and it's added here.
+pub const VERSIONS: &[&str] = &["v1"];
+
+pub fn versions_router() -> Router<AppState> {
+    Router::new().route("/api/versions", get(list_versions))
+}
+
+// where a version's routes are mounted, which `/api/{version}` is routed to
+pub fn route_prefix(version: &str) -> String {
+    if version == VERSIONS[0] {
+        String::new()
+    } else {
+        format!("/{version}")
+    }
+}
+
+// the version a routed path belongs to
+pub fn version_of(path: &str) -> &'static str {
+    VERSIONS
+        .iter()
+        .skip(1)
+        .find(|&&version| {
+            path.strip_prefix(&route_prefix(version))
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+        })
+        .copied()
+        .unwrap_or(VERSIONS[0])
+}
+
+async fn list_versions(State(state): State<AppState>) -> Json<ApiVersionsResponse> {
+    let now = Utc::now();
+    let versions = VERSIONS
+        .iter()
+        .map(|&version| {
+            let deprecation = state.config.api.deprecations.get(version);
+            let status = match deprecation {
+                None => VersionStatus::Supported,
+                Some(d) if d.sunset_at.is_some_and(|sunset| sunset <= now) => VersionStatus::Sunset,
+                Some(_) => VersionStatus::Deprecated,
+            };
+            ApiVersion {
+                version,
+                prefix: format!("/api/{version}"),
+                status,
+                deprecated_at: deprecation.map(|d| d.deprecated_at),
+                sunset_at: deprecation.and_then(|d| d.sunset_at),
+                successor: deprecation.and_then(|d| d.successor.clone()),
+            }
+        })
+        .collect();
+
+    Json(ApiVersionsResponse {
+        success: true,
+        current: VERSIONS[VERSIONS.len() - 1],
+        versions,
+    })
+}
@@ -0,0 +1,161 @@
+use axum::{
+    Json, Router,
+    body::Bytes,
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::post,
+};
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use metrics::counter;
+use sha2::Sha256;
+use std::sync::Mutex;
+use tracing::info;
+
+use crate::errors::{ApiError, ErrorCode, error_response};
+use crate::integrity;
+use crate::models::webhooks::{WebhookEvent, WebhookResponse};
+use crate::pinata::files;
+use crate::state::AppState;
+
+const SIGNATURE_HEADER: &str = "x-webhook-signature";
+const TIMESTAMP_HEADER: &str = "x-webhook-timestamp";
+
+// when the last correctly signed delivery arrived, reported by /readyz
+static LAST_DELIVERY: Mutex<Option<DateTime<Utc>>> = Mutex::new(None);
+
+pub fn last_delivery_at() -> Option<DateTime<Utc>> {
+    *LAST_DELIVERY.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+pub fn webhooks_router() -> Router<AppState> {
+    Router::new().route("/webhooks/pinata", post(receive_pinata_webhook))
+}
+
+// Deliveries are signed as `x-webhook-signature: sha256=<hex>`, the HMAC-SHA256 of
+// `<x-webhook-timestamp>.<raw body>` with WEBHOOK_SECRET
+fn verify_signature(
+    secret: &str,
+    tolerance_secs: u64,
+    headers: &HeaderMap,
+    body: &[u8],
+) -> Result<(), &'static str> {
+    let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+
+    let timestamp = header(TIMESTAMP_HEADER).ok_or("Missing timestamp header")?;
+    let sent_at: i64 = timestamp
+        .parse()
+        .map_err(|_| "Malformed timestamp header")?;
+    if Utc::now().timestamp().abs_diff(sent_at) > tolerance_secs {
+        return Err("Timestamp outside the allowed window");
+    }
+
+    let signature = header(SIGNATURE_HEADER)
+        .and_then(|s| s.strip_prefix("sha256="))
+        .and_then(decode_hex)
+        .ok_or("Missing or malformed signature header")?;
+
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).map_err(|_| "Invalid secret")?;
+    mac.update(timestamp.as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    mac.verify_slice(&signature)
+        .map_err(|_| "Signature does not match")
+}
+
+fn decode_hex(raw: &str) -> Option<Vec<u8>> {
+    if !raw.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..raw.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(raw.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+// keep the local catalog in step with changes made outside this backend, e.g. in the Pinata dashboard
+async fn receive_pinata_webhook(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let webhooks = &state.config.webhooks;
+    let Some(secret) = webhooks.secret.as_deref() else {
+        return error_response(
+            StatusCode::FORBIDDEN,
+            ErrorCode::Forbidden,
+            "Webhooks disabled",
+            "Set WEBHOOK_SECRET to accept webhook deliveries".to_string(),
+        );
+    };
+
+    if let Err(reason) = verify_signature(secret, webhooks.tolerance_secs, &headers, &body) {
+        counter!("webhook_events_total", "result" => "rejected").increment(1);
+        return error_response(
+            StatusCode::UNAUTHORIZED,
+            ErrorCode::Unauthorized,
+            "Invalid webhook signature",
+            reason.to_string(),
+        );
+    }
+    *LAST_DELIVERY.lock().unwrap_or_else(|e| e.into_inner()) = Some(Utc::now());
+
+    let event: WebhookEvent = match serde_json::from_slice(&body) {
+        Ok(event) => event,
+        Err(e) => {
+            return error_response(
+                StatusCode::BAD_REQUEST,
+                ErrorCode::ValidationFailed,
+                "Invalid webhook payload",
+                e.to_string(),
+            );
+        }
+    };
+
+    match apply_event(&state, &event).await {
+        Ok(action) => {
+            state.cache.invalidate_all();
+            integrity::upstream_changed(&state);
+            info!("Webhook {}: {action}", event.event);
+            counter!("webhook_events_total", "result" => action).increment(1);
+            Json(WebhookResponse {
+                success: true,
+                event: event.event,
+                action,
+                message: None,
+            })
+            .into_response()
+        }
+        Err(e) => {
+            counter!("webhook_events_total", "result" => "failed").increment(1);
+            // fall back to a full resync so the change isn't lost
+            state.catalog_changed_upstream();
+            e.into_response()
+        }
+    }
+}
+
+async fn apply_event(state: &AppState, event: &WebhookEvent) -> Result<&'static str, ApiError> {
+    let Some(db) = &state.db else {
+        return Ok("ignored");
+    };
+
+    match (event.event.as_str(), event.data.id.as_deref()) {
+        ("file.deleted", Some(id)) => {
+            db.remove_file(id).await?;
+            Ok("removed")
+        }
+        ("file.created" | "file.updated", Some(id)) => {
+            let file = files::get_file(&state.pinata, id).await?;
+            db.upsert_file(&file).await?;
+            Ok("updated")
+        }
+        // group changes and anything unrecognised: reconcile everything
+        _ => {
+            state.catalog_changed_upstream();
+            Ok("resync")
+        }
+    }
+}
@@ -0,0 +1,180 @@
+use metrics::counter;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tracing::warn;
+
+use crate::config::{ScanAction, ScanConfig, ScanMode};
+use crate::errors::ApiError;
+use crate::spool::SpooledFile;
+
+// chunk size for clamd INSTREAM, well under clamd's default StreamMaxLength
+const CHUNK_SIZE: usize = 64 * 1024;
+// entropy is only meaningful once there's enough data to judge
+const MIN_ENTROPY_SAMPLE: u64 = 64 * 1024;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScanVerdict {
+    Clean,
+    Suspicious(String),
+}
+
+// Run the configured checks on an upload. Returns the finding to record when a
+// suspicious file is let through in flag mode, and an error when it's rejected.
+pub async fn scan_upload(
+    config: &ScanConfig,
+    filename: &str,
+    file: &SpooledFile,
+) -> Result<Option<String>, ApiError> {
+    if config.mode == ScanMode::Off {
+        return Ok(None);
+    }
+
+    let verdict = match sanity_check(config, file).await? {
+        ScanVerdict::Clean if config.mode == ScanMode::Clamd => clamd_scan(config, file).await?,
+        verdict => verdict,
+    };
+
+    match verdict {
+        ScanVerdict::Clean => {
+            counter!("upload_scan_total", "verdict" => "clean").increment(1);
+            Ok(None)
+        }
+        ScanVerdict::Suspicious(reason) => {
+            warn!("Upload scan flagged {filename}: {reason}");
+            match config.action {
+                ScanAction::Reject => {
+                    counter!("upload_scan_total", "verdict" => "rejected").increment(1);
+                    Err(ApiError::Api(format!(
+                        "File {filename} was rejected by the upload scanner: {reason}"
+                    )))
+                }
+                ScanAction::Flag => {
+                    counter!("upload_scan_total", "verdict" => "flagged").increment(1);
+                    Ok(Some(reason))
+                }
+            }
+        }
+    }
+}
+
+// Shannon entropy in bits per byte
+fn entropy(counts: &[u64; 256], total: u64) -> f64 {
+    counts
+        .iter()
+        .filter(|&&c| c > 0)
+        .map(|&c| {
+            let p = c as f64 / total as f64;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+async fn sanity_check(config: &ScanConfig, file: &SpooledFile) -> Result<ScanVerdict, ApiError> {
+    let len = file.len();
+    if len == 0 {
+        return Ok(ScanVerdict::Suspicious("file is empty".to_string()));
+    }
+    if len > config.max_file_bytes {
+        return Ok(ScanVerdict::Suspicious(format!(
+            "file is {len} bytes, over the {} byte scan limit",
+            config.max_file_bytes
+        )));
+    }
+    if len < MIN_ENTROPY_SAMPLE {
+        return Ok(ScanVerdict::Clean);
+    }
+
+    // images are compressed and close to 8 bits/byte; long runs of padding are not
+    let mut counts = [0u64; 256];
+    let mut reader = file.reader()?;
+    let mut buffer = vec![0u8; CHUNK_SIZE];
+    loop {
+        let read = reader.read(&mut buffer).await.map_err(scan_io_error)?;
+        if read == 0 {
+            break;
+        }
+        for &byte in &buffer[..read] {
+            counts[byte as usize] += 1;
+        }
+    }
+
+    let bits = entropy(&counts, len);
+    if bits < config.min_entropy {
+        return Ok(ScanVerdict::Suspicious(format!(
+            "content entropy {bits:.2} bits/byte is below {:.2}",
+            config.min_entropy
+        )));
+    }
+
+    Ok(ScanVerdict::Clean)
+}
+
+fn scan_io_error(e: std::io::Error) -> ApiError {
+    ApiError::Api(format!("Upload scan failed: {e}"))
+}
+
+async fn clamd_scan(config: &ScanConfig, file: &SpooledFile) -> Result<ScanVerdict, ApiError> {
+    let address = config.clamd_address.as_str();
+
+    #[cfg(unix)]
+    if address.starts_with('/') {
+        let stream = tokio::net::UnixStream::connect(address)
+            .await
+            .map_err(scan_io_error)?;
+        return instream(stream, file).await;
+    }
+
+    let stream = tokio::net::TcpStream::connect(address)
+        .await
+        .map_err(scan_io_error)?;
+    instream(stream, file).await
+}
+
+// clamd's INSTREAM protocol: length-prefixed chunks, then a zero-length terminator
+async fn instream<S>(mut stream: S, file: &SpooledFile) -> Result<ScanVerdict, ApiError>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    stream
+        .write_all(b"zINSTREAM\0")
+        .await
+        .map_err(scan_io_error)?;
+
+    let mut reader = file.reader()?;
+    let mut buffer = vec![0u8; CHUNK_SIZE];
+    loop {
+        let read = reader.read(&mut buffer).await.map_err(scan_io_error)?;
+        if read == 0 {
+            break;
+        }
+        stream
+            .write_all(&(read as u32).to_be_bytes())
+            .await
+            .map_err(scan_io_error)?;
+        stream
+            .write_all(&buffer[..read])
+            .await
+            .map_err(scan_io_error)?;
+    }
+    stream
+        .write_all(&0u32.to_be_bytes())
+        .await
+        .map_err(scan_io_error)?;
+
+    let mut reply = Vec::new();
+    stream
+        .read_to_end(&mut reply)
+        .await
+        .map_err(scan_io_error)?;
+    let reply = String::from_utf8_lossy(&reply);
+    let reply = reply.trim_end_matches('\0').trim();
+
+    // e.g. `stream: OK` or `stream: Eicar-Test-Signature FOUND`
+    match reply.strip_prefix("stream:").map(str::trim) {
+        Some("OK") => Ok(ScanVerdict::Clean),
+        Some(found) if found.ends_with("FOUND") => Ok(ScanVerdict::Suspicious(format!(
+            "clamd found {}",
+            found.trim_end_matches("FOUND").trim()
+        ))),
+        _ => Err(ApiError::Api(format!("Unexpected clamd reply: {reply}"))),
+    }
+}
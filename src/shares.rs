@@ -0,0 +1,166 @@
+use std::collections::BTreeMap;
+use std::sync::LazyLock;
+
+use chrono::Utc;
+use rand::RngCore;
+use tracing::{Instrument, info_span, warn};
+
+use crate::errors::ApiError;
+use crate::group_notifications::GroupEvent;
+use crate::models::shares::{ShareLink, SharePolicy};
+use crate::notify::Notifier;
+use crate::store::JsonStore;
+
+// how often links are checked for having expired since the last look
+const EXPIRY_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+
+// share links by token
+static SHARES: LazyLock<JsonStore<BTreeMap<String, ShareLink>>> =
+    LazyLock::new(|| JsonStore::open("share_links"));
+
+fn random_token() -> String {
+    let mut buf = [0u8; 16];
+    rand::rng().fill_bytes(&mut buf);
+    buf.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+pub fn create(
+    group_id: &str,
+    label: Option<String>,
+    expires_in_secs: Option<u64>,
+    policy: SharePolicy,
+) -> Result<ShareLink, ApiError> {
+    let now = Utc::now();
+    let share = ShareLink {
+        token: random_token(),
+        group_id: group_id.to_string(),
+        label: label
+            .map(|l| l.trim().to_string())
+            .filter(|l| !l.is_empty()),
+        created_at: now,
+        expires_at: expires_in_secs.map(|secs| now + chrono::Duration::seconds(secs as i64)),
+        revoked_at: None,
+        policy,
+        usage: Default::default(),
+        expiry_noticed_at: None,
+    };
+
+    SHARES.update(|shares| shares.insert(share.token.clone(), share.clone()))?;
+    Ok(share)
+}
+
+// every link ever issued, newest first
+pub fn list() -> Vec<ShareLink> {
+    let mut shares: Vec<ShareLink> = SHARES.read(|shares| shares.values().cloned().collect());
+    shares.sort_by_key(|share| std::cmp::Reverse(share.created_at));
+    shares
+}
+
+pub fn revoke(token: &str) -> Result<Option<ShareLink>, ApiError> {
+    SHARES.update(|shares| {
+        let share = shares.get_mut(token)?;
+        share.revoked_at.get_or_insert_with(Utc::now);
+        Some(share.clone())
+    })
+}
+
+// the link behind a token, if it still grants access
+pub fn resolve(token: &str) -> Option<ShareLink> {
+    SHARES
+        .read(|shares| shares.get(token).cloned())
+        .filter(|share| share.is_active(Utc::now()))
+}
+
+pub fn get(token: &str) -> Option<ShareLink> {
+    SHARES.read(|shares| shares.get(token).cloned())
+}
+
+// count a gallery visit, returning the link when it still grants access
+pub fn record_view(token: &str) -> Result<Option<ShareLink>, ApiError> {
+    SHARES.update(|shares| {
+        let now = Utc::now();
+        let share = shares.get_mut(token).filter(|share| share.is_active(now))?;
+        share.usage.views += 1;
+        share.usage.last_accessed_at = Some(now);
+        Some(share.clone())
+    })
+}
+
+#[derive(Debug)]
+pub enum DownloadGrant {
+    Granted(ShareLink),
+    LimitReached,
+    NotFound,
+}
+
+// take one download off the link's allowance, if it has any left
+pub fn claim_download(token: &str, file_id: &str) -> Result<DownloadGrant, ApiError> {
+    SHARES.update(|shares| {
+        let now = Utc::now();
+        let Some(share) = shares.get_mut(token).filter(|share| share.is_active(now)) else {
+            return DownloadGrant::NotFound;
+        };
+        if share.remaining_downloads() == Some(0) {
+            return DownloadGrant::LimitReached;
+        }
+
+        share.usage.downloads += 1;
+        *share.usage.files.entry(file_id.to_string()).or_default() += 1;
+        share.usage.last_accessed_at = Some(now);
+        DownloadGrant::Granted(share.clone())
+    })
+}
+
+// links that ran out since the last call, each reported once; revoked ones weren't
+// left to expire
+fn take_expired() -> Result<Vec<ShareLink>, ApiError> {
+    SHARES.update(|shares| {
+        let now = Utc::now();
+        shares
+            .values_mut()
+            .filter(|share| {
+                share.revoked_at.is_none()
+                    && share.expiry_noticed_at.is_none()
+                    && share.expires_at.is_some_and(|expires| expires <= now)
+            })
+            .map(|share| {
+                share.expiry_noticed_at = Some(now);
+                share.clone()
+            })
+            .collect()
+    })
+}
+
+// tell the groups that want to hear of it when one of their links expires
+pub fn spawn_expiry_notices(notifier: Notifier) {
+    let span = info_span!("share_expiry");
+    tokio::spawn(
+        async move {
+            let mut interval = tokio::time::interval(EXPIRY_CHECK_INTERVAL);
+            loop {
+                interval.tick().await;
+                let expired = match take_expired() {
+                    Ok(expired) => expired,
+                    Err(e) => {
+                        warn!("Failed to check share links for expiry: {e}");
+                        continue;
+                    }
+                };
+                for share in expired {
+                    notifier.send_for_group(
+                        Some(&share.group_id),
+                        GroupEvent::ShareExpiry,
+                        serde_json::json!({
+                            "token": share.token,
+                            "group_id": share.group_id,
+                            "label": share.label,
+                            "expires_at": share.expires_at,
+                            "usage": share.usage,
+                        }),
+                    );
+                }
+            }
+        }
+        .instrument(span),
+    );
+}
@@ -0,0 +1,284 @@
+use std::io::Cursor;
+use std::path::Path;
+use std::sync::Arc;
+
+use axum::extract::multipart::{Field, MultipartError};
+use axum::http::StatusCode;
+use reqwest::{Body, multipart::Part};
+use sha2::{Digest, Sha256};
+use tempfile::NamedTempFile;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tokio_stream::StreamExt;
+use tokio_util::io::ReaderStream;
+
+use crate::errors::ApiError;
+
+// told how many bytes of an attempt have been handed to the outbound request so far
+pub type Progress = Arc<dyn Fn(u64) + Send + Sync>;
+
+// chunk size when progress is reported, so a report isn't made every few kilobytes
+const PROGRESS_CHUNK_BYTES: usize = 64 * 1024;
+
+// An uploaded file held in memory while small, or in a temp file once it
+// grows past the spool threshold. The temp file is removed when this is dropped.
+#[derive(Debug)]
+pub enum SpooledFile {
+    Memory(Vec<u8>),
+    Disk { file: NamedTempFile, len: u64 },
+}
+
+impl SpooledFile {
+    pub fn len(&self) -> u64 {
+        match self {
+            Self::Memory(data) => data.len() as u64,
+            Self::Disk { len, .. } => *len,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn is_on_disk(&self) -> bool {
+        matches!(self, Self::Disk { .. })
+    }
+
+    // read the contents from the start
+    pub fn reader(&self) -> Result<Box<dyn AsyncRead + Send + Unpin + '_>, ApiError> {
+        match self {
+            Self::Memory(data) => Ok(Box::new(data.as_slice())),
+            Self::Disk { file, .. } => {
+                let reader = file.reopen().map_err(|e| {
+                    ApiError::Internal(format!("Failed to reopen spooled file: {e}"))
+                })?;
+                Ok(Box::new(tokio::fs::File::from_std(reader)))
+            }
+        }
+    }
+
+    // up to the first `n` bytes, e.g. to tell what kind of file it is
+    pub async fn head(&self, n: usize) -> Result<Vec<u8>, ApiError> {
+        if let Self::Memory(data) = self {
+            return Ok(data[..n.min(data.len())].to_vec());
+        }
+        let mut head = Vec::with_capacity(n);
+        self.reader()?
+            .take(n as u64)
+            .read_to_end(&mut head)
+            .await
+            .map_err(|e| ApiError::Internal(format!("Failed to read spooled file: {e}")))?;
+        Ok(head)
+    }
+
+    // hex SHA-256 of the contents
+    pub async fn sha256(&self) -> Result<String, ApiError> {
+        let mut reader = self.reader()?;
+        let mut hasher = Sha256::new();
+        let mut buffer = vec![0; PROGRESS_CHUNK_BYTES];
+        loop {
+            let read = reader
+                .read(&mut buffer)
+                .await
+                .map_err(|e| ApiError::Internal(format!("Failed to read spooled file: {e}")))?;
+            if read == 0 {
+                break;
+            }
+            hasher.update(&buffer[..read]);
+        }
+        Ok(format!("{:x}", hasher.finalize()))
+    }
+
+    // Let `f` change the contents in place, without changing their length. The
+    // contents are written back to disk only when `f` says it changed something.
+    pub async fn modify<R>(
+        &mut self,
+        f: impl FnOnce(&mut [u8]) -> Result<(R, bool), ApiError>,
+    ) -> Result<R, ApiError> {
+        let file = match self {
+            Self::Memory(data) => return f(data).map(|(result, _)| result),
+            Self::Disk { file, .. } => file,
+        };
+
+        let mut data = tokio::fs::read(file.path()).await.map_err(spool_error)?;
+        let (result, changed) = f(&mut data)?;
+        if changed {
+            tokio::fs::write(file.path(), &data)
+                .await
+                .map_err(spool_error)?;
+        }
+        Ok(result)
+    }
+
+    // a fresh multipart part over the contents, so each retry can re-send it
+    pub fn to_part(&self, progress: Option<&Progress>) -> Result<Part, ApiError> {
+        if let Some(progress) = progress {
+            return self.counted_part(progress.clone());
+        }
+
+        match self {
+            Self::Memory(data) => Ok(Part::bytes(data.clone())),
+            Self::Disk { file, len } => {
+                let reader = file.reopen().map_err(|e| {
+                    ApiError::Internal(format!("Failed to reopen spooled file: {e}"))
+                })?;
+                let body = Body::from(tokio::fs::File::from_std(reader));
+                Ok(Part::stream_with_length(body, *len))
+            }
+        }
+    }
+
+    fn counted_part(&self, progress: Progress) -> Result<Part, ApiError> {
+        let reader: Box<dyn AsyncRead + Send + Unpin> = match self {
+            Self::Memory(data) => Box::new(Cursor::new(data.clone())),
+            Self::Disk { file, .. } => {
+                let reader = file.reopen().map_err(|e| {
+                    ApiError::Internal(format!("Failed to reopen spooled file: {e}"))
+                })?;
+                Box::new(tokio::fs::File::from_std(reader))
+            }
+        };
+
+        let mut sent = 0;
+        let stream = ReaderStream::with_capacity(reader, PROGRESS_CHUNK_BYTES).map(move |chunk| {
+            if let Ok(chunk) = &chunk {
+                sent += chunk.len() as u64;
+                progress(sent);
+            }
+            chunk
+        });
+        Ok(Part::stream_with_length(
+            Body::wrap_stream(stream),
+            self.len(),
+        ))
+    }
+}
+
+fn spool_error(e: std::io::Error) -> ApiError {
+    ApiError::Internal(format!("Failed to spool upload to disk: {e}"))
+}
+
+// Collects an upload chunk by chunk, moving to disk once it exceeds `threshold` bytes
+pub struct Spooler<'a> {
+    threshold: u64,
+    dir: Option<&'a Path>,
+    buffer: Vec<u8>,
+    disk: Option<(NamedTempFile, tokio::fs::File)>,
+    len: u64,
+}
+
+impl<'a> Spooler<'a> {
+    pub fn new(threshold: u64, dir: Option<&'a Path>) -> Self {
+        Self {
+            threshold,
+            dir,
+            buffer: Vec::new(),
+            disk: None,
+            len: 0,
+        }
+    }
+
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub async fn push(&mut self, chunk: &[u8]) -> Result<(), ApiError> {
+        self.len += chunk.len() as u64;
+
+        if let Some((_, writer)) = &mut self.disk {
+            return writer.write_all(chunk).await.map_err(spool_error);
+        }
+
+        self.buffer.extend_from_slice(chunk);
+        if self.len > self.threshold {
+            let file = match self.dir {
+                Some(dir) => NamedTempFile::new_in(dir),
+                None => NamedTempFile::new(),
+            }
+            .map_err(spool_error)?;
+
+            let mut writer = tokio::fs::File::from_std(file.reopen().map_err(spool_error)?);
+            writer.write_all(&self.buffer).await.map_err(spool_error)?;
+            self.buffer = Vec::new();
+            self.disk = Some((file, writer));
+        }
+        Ok(())
+    }
+
+    pub async fn finish(self) -> Result<SpooledFile, ApiError> {
+        match self.disk {
+            Some((file, mut writer)) => {
+                writer.flush().await.map_err(spool_error)?;
+                Ok(SpooledFile::Disk {
+                    file,
+                    len: self.len,
+                })
+            }
+            None => Ok(SpooledFile::Memory(self.buffer)),
+        }
+    }
+}
+
+const OVER_REQUEST_LIMIT: &str = "The upload went over its request size limit";
+
+// a failed read of a field, which is how the request going over its size limit shows up
+pub fn read_error(e: MultipartError) -> ApiError {
+    if e.status() == StatusCode::PAYLOAD_TOO_LARGE {
+        ApiError::TooLarge(OVER_REQUEST_LIMIT.to_string())
+    } else {
+        ApiError::Validation(format!("Failed to read file data: {e}"))
+    }
+}
+
+// whether reading stopped at the request's limit rather than the file's own
+pub fn over_request_limit(e: &ApiError) -> bool {
+    matches!(e, ApiError::TooLarge(message) if message == OVER_REQUEST_LIMIT)
+}
+
+pub fn too_large(max_bytes: u64) -> ApiError {
+    ApiError::TooLarge(format!("File exceeds the {max_bytes} byte upload limit"))
+}
+
+// read a multipart field into a spool, failing once it grows past `max_bytes`
+pub async fn spool_field(
+    mut field: Field<'_>,
+    threshold: u64,
+    dir: Option<&Path>,
+    max_bytes: u64,
+) -> Result<SpooledFile, ApiError> {
+    let mut spooler = Spooler::new(threshold, dir);
+
+    while let Some(chunk) = field.chunk().await.map_err(read_error)? {
+        if spooler.len() + chunk.len() as u64 > max_bytes {
+            return Err(too_large(max_bytes));
+        }
+        spooler.push(&chunk).await?;
+    }
+
+    spooler.finish().await
+}
+
+// read a raw request body into a spool, failing once it grows past `max_bytes`
+pub async fn spool_body(
+    body: axum::body::Body,
+    threshold: u64,
+    dir: Option<&Path>,
+    max_bytes: u64,
+) -> Result<SpooledFile, ApiError> {
+    let mut spooler = Spooler::new(threshold, dir);
+    let mut stream = body.into_data_stream();
+
+    while let Some(chunk) = stream.next().await {
+        let chunk =
+            chunk.map_err(|e| ApiError::Validation(format!("Failed to read file data: {e}")))?;
+        if spooler.len() + chunk.len() as u64 > max_bytes {
+            return Err(too_large(max_bytes));
+        }
+        spooler.push(&chunk).await?;
+    }
+
+    spooler.finish().await
+}
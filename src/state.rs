@@ -0,0 +1,235 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+
+use crate::cache::{LastGood, ResponseCache};
+use crate::config::Config;
+use crate::db::{self, Db};
+use crate::errors::ApiError;
+use crate::group_counts;
+use crate::integrity;
+use crate::middleware::proxy_limits::ProxyLimits;
+use crate::middleware::rate_limit::RateLimits;
+use crate::middleware::upload_queue::UploadQueue;
+use crate::models::{PinataFile, PinataGroup};
+use crate::notify::Notifier;
+use crate::partial_groups::PartialGroups;
+use crate::pinata::accounts::PinataAccounts;
+use crate::pinata::{FilesQuery, ListOptions, PinataClient, SortOrder, groups, list_files};
+use crate::purge::CachePurger;
+use crate::reencode;
+use crate::shares;
+use crate::thumbnail_store::ThumbnailStore;
+use crate::upload_pipeline::UploadPipeline;
+
+// Shared by every handler through `Router::with_state`
+#[derive(Clone)]
+pub struct AppState {
+    pub config: Arc<Config>,
+    // the account writes go to, `accounts.client()`
+    pub pinata: PinataClient,
+    pub accounts: PinataAccounts,
+    pub db: Option<Db>,
+    pub cache: ResponseCache,
+    pub last_good: LastGood,
+    pub thumbnails: ThumbnailStore,
+    pub upload_queue: UploadQueue,
+    pub proxy_limits: ProxyLimits,
+    pub rate_limits: RateLimits,
+    pub notifier: Notifier,
+    pub purger: CachePurger,
+    pub partial_groups: PartialGroups,
+    pub upload_pipeline: UploadPipeline,
+    // bounds how many uploads are decoded and thumbnailed at once
+    pub processing: Arc<Semaphore>,
+}
+
+impl AppState {
+    pub async fn new(config: Config) -> Result<Self, ApiError> {
+        config.install();
+        let db = match &config.database {
+            Some(database) => Some(Db::connect(&database.url).await?),
+            None => None,
+        };
+
+        let pinata = PinataClient::new(config.pinata.clone())?;
+        Ok(Self {
+            accounts: PinataAccounts::new(&pinata, &config.pinata),
+            pinata,
+            cache: ResponseCache::new(&config.cache),
+            last_good: LastGood::new(
+                config
+                    .cache
+                    .serve_stale
+                    .then(|| config.storage.data_dir.join("last_good")),
+            ),
+            thumbnails: ThumbnailStore::new(
+                config.storage.data_dir.join("thumbnails"),
+                config.cache.thumbnail_max_bytes,
+            ),
+            upload_queue: UploadQueue::new(config.upload_queue),
+            proxy_limits: ProxyLimits::new(config.proxy, config.server.trust_forwarded_for),
+            rate_limits: RateLimits::new(
+                config.rate_limit.clone(),
+                config.server.trust_forwarded_for,
+            ),
+            notifier: Notifier::new(&config.webhooks)?,
+            purger: CachePurger::new(config.cache_purge.clone())?,
+            partial_groups: PartialGroups::default(),
+            upload_pipeline: UploadPipeline::new(&config.upload)?,
+            processing: Arc::new(Semaphore::new(config.processing.max_concurrent)),
+            config: Arc::new(config),
+            db,
+        })
+    }
+
+    // load and validate the configuration once, so misconfiguration fails at startup rather than per request
+    // start keeping the local mirror in sync, when there is one, finish any
+    // maintenance job a restart interrupted and check the catalog's integrity
+    pub fn spawn_background_tasks(&self) {
+        if let (Some(db), Some(database)) = (&self.db, &self.config.database) {
+            db::sync::spawn(
+                db.clone(),
+                self.pinata.clone(),
+                Duration::from_secs(database.sync_interval_secs),
+            );
+        }
+        reencode::resume(self);
+        shares::spawn_expiry_notices(self.notifier.clone());
+        integrity::spawn_startup_check(self);
+    }
+
+    // the same state with listings and writes going to the Pinata account `name` only
+    pub fn for_account(&self, name: &str) -> Result<Self, ApiError> {
+        let accounts = self.accounts.only(name)?;
+        Ok(Self {
+            pinata: accounts.client().clone(),
+            accounts,
+            ..self.clone()
+        })
+    }
+
+    // listing every account again, for results kept for other requests too
+    pub fn unscoped(&self) -> Self {
+        let accounts = self.accounts.unscoped();
+        Self {
+            pinata: accounts.client().clone(),
+            accounts,
+            ..self.clone()
+        }
+    }
+
+    // the mirror only follows the PINATA_JWT account
+    fn mirror_of(&self, pinata: &PinataClient) -> Option<&Db> {
+        self.db
+            .as_ref()
+            .filter(|db| db.is_synced() && pinata.account() == self.accounts.primary())
+    }
+
+    // the account to tag a listed file or group with, None for the primary one
+    fn account_tag(&self, pinata: &PinataClient) -> Option<String> {
+        (pinata.account() != self.accounts.primary()).then(|| pinata.account().to_string())
+    }
+
+    // serve file listings from the local mirror once it's synced, otherwise from Pinata,
+    // merged across the listed accounts
+    pub async fn list_files(
+        &self,
+        query: FilesQuery,
+        options: ListOptions,
+    ) -> Result<Vec<PinataFile>, ApiError> {
+        if self.accounts.is_single() {
+            return self.list_account_files(&self.pinata, query, options).await;
+        }
+
+        let mut tasks = JoinSet::new();
+        for (index, pinata) in self.accounts.listed().enumerate() {
+            let (state, pinata, query) = (self.clone(), pinata.clone(), query.clone());
+            tasks.spawn(async move {
+                let files = state.list_account_files(&pinata, query, options).await;
+                (index, files)
+            });
+        }
+        let mut listed = Vec::new();
+        while let Some(joined) = tasks.join_next().await {
+            let (index, files) =
+                joined.map_err(|e| ApiError::Internal(format!("Account listing failed: {e}")))?;
+            listed.push((index, files?));
+        }
+        listed.sort_by_key(|(index, _)| *index);
+
+        let mut files: Vec<PinataFile> = listed.into_iter().flat_map(|(_, files)| files).collect();
+        match options.order {
+            Some(SortOrder::Asc) => files.sort_by_key(|file| file.created_at),
+            Some(SortOrder::Desc) => files.sort_by_key(|file| std::cmp::Reverse(file.created_at)),
+            None => {}
+        }
+        files.truncate(options.limit.unwrap_or(usize::MAX));
+        Ok(files)
+    }
+
+    async fn list_account_files(
+        &self,
+        pinata: &PinataClient,
+        query: FilesQuery,
+        options: ListOptions,
+    ) -> Result<Vec<PinataFile>, ApiError> {
+        let mut files = match self.mirror_of(pinata) {
+            Some(db) => db.list_files(&query, options).await?,
+            None => list_files(pinata, query, options).await?,
+        };
+        if let Some(account) = self.account_tag(pinata) {
+            for file in &mut files {
+                file.account = Some(account.clone());
+            }
+        }
+        Ok(files)
+    }
+
+    // groups of this deployment's namespace only, so staging and production don't see each other's
+    pub async fn list_groups(&self, limit: Option<usize>) -> Result<Vec<PinataGroup>, ApiError> {
+        let mut all = Vec::new();
+        for pinata in self.accounts.listed() {
+            // the namespace is applied after fetching, so the limit has to be too
+            let listed = match self.mirror_of(pinata) {
+                Some(db) => db.list_groups(None).await?,
+                None => groups::list_groups(pinata, ListOptions::default()).await?,
+            };
+            let account = self.account_tag(pinata);
+            all.extend(listed.into_iter().map(|group| PinataGroup {
+                account: account.clone(),
+                ..group
+            }));
+        }
+
+        let namespace = self.pinata.group_namespace();
+        Ok(all
+            .into_iter()
+            .filter_map(|group| groups::in_namespace(namespace, group))
+            .take(limit.unwrap_or(usize::MAX))
+            .collect())
+    }
+
+    // something was written to Pinata: drop cached listings and refresh the mirror soon
+    pub fn catalog_changed(&self) {
+        self.listings_changed();
+        integrity::catalog_changed(self);
+    }
+
+    // Pinata told of a change made elsewhere: the same, but it's checked against the
+    // integrity manifest rather than taken into it
+    pub fn catalog_changed_upstream(&self) {
+        self.listings_changed();
+        integrity::upstream_changed(self);
+    }
+
+    fn listings_changed(&self) {
+        self.cache.invalidate_all();
+        group_counts::invalidate();
+        if let Some(db) = &self.db {
+            db.request_sync();
+        }
+    }
+}
@@ -0,0 +1,64 @@
+use std::fs;
+use std::path::PathBuf;
+use std::sync::RwLock;
+
+use serde::{Serialize, de::DeserializeOwned};
+use tracing::warn;
+
+use crate::config;
+use crate::errors::ApiError;
+
+// Small piece of backend-owned state persisted as a JSON file under the data dir.
+// Reads come from memory; every update rewrites the file atomically.
+pub struct JsonStore<T> {
+    path: PathBuf,
+    data: RwLock<T>,
+}
+
+impl<T: Serialize + DeserializeOwned + Default> JsonStore<T> {
+    // load `<data dir>/<name>.json`, starting empty when it doesn't exist yet
+    pub fn open(name: &str) -> Self {
+        let path = config::storage().data_dir.join(format!("{name}.json"));
+
+        let data = match fs::read_to_string(&path) {
+            Ok(raw) => serde_json::from_str(&raw).unwrap_or_else(|e| {
+                warn!("Ignoring unreadable store {}: {e}", path.display());
+                T::default()
+            }),
+            Err(_) => T::default(),
+        };
+
+        Self {
+            path,
+            data: RwLock::new(data),
+        }
+    }
+
+    pub fn read<R>(&self, f: impl FnOnce(&T) -> R) -> R {
+        f(&self.data.read().unwrap())
+    }
+
+    // apply `f` and persist the result; the change is kept in memory even if the write fails
+    pub fn update<R>(&self, f: impl FnOnce(&mut T) -> R) -> Result<R, ApiError> {
+        let mut data = self.data.write().unwrap();
+        let result = f(&mut data);
+        self.persist(&data)?;
+        Ok(result)
+    }
+
+    fn persist(&self, data: &T) -> Result<(), ApiError> {
+        let store_error = |e: std::io::Error| {
+            ApiError::Internal(format!("Failed to write {}: {e}", self.path.display()))
+        };
+
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir).map_err(store_error)?;
+        }
+
+        // write then rename so a crash never leaves a half-written file
+        let tmp = self.path.with_extension("json.tmp");
+        fs::write(&tmp, serde_json::to_vec_pretty(data)?).map_err(store_error)?;
+        fs::rename(&tmp, &self.path).map_err(store_error)?;
+        Ok(())
+    }
+}
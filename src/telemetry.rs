@@ -0,0 +1,294 @@
+use std::future::Future;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use rand::RngCore;
+use reqwest::{Client, Method, RequestBuilder, Response, Url};
+use serde_json::{Map, Value, json};
+use tokio::sync::mpsc;
+use tracing::{Instrument, Subscriber, field, info_span, span, warn};
+use tracing_subscriber::{Layer, layer::Context, registry::LookupSpan};
+
+use crate::config::TelemetryConfig;
+use crate::logging::JsonVisitor;
+
+// closed spans waiting to be sent; past this they're dropped rather than held
+const QUEUE_SIZE: usize = 4096;
+// spans per export
+const BATCH_SIZE: usize = 512;
+// how long a closed span waits at most before it's sent
+const FLUSH_EVERY: Duration = Duration::from_secs(5);
+
+// path segments at least this long are ids or CIDs
+const ID_SEGMENT_LEN: usize = 20;
+
+// OTLP span kinds
+const KIND_INTERNAL: u8 = 1;
+const KIND_SERVER: u8 = 2;
+const KIND_CLIENT: u8 = 3;
+
+// the endpoint a call is made to with its ids left out, so calls to it group
+// together, e.g. `GET /v3/files/public/{id}`
+fn endpoint(method: &Method, url: &Url) -> String {
+    let path: Vec<&str> = url
+        .path_segments()
+        .into_iter()
+        .flatten()
+        .map(|segment| {
+            let numeric = !segment.is_empty() && segment.bytes().all(|b| b.is_ascii_digit());
+            if numeric || segment.len() >= ID_SEGMENT_LEN {
+                "{id}"
+            } else {
+                segment
+            }
+        })
+        .collect();
+    format!("{method} /{}", path.join("/"))
+}
+
+// Outbound calls made in a client span of their own, so a trace shows each Pinata,
+// gateway or webhook call under the request or job that made it, with its outcome
+pub trait SendTraced {
+    fn send_traced(self) -> impl Future<Output = reqwest::Result<Response>> + Send;
+
+    // the same, for the `retry`th retry of a call
+    fn send_retry(self, retry: u32) -> impl Future<Output = reqwest::Result<Response>> + Send;
+}
+
+impl SendTraced for RequestBuilder {
+    fn send_traced(self) -> impl Future<Output = reqwest::Result<Response>> + Send {
+        self.send_retry(0)
+    }
+
+    fn send_retry(self, retry: u32) -> impl Future<Output = reqwest::Result<Response>> + Send {
+        let (client, request) = self.build_split();
+        async move {
+            let request = request?;
+            let span = info_span!(
+                "http_client",
+                otel.name = %endpoint(request.method(), request.url()),
+                otel.kind = "client",
+                http.request.method = %request.method(),
+                server.address = request.url().host_str().unwrap_or_default(),
+                retry,
+                http.response.status_code = field::Empty,
+                error = field::Empty,
+            );
+            let result = client.execute(request).instrument(span.clone()).await;
+            match &result {
+                Ok(response) => {
+                    span.record("http.response.status_code", response.status().as_u16())
+                }
+                Err(e) => span.record("error", e.to_string()),
+            };
+            result
+        }
+    }
+}
+
+// what the layer keeps on a span while it's open
+struct OtelSpan {
+    trace_id: [u8; 16],
+    span_id: [u8; 8],
+    parent_id: Option<[u8; 8]>,
+    started_at: SystemTime,
+    attributes: Map<String, Value>,
+}
+
+fn random_id<const N: usize>() -> [u8; N] {
+    let mut id = [0u8; N];
+    rand::rng().fill_bytes(&mut id);
+    id
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn unix_nanos(at: SystemTime) -> String {
+    at.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos()
+        .to_string()
+}
+
+fn otlp_value(value: &Value) -> Value {
+    match value {
+        Value::Bool(b) => json!({ "boolValue": b }),
+        Value::Number(n) if n.is_i64() || n.is_u64() => json!({ "intValue": n.to_string() }),
+        Value::Number(n) => json!({ "doubleValue": n.as_f64() }),
+        Value::String(s) => json!({ "stringValue": s }),
+        other => json!({ "stringValue": other.to_string() }),
+    }
+}
+
+fn otlp_attribute(key: &str, value: &Value) -> Value {
+    json!({ "key": key, "value": otlp_value(value) })
+}
+
+// A closed span as OTLP JSON. Spans name themselves with `otel.name` and `otel.kind`
+// fields, except the per-request ones, which are named after their route.
+fn otlp_span(name: &str, span: OtelSpan, ended_at: SystemTime) -> Value {
+    let attributes = &span.attributes;
+    let text = |key: &str| attributes.get(key).and_then(Value::as_str);
+    let (name, kind) = match (text("otel.name"), text("otel.kind")) {
+        (Some(name), Some("client")) => (name.to_string(), KIND_CLIENT),
+        (Some(name), _) => (name.to_string(), KIND_INTERNAL),
+        (None, _) if name == "request" => (
+            format!(
+                "{} {}",
+                text("method").unwrap_or_default(),
+                text("route").unwrap_or_default()
+            ),
+            KIND_SERVER,
+        ),
+        (None, _) => (name.to_string(), KIND_INTERNAL),
+    };
+
+    let failed_status = ["status", "http.response.status_code"]
+        .iter()
+        .filter_map(|key| attributes.get(*key).and_then(Value::as_u64))
+        .any(|status| status >= 500);
+    let status = match text("error") {
+        Some(message) => json!({ "code": 2, "message": message }),
+        None if failed_status => json!({ "code": 2 }),
+        None => json!({}),
+    };
+
+    json!({
+        "traceId": hex(&span.trace_id),
+        "spanId": hex(&span.span_id),
+        "parentSpanId": span.parent_id.map(|id| hex(&id)).unwrap_or_default(),
+        "name": name,
+        "kind": kind,
+        "startTimeUnixNano": unix_nanos(span.started_at),
+        "endTimeUnixNano": unix_nanos(ended_at),
+        "attributes": attributes
+            .iter()
+            .filter(|(key, _)| !key.starts_with("otel."))
+            .map(|(key, value)| otlp_attribute(key, value))
+            .collect::<Vec<_>>(),
+        "status": status,
+    })
+}
+
+// Collects spans with their timing and fields, parented as they were opened, and
+// hands each over to the exporter once it closes
+pub struct OtlpLayer {
+    spans: mpsc::Sender<Value>,
+}
+
+impl<S> Layer<S> for OtlpLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &span::Attributes<'_>, id: &span::Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let parent = span.parent().and_then(|parent| {
+            let extensions = parent.extensions();
+            let parent = extensions.get::<OtelSpan>()?;
+            Some((parent.trace_id, parent.span_id))
+        });
+        let mut fields = JsonVisitor::default();
+        attrs.record(&mut fields);
+
+        span.extensions_mut().insert(OtelSpan {
+            trace_id: parent.map_or_else(random_id, |(trace_id, _)| trace_id),
+            span_id: random_id(),
+            parent_id: parent.map(|(_, span_id)| span_id),
+            started_at: SystemTime::now(),
+            attributes: fields.0,
+        });
+    }
+
+    fn on_record(&self, id: &span::Id, values: &span::Record<'_>, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let mut extensions = span.extensions_mut();
+        if let Some(otel) = extensions.get_mut::<OtelSpan>() {
+            let mut fields = JsonVisitor(std::mem::take(&mut otel.attributes));
+            values.record(&mut fields);
+            otel.attributes = fields.0;
+        }
+    }
+
+    fn on_close(&self, id: span::Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(&id) else {
+            return;
+        };
+        let Some(otel) = span.extensions_mut().remove::<OtelSpan>() else {
+            return;
+        };
+        // a collector that can't keep up loses spans, the requests don't wait on it
+        let _ = self
+            .spans
+            .try_send(otlp_span(span.name(), otel, SystemTime::now()));
+    }
+}
+
+// The layer to install when an OTLP endpoint is configured, with the task that
+// exports what it collects started alongside. Needs a running runtime.
+pub fn otlp_layer(config: &TelemetryConfig) -> Option<OtlpLayer> {
+    let endpoint = config.otlp_endpoint.clone()?;
+    let (spans, received) = mpsc::channel(QUEUE_SIZE);
+    tokio::spawn(export(config.clone(), endpoint, received));
+    Some(OtlpLayer { spans })
+}
+
+// post closed spans to the collector in batches, until the layer is gone
+async fn export(config: TelemetryConfig, endpoint: String, mut received: mpsc::Receiver<Value>) {
+    // a client of its own, whose calls aren't traced
+    let client = Client::new();
+    let resource = json!({
+        "attributes": [otlp_attribute("service.name", &config.service_name.clone().into())],
+    });
+    let scope = json!({ "name": env!("CARGO_PKG_NAME"), "version": env!("CARGO_PKG_VERSION") });
+    let mut failing = false;
+
+    let mut open = true;
+    while open {
+        let mut batch = Vec::new();
+        let flush = tokio::time::sleep(FLUSH_EVERY);
+        tokio::pin!(flush);
+        while batch.len() < BATCH_SIZE {
+            tokio::select! {
+                span = received.recv() => match span {
+                    Some(span) => batch.push(span),
+                    None => {
+                        open = false;
+                        break;
+                    }
+                },
+                _ = &mut flush => break,
+            }
+        }
+        if batch.is_empty() {
+            continue;
+        }
+
+        let body = json!({ "resourceSpans": [{
+            "resource": resource,
+            "scopeSpans": [{ "scope": scope, "spans": batch }],
+        }]});
+        let request = config.otlp_headers.iter().fold(
+            client.post(&endpoint).json(&body),
+            |request, (name, value)| request.header(name, value),
+        );
+        let failure = match request.send().await {
+            Ok(response) if response.status().is_success() => None,
+            Ok(response) => Some(format!("the collector answered {}", response.status())),
+            Err(e) => Some(e.to_string()),
+        };
+        // only the first of a run of failures is logged
+        match failure {
+            Some(e) if !failing => {
+                warn!("Failed to export spans to {endpoint}: {e}");
+                failing = true;
+            }
+            Some(_) => {}
+            None => failing = false,
+        }
+    }
+}
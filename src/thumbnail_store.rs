@@ -0,0 +1,327 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use axum::body::Bytes;
+use metrics::{counter, gauge};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::warn;
+
+// A generated copy of a pinned file: which file, how wide (None for full size) and
+// what was done to it, e.g. `watermarked.jpeg`
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ThumbnailKey {
+    pub cid: String,
+    pub width: Option<u32>,
+    pub format: String,
+}
+
+impl ThumbnailKey {
+    pub fn new(cid: &str, width: Option<u32>, format: &str) -> Self {
+        Self {
+            cid: cid.to_string(),
+            width,
+            format: format.to_string(),
+        }
+    }
+
+    // the file its record is kept in
+    fn file_name(&self) -> String {
+        let width = self.width.map_or("full".to_string(), |w| w.to_string());
+        let digest = Sha256::digest(format!("{}/{width}/{}", self.cid, self.format));
+        format!("{}.json", hex(&digest[..16]))
+    }
+}
+
+pub struct Thumbnail {
+    pub content_type: String,
+    pub body: Bytes,
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+// what's kept on disk for a key, next to the content it names
+#[derive(Serialize, Deserialize)]
+struct Record {
+    key: ThumbnailKey,
+    // hex SHA-256 of the content, its file name under `blobs`
+    blob: String,
+    content_type: String,
+}
+
+struct Entry {
+    blob: String,
+    content_type: String,
+    // when it was last stored or served, for eviction
+    used: u64,
+}
+
+struct Blob {
+    size: u64,
+    // keys naming it; it's deleted with the last of them
+    keys: usize,
+}
+
+#[derive(Default)]
+struct Index {
+    entries: HashMap<ThumbnailKey, Entry>,
+    blobs: HashMap<String, Blob>,
+    // keys by when they were last used, least recent first
+    by_use: BTreeMap<u64, ThumbnailKey>,
+    clock: u64,
+    bytes: u64,
+}
+
+impl Index {
+    fn touch(&mut self, key: &ThumbnailKey) {
+        self.clock += 1;
+        if let Some(entry) = self.entries.get_mut(key) {
+            self.by_use.remove(&entry.used);
+            entry.used = self.clock;
+            self.by_use.insert(self.clock, key.clone());
+        }
+    }
+
+    // add or replace `key`, returning the blob it named before when nothing else does
+    fn insert(
+        &mut self,
+        key: ThumbnailKey,
+        blob: &str,
+        size: u64,
+        content_type: String,
+    ) -> Option<String> {
+        let replaced = self.remove(&key).filter(|old| old != blob);
+        self.clock += 1;
+        self.blobs
+            .entry(blob.to_string())
+            .or_insert_with(|| {
+                self.bytes += size;
+                Blob { size, keys: 0 }
+            })
+            .keys += 1;
+        self.by_use.insert(self.clock, key.clone());
+        self.entries.insert(
+            key,
+            Entry {
+                blob: blob.to_string(),
+                content_type,
+                used: self.clock,
+            },
+        );
+        replaced
+    }
+
+    // forget `key`, returning its blob when no other key names it any more
+    fn remove(&mut self, key: &ThumbnailKey) -> Option<String> {
+        let entry = self.entries.remove(key)?;
+        self.by_use.remove(&entry.used);
+        let blob = self.blobs.get_mut(&entry.blob)?;
+        blob.keys -= 1;
+        if blob.keys > 0 {
+            return None;
+        }
+        self.bytes -= blob.size;
+        self.blobs.remove(&entry.blob);
+        Some(entry.blob)
+    }
+
+    // drop the least recently used keys until the blobs fit in `max_bytes`
+    fn evict(&mut self, max_bytes: u64) -> Vec<(ThumbnailKey, Option<String>)> {
+        let mut evicted = Vec::new();
+        while self.bytes > max_bytes {
+            let Some((_, key)) = self.by_use.pop_first() else {
+                break;
+            };
+            let blob = self.remove(&key);
+            evicted.push((key, blob));
+        }
+        evicted
+    }
+}
+
+struct Store {
+    dir: PathBuf,
+    max_bytes: u64,
+    index: Mutex<Index>,
+}
+
+// Generated copies of pinned files, resized and watermarked, kept under the data dir
+// so they aren't made again for every request. The content is stored once by its
+// SHA-256 however many keys name it; past `max_bytes` the least recently used keys
+// go first.
+#[derive(Clone)]
+pub struct ThumbnailStore {
+    store: Option<Arc<Store>>,
+}
+
+impl ThumbnailStore {
+    // a cap of 0 turns the store off
+    pub fn new(dir: PathBuf, max_bytes: u64) -> Self {
+        let store = (max_bytes > 0).then(|| {
+            let index = load(&dir);
+            let store = Store {
+                dir,
+                max_bytes,
+                index: Mutex::new(index),
+            };
+            // a lowered cap applies to what earlier runs stored too
+            for (key, blob) in store.evict() {
+                let _ = std::fs::remove_file(store.record_path(&key));
+                if let Some(blob) = blob {
+                    let _ = std::fs::remove_file(store.blob_path(&blob));
+                }
+            }
+            Arc::new(store)
+        });
+        Self { store }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.store.is_some()
+    }
+
+    pub async fn get(&self, key: &ThumbnailKey) -> Option<Thumbnail> {
+        let store = self.store.as_ref()?;
+        let found = {
+            let mut index = store.index.lock().unwrap();
+            index.touch(key);
+            index
+                .entries
+                .get(key)
+                .map(|entry| (entry.blob.clone(), entry.content_type.clone()))
+        };
+        let outcome = if found.is_some() { "hit" } else { "miss" };
+        counter!("thumbnail_store_total", "outcome" => outcome).increment(1);
+        let (blob, content_type) = found?;
+
+        match tokio::fs::read(store.blob_path(&blob)).await {
+            Ok(body) => Some(Thumbnail {
+                content_type,
+                body: body.into(),
+            }),
+            // e.g. removed by hand; made again on the next miss
+            Err(e) => {
+                warn!("Failed to read stored thumbnail {blob}: {e}");
+                let orphan = store.index.lock().unwrap().remove(key);
+                store.delete(vec![(key.clone(), orphan)]).await;
+                None
+            }
+        }
+    }
+
+    // keep `body` as the copy `key` names
+    pub async fn put(&self, key: ThumbnailKey, content_type: &str, body: &Bytes) {
+        let Some(store) = &self.store else {
+            return;
+        };
+        let size = body.len() as u64;
+        if size > store.max_bytes {
+            return;
+        }
+        let blob = hex(&Sha256::digest(body));
+        let record = Record {
+            key: key.clone(),
+            blob: blob.clone(),
+            content_type: content_type.to_string(),
+        };
+
+        let stored = store.index.lock().unwrap().blobs.contains_key(&blob);
+        let result = async {
+            if !stored {
+                write_atomically(&store.blob_path(&blob), body).await?;
+            }
+            let record = serde_json::to_vec(&record).map_err(std::io::Error::other)?;
+            write_atomically(&store.record_path(&key), &record).await
+        }
+        .await;
+        if let Err(e) = result {
+            warn!("Failed to store thumbnail of {}: {e}", key.cid);
+            return;
+        }
+
+        let replaced =
+            store
+                .index
+                .lock()
+                .unwrap()
+                .insert(key, &blob, size, content_type.to_string());
+        if let Some(replaced) = replaced {
+            let _ = tokio::fs::remove_file(store.blob_path(&replaced)).await;
+        }
+        let evicted = store.evict();
+        store.delete(evicted).await;
+    }
+}
+
+impl Store {
+    fn blob_path(&self, blob: &str) -> PathBuf {
+        self.dir.join("blobs").join(blob)
+    }
+
+    fn record_path(&self, key: &ThumbnailKey) -> PathBuf {
+        self.dir.join("keys").join(key.file_name())
+    }
+
+    fn evict(&self) -> Vec<(ThumbnailKey, Option<String>)> {
+        let mut index = self.index.lock().unwrap();
+        let evicted = index.evict(self.max_bytes);
+        counter!("thumbnail_store_evictions_total").increment(evicted.len() as u64);
+        gauge!("thumbnail_store_bytes").set(index.bytes as f64);
+        evicted
+    }
+
+    // remove the files of keys no longer kept, and of blobs no key names
+    async fn delete(&self, removed: Vec<(ThumbnailKey, Option<String>)>) {
+        for (key, blob) in removed {
+            let _ = tokio::fs::remove_file(self.record_path(&key)).await;
+            if let Some(blob) = blob {
+                let _ = tokio::fs::remove_file(self.blob_path(&blob)).await;
+            }
+        }
+    }
+}
+
+async fn write_atomically(path: &Path, data: &[u8]) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        tokio::fs::create_dir_all(dir).await?;
+    }
+    let tmp = path.with_extension("tmp");
+    tokio::fs::write(&tmp, data).await?;
+    tokio::fs::rename(&tmp, path).await
+}
+
+// What earlier runs stored, oldest first so it's evicted first. Records whose content
+// is missing, and content no record names, are dropped.
+fn load(dir: &Path) -> Index {
+    let mut records: Vec<(std::time::SystemTime, Record, u64)> =
+        std::fs::read_dir(dir.join("keys"))
+            .into_iter()
+            .flatten()
+            .filter_map(|entry| {
+                let path = entry.ok()?.path();
+                let record: Record = serde_json::from_slice(&std::fs::read(&path).ok()?).ok()?;
+                let blob = std::fs::metadata(dir.join("blobs").join(&record.blob)).ok()?;
+                let modified = std::fs::metadata(&path).ok()?.modified().ok()?;
+                Some((modified, record, blob.len()))
+            })
+            .collect();
+    records.sort_by_key(|(modified, _, _)| *modified);
+
+    let mut index = Index::default();
+    for (_, record, size) in records {
+        // a record is named after its key, so no two name the same one
+        index.insert(record.key, &record.blob, size, record.content_type);
+    }
+
+    for entry in std::fs::read_dir(dir.join("blobs")).into_iter().flatten() {
+        let Ok(entry) = entry else { continue };
+        let name = entry.file_name().to_string_lossy().to_string();
+        if !index.blobs.contains_key(&name) {
+            let _ = std::fs::remove_file(entry.path());
+        }
+    }
+    index
+}
@@ -0,0 +1,392 @@
+use std::path::PathBuf;
+
+use chrono::{DateTime, Datelike, Duration, FixedOffset, NaiveDate, NaiveDateTime, NaiveTime, Utc};
+
+// Time zones for showing and reading dates the way the photographer sees them: a
+// fixed offset such as `+02:00`, or an IANA name such as `Europe/Berlin` read from
+// the system's zoneinfo database (TZDIR, else /usr/share/zoneinfo), daylight saving
+// included.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimeZone {
+    name: String,
+    kind: Kind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Kind {
+    Fixed(FixedOffset),
+    Rules(Rules),
+}
+
+// a zoneinfo file: past transitions, and the rule that carries on after the last one
+#[derive(Debug, Clone, PartialEq)]
+struct Rules {
+    // UTC seconds each offset takes effect at, in order
+    transitions: Vec<(i64, i32)>,
+    // before the first transition
+    initial: i32,
+    future: Option<Posix>,
+}
+
+// A POSIX TZ rule, e.g. `CET-1CEST,M3.5.0,M10.5.0/3`. Offsets are seconds east of
+// UTC, unlike the string's own sign.
+#[derive(Debug, Clone, PartialEq)]
+struct Posix {
+    standard: i32,
+    daylight: Option<(i32, RuleDate, i32, RuleDate, i32)>,
+}
+
+// the day a daylight period starts or ends on
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum RuleDate {
+    // month, week (5 being the last) and weekday, Sunday 0
+    Weekday(u32, u32, u32),
+    // 1 to 365, February 29th never counted
+    Julian(u32),
+    // 0 to 365, February 29th counted in leap years
+    Ordinal(u32),
+}
+
+impl Default for TimeZone {
+    fn default() -> Self {
+        Self::utc()
+    }
+}
+
+impl std::fmt::Display for TimeZone {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.name)
+    }
+}
+
+// `+02:00`, `-0530`, `+09` or `Z`, as a fixed offset east of UTC
+pub fn parse_offset(raw: &str) -> Option<FixedOffset> {
+    let raw = raw.trim();
+    if raw.eq_ignore_ascii_case("z") {
+        return FixedOffset::east_opt(0);
+    }
+    let (sign, rest) = match raw.split_at_checked(1)? {
+        ("+", rest) => (1, rest),
+        ("-", rest) => (-1, rest),
+        _ => return None,
+    };
+    let digits: String = rest.chars().filter(|c| *c != ':').collect();
+    if !digits.chars().all(|c| c.is_ascii_digit()) || !matches!(digits.len(), 2 | 4) {
+        return None;
+    }
+    let hours: i32 = digits[..2].parse().ok()?;
+    let minutes: i32 = digits.get(2..).map_or(Some(0), |m| m.parse().ok())?;
+    if hours > 18 || minutes > 59 {
+        return None;
+    }
+    FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60))
+}
+
+fn zoneinfo_dir() -> PathBuf {
+    std::env::var_os("TZDIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("/usr/share/zoneinfo"))
+}
+
+impl TimeZone {
+    pub fn utc() -> Self {
+        Self {
+            name: "UTC".to_string(),
+            kind: Kind::Fixed(FixedOffset::east_opt(0).unwrap()),
+        }
+    }
+
+    pub fn parse(name: &str) -> Result<Self, String> {
+        let name = name.trim();
+        if name.eq_ignore_ascii_case("utc") || name.eq_ignore_ascii_case("z") {
+            return Ok(Self::utc());
+        }
+        if let Some(offset) = parse_offset(name) {
+            return Ok(Self {
+                name: offset.to_string(),
+                kind: Kind::Fixed(offset),
+            });
+        }
+
+        let valid = !name.is_empty()
+            && !name.starts_with('/')
+            && name.split('/').all(|part| part != ".." && !part.is_empty())
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '/' | '_' | '-' | '+'));
+        if !valid {
+            return Err(format!(
+                "'{name}' is neither an offset like +02:00 nor a zone name like Europe/Berlin"
+            ));
+        }
+        let path = zoneinfo_dir().join(name);
+        let data = std::fs::read(&path)
+            .map_err(|e| format!("no time zone '{name}' in {}: {e}", path.display()))?;
+        let rules = Rules::parse(&data)
+            .ok_or_else(|| format!("{} isn't a zoneinfo file", path.display()))?;
+        Ok(Self {
+            name: name.to_string(),
+            kind: Kind::Rules(rules),
+        })
+    }
+
+    pub fn offset_at(&self, at: DateTime<Utc>) -> FixedOffset {
+        let seconds = match &self.kind {
+            Kind::Fixed(offset) => return *offset,
+            Kind::Rules(rules) => rules.offset_at(at.timestamp()),
+        };
+        FixedOffset::east_opt(seconds).unwrap_or_else(|| FixedOffset::east_opt(0).unwrap())
+    }
+
+    pub fn to_local(&self, at: DateTime<Utc>) -> DateTime<FixedOffset> {
+        at.with_timezone(&self.offset_at(at))
+    }
+
+    // A wall clock time in this zone. Times skipped by a clock change are read with
+    // the offset before it, times repeated by one as the earlier of the two.
+    pub fn from_local(&self, local: NaiveDateTime) -> DateTime<FixedOffset> {
+        let before = self.offset_at(local.and_utc() - Duration::days(1));
+        let after = self.offset_at(local.and_utc() + Duration::days(1));
+        let offset = [before, after]
+            .into_iter()
+            .find(|offset| {
+                let utc = local - Duration::seconds(offset.local_minus_utc() as i64);
+                self.offset_at(utc.and_utc()) == *offset
+            })
+            .unwrap_or(before);
+        let utc = local - Duration::seconds(offset.local_minus_utc() as i64);
+        utc.and_utc().with_timezone(&offset)
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    at: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        let bytes = self.data.get(self.at..self.at.checked_add(len)?)?;
+        self.at += len;
+        Some(bytes)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_be_bytes(self.take(4)?.try_into().ok()?))
+    }
+
+    fn i32(&mut self) -> Option<i32> {
+        Some(i32::from_be_bytes(self.take(4)?.try_into().ok()?))
+    }
+
+    fn i64(&mut self) -> Option<i64> {
+        Some(i64::from_be_bytes(self.take(8)?.try_into().ok()?))
+    }
+}
+
+impl Rules {
+    // RFC 8536; version 2 and later files repeat the data with 64-bit times and end
+    // with a POSIX rule for what comes after the last transition
+    fn parse(data: &[u8]) -> Option<Self> {
+        let mut reader = Reader { data, at: 0 };
+        let (version, counts) = Self::header(&mut reader)?;
+        if version >= b'2' {
+            // skip the 32-bit block
+            let [isut, isstd, leap, time, types, chars] = counts;
+            reader.take(time * 5 + types * 6 + chars + leap * 8 + isstd + isut)?;
+            let (_, counts) = Self::header(&mut reader)?;
+            let mut rules = Self::block(&mut reader, counts, 8)?;
+            let footer = data.get(reader.at..)?;
+            let footer = std::str::from_utf8(footer).ok()?.trim_matches('\n');
+            rules.future = (!footer.is_empty()).then(|| Posix::parse(footer)).flatten();
+            Some(rules)
+        } else {
+            Self::block(&mut reader, counts, 4)
+        }
+    }
+
+    fn header(reader: &mut Reader) -> Option<(u8, [usize; 6])> {
+        if reader.take(4)? != b"TZif" {
+            return None;
+        }
+        let version = reader.take(1)?[0];
+        reader.take(15)?;
+        let mut counts = [0usize; 6];
+        for count in &mut counts {
+            *count = reader.u32()? as usize;
+        }
+        Some((version.max(b'1'), counts))
+    }
+
+    fn block(reader: &mut Reader, counts: [usize; 6], time_size: usize) -> Option<Self> {
+        let [isut, isstd, leap, time, types, chars] = counts;
+        let mut times = Vec::with_capacity(time);
+        for _ in 0..time {
+            times.push(if time_size == 8 {
+                reader.i64()?
+            } else {
+                reader.i32()? as i64
+            });
+        }
+        let indices = reader.take(time)?.to_vec();
+        let mut offsets = Vec::with_capacity(types);
+        for _ in 0..types {
+            offsets.push(reader.i32()?);
+            reader.take(2)?;
+        }
+        reader.take(chars + leap * (time_size + 4) + isstd + isut)?;
+
+        let transitions = times
+            .into_iter()
+            .zip(indices)
+            .map(|(at, index)| Some((at, *offsets.get(index as usize)?)))
+            .collect::<Option<Vec<_>>>()?;
+        Some(Self {
+            transitions,
+            initial: *offsets.first()?,
+            future: None,
+        })
+    }
+
+    fn offset_at(&self, at: i64) -> i32 {
+        match self.transitions.partition_point(|(start, _)| *start <= at) {
+            0 => self.initial,
+            n if n == self.transitions.len() => match &self.future {
+                Some(posix) => posix.offset_at(at),
+                None => self.transitions[n - 1].1,
+            },
+            n => self.transitions[n - 1].1,
+        }
+    }
+}
+
+impl Posix {
+    fn parse(rule: &str) -> Option<Self> {
+        let mut rest = rule;
+        skip_name(&mut rest)?;
+        let standard = -parse_time(&mut rest)?;
+        if rest.is_empty() {
+            return Some(Self {
+                standard,
+                daylight: None,
+            });
+        }
+        skip_name(&mut rest)?;
+        let daylight = if rest.starts_with(',') {
+            standard + 3600
+        } else {
+            -parse_time(&mut rest)?
+        };
+        let (start, end) = rest.strip_prefix(',')?.split_once(',')?;
+        let (start, start_time) = parse_rule_date(start)?;
+        let (end, end_time) = parse_rule_date(end)?;
+        Some(Self {
+            standard,
+            daylight: Some((daylight, start, start_time, end, end_time)),
+        })
+    }
+
+    fn offset_at(&self, at: i64) -> i32 {
+        let Some((daylight, start, start_time, end, end_time)) = self.daylight else {
+            return self.standard;
+        };
+        let Some(local) = DateTime::from_timestamp(at + self.standard as i64, 0) else {
+            return self.standard;
+        };
+        let year = local.year();
+        // a start is given in standard time and an end in daylight time
+        let starts = start.timestamp(year, start_time) - self.standard as i64;
+        let ends = end.timestamp(year, end_time) - daylight as i64;
+        let in_daylight = if starts < ends {
+            starts <= at && at < ends
+        } else {
+            !(ends <= at && at < starts)
+        };
+        if in_daylight { daylight } else { self.standard }
+    }
+}
+
+impl RuleDate {
+    // the local seconds since the epoch this day plus `time` is, in `year`
+    fn timestamp(self, year: i32, time: i32) -> i64 {
+        let leap = NaiveDate::from_ymd_opt(year, 2, 29).is_some();
+        let day = match self {
+            Self::Weekday(month, week, weekday) => {
+                let first = NaiveDate::from_ymd_opt(year, month, 1).unwrap_or_default();
+                let first_weekday = first.weekday().num_days_from_sunday();
+                let mut day = first + Duration::days(((weekday + 7 - first_weekday) % 7) as i64);
+                for _ in 1..week {
+                    let next = day + Duration::days(7);
+                    if next.month() != month {
+                        break;
+                    }
+                    day = next;
+                }
+                day
+            }
+            Self::Julian(n) => {
+                let skip_leap = leap && n >= 60;
+                NaiveDate::from_yo_opt(year, n + skip_leap as u32).unwrap_or_default()
+            }
+            Self::Ordinal(n) => NaiveDate::from_yo_opt(year, n + 1).unwrap_or_default(),
+        };
+        day.and_time(NaiveTime::MIN).and_utc().timestamp() + time as i64
+    }
+}
+
+// a zone abbreviation, `CET` or `<+03>`
+fn skip_name(rest: &mut &str) -> Option<()> {
+    let len = if let Some(quoted) = rest.strip_prefix('<') {
+        quoted.find('>')? + 2
+    } else {
+        rest.find(|c: char| !c.is_ascii_alphabetic())
+            .unwrap_or(rest.len())
+    };
+    if len < 3 {
+        return None;
+    }
+    *rest = &rest[len..];
+    Some(())
+}
+
+// `[+-]h[h][:mm[:ss]]` in seconds, positive west of UTC as POSIX writes it
+fn parse_time(rest: &mut &str) -> Option<i32> {
+    let end = rest
+        .find(|c: char| !(c.is_ascii_digit() || matches!(c, ':' | '+' | '-')))
+        .unwrap_or(rest.len());
+    let (raw, remaining) = rest.split_at(end);
+    *rest = remaining;
+    let (sign, raw) = match raw.strip_prefix('-') {
+        Some(raw) => (-1, raw),
+        None => (1, raw.strip_prefix('+').unwrap_or(raw)),
+    };
+    let mut seconds = 0;
+    for (part, unit) in raw.split(':').zip([3600, 60, 1]) {
+        seconds += part.parse::<i32>().ok()? * unit;
+    }
+    Some(sign * seconds)
+}
+
+fn parse_rule_date(raw: &str) -> Option<(RuleDate, i32)> {
+    let (date, time) = match raw.split_once('/') {
+        Some((date, mut time)) => (date, parse_time(&mut time)?),
+        None => (raw, 2 * 3600),
+    };
+    let date = if let Some(spec) = date.strip_prefix('M') {
+        let mut parts = spec.split('.').map(|p| p.parse::<u32>().ok());
+        let (Some(Some(month)), Some(Some(week)), Some(Some(weekday))) =
+            (parts.next(), parts.next(), parts.next())
+        else {
+            return None;
+        };
+        if !(1..=12).contains(&month) || !(1..=5).contains(&week) || weekday > 6 {
+            return None;
+        }
+        RuleDate::Weekday(month, week, weekday)
+    } else if let Some(day) = date.strip_prefix('J') {
+        RuleDate::Julian(day.parse().ok().filter(|d| (1..=365).contains(d))?)
+    } else {
+        RuleDate::Ordinal(date.parse().ok().filter(|d| *d <= 365)?)
+    };
+    Some((date, time))
+}
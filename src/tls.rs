@@ -0,0 +1,116 @@
+use std::fmt::Display;
+use std::io;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::serve::Listener;
+use rustls::ServerConfig;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, pem::PemObject};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio_rustls::{TlsAcceptor, server::TlsStream};
+use tracing::{Instrument, debug, info_span};
+
+use crate::config::TlsConfig;
+use crate::errors::ApiError;
+
+// a client that hasn't finished its handshake by then is dropped
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+// handshaken connections waiting for the server to take them
+const ACCEPTED_BACKLOG: usize = 64;
+
+fn tls_error(what: &str, path: &Path, e: impl Display) -> ApiError {
+    ApiError::Api(format!(
+        "Invalid configuration: {what} {}: {e}",
+        path.display()
+    ))
+}
+
+// the certificate chain and key, read once at startup, offering HTTP/2 and HTTP/1.1
+pub fn server_config(config: &TlsConfig) -> Result<Arc<ServerConfig>, ApiError> {
+    let certs = CertificateDer::pem_file_iter(&config.cert_path)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| tls_error("TLS_CERT_PATH", &config.cert_path, e))?;
+    if certs.is_empty() {
+        return Err(tls_error(
+            "TLS_CERT_PATH",
+            &config.cert_path,
+            "no certificate in it",
+        ));
+    }
+    let key = PrivateKeyDer::from_pem_file(&config.key_path)
+        .map_err(|e| tls_error("TLS_KEY_PATH", &config.key_path, e))?;
+
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let mut server = ServerConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .and_then(|builder| builder.with_no_client_auth().with_single_cert(certs, key))
+        .map_err(|e| tls_error("TLS_KEY_PATH", &config.key_path, e))?;
+    server.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    Ok(Arc::new(server))
+}
+
+// TLS over a TCP listener, for `axum::serve`. Handshakes run on tasks of their own,
+// so a slow client doesn't hold up the ones behind it.
+pub struct TlsListener {
+    accepted: mpsc::Receiver<(TlsStream<TcpStream>, SocketAddr)>,
+    local_addr: SocketAddr,
+}
+
+impl TlsListener {
+    pub async fn bind(address: SocketAddr, config: Arc<ServerConfig>) -> io::Result<Self> {
+        let mut tcp = TcpListener::bind(address).await?;
+        let local_addr = tcp.local_addr()?;
+        let acceptor = TlsAcceptor::from(config);
+        let (sender, accepted) = mpsc::channel(ACCEPTED_BACKLOG);
+
+        tokio::spawn(
+            async move {
+                loop {
+                    // the server stopped taking connections
+                    let (stream, peer) = tokio::select! {
+                        _ = sender.closed() => break,
+                        accepted = Listener::accept(&mut tcp) => accepted,
+                    };
+                    let acceptor = acceptor.clone();
+                    let sender = sender.clone();
+                    tokio::spawn(async move {
+                        match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await
+                        {
+                            Ok(Ok(stream)) => {
+                                let _ = sender.send((stream, peer)).await;
+                            }
+                            Ok(Err(e)) => debug!("TLS handshake with {peer} failed: {e}"),
+                            Err(_) => debug!("TLS handshake with {peer} timed out"),
+                        }
+                    });
+                }
+            }
+            .instrument(info_span!("tls_accept")),
+        );
+
+        Ok(Self {
+            accepted,
+            local_addr,
+        })
+    }
+}
+
+impl Listener for TlsListener {
+    type Io = TlsStream<TcpStream>;
+    type Addr = SocketAddr;
+
+    async fn accept(&mut self) -> (Self::Io, Self::Addr) {
+        match self.accepted.recv().await {
+            Some(accepted) => accepted,
+            // the accept loop only stops once this is dropped
+            None => std::future::pending().await,
+        }
+    }
+
+    fn local_addr(&self) -> io::Result<Self::Addr> {
+        Ok(self.local_addr)
+    }
+}
@@ -0,0 +1,216 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, LazyLock, Mutex};
+
+use chrono::Utc;
+use rand::RngCore;
+use tokio::sync::{Semaphore, broadcast};
+use tracing::warn;
+
+use crate::errors::ApiError;
+use crate::models::uploads::{
+    JobEvent, JobFileStatus, JobStatus, UploadJob, UploadJobFile, UploadedFileInfo,
+};
+use crate::spool::Progress;
+use crate::store::JsonStore;
+
+// finished jobs beyond this many are dropped, oldest first
+const MAX_JOBS: usize = 200;
+
+// events a slow subscriber may fall behind by before it misses some
+const EVENT_BUFFER: usize = 64;
+
+// bytes between progress events for one file
+const PROGRESS_STEP_BYTES: u64 = 256 * 1024;
+
+// Background uploads by id. A job's spooled files don't survive a restart, so
+// anything unfinished at startup is marked interrupted.
+static JOBS: LazyLock<JsonStore<BTreeMap<String, UploadJob>>> = LazyLock::new(|| {
+    let store: JsonStore<BTreeMap<String, UploadJob>> = JsonStore::open("upload_jobs");
+    let result = store.update(|jobs| {
+        for job in jobs.values_mut().filter(|job| !job.status.is_finished()) {
+            job.status = JobStatus::Interrupted;
+            job.finished_at = Some(Utc::now());
+        }
+    });
+    if let Err(e) = result {
+        warn!("Failed to mark interrupted upload jobs: {e}");
+    }
+    store
+});
+
+// jobs run one at a time, each pinning its files in parallel
+pub static TURNS: Semaphore = Semaphore::const_new(1);
+
+// live events of the jobs this process is running; dropped once a job finishes
+static EVENTS: LazyLock<Mutex<HashMap<String, broadcast::Sender<JobEvent>>>> =
+    LazyLock::new(Mutex::default);
+
+fn publish(id: &str, event: JobEvent) {
+    if let Some(events) = EVENTS.lock().unwrap().get(id) {
+        // nobody listening is fine
+        let _ = events.send(event);
+    }
+}
+
+// the job as it stands, plus its live events while it's still running
+pub fn subscribe(id: &str) -> Option<(UploadJob, Option<broadcast::Receiver<JobEvent>>)> {
+    // subscribe before reading the job, so no event falls between the two
+    let events = EVENTS.lock().unwrap().get(id).map(|tx| tx.subscribe());
+    let job = get(id)?;
+    let events = events.filter(|_| !job.status.is_finished());
+    Some((job, events))
+}
+
+fn random_id() -> String {
+    let mut buf = [0u8; 12];
+    rand::rng().fill_bytes(&mut buf);
+    buf.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+pub fn create(files: Vec<UploadJobFile>) -> Result<UploadJob, ApiError> {
+    let job = UploadJob {
+        id: random_id(),
+        status: JobStatus::Queued,
+        group_id: None,
+        created_at: Utc::now(),
+        finished_at: None,
+        files,
+        message: None,
+    };
+
+    JOBS.update(|jobs| {
+        jobs.insert(job.id.clone(), job.clone());
+        if !job.status.is_finished() {
+            let (events, _) = broadcast::channel(EVENT_BUFFER);
+            EVENTS.lock().unwrap().insert(job.id.clone(), events);
+        }
+
+        let mut finished: Vec<(chrono::DateTime<Utc>, String)> = jobs
+            .values()
+            .filter(|job| job.status.is_finished())
+            .map(|job| (job.created_at, job.id.clone()))
+            .collect();
+        if jobs.len() > MAX_JOBS {
+            finished.sort();
+            for (_, id) in finished.into_iter().take(jobs.len() - MAX_JOBS) {
+                jobs.remove(&id);
+            }
+        }
+    })?;
+    Ok(job)
+}
+
+pub fn get(id: &str) -> Option<UploadJob> {
+    JOBS.read(|jobs| jobs.get(id).cloned())
+}
+
+// progress is best effort: a failed write only costs the status page, not the upload.
+// Returns what `f` did, or None for an unknown job
+fn update<R>(id: &str, f: impl FnOnce(&mut UploadJob) -> R) -> Option<R> {
+    let result = JOBS.update(|jobs| jobs.get_mut(id).map(f));
+    match result {
+        Ok(result) => result,
+        Err(e) => {
+            warn!("Failed to record progress of upload job {id}: {e}");
+            None
+        }
+    }
+}
+
+pub fn start(id: &str, group_id: Option<String>) {
+    update(id, |job| {
+        job.status = JobStatus::Running;
+        job.group_id = group_id.clone();
+    });
+    publish(id, JobEvent::Started { group_id });
+}
+
+pub fn file_started(id: &str, index: usize) {
+    let field = update(id, |job| {
+        let file = job.files.get_mut(index)?;
+        file.status = JobFileStatus::Uploading;
+        Some(file.field.clone())
+    });
+    if let Some(Some(field)) = field {
+        publish(id, JobEvent::FileStarted { index, field });
+    }
+}
+
+// reports bytes sent for one of the job's files, every so often rather than every chunk
+pub fn progress(id: &str, index: usize, size_bytes: u64) -> Progress {
+    let id = id.to_string();
+    let reported = AtomicU64::new(0);
+    Arc::new(move |bytes_sent| {
+        let last = reported.load(Ordering::Relaxed);
+        // a retry starts again from zero
+        if bytes_sent < last || bytes_sent - last >= PROGRESS_STEP_BYTES || bytes_sent == size_bytes
+        {
+            reported.store(bytes_sent, Ordering::Relaxed);
+            publish(
+                &id,
+                JobEvent::Progress {
+                    index,
+                    bytes_sent,
+                    size_bytes,
+                },
+            );
+        }
+    })
+}
+
+pub fn file_finished(id: &str, index: usize, result: &Result<UploadedFileInfo, ApiError>) {
+    let file = update(id, |job| {
+        let file = job.files.get_mut(index)?;
+        match result {
+            Ok(info) => {
+                file.status = JobFileStatus::Uploaded;
+                file.id = Some(info.id.clone());
+                file.cid = Some(info.cid.clone());
+            }
+            Err(e) => {
+                file.status = JobFileStatus::Failed;
+                file.message = Some(e.to_string());
+            }
+        }
+        Some(file.clone())
+    });
+    if let Some(Some(file)) = file {
+        publish(id, JobEvent::FileFinished { index, file });
+    }
+}
+
+// `error` is set when the job stopped before trying its files
+pub fn finish(id: &str, error: Option<String>) {
+    let finished = update(id, |job| {
+        job.finished_at = Some(Utc::now());
+        match error {
+            Some(message) => {
+                job.status = JobStatus::Failed;
+                for file in &mut job.files {
+                    if file.status != JobFileStatus::Uploaded {
+                        file.status = JobFileStatus::Failed;
+                        file.message.get_or_insert_with(|| message.clone());
+                    }
+                }
+                job.message = Some(message);
+            }
+            None => {
+                job.status = JobStatus::Completed;
+                let failed = job
+                    .files
+                    .iter()
+                    .filter(|f| f.status == JobFileStatus::Failed)
+                    .count();
+                job.message = (failed > 0)
+                    .then(|| format!("{failed} of {} files failed to upload", job.files.len()));
+            }
+        }
+        (job.status, job.message.clone())
+    });
+    if let Some((status, message)) = finished {
+        publish(id, JobEvent::Finished { status, message });
+    }
+    // dropping the sender ends every subscriber's stream once they've read the rest
+    EVENTS.lock().unwrap().remove(id);
+}
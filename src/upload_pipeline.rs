@@ -0,0 +1,321 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use tracing::{info, warn};
+
+use crate::config::{UploadConfig, WatermarkConfig};
+use crate::errors::ApiError;
+use crate::exif;
+use crate::group_notifications::GroupEvent;
+use crate::models::{
+    PhotoAttributes,
+    uploads::{PhotoMetadata, UploadedFileInfo},
+};
+use crate::processing;
+use crate::spool::SpooledFile;
+use crate::state::AppState;
+
+// the built-in stages, as UPLOAD_DISABLED_STAGES names them
+pub const EXIF: &str = "exif";
+pub const WATERMARK: &str = "watermark";
+pub const THUMBNAILS: &str = "thumbnails";
+pub const WEBHOOKS: &str = "webhooks";
+
+// what an upload asked for that applies to each of its files
+#[derive(Debug, Clone, Copy, Default)]
+pub struct UploadSettings {
+    pub strip_exif: bool,
+}
+
+// a file on its way through the pipeline, with the keyvalues it will be pinned with
+pub struct UploadFile {
+    pub filename: String,
+    // sniffed from the file's first bytes
+    pub mime: &'static str,
+    pub title: String,
+    pub metadata: PhotoMetadata,
+    pub attributes: PhotoAttributes,
+    pub settings: UploadSettings,
+    // reported back with the pinned file
+    pub stripped_exif_tags: Vec<String>,
+}
+
+// a file once it's pinned
+pub struct PinnedFile {
+    pub info: UploadedFileInfo,
+    // the content pinned, when it was received rather than streamed through; a stage
+    // working on it after the request takes it
+    pub data: Option<SpooledFile>,
+    // composited onto the variants made of it
+    pub watermark: Option<WatermarkConfig>,
+}
+
+// A stage of the upload pipeline. Each hook runs for every file, in the order the
+// stages were registered; one that fails before the pin fails the file.
+#[async_trait]
+pub trait UploadPlugin: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    // whether `post_process` needs the files of such an upload whole; when no stage
+    // does, they're streamed to Pinata as they arrive and `post_process` is skipped
+    fn processes_content(&self, _settings: &UploadSettings) -> bool {
+        false
+    }
+
+    // before the file's content is checked, e.g. to reject it
+    async fn pre_validate(
+        &self,
+        _state: &AppState,
+        _file: &mut UploadFile,
+    ) -> Result<(), ApiError> {
+        Ok(())
+    }
+
+    // once the content was received and checked, which may be changed in place
+    // before it's hashed and pinned
+    async fn post_process(
+        &self,
+        _state: &AppState,
+        _file: &mut UploadFile,
+        _data: &mut SpooledFile,
+    ) -> Result<(), ApiError> {
+        Ok(())
+    }
+
+    // right before the file is pinned, the last chance to change its keyvalues; not
+    // run for a duplicate of a file already pinned
+    async fn pre_pin(&self, _state: &AppState, _file: &mut UploadFile) -> Result<(), ApiError> {
+        Ok(())
+    }
+
+    // Once the file is pinned; not run for a duplicate. The upload has succeeded by
+    // now, so a failure here is only logged.
+    async fn post_pin(
+        &self,
+        _state: &AppState,
+        _file: &UploadFile,
+        _pinned: &mut PinnedFile,
+    ) -> Result<(), ApiError> {
+        Ok(())
+    }
+}
+
+// The stages every upload goes through, in order. Custom ones are added in
+// `built_in` next to the rest.
+#[derive(Clone)]
+pub struct UploadPipeline {
+    plugins: Arc<Vec<Arc<dyn UploadPlugin>>>,
+}
+
+fn built_in() -> Vec<Arc<dyn UploadPlugin>> {
+    vec![
+        Arc::new(StripExif),
+        // before thumbnails, which composite it
+        Arc::new(Watermark),
+        Arc::new(Thumbnails),
+        Arc::new(Webhooks),
+    ]
+}
+
+impl UploadPipeline {
+    pub fn new(config: &UploadConfig) -> Result<Self, ApiError> {
+        Self::with_plugins(built_in(), &config.disabled_stages)
+    }
+
+    // `plugins` less those `disabled` names, each of which has to be one of them
+    pub fn with_plugins(
+        plugins: Vec<Arc<dyn UploadPlugin>>,
+        disabled: &[String],
+    ) -> Result<Self, ApiError> {
+        for name in disabled {
+            if !plugins.iter().any(|p| p.name() == name) {
+                let known: Vec<&str> = plugins.iter().map(|p| p.name()).collect();
+                return Err(ApiError::Api(format!(
+                    "Invalid configuration: UPLOAD_DISABLED_STAGES names an unknown stage '{name}', expected any of {}",
+                    known.join(", ")
+                )));
+            }
+        }
+        let plugins = plugins
+            .into_iter()
+            .filter(|p| {
+                let enabled = !disabled.iter().any(|name| name == p.name());
+                if !enabled {
+                    info!("Upload stage {} is disabled", p.name());
+                }
+                enabled
+            })
+            .collect();
+        Ok(Self {
+            plugins: Arc::new(plugins),
+        })
+    }
+
+    pub fn processes_content(&self, settings: &UploadSettings) -> bool {
+        self.plugins.iter().any(|p| p.processes_content(settings))
+    }
+
+    pub async fn pre_validate(
+        &self,
+        state: &AppState,
+        file: &mut UploadFile,
+    ) -> Result<(), ApiError> {
+        for plugin in self.plugins.iter() {
+            plugin.pre_validate(state, file).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn post_process(
+        &self,
+        state: &AppState,
+        file: &mut UploadFile,
+        data: &mut SpooledFile,
+    ) -> Result<(), ApiError> {
+        for plugin in self.plugins.iter() {
+            plugin.post_process(state, file, data).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn pre_pin(&self, state: &AppState, file: &mut UploadFile) -> Result<(), ApiError> {
+        for plugin in self.plugins.iter() {
+            plugin.pre_pin(state, file).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn post_pin(&self, state: &AppState, file: &UploadFile, pinned: &mut PinnedFile) {
+        if pinned.info.duplicate {
+            return;
+        }
+        for plugin in self.plugins.iter() {
+            if let Err(e) = plugin.post_pin(state, file, pinned).await {
+                warn!(
+                    "Upload stage {} failed for {}: {e}",
+                    plugin.name(),
+                    pinned.info.id
+                );
+            }
+        }
+    }
+}
+
+// takes GPS and camera identifying tags out of files whose upload asks for it
+struct StripExif;
+
+#[async_trait]
+impl UploadPlugin for StripExif {
+    fn name(&self) -> &'static str {
+        EXIF
+    }
+
+    fn processes_content(&self, settings: &UploadSettings) -> bool {
+        settings.strip_exif
+    }
+
+    async fn post_process(
+        &self,
+        _state: &AppState,
+        file: &mut UploadFile,
+        data: &mut SpooledFile,
+    ) -> Result<(), ApiError> {
+        if !file.settings.strip_exif {
+            return Ok(());
+        }
+        let mime = file.mime;
+        let removed = data
+            .modify(|bytes| {
+                let removed = exif::strip_sensitive(bytes, mime)?;
+                let changed = !removed.is_empty();
+                Ok((removed, changed))
+            })
+            .await?;
+        if !removed.is_empty() {
+            info!(
+                "Stripped EXIF tags from {}: {}",
+                file.filename,
+                removed.join(", ")
+            );
+        }
+        file.stripped_exif_tags.extend(removed);
+        Ok(())
+    }
+}
+
+// the configured watermark, for the variants made of the file
+struct Watermark;
+
+#[async_trait]
+impl UploadPlugin for Watermark {
+    fn name(&self) -> &'static str {
+        WATERMARK
+    }
+
+    async fn post_pin(
+        &self,
+        state: &AppState,
+        _file: &UploadFile,
+        pinned: &mut PinnedFile,
+    ) -> Result<(), ApiError> {
+        pinned.watermark = state.config.watermark.clone();
+        Ok(())
+    }
+}
+
+// dimensions, analysis, blurhash and thumbnails, worked out after the response is sent
+struct Thumbnails;
+
+#[async_trait]
+impl UploadPlugin for Thumbnails {
+    fn name(&self) -> &'static str {
+        THUMBNAILS
+    }
+
+    async fn post_pin(
+        &self,
+        state: &AppState,
+        _file: &UploadFile,
+        pinned: &mut PinnedFile,
+    ) -> Result<(), ApiError> {
+        processing::spawn_for_upload(
+            state.clone(),
+            pinned.info.id.clone(),
+            pinned.info.cid.clone(),
+            pinned.data.take(),
+            pinned.watermark.take(),
+        );
+        Ok(())
+    }
+}
+
+// tells WEBHOOK_NOTIFY_URL about each file pinned, into groups that want to hear of it
+struct Webhooks;
+
+#[async_trait]
+impl UploadPlugin for Webhooks {
+    fn name(&self) -> &'static str {
+        WEBHOOKS
+    }
+
+    async fn post_pin(
+        &self,
+        state: &AppState,
+        file: &UploadFile,
+        pinned: &mut PinnedFile,
+    ) -> Result<(), ApiError> {
+        state.notifier.send_for_group(
+            pinned.info.group_id.as_deref(),
+            GroupEvent::Upload,
+            serde_json::json!({
+                "file_id": pinned.info.id,
+                "name": pinned.info.name,
+                "cid": pinned.info.cid,
+                "group_id": pinned.info.group_id,
+                "mime_type": file.mime,
+                "account": state.pinata.account(),
+            }),
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,251 @@
+use std::collections::{BTreeMap, HashSet};
+use std::io::SeekFrom;
+use std::path::PathBuf;
+use std::sync::{LazyLock, Mutex};
+
+use axum::body::Body;
+use chrono::{Duration, Utc};
+use rand::RngCore;
+use tempfile::{NamedTempFile, TempPath};
+use tokio::io::{AsyncSeekExt, AsyncWriteExt};
+use tokio_stream::StreamExt;
+use tracing::warn;
+
+use crate::config;
+use crate::errors::ApiError;
+use crate::models::uploads::{CreateUploadSession, SessionStatus, UploadSession, UploadSource};
+use crate::spool::SpooledFile;
+use crate::store::JsonStore;
+
+// sessions untouched for this long are dropped along with their data
+const SESSION_TTL: Duration = Duration::hours(24);
+
+// Chunked uploads by id. The received bytes are kept under the data dir, so a
+// session survives a restart; one that was pinning goes back to waiting for a retry.
+static SESSIONS: LazyLock<JsonStore<BTreeMap<String, UploadSession>>> = LazyLock::new(|| {
+    let store: JsonStore<BTreeMap<String, UploadSession>> = JsonStore::open("upload_sessions");
+    let result = store.update(|sessions| {
+        for session in sessions
+            .values_mut()
+            .filter(|session| session.status == SessionStatus::Pinning)
+        {
+            session.status = SessionStatus::Receiving;
+            session.message = Some("Interrupted while pinning, send the last chunk again".into());
+        }
+    });
+    if let Err(e) = result {
+        warn!("Failed to reset interrupted upload sessions: {e}");
+    }
+    store
+});
+
+// sessions a request is currently writing to or pinning
+static BUSY: LazyLock<Mutex<HashSet<String>>> = LazyLock::new(Mutex::default);
+
+fn random_id() -> String {
+    let mut buf = [0u8; 12];
+    rand::rng().fill_bytes(&mut buf);
+    buf.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+// where a session's bytes are written
+pub fn data_path(id: &str) -> PathBuf {
+    config::storage()
+        .data_dir
+        .join("upload_sessions")
+        .join(format!("{id}.part"))
+}
+
+fn remove_data(id: &str) {
+    let path = data_path(id);
+    if let Err(e) = std::fs::remove_file(&path)
+        && e.kind() != std::io::ErrorKind::NotFound
+    {
+        warn!("Failed to remove {}: {e}", path.display());
+    }
+}
+
+pub fn create(
+    request: CreateUploadSession,
+    source: UploadSource,
+) -> Result<UploadSession, ApiError> {
+    let now = Utc::now();
+    let session = UploadSession {
+        id: random_id(),
+        filename: request.filename,
+        size_bytes: request.size_bytes,
+        received_bytes: 0,
+        status: SessionStatus::Receiving,
+        metadata: request.metadata,
+        create_new_group: request.create_new_group,
+        group_id: request.group_id,
+        group_name: request.group_name,
+        account: request.account,
+        created_at: now,
+        updated_at: now,
+        file_id: None,
+        cid: None,
+        message: None,
+        source: Some(source),
+    };
+
+    let expired = SESSIONS.update(|sessions| {
+        let expired: Vec<String> = sessions
+            .values()
+            .filter(|s| now - s.updated_at > SESSION_TTL)
+            .map(|s| s.id.clone())
+            .collect();
+        for id in &expired {
+            sessions.remove(id);
+        }
+        sessions.insert(session.id.clone(), session.clone());
+        expired
+    })?;
+    for id in expired {
+        remove_data(&id);
+    }
+    Ok(session)
+}
+
+pub fn get(id: &str) -> Option<UploadSession> {
+    SESSIONS.read(|sessions| sessions.get(id).cloned())
+}
+
+pub fn update(id: &str, f: impl FnOnce(&mut UploadSession)) -> Result<(), ApiError> {
+    SESSIONS.update(|sessions| {
+        if let Some(session) = sessions.get_mut(id) {
+            f(session);
+            session.updated_at = Utc::now();
+        }
+    })
+}
+
+pub fn remove(id: &str) -> Result<Option<UploadSession>, ApiError> {
+    let removed = SESSIONS.update(|sessions| sessions.remove(id))?;
+    remove_data(id);
+    Ok(removed)
+}
+
+// Held while a request works on a session, so two chunks can't be written at once
+pub struct Claim(String);
+
+impl Drop for Claim {
+    fn drop(&mut self) {
+        BUSY.lock().unwrap().remove(&self.0);
+    }
+}
+
+// None when another request already holds the session
+pub fn claim(id: &str) -> Option<Claim> {
+    BUSY.lock()
+        .unwrap()
+        .insert(id.to_string())
+        .then(|| Claim(id.to_string()))
+}
+
+// the pinned file is recorded and its data is no longer needed
+pub fn complete(id: &str, file_id: String, cid: String) -> Result<(), ApiError> {
+    update(id, |session| {
+        session.status = SessionStatus::Completed;
+        session.file_id = Some(file_id);
+        session.cid = Some(cid);
+        session.message = None;
+    })?;
+    remove_data(id);
+    Ok(())
+}
+
+// Pinning consumes the file it's given, so it gets a second link to the data and
+// the session keeps its own in case the pin fails and has to be retried
+pub fn pinning_copy(id: &str, len: u64) -> Result<SpooledFile, ApiError> {
+    let io_error = |e: std::io::Error| {
+        ApiError::Internal(format!("Failed to prepare session {id} for pinning: {e}"))
+    };
+
+    let part = data_path(id);
+    let link = part.with_extension("pinning");
+    let _ = std::fs::remove_file(&link);
+    std::fs::hard_link(&part, &link)
+        .or_else(|_| std::fs::copy(&part, &link).map(|_| ()))
+        .map_err(io_error)?;
+
+    let file = std::fs::File::open(&link).map_err(io_error)?;
+    let path = TempPath::try_from_path(link).map_err(io_error)?;
+    Ok(SpooledFile::Disk {
+        file: NamedTempFile::from_parts(file, path),
+        len,
+    })
+}
+
+// Write a chunk at `start`, returning how many of its bytes made it to disk. Bytes
+// past the recorded offset (left by a write that never got recorded) are overwritten.
+pub async fn write_chunk(
+    id: &str,
+    start: u64,
+    len: u64,
+    body: Body,
+) -> (u64, Result<(), ApiError>) {
+    let io_error =
+        |e: std::io::Error| ApiError::Internal(format!("Failed to store upload chunk: {e}"));
+    let path = data_path(id);
+
+    let opened = async {
+        if let Some(dir) = path.parent() {
+            tokio::fs::create_dir_all(dir).await?;
+        }
+        let file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(false)
+            .open(&path)
+            .await?;
+        file.set_len(start).await?;
+        Ok::<_, std::io::Error>(file)
+    };
+    let mut file = match opened.await {
+        Ok(file) => file,
+        Err(e) => return (0, Err(io_error(e))),
+    };
+    if let Err(e) = file.seek(SeekFrom::Start(start)).await {
+        return (0, Err(io_error(e)));
+    }
+
+    let mut written = 0u64;
+    let mut stream = body.into_data_stream();
+    let result = loop {
+        let chunk = match stream.next().await {
+            Some(Ok(chunk)) => chunk,
+            Some(Err(e)) => {
+                break Err(ApiError::Validation(format!(
+                    "Failed to read upload chunk: {e}"
+                )));
+            }
+            None if written < len => {
+                break Err(ApiError::Unprocessable(format!(
+                    "Chunk ended after {written} of {len} bytes"
+                )));
+            }
+            None => break Ok(()),
+        };
+        if written + chunk.len() as u64 > len {
+            // nothing of an oversized chunk is kept
+            let _ = file.set_len(start).await;
+            return (
+                0,
+                Err(ApiError::Unprocessable(format!(
+                    "Chunk is longer than the {len} bytes its Content-Range covers"
+                ))),
+            );
+        }
+        if let Err(e) = file.write_all(&chunk).await {
+            break Err(io_error(e));
+        }
+        written += chunk.len() as u64;
+    };
+
+    // whatever arrived before a failure is kept, so the client resumes from there
+    if let Err(e) = file.flush().await {
+        return (0, Err(io_error(e)));
+    }
+    (written, result)
+}
@@ -0,0 +1,53 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::LazyLock;
+
+use tracing::warn;
+
+use crate::errors::ApiError;
+use crate::store::JsonStore;
+
+// Files a group shows without holding them, by group id. A Pinata file has one group,
+// so when duplicate pins are collapsed to one copy, the other groups reference it here.
+static REFERENCES: LazyLock<JsonStore<BTreeMap<String, BTreeSet<String>>>> =
+    LazyLock::new(|| JsonStore::open("virtual_albums"));
+
+pub fn references(group_id: &str) -> Vec<String> {
+    REFERENCES.read(|albums| {
+        albums
+            .get(group_id)
+            .map(|ids| ids.iter().cloned().collect())
+            .unwrap_or_default()
+    })
+}
+
+pub fn add(group_id: &str, file_id: &str) -> Result<(), ApiError> {
+    REFERENCES.update(|albums| {
+        albums
+            .entry(group_id.to_string())
+            .or_default()
+            .insert(file_id.to_string());
+    })
+}
+
+pub fn remove_group(group_id: &str) {
+    if let Err(e) = REFERENCES.update(|albums| albums.remove(group_id)) {
+        warn!("Failed to drop the references of group {group_id}: {e}");
+    }
+}
+
+// a deleted file is no longer shown anywhere
+pub fn remove_file(file_id: &str) {
+    let referenced = REFERENCES.read(|albums| albums.values().any(|ids| ids.contains(file_id)));
+    if !referenced {
+        return;
+    }
+    let result = REFERENCES.update(|albums| {
+        albums.retain(|_, ids| {
+            ids.remove(file_id);
+            !ids.is_empty()
+        })
+    });
+    if let Err(e) = result {
+        warn!("Failed to drop the references to file {file_id}: {e}");
+    }
+}
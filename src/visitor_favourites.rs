@@ -0,0 +1,52 @@
+use std::collections::BTreeMap;
+use std::sync::LazyLock;
+
+use chrono::Utc;
+
+use crate::errors::ApiError;
+use crate::models::favourites::VisitorFavourite;
+use crate::store::JsonStore;
+
+// enough for a client's selection from a gallery, small enough to keep a scraper cheap
+pub const MAX_FAVOURITES: usize = 500;
+
+// favourites by visitor id, oldest first
+static FAVOURITES: LazyLock<JsonStore<BTreeMap<String, Vec<VisitorFavourite>>>> =
+    LazyLock::new(|| JsonStore::open("visitor_favourites"));
+
+pub fn list(visitor_id: &str) -> Vec<VisitorFavourite> {
+    FAVOURITES.read(|all| all.get(visitor_id).cloned().unwrap_or_default())
+}
+
+// adding a file twice keeps the first entry
+pub fn add(visitor_id: &str, file_id: &str) -> Result<Vec<VisitorFavourite>, ApiError> {
+    FAVOURITES.update(|all| {
+        let favourites = all.entry(visitor_id.to_string()).or_default();
+        if !favourites.iter().any(|f| f.file_id == file_id) {
+            if favourites.len() >= MAX_FAVOURITES {
+                return Err(ApiError::Conflict(format!(
+                    "At most {MAX_FAVOURITES} favourites can be kept"
+                )));
+            }
+            favourites.push(VisitorFavourite {
+                file_id: file_id.to_string(),
+                added_at: Utc::now(),
+            });
+        }
+        Ok(favourites.clone())
+    })?
+}
+
+pub fn remove(visitor_id: &str, file_id: &str) -> Result<Vec<VisitorFavourite>, ApiError> {
+    FAVOURITES.update(|all| {
+        let Some(favourites) = all.get_mut(visitor_id) else {
+            return Vec::new();
+        };
+        favourites.retain(|f| f.file_id != file_id);
+        let remaining = favourites.clone();
+        if remaining.is_empty() {
+            all.remove(visitor_id);
+        }
+        remaining
+    })
+}
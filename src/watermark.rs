@@ -0,0 +1,182 @@
+use std::str::FromStr;
+
+use image::{DynamicImage, GenericImageView, Rgba, RgbaImage, imageops::FilterType};
+
+use crate::config::WatermarkConfig;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Position {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+    Center,
+}
+
+impl FromStr for Position {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "top-left" => Ok(Self::TopLeft),
+            "top-right" => Ok(Self::TopRight),
+            "bottom-left" => Ok(Self::BottomLeft),
+            "bottom-right" => Ok(Self::BottomRight),
+            "center" => Ok(Self::Center),
+            other => Err(format!(
+                "unknown position '{other}', expected top-left, top-right, bottom-left, bottom-right or center"
+            )),
+        }
+    }
+}
+
+// gap between the mark and the edges, as a share of the shorter side
+const MARGIN: f32 = 0.03;
+
+// 5x7 glyphs, a row per byte with the leftmost pixel in bit 4. Lowercase letters are
+// drawn as capitals, anything else missing as `?`.
+const GLYPH_WIDTH: u32 = 5;
+const GLYPH_HEIGHT: u32 = 7;
+const FONT: &[(char, [u8; 7])] = &[
+    ('A', [0x0e, 0x11, 0x11, 0x1f, 0x11, 0x11, 0x11]),
+    ('B', [0x1e, 0x11, 0x11, 0x1e, 0x11, 0x11, 0x1e]),
+    ('C', [0x0e, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0e]),
+    ('D', [0x1e, 0x11, 0x11, 0x11, 0x11, 0x11, 0x1e]),
+    ('E', [0x1f, 0x10, 0x10, 0x1e, 0x10, 0x10, 0x1f]),
+    ('F', [0x1f, 0x10, 0x10, 0x1e, 0x10, 0x10, 0x10]),
+    ('G', [0x0e, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0f]),
+    ('H', [0x11, 0x11, 0x11, 0x1f, 0x11, 0x11, 0x11]),
+    ('I', [0x0e, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0e]),
+    ('J', [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0c]),
+    ('K', [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11]),
+    ('L', [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1f]),
+    ('M', [0x11, 0x1b, 0x15, 0x15, 0x11, 0x11, 0x11]),
+    ('N', [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11]),
+    ('O', [0x0e, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0e]),
+    ('P', [0x1e, 0x11, 0x11, 0x1e, 0x10, 0x10, 0x10]),
+    ('Q', [0x0e, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0d]),
+    ('R', [0x1e, 0x11, 0x11, 0x1e, 0x14, 0x12, 0x11]),
+    ('S', [0x0f, 0x10, 0x10, 0x0e, 0x01, 0x01, 0x1e]),
+    ('T', [0x1f, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04]),
+    ('U', [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0e]),
+    ('V', [0x11, 0x11, 0x11, 0x11, 0x11, 0x0a, 0x04]),
+    ('W', [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0a]),
+    ('X', [0x11, 0x11, 0x0a, 0x04, 0x0a, 0x11, 0x11]),
+    ('Y', [0x11, 0x11, 0x11, 0x0a, 0x04, 0x04, 0x04]),
+    ('Z', [0x1f, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1f]),
+    ('0', [0x0e, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0e]),
+    ('1', [0x04, 0x0c, 0x04, 0x04, 0x04, 0x04, 0x0e]),
+    ('2', [0x0e, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1f]),
+    ('3', [0x1f, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0e]),
+    ('4', [0x02, 0x06, 0x0a, 0x12, 0x1f, 0x02, 0x02]),
+    ('5', [0x1f, 0x10, 0x1e, 0x01, 0x01, 0x11, 0x0e]),
+    ('6', [0x06, 0x08, 0x10, 0x1e, 0x11, 0x11, 0x0e]),
+    ('7', [0x1f, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08]),
+    ('8', [0x0e, 0x11, 0x11, 0x0e, 0x11, 0x11, 0x0e]),
+    ('9', [0x0e, 0x11, 0x11, 0x0f, 0x01, 0x02, 0x0c]),
+    (' ', [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]),
+    ('.', [0x00, 0x00, 0x00, 0x00, 0x00, 0x0c, 0x0c]),
+    (',', [0x00, 0x00, 0x00, 0x00, 0x0c, 0x04, 0x08]),
+    ('-', [0x00, 0x00, 0x00, 0x1f, 0x00, 0x00, 0x00]),
+    ('_', [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x1f]),
+    (':', [0x00, 0x0c, 0x0c, 0x00, 0x0c, 0x0c, 0x00]),
+    ('/', [0x00, 0x01, 0x02, 0x04, 0x08, 0x10, 0x00]),
+    ('\'', [0x04, 0x04, 0x08, 0x00, 0x00, 0x00, 0x00]),
+    ('!', [0x04, 0x04, 0x04, 0x04, 0x04, 0x00, 0x04]),
+    ('?', [0x0e, 0x11, 0x01, 0x02, 0x04, 0x00, 0x04]),
+    ('@', [0x0e, 0x11, 0x01, 0x0d, 0x15, 0x15, 0x0e]),
+    ('&', [0x0c, 0x12, 0x14, 0x08, 0x15, 0x12, 0x0d]),
+    ('(', [0x02, 0x04, 0x08, 0x08, 0x08, 0x04, 0x02]),
+    (')', [0x08, 0x04, 0x02, 0x02, 0x02, 0x04, 0x08]),
+    ('+', [0x00, 0x04, 0x04, 0x1f, 0x04, 0x04, 0x00]),
+    ('#', [0x0a, 0x0a, 0x1f, 0x0a, 0x1f, 0x0a, 0x0a]),
+    ('©', [0x0e, 0x11, 0x17, 0x19, 0x17, 0x11, 0x0e]),
+];
+
+fn glyph(c: char) -> [u8; 7] {
+    let find = |c: char| FONT.iter().find(|(g, _)| *g == c).map(|(_, rows)| *rows);
+    find(c.to_ascii_uppercase())
+        .or_else(|| find('?'))
+        .unwrap_or_default()
+}
+
+// Draw `text` a pixel per font pixel, white with a dark shadow so it reads on light
+// and dark photos alike. It's scaled up with the nearest filter when applied.
+pub fn render_text(text: &str) -> RgbaImage {
+    let chars: Vec<char> = text.chars().collect();
+    let advance = GLYPH_WIDTH + 1;
+    let mut mark = RgbaImage::new(chars.len() as u32 * advance + 1, GLYPH_HEIGHT + 1);
+
+    for (shadow, color) in [(1, Rgba([0, 0, 0, 160])), (0, Rgba([255, 255, 255, 255]))] {
+        for (i, c) in chars.iter().enumerate() {
+            for (y, row) in glyph(*c).iter().enumerate() {
+                for x in 0..GLYPH_WIDTH {
+                    if row & (1 << (GLYPH_WIDTH - 1 - x)) != 0 {
+                        let px = i as u32 * advance + x + shadow;
+                        mark.put_pixel(px, y as u32 + shadow, color);
+                    }
+                }
+            }
+        }
+    }
+    mark
+}
+
+// Composite the mark onto a web-sized variant, sized as a share of its width
+pub fn apply(image: DynamicImage, config: &WatermarkConfig) -> DynamicImage {
+    let (width, height) = image.dimensions();
+    let margin = (width.min(height) as f32 * MARGIN).round() as u32;
+
+    let (mark_width, mark_height) = config.mark.dimensions();
+    let target = (width as f32 * config.scale).round().max(1.0);
+    // never taller than the image leaves room for
+    let room = height.saturating_sub(2 * margin).max(1) as f32;
+    let factor = (target / mark_width as f32).min(room / mark_height as f32);
+    let scaled_width = ((mark_width as f32 * factor).round() as u32).max(1);
+    let scaled_height = ((mark_height as f32 * factor).round() as u32).max(1);
+    let filter = if config.pixelated {
+        FilterType::Nearest
+    } else {
+        FilterType::Lanczos3
+    };
+    let mark = image::imageops::resize(&*config.mark, scaled_width, scaled_height, filter);
+
+    let (x0, y0) = match config.position {
+        Position::TopLeft => (margin, margin),
+        Position::TopRight => (width.saturating_sub(scaled_width + margin), margin),
+        Position::BottomLeft => (margin, height.saturating_sub(scaled_height + margin)),
+        Position::BottomRight => (
+            width.saturating_sub(scaled_width + margin),
+            height.saturating_sub(scaled_height + margin),
+        ),
+        Position::Center => (
+            width.saturating_sub(scaled_width) / 2,
+            height.saturating_sub(scaled_height) / 2,
+        ),
+    };
+
+    let had_alpha = image.color().has_alpha();
+    let mut canvas = image.into_rgba8();
+    for (x, y, pixel) in mark.enumerate_pixels() {
+        let (cx, cy) = (x0 + x, y0 + y);
+        if cx >= width || cy >= height {
+            continue;
+        }
+        let alpha = pixel[3] as f32 / 255.0 * config.opacity;
+        if alpha <= 0.0 {
+            continue;
+        }
+        let below = canvas.get_pixel_mut(cx, cy);
+        for channel in 0..3 {
+            below[channel] = (below[channel] as f32 * (1.0 - alpha) + pixel[channel] as f32 * alpha)
+                .round() as u8;
+        }
+        below[3] = below[3].max((alpha * 255.0).round() as u8);
+    }
+
+    if had_alpha {
+        DynamicImage::ImageRgba8(canvas)
+    } else {
+        DynamicImage::ImageRgb8(DynamicImage::ImageRgba8(canvas).into_rgb8())
+    }
+}
@@ -0,0 +1,145 @@
+// Listings across several Pinata accounts, against a mock telling them apart by JWT.
+mod common;
+
+use axum::{
+    Json, Router,
+    http::{HeaderMap, StatusCode, header::AUTHORIZATION},
+    routing::get,
+};
+use esemese_backend::config::PinataAccountConfig;
+use serde_json::{Value, json};
+
+// the account a request was made with: `personal` for the test JWT, else `client`
+fn account(headers: &HeaderMap) -> &'static str {
+    match headers.get(AUTHORIZATION).and_then(|v| v.to_str().ok()) {
+        Some("Bearer client-jwt") => "client",
+        _ => "personal",
+    }
+}
+
+async fn list_groups(headers: HeaderMap) -> Json<Value> {
+    let account = account(&headers);
+    Json(json!({ "data": {
+        "groups": [{
+            "id": format!("group-{account}"),
+            "name": format!("{account} work"),
+            "is_public": true,
+            "created_at": "2025-07-01T12:00:00Z",
+        }],
+        "next_page_token": null,
+    }}))
+}
+
+async fn list_files(headers: HeaderMap) -> Json<Value> {
+    let account = account(&headers);
+    let created_at = match account {
+        "client" => "2025-07-03T12:00:00Z",
+        _ => "2025-07-02T12:00:00Z",
+    };
+    Json(json!({ "data": {
+        "files": [{
+            "id": format!("file-{account}"),
+            "name": "photo.jpg",
+            "cid": format!("bafy{account}"),
+            "size": 1000,
+            "number_of_files": 1,
+            "mime_type": "image/jpeg",
+            "group_id": format!("group-{account}"),
+            "keyvalues": { "category": "street" },
+            "created_at": created_at,
+        }],
+        "next_page_token": null,
+    }}))
+}
+
+async fn spawn_two_accounts() -> String {
+    let mock = Router::new()
+        .route("/v3/groups/public", get(list_groups))
+        .route("/v3/files/public", get(list_files));
+    common::spawn_app_with_config(mock, |config| {
+        config.cache.ttl_secs = 0;
+        config.pinata.account = "personal".to_string();
+        config.pinata.accounts = vec![PinataAccountConfig {
+            name: "client".to_string(),
+            jwt: "client-jwt".to_string(),
+            gateway: None,
+            gateway_key: None,
+            group_prefixes: vec!["client-".to_string()],
+        }];
+    })
+    .await
+}
+
+async fn get_json(url: String) -> (StatusCode, Value) {
+    let response = reqwest::get(url).await.unwrap();
+    let status = response.status();
+    (status, response.json().await.unwrap())
+}
+
+#[tokio::test]
+async fn listings_cover_every_account() {
+    let base_url = spawn_two_accounts().await;
+
+    let (status, body) = get_json(format!("{base_url}/groups")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        body["groups"],
+        json!([
+            {
+                "id": "group-personal",
+                "name": "personal work",
+                "is_public": true,
+                "created_at": "2025-07-01T12:00:00.000Z",
+            },
+            {
+                "id": "group-client",
+                "name": "client work",
+                "is_public": true,
+                "created_at": "2025-07-01T12:00:00.000Z",
+                "account": "client",
+            },
+        ])
+    );
+
+    let (_, body) = get_json(format!("{base_url}/files-category?categories=street")).await;
+    let accounts: Vec<&Value> = body["images"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|f| &f["account"])
+        .collect();
+    assert_eq!(accounts, [&Value::Null, &json!("client")]);
+}
+
+#[tokio::test]
+async fn account_parameter_narrows_listings() {
+    let base_url = spawn_two_accounts().await;
+
+    let (_, body) = get_json(format!("{base_url}/groups?account=client")).await;
+    let ids: Vec<&Value> = body["groups"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|g| &g["id"])
+        .collect();
+    assert_eq!(ids, [&json!("group-client")]);
+
+    let (_, body) = get_json(format!("{base_url}/groups?account=personal")).await;
+    let ids: Vec<&Value> = body["groups"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|g| &g["id"])
+        .collect();
+    assert_eq!(ids, [&json!("group-personal")]);
+
+    let (status, body) = get_json(format!("{base_url}/groups?account=archive")).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(
+        body["message"]
+            .as_str()
+            .unwrap()
+            .contains("personal, client"),
+        "{body}"
+    );
+}
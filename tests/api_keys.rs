@@ -0,0 +1,87 @@
+// API keys kept in the local database, against the mock Pinata backend.
+mod common;
+
+use axum::http::StatusCode;
+use esemese_backend::config::DatabaseConfig;
+use serde_json::{Value, json};
+
+const ADMIN_TOKEN: &str = "sekret";
+
+async fn spawn_app(database: Option<DatabaseConfig>) -> String {
+    common::spawn_app_with_config(common::mock_pinata_router(), |config| {
+        config.auth.admin_token = Some(ADMIN_TOKEN.to_string());
+        config.database = database;
+    })
+    .await
+}
+
+#[tokio::test]
+async fn revoked_keys_stop_working() {
+    let dir = tempfile::tempdir().unwrap();
+    let base_url = spawn_app(Some(DatabaseConfig {
+        url: format!("sqlite://{}", dir.path().join("keys.db").display()),
+        sync_interval_secs: 300,
+    }))
+    .await;
+    let client = reqwest::Client::new();
+
+    let response = client
+        .post(format!("{base_url}/admin/keys"))
+        .bearer_auth(ADMIN_TOKEN)
+        .json(&json!({ "name": "site build" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let created: Value = response.json().await.unwrap();
+    let id = created["key"]["id"].as_str().unwrap();
+    let secret = created["secret"].as_str().unwrap();
+
+    let read = || {
+        client
+            .get(format!("{base_url}/groups"))
+            .header("x-api-key", secret)
+            .send()
+    };
+    assert_eq!(read().await.unwrap().status(), StatusCode::OK);
+
+    let response = client
+        .delete(format!("{base_url}/admin/keys/{id}"))
+        .bearer_auth(ADMIN_TOKEN)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let revoked: Value = response.json().await.unwrap();
+    assert!(revoked["key"]["revoked_at"].is_string());
+
+    assert_eq!(read().await.unwrap().status(), StatusCode::UNAUTHORIZED);
+
+    // the record stays listed
+    let listed: Value = client
+        .get(format!("{base_url}/admin/keys"))
+        .bearer_auth(ADMIN_TOKEN)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(listed["keys"][0]["id"], id);
+}
+
+#[tokio::test]
+async fn keys_need_a_database() {
+    let base_url = spawn_app(None).await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{base_url}/admin/keys"))
+        .bearer_auth(ADMIN_TOKEN)
+        .json(&json!({ "name": "site build" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["code"], "FEATURE_DISABLED");
+}
@@ -0,0 +1,56 @@
+// Who may reach which routes, against the mock Pinata backend with ADMIN_TOKEN set.
+mod common;
+
+use axum::http::StatusCode;
+
+const ADMIN_TOKEN: &str = "sekret";
+
+async fn spawn_app() -> String {
+    common::spawn_app_with_config(common::mock_pinata_router(), |config| {
+        config.auth.admin_token = Some(ADMIN_TOKEN.to_string());
+    })
+    .await
+}
+
+#[tokio::test]
+async fn owner_reads_need_credentials() {
+    let base_url = spawn_app().await;
+    let client = reqwest::Client::new();
+
+    for path in [
+        "/maintenance/consistency",
+        "/maintenance/duplicates",
+        "/maintenance/reencode",
+        "/maintenance/integrity",
+        "/metrics",
+        "/files/file-0-0/analysis",
+        "/upload/jobs/some-job",
+        "/upload/jobs/some-job/events",
+    ] {
+        let response = client
+            .get(format!("{base_url}{path}"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED, "{path}");
+    }
+
+    // the admin token gets past the check
+    let response = client
+        .get(format!("{base_url}/metrics"))
+        .bearer_auth(ADMIN_TOKEN)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn catalog_reads_stay_public() {
+    let base_url = spawn_app().await;
+
+    for path in ["/groups", "/status", "/readyz"] {
+        let response = reqwest::get(format!("{base_url}{path}")).await.unwrap();
+        assert!(response.status().is_success(), "{path}");
+    }
+}
@@ -0,0 +1,195 @@
+// Failure handling against a mock Pinata backend wrapped in the chaos layer.
+mod common;
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use axum::{
+    extract::{Request, State},
+    http::StatusCode,
+    middleware::{Next, from_fn_with_state},
+    response::{IntoResponse, Response},
+};
+use esemese_backend::chaos::ChaosConfig;
+use serde_json::Value;
+
+async fn statuses(base_url: &str, path: &str, count: usize) -> Vec<u16> {
+    let client = reqwest::Client::new();
+    let mut statuses = Vec::with_capacity(count);
+    for _ in 0..count {
+        let response = client
+            .get(format!("{base_url}{path}"))
+            .send()
+            .await
+            .unwrap();
+        statuses.push(response.status().as_u16());
+    }
+    statuses
+}
+
+#[tokio::test]
+async fn upstream_errors_surface_as_bad_gateway() {
+    let base_url = common::spawn_app_with_chaos(ChaosConfig {
+        error_rate: 1.0,
+        ..ChaosConfig::default()
+    })
+    .await;
+
+    assert_eq!(statuses(&base_url, "/groups", 3).await, vec![502; 3]);
+}
+
+#[tokio::test]
+async fn dropped_connections_surface_as_bad_gateway() {
+    let base_url = common::spawn_app_with_chaos(ChaosConfig {
+        drop_rate: 1.0,
+        ..ChaosConfig::default()
+    })
+    .await;
+
+    assert_eq!(statuses(&base_url, "/groups", 3).await, vec![502; 3]);
+}
+
+#[tokio::test]
+async fn error_bodies_carry_the_request_id() {
+    let base_url = common::spawn_app_with_chaos(ChaosConfig {
+        error_rate: 1.0,
+        ..ChaosConfig::default()
+    })
+    .await;
+    let client = reqwest::Client::new();
+
+    let response = client
+        .get(format!("{base_url}/groups"))
+        .header("x-request-id", "upload-report-42")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.headers()["x-request-id"], "upload-report-42");
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["request_id"], "upload-report-42");
+
+    // a made-up id is reported the same way
+    let response = client
+        .get(format!("{base_url}/groups"))
+        .send()
+        .await
+        .unwrap();
+    let id = response.headers()["x-request-id"]
+        .to_str()
+        .unwrap()
+        .to_string();
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["request_id"], id.as_str());
+}
+
+#[tokio::test]
+async fn error_bodies_are_problems_with_a_code() {
+    let base_url = common::spawn_app_with_chaos(ChaosConfig {
+        error_rate: 1.0,
+        ..ChaosConfig::default()
+    })
+    .await;
+
+    let response = reqwest::get(format!("{base_url}/groups")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+    assert_eq!(
+        response.headers()["content-type"],
+        "application/problem+json"
+    );
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["code"], "PINATA_UNAVAILABLE");
+    assert_eq!(body["status"], 502);
+    assert!(body["detail"].is_string());
+
+    // a path that isn't routed is told apart from Pinata failing
+    let body: Value = reqwest::get(format!("{base_url}/nowhere"))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(body["code"], "NOT_FOUND");
+}
+
+#[tokio::test]
+async fn seeded_faults_are_reproducible() {
+    let config = ChaosConfig {
+        error_rate: 0.5,
+        seed: 7,
+        ..ChaosConfig::default()
+    };
+
+    let first = statuses(&common::spawn_app_with_chaos(config).await, "/groups", 12).await;
+    let second = statuses(&common::spawn_app_with_chaos(config).await, "/groups", 12).await;
+
+    assert_eq!(first, second);
+    assert!(first.contains(&200) && first.contains(&502), "{first:?}");
+}
+
+#[tokio::test]
+async fn readiness_reports_upstream_latency_and_failure() {
+    let slow = common::spawn_app_with_chaos(ChaosConfig {
+        latency_ms: 200,
+        ..ChaosConfig::default()
+    })
+    .await;
+    let response = reqwest::get(format!("{slow}/readyz")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let report: Value = response.json().await.unwrap();
+    assert!(report["components"][0]["latency_ms"].as_u64().unwrap() >= 200);
+
+    let failing = common::spawn_app_with_chaos(ChaosConfig {
+        error_rate: 1.0,
+        ..ChaosConfig::default()
+    })
+    .await;
+    let response = reqwest::get(format!("{failing}/readyz")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+}
+
+// the mock answers normally until `down` is set, then fails everything
+async fn outage(State(down): State<Arc<AtomicBool>>, request: Request, next: Next) -> Response {
+    if down.load(Ordering::Relaxed) {
+        return StatusCode::SERVICE_UNAVAILABLE.into_response();
+    }
+    next.run(request).await
+}
+
+#[tokio::test]
+async fn outage_serves_last_good_listing_marked_stale() {
+    let down = Arc::new(AtomicBool::new(false));
+    let mock = common::mock_pinata_router().layer(from_fn_with_state(down.clone(), outage));
+    let data_dir = std::env::temp_dir().join(format!("esemese-stale-{}", std::process::id()));
+    let base_url = common::spawn_app_with_config(mock, |config| {
+        // nothing in memory, so every request reaches Pinata
+        config.cache.ttl_secs = 0;
+        config.cache.serve_stale = true;
+        config.storage.data_dir = data_dir.clone();
+    })
+    .await;
+
+    let fresh: Value = reqwest::get(format!("{base_url}/groups"))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert!(fresh.get("stale").is_none());
+
+    down.store(true, Ordering::Relaxed);
+    let response = reqwest::get(format!("{base_url}/groups")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["x-cache"], "stale");
+    let stale: Value = response.json().await.unwrap();
+    assert_eq!(stale["stale"], true);
+    assert!(stale["saved_at"].is_string());
+    assert_eq!(stale["groups"], fresh["groups"]);
+
+    // a listing never served before has nothing to fall back on
+    let response = reqwest::get(format!("{base_url}/groups?visibility=public"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+
+    let _ = std::fs::remove_dir_all(data_dir);
+}
@@ -0,0 +1,168 @@
+#![allow(dead_code)]
+
+use std::net::SocketAddr;
+
+use axum::{
+    Json, Router,
+    extract::{Path, Query},
+    middleware::from_fn_with_state,
+    routing::get,
+};
+use esemese_backend::chaos::{Chaos, ChaosConfig};
+use esemese_backend::config::Config;
+use serde::Deserialize;
+use serde_json::{Value, json};
+use tokio::net::TcpListener;
+
+// size of the fake catalog served by the mock Pinata backend
+pub const MOCK_GROUPS: usize = 8;
+pub const MOCK_FILES_PER_GROUP: usize = 24;
+
+#[derive(Debug, Deserialize)]
+struct FilesQuery {
+    group: Option<String>,
+    limit: Option<usize>,
+    #[serde(rename = "pageToken")]
+    page_token: Option<String>,
+}
+
+// Pinata's page size when no `limit` is sent
+const MOCK_DEFAULT_PAGE_SIZE: usize = 10;
+
+fn mock_file(group: usize, index: usize) -> Value {
+    json!({
+        "id": format!("file-{group}-{index}"),
+        "name": format!("photo-{index}.jpg"),
+        "cid": format!("bafymockcid{group}x{index}"),
+        "size": 2_500_000,
+        "number_of_files": 1,
+        "mime_type": "image/jpeg",
+        "group_id": format!("group-{group}"),
+        "keyvalues": { "category": if index.is_multiple_of(2) { "street" } else { "travel" } },
+        "created_at": "2025-07-01T12:00:00Z",
+    })
+}
+
+fn mock_group(index: usize) -> Value {
+    json!({
+        "id": format!("group-{index}"),
+        "name": format!("Collection {index}"),
+        "is_public": true,
+        "created_at": "2025-07-01T12:00:00Z",
+    })
+}
+
+async fn list_groups() -> Json<Value> {
+    let groups: Vec<Value> = (0..MOCK_GROUPS).map(mock_group).collect();
+
+    Json(json!({ "data": { "groups": groups, "next_page_token": null } }))
+}
+
+async fn get_group(Path(id): Path<String>) -> Result<Json<Value>, axum::http::StatusCode> {
+    let index = id
+        .strip_prefix("group-")
+        .and_then(|i| i.parse().ok())
+        .filter(|i| *i < MOCK_GROUPS)
+        .ok_or(axum::http::StatusCode::NOT_FOUND)?;
+    Ok(Json(json!({ "data": mock_group(index) })))
+}
+
+async fn get_file(Path(id): Path<String>) -> Result<Json<Value>, axum::http::StatusCode> {
+    let (group, index) = id
+        .strip_prefix("file-")
+        .and_then(|rest| rest.split_once('-'))
+        .and_then(|(g, i)| Some((g.parse().ok()?, i.parse().ok()?)))
+        .filter(|(g, i)| *g < MOCK_GROUPS && *i < MOCK_FILES_PER_GROUP)
+        .ok_or(axum::http::StatusCode::NOT_FOUND)?;
+    Ok(Json(json!({ "data": mock_file(group, index) })))
+}
+
+async fn list_files(Query(query): Query<FilesQuery>) -> Json<Value> {
+    let groups: Vec<usize> = match query
+        .group
+        .as_deref()
+        .and_then(|g| g.strip_prefix("group-"))
+    {
+        Some(id) => id.parse().into_iter().collect(),
+        None => (0..MOCK_GROUPS).collect(),
+    };
+
+    let offset: usize = query
+        .page_token
+        .and_then(|t| t.parse().ok())
+        .unwrap_or_default();
+    let page_size = query.limit.unwrap_or(MOCK_DEFAULT_PAGE_SIZE);
+
+    let all: Vec<Value> = groups
+        .into_iter()
+        .flat_map(|g| (0..MOCK_FILES_PER_GROUP).map(move |i| mock_file(g, i)))
+        .collect();
+
+    let files: Vec<Value> = all.iter().skip(offset).take(page_size).cloned().collect();
+    let next_page_token =
+        (offset + page_size < all.len()).then(|| (offset + page_size).to_string());
+
+    Json(json!({ "data": { "files": files, "next_page_token": next_page_token } }))
+}
+
+pub fn mock_pinata_router() -> Router {
+    Router::new()
+        .route("/v3/groups/public", get(list_groups))
+        .route("/v3/groups/public/{id}", get(get_group))
+        .route("/v3/files/public", get(list_files))
+        .route("/v3/files/public/{id}", get(get_file))
+}
+
+async fn serve(router: Router) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+    address
+}
+
+// start the mock Pinata backend and the app pointed at it, returning the app's base url
+pub async fn spawn_app() -> String {
+    spawn_app_with_mock(mock_pinata_router()).await
+}
+
+// same, with the mock backend injecting the given faults
+pub async fn spawn_app_with_chaos(config: ChaosConfig) -> String {
+    let mock = mock_pinata_router().layer(from_fn_with_state(
+        Chaos::new(config),
+        esemese_backend::chaos::inject,
+    ));
+    spawn_app_with_mock(mock).await
+}
+
+// start the app pointed at any mock Pinata backend
+pub async fn spawn_app_with_mock(mock: Router) -> String {
+    spawn_app_with_config(mock, |_| {}).await
+}
+
+// same, with the test's own changes to the config on top
+pub async fn spawn_app_with_config(mock: Router, configure: impl FnOnce(&mut Config)) -> String {
+    let mock = serve(mock).await;
+
+    // SAFETY: set before the app starts and every test in a binary writes the same value
+    unsafe {
+        std::env::set_var("PINATA_JWT", "test-jwt");
+    }
+
+    // the mock's address goes straight into the config, so tests may run side by side
+    let mut config = Config::load().expect("mock Pinata config is valid");
+    config.pinata.api_url = format!("http://{mock}");
+    config.pinata.uploads_url = format!("http://{mock}");
+    // every request comes from one address, which the budgets would soon turn away
+    config.rate_limit.per_minute = 0;
+    config.rate_limit.routes.clear();
+    // tests share the data dir, and one test's last good listing or thumbnail isn't another's
+    config.cache.serve_stale = false;
+    config.cache.thumbnail_max_bytes = 0;
+    configure(&mut config);
+
+    let state = esemese_backend::AppState::new(config)
+        .await
+        .expect("mock Pinata config is valid");
+    let address = serve(esemese_backend::app(state)).await;
+    format!("http://{address}")
+}
@@ -0,0 +1,57 @@
+// Known answers for the Keccak-256 and signer recovery behind signing in with Ethereum.
+use esemese_backend::auth::eth::{keccak256, recover_signer};
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn signature(hex: &str) -> [u8; 65] {
+    let hex = hex.strip_prefix("0x").unwrap();
+    let mut bytes = [0u8; 65];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).unwrap();
+    }
+    bytes
+}
+
+#[test]
+fn keccak256_known_answers() {
+    assert_eq!(
+        hex(&keccak256(b"")),
+        "c5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470"
+    );
+    assert_eq!(
+        hex(&keccak256(b"abc")),
+        "4e03657aea45a94fc7d47ba826c8d667c0d1e6e33a64a036ec44f58fa12d6c45"
+    );
+    // longer than one 136-byte block
+    assert_eq!(
+        hex(&keccak256(&[b'a'; 200])),
+        "96ea54061def936c4be90b518992fdc6f12f535068a256229aca54267b4d084d"
+    );
+}
+
+// signed with `personal_sign` by private key 1, whose address is well known
+const MESSAGE: &str = "esemese sign-in test";
+const SIGNATURE: &str = "0xf973a0b87062c389d125d8199e803b832b6ac6bf7867a4f6cd87506060fc4c586abba46a1a7f39530cfa4751e8e6f9f4e80efb00b49f16029045705838bd4b6a1c";
+const SIGNER: &str = "0x7e5f4552091a69125d5dfcb7b8c2659029395bdf";
+
+#[test]
+fn recovers_the_signer_of_a_personal_message() {
+    let signed = signature(SIGNATURE);
+    assert_eq!(recover_signer(MESSAGE, &signed).as_deref(), Some(SIGNER));
+
+    // v as a bare recovery bit works the same
+    let mut bare = signed;
+    bare[64] -= 27;
+    assert_eq!(recover_signer(MESSAGE, &bare).as_deref(), Some(SIGNER));
+
+    // another message or the other recovery bit is someone else
+    assert_ne!(
+        recover_signer("esemese sign-in test!", &signed).as_deref(),
+        Some(SIGNER)
+    );
+    let mut flipped = signed;
+    flipped[64] = 27 + 28 - flipped[64];
+    assert_ne!(recover_signer(MESSAGE, &flipped).as_deref(), Some(SIGNER));
+}
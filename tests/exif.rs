@@ -0,0 +1,82 @@
+// Location carried in XMP rather than EXIF, taken out before pinning.
+use esemese_backend::exif::strip_sensitive;
+use flate2::Crc;
+
+const XMP: &str = r#"<?xpacket begin="" id="W5M0MpCehiHzreSzNTczkc9d"?>
+<x:xmpmeta xmlns:x="adobe:ns:meta/"><rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+<rdf:Description xmlns:exif="http://ns.adobe.com/exif/1.0/" exif:GPSLatitude="51,30.4416N" exif:GPSLongitude="0,7.5984W"/>
+</rdf:RDF></x:xmpmeta>
+<?xpacket end="w"?>"#;
+
+fn contains(haystack: &[u8], needle: &str) -> bool {
+    haystack
+        .windows(needle.len())
+        .any(|window| window == needle.as_bytes())
+}
+
+fn jpeg_with_xmp() -> Vec<u8> {
+    let mut segment = b"http://ns.adobe.com/xap/1.0/\0".to_vec();
+    segment.extend_from_slice(XMP.as_bytes());
+    let mut jpeg = vec![0xff, 0xd8, 0xff, 0xe1];
+    jpeg.extend_from_slice(&(segment.len() as u16 + 2).to_be_bytes());
+    jpeg.extend_from_slice(&segment);
+    // an empty scan, then the end of the image
+    jpeg.extend_from_slice(&[0xff, 0xda, 0x00, 0x02, 0xff, 0xd9]);
+    jpeg
+}
+
+fn png_chunk(kind: &[u8; 4], data: &[u8]) -> Vec<u8> {
+    let mut chunk = (data.len() as u32).to_be_bytes().to_vec();
+    chunk.extend_from_slice(kind);
+    chunk.extend_from_slice(data);
+    let mut crc = Crc::new();
+    crc.update(&chunk[4..]);
+    chunk.extend_from_slice(&crc.sum().to_be_bytes());
+    chunk
+}
+
+fn png_with_xmp() -> Vec<u8> {
+    let mut itxt = b"XML:com.adobe.xmp\0\0\0\0\0".to_vec();
+    itxt.extend_from_slice(XMP.as_bytes());
+    let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+    png.extend(png_chunk(b"IHDR", &[0, 0, 0, 1, 0, 0, 0, 1, 8, 0, 0, 0, 0]));
+    png.extend(png_chunk(b"iTXt", &itxt));
+    png.extend(png_chunk(b"IEND", &[]));
+    png
+}
+
+#[test]
+fn jpeg_xmp_location_is_removed() {
+    let mut jpeg = jpeg_with_xmp();
+    let size = jpeg.len();
+    assert!(contains(&jpeg, "GPSLatitude"));
+
+    let removed = strip_sensitive(&mut jpeg, "image/jpeg").unwrap();
+    assert_eq!(removed, ["XMP"]);
+    assert_eq!(jpeg.len(), size);
+    assert!(!contains(&jpeg, "GPSLatitude"));
+    assert!(!contains(&jpeg, "GPSLongitude"));
+    assert!(jpeg.ends_with(&[0xff, 0xda, 0x00, 0x02, 0xff, 0xd9]));
+}
+
+#[test]
+fn png_xmp_location_is_removed() {
+    let mut png = png_with_xmp();
+    let size = png.len();
+
+    let removed = strip_sensitive(&mut png, "image/png").unwrap();
+    assert_eq!(removed, ["XMP"]);
+    assert_eq!(png.len(), size);
+    assert!(!contains(&png, "GPSLatitude"));
+    assert!(!contains(&png, "GPSLongitude"));
+
+    // the chunk still checks out, so decoders don't reject the file
+    let start = 8 + 25;
+    let len = u32::from_be_bytes(png[start..start + 4].try_into().unwrap()) as usize;
+    let mut crc = Crc::new();
+    crc.update(&png[start + 4..start + 8 + len]);
+    assert_eq!(
+        png[start + 8 + len..start + 12 + len],
+        crc.sum().to_be_bytes()
+    );
+}
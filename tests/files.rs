@@ -0,0 +1,40 @@
+// Bulk deletes by id, against the mock Pinata backend.
+mod common;
+
+use axum::http::StatusCode;
+use serde_json::{Value, json};
+
+const ADMIN_TOKEN: &str = "sekret";
+
+async fn dry_run(namespace: Option<&str>, ids: &[&str]) -> (StatusCode, Value) {
+    let base_url = common::spawn_app_with_config(common::mock_pinata_router(), |config| {
+        config.auth.admin_token = Some(ADMIN_TOKEN.to_string());
+        config.pinata.group_namespace = namespace.map(str::to_string);
+    })
+    .await;
+    let response = reqwest::Client::new()
+        .post(format!("{base_url}/files/delete"))
+        .bearer_auth(ADMIN_TOKEN)
+        .json(&json!({ "ids": ids, "dry_run": true }))
+        .send()
+        .await
+        .unwrap();
+    (response.status(), response.json().await.unwrap())
+}
+
+#[tokio::test]
+async fn own_files_can_be_deleted_by_id() {
+    let (status, body) = dry_run(None, &["file-0-0", "file-1-2"]).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert_eq!(body["matched_ids"], json!(["file-0-0", "file-1-2"]));
+}
+
+#[tokio::test]
+async fn ids_outside_the_namespace_are_refused() {
+    // the mock's groups carry no prefix, so none of them is this deployment's
+    let (status, body) = dry_run(Some("studio"), &["file-0-0"]).await;
+    assert_eq!(status, StatusCode::NOT_FOUND, "{body}");
+
+    let (status, _) = dry_run(None, &["file-0-0", "no-such-file"]).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
@@ -0,0 +1,184 @@
+// The JSON contract on both sides: recorded Pinata v3 responses in tests/fixtures/pinata
+// must still deserialize into the models, and the public responses built from them
+// must still match tests/golden. After a deliberate change to the contract, re-record
+// the golden files with:
+//   UPDATE_GOLDEN=1 cargo test --test golden
+mod common;
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use axum::{
+    Json, Router,
+    extract::{Path, Query},
+    http::StatusCode,
+    routing::get,
+};
+use esemese_backend::models::{
+    PinataFilesResponse, groups::PinataGroupResponse, uploads::PinataUploadResponse,
+};
+use serde_json::{Value, json};
+
+fn fixture(name: &str) -> Value {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures/pinata")
+        .join(format!("{name}.json"));
+    let raw = std::fs::read_to_string(&path)
+        .unwrap_or_else(|e| panic!("reading {}: {e}", path.display()));
+    serde_json::from_str(&raw).unwrap_or_else(|e| panic!("parsing {}: {e}", path.display()))
+}
+
+// compare with tests/golden/{name}.json, or rewrite it when UPDATE_GOLDEN is set
+fn assert_golden(name: &str, actual: &Value) {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(format!("{name}.json"));
+    let pretty = serde_json::to_string_pretty(actual).unwrap();
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        std::fs::write(&path, pretty + "\n").unwrap();
+        return;
+    }
+
+    let raw = std::fs::read_to_string(&path).unwrap_or_else(|e| {
+        panic!(
+            "reading {}: {e}; record it with UPDATE_GOLDEN=1",
+            path.display()
+        )
+    });
+    let expected: Value = serde_json::from_str(&raw).unwrap();
+    assert!(
+        *actual == expected,
+        "{name} no longer matches {}, got:\n{pretty}",
+        path.display()
+    );
+}
+
+// Pinata's files listing over the recorded files, with the filters the backend sends
+async fn list_files(Query(query): Query<HashMap<String, String>>) -> Json<Value> {
+    let filters: HashMap<String, Value> = query
+        .get("metadata[keyvalues]")
+        .map(|raw| serde_json::from_str(raw).unwrap())
+        .unwrap_or_default();
+    let limit = query
+        .get("limit")
+        .and_then(|l| l.parse().ok())
+        .unwrap_or(usize::MAX);
+
+    let files: Vec<Value> = fixture("files")["data"]["files"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter(|file| {
+            query
+                .get("group")
+                .is_none_or(|group| file["group_id"] == group.as_str())
+        })
+        .filter(|file| {
+            filters
+                .iter()
+                .all(|(key, filter)| file["keyvalues"][key] == filter["value"])
+        })
+        .take(limit)
+        .cloned()
+        .collect();
+    Json(json!({ "data": { "files": files, "next_page_token": null } }))
+}
+
+async fn get_group(Path(id): Path<String>) -> Result<Json<Value>, StatusCode> {
+    fixture("groups")["data"]["groups"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|group| group["id"] == id.as_str())
+        .map(|group| Json(json!({ "data": group })))
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+fn fixture_pinata_router() -> Router {
+    Router::new()
+        .route(
+            "/v3/groups/public",
+            get(|| async { Json(fixture("groups")) }),
+        )
+        .route("/v3/groups/public/{id}", get(get_group))
+        .route("/v3/files/public", get(list_files))
+}
+
+#[test]
+fn recorded_groups_deserialize() {
+    let response: PinataGroupResponse = serde_json::from_value(fixture("groups")).unwrap();
+    let groups = &response.data.groups;
+
+    assert_eq!(groups.len(), 2);
+    assert_eq!(groups[0].name, "Lagos at Night");
+    assert_eq!(groups[1].is_public, Some(false));
+    assert_eq!(
+        groups[0].created_at.to_rfc3339(),
+        "2025-06-14T09:21:33.512+00:00"
+    );
+    assert_eq!(response.data.next_page_token, None);
+}
+
+#[test]
+fn recorded_files_deserialize() {
+    let response: PinataFilesResponse = serde_json::from_value(fixture("files")).unwrap();
+    let [photo, variant, market] = &response.data.files[..] else {
+        panic!("expected three files, got {:?}", response.data.files);
+    };
+
+    // keyvalues are read into the typed attributes and the fields derived after upload
+    assert_eq!(photo.keyvalues.tags, ["bridge", "night", "rain"]);
+    assert_eq!(photo.keyvalues.rating, Some(5));
+    assert_eq!(
+        photo.keyvalues.exposure.shutter_speed.as_deref(),
+        Some("1/60")
+    );
+    assert_eq!((photo.width, photo.height), (Some(6240), Some(4160)));
+    assert_eq!(
+        photo.captured_at.map(|t| t.to_rfc3339()).as_deref(),
+        Some("2025-06-13T21:47:05+01:00")
+    );
+    assert!(variant.is_variant() && !photo.is_variant());
+    // a rating that isn't a number is kept as it came rather than dropped
+    assert_eq!(market.keyvalues.rating, None);
+    assert_eq!(market.keyvalues.extra["rating"], "great");
+
+    assert_golden("pinata_files", &serde_json::to_value(&response).unwrap());
+}
+
+#[test]
+fn recorded_upload_deserializes() {
+    let response: PinataUploadResponse = serde_json::from_value(fixture("upload")).unwrap();
+
+    assert_eq!(response.data.name, "DSCF4100.jpg");
+    assert_eq!(
+        response.data.group_id.as_deref(),
+        Some("01975f2a-3c41-7d2e-9b1a-5e8f0c6d4a21")
+    );
+    assert_eq!(response.data.keyvalues.unwrap()["category"], "street");
+}
+
+#[tokio::test]
+async fn public_responses_match_golden() {
+    let base_url = common::spawn_app_with_mock(fixture_pinata_router()).await;
+    let client = reqwest::Client::new();
+
+    for (name, path) in [
+        ("groups", "/groups"),
+        ("groups_public", "/groups?visibility=public"),
+        ("groups_with_thumbnails", "/groups-with-thumbnails"),
+        (
+            "group_images",
+            "/group-images?group_id=01975f2a-3c41-7d2e-9b1a-5e8f0c6d4a21",
+        ),
+        ("files_category", "/files-category?categories=street"),
+    ] {
+        let response = client
+            .get(format!("{base_url}{path}"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK, "{path}");
+        assert_golden(name, &response.json().await.unwrap());
+    }
+}
@@ -0,0 +1,72 @@
+// Load/soak test of the listing pipeline against the mock Pinata backend.
+//
+// Tune with LOAD_REQUESTS and LOAD_CONCURRENCY, e.g. for a soak run:
+//   LOAD_REQUESTS=20000 LOAD_CONCURRENCY=128 cargo test --release --test load -- --nocapture
+mod common;
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use tokio::{sync::Semaphore, task::JoinSet};
+
+const ENDPOINTS: &[&str] = &[
+    "/groups",
+    "/groups-with-thumbnails",
+    "/group-images?group_id=group-1&limit=12",
+    "/files-category?categories=street,travel&limit=12",
+];
+
+fn env_or(name: &str, default: usize) -> usize {
+    std::env::var(name)
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(default)
+}
+
+fn percentile(sorted: &[Duration], p: f64) -> Duration {
+    let index = ((sorted.len() as f64 - 1.0) * p).round() as usize;
+    sorted[index]
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn listing_endpoints_under_load() {
+    let base_url = common::spawn_app().await;
+    let requests = env_or("LOAD_REQUESTS", 64);
+    let concurrency = env_or("LOAD_CONCURRENCY", 8);
+
+    let client = reqwest::Client::new();
+    let permits = Arc::new(Semaphore::new(concurrency));
+    let mut tasks = JoinSet::new();
+    let started = Instant::now();
+
+    for i in 0..requests {
+        let url = format!("{base_url}{}", ENDPOINTS[i % ENDPOINTS.len()]);
+        let client = client.clone();
+        let permit = permits.clone().acquire_owned().await.unwrap();
+
+        tasks.spawn(async move {
+            let start = Instant::now();
+            let status = client.get(&url).send().await.map(|r| r.status());
+            drop(permit);
+            (url, status, start.elapsed())
+        });
+    }
+
+    let mut latencies = Vec::with_capacity(requests);
+    while let Some(result) = tasks.join_next().await {
+        let (url, status, elapsed) = result.unwrap();
+        let status = status.unwrap_or_else(|e| panic!("request to {url} failed: {e}"));
+        assert!(status.is_success(), "{url} returned {status}");
+        latencies.push(elapsed);
+    }
+
+    let total = started.elapsed();
+    latencies.sort();
+    println!(
+        "{requests} requests @ {concurrency} concurrent in {total:?}: p50 {:?}, p95 {:?}, p99 {:?}, max {:?}",
+        percentile(&latencies, 0.50),
+        percentile(&latencies, 0.95),
+        percentile(&latencies, 0.99),
+        latencies[latencies.len() - 1],
+    );
+}
@@ -0,0 +1,71 @@
+// What a share link's gallery hands out, against the mock Pinata backend.
+mod common;
+
+use axum::http::StatusCode;
+use serde_json::{Value, json};
+
+const ADMIN_TOKEN: &str = "sekret";
+
+async fn shared_gallery(policy: Value) -> Value {
+    let base_url = common::spawn_app_with_config(common::mock_pinata_router(), |config| {
+        config.auth.admin_token = Some(ADMIN_TOKEN.to_string());
+        config.pinata.gateway = Some("example.mypinata.cloud".to_string());
+    })
+    .await;
+    let client = reqwest::Client::new();
+
+    let mut body = json!({ "group_id": "group-0" });
+    body.as_object_mut()
+        .unwrap()
+        .extend(policy.as_object().unwrap().clone());
+    let response = client
+        .post(format!("{base_url}/admin/shares"))
+        .bearer_auth(ADMIN_TOKEN)
+        .json(&body)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let created: Value = response.json().await.unwrap();
+    let token = created["share"]["token"].as_str().unwrap();
+
+    let response = reqwest::get(format!("{base_url}/share/{token}"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    response.json().await.unwrap()
+}
+
+#[tokio::test]
+async fn resized_share_hides_the_originals() {
+    let gallery = shared_gallery(json!({})).await;
+    let images = gallery["images"].as_array().unwrap();
+    assert!(!images.is_empty());
+    for image in images {
+        assert!(image.get("cid").is_none(), "{image}");
+        assert!(image.get("keyvalues").is_none(), "{image}");
+        assert!(
+            image["thumbnail_url"]
+                .as_str()
+                .unwrap()
+                .contains("img-width=")
+        );
+        assert!(image["display_url"].is_string());
+    }
+}
+
+#[tokio::test]
+async fn watermarked_share_has_no_unmarked_display_copy() {
+    let gallery = shared_gallery(json!({ "watermark": true })).await;
+    for image in gallery["images"].as_array().unwrap() {
+        assert!(image["display_url"].is_null(), "{image}");
+    }
+}
+
+#[tokio::test]
+async fn full_resolution_share_names_the_originals() {
+    let gallery = shared_gallery(json!({ "full_resolution": true })).await;
+    for image in gallery["images"].as_array().unwrap() {
+        assert!(image["cid"].is_string(), "{image}");
+    }
+}
@@ -0,0 +1,87 @@
+// Chunked uploads whose chunks don't match their Content-Range, against the mock
+// Pinata backend.
+mod common;
+
+use axum::http::StatusCode;
+use serde_json::{Value, json};
+
+const ADMIN_TOKEN: &str = "sekret";
+const SIZE: usize = 20;
+
+async fn open_session(client: &reqwest::Client) -> (String, String) {
+    let base_url = common::spawn_app_with_config(common::mock_pinata_router(), |config| {
+        config.auth.admin_token = Some(ADMIN_TOKEN.to_string());
+    })
+    .await;
+    let response = client
+        .post(format!("{base_url}/upload/sessions"))
+        .bearer_auth(ADMIN_TOKEN)
+        .json(&json!({
+            "filename": "chunked.jpg",
+            "size_bytes": SIZE,
+            "group_id": "group-0",
+            "metadata": {
+                "title": "Chunked",
+                "description": "",
+                "category": "street",
+                "camera": "",
+                "lens": "",
+                "iso": "",
+                "aperture": "",
+                "shutterSpeed": "",
+            },
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let body: Value = response.json().await.unwrap();
+    let id = body["session"]["id"].as_str().unwrap().to_string();
+    (base_url, id)
+}
+
+async fn send_chunk(
+    client: &reqwest::Client,
+    base_url: &str,
+    id: &str,
+    range: &str,
+    body: Vec<u8>,
+) -> (StatusCode, Value) {
+    let response = client
+        .patch(format!("{base_url}/upload/sessions/{id}"))
+        .bearer_auth(ADMIN_TOKEN)
+        .header("content-range", range)
+        .body(body)
+        .send()
+        .await
+        .unwrap();
+    (response.status(), response.json().await.unwrap())
+}
+
+#[tokio::test]
+async fn short_chunk_is_unprocessable() {
+    let client = reqwest::Client::new();
+    let (base_url, id) = open_session(&client).await;
+
+    let (status, body) = send_chunk(&client, &base_url, &id, "bytes 0-9/20", vec![1; 5]).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body["code"], "VALIDATION_FAILED");
+
+    // what did arrive is kept, to resume from
+    let (_, body) = send_chunk(&client, &base_url, &id, "bytes 5-9/20", vec![1; 5]).await;
+    assert_eq!(body["session"]["received_bytes"], 10, "{body}");
+}
+
+#[tokio::test]
+async fn overlong_chunk_is_unprocessable() {
+    let client = reqwest::Client::new();
+    let (base_url, id) = open_session(&client).await;
+
+    let (status, body) = send_chunk(&client, &base_url, &id, "bytes 0-9/20", vec![1; 15]).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body["code"], "VALIDATION_FAILED");
+
+    // nothing of it is kept
+    let (status, _) = send_chunk(&client, &base_url, &id, "bytes 0-9/20", vec![1; 10]).await;
+    assert_eq!(status, StatusCode::OK);
+}